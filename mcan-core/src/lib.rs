@@ -49,11 +49,22 @@ pub use fugit;
 ///
 /// unsafe impl CanId for Can1 {
 ///     const ADDRESS: *const () = 0xBEEF0000 as *const _;
+///     const MESSAGE_RAM_SIZE: usize = 16 * 1024;
 /// }
 /// ```
 pub unsafe trait CanId {
     /// Static address of HW register controlling corresponding CAN peripheral
     const ADDRESS: *const ();
+
+    /// Size in bytes of the RAM region the peripheral can use as `Message
+    /// RAM`, starting at the eligible start address of its [`Dependencies`].
+    ///
+    /// [`mcan`] rejects capacities that exceed it at compile time. Defaults to
+    /// the 64K addressable by the peripheral; target HALs should provide the
+    /// size of the SRAM actually available to it.
+    ///
+    /// [`mcan`]: <https://docs.rs/crate/mcan/>
+    const MESSAGE_RAM_SIZE: usize = 1 << 16;
 }
 
 /// Trait representing CAN peripheral dependencies
@@ -290,3 +301,56 @@ pub unsafe trait Dependencies<Id: CanId> {
     /// be equal to or slower than the host clock.
     fn can_clock(&self) -> fugit::HertzU32;
 }
+
+/// Trait representing a DMA channel able to move elements in and out of
+/// `Message RAM`
+///
+/// [`mcan`] uses implementations of this trait to copy received elements out
+/// of `Message RAM` and transmit elements into it without involving the CPU.
+/// This is mostly worthwhile for CAN FD elements with 64 byte payloads.
+///
+/// Completion is expected to be signaled by the DMA interrupt of the target
+/// platform. Once [`MessageRamDma::is_complete`] returns `true`, [`mcan`]
+/// finishes the operation by acknowledging the received element or requesting
+/// the transmission.
+///
+/// # Safety
+/// - [`MessageRamDma::is_complete`] must only return `true` once every word of
+///   the most recently started transfer has been written to its destination
+/// - Transfers must be performed with 32-bit accesses, as `Message RAM` might
+///   not support narrower ones
+///
+/// [`mcan`]: <https://docs.rs/crate/mcan/>
+pub unsafe trait MessageRamDma {
+    /// Error reported when a transfer cannot be started
+    type Error;
+
+    /// Starts copying `words` 32-bit words from `source` to `destination`.
+    ///
+    /// # Safety
+    /// Both regions are valid for `words` 32-bit words, aligned to 4 bytes and
+    /// not accessed by anything else until the transfer has completed.
+    unsafe fn start_transfer(
+        &mut self,
+        source: *const u32,
+        destination: *mut u32,
+        words: usize,
+    ) -> Result<(), Self::Error>;
+
+    /// Returns `true` once the most recently started transfer has completed.
+    fn is_complete(&mut self) -> bool;
+
+    /// Blocks until the most recently started transfer has completed.
+    ///
+    /// [`mcan`] calls this when a transfer is waited for or dropped before it
+    /// has completed. The default implementation busy-waits on
+    /// [`MessageRamDma::is_complete`]. Platforms can override it to sleep
+    /// until the DMA interrupt, e.g. with `WFI`.
+    ///
+    /// [`mcan`]: <https://docs.rs/crate/mcan/>
+    fn wait(&mut self) {
+        while !self.is_complete() {
+            core::hint::spin_loop();
+        }
+    }
+}
@@ -0,0 +1,496 @@
+//! Generates `mcan` message types from DBC files
+//!
+//! This crate is meant to be used from build scripts. For every message in a
+//! DBC file, a struct with the physical values of its signals is declared with
+//! [`mcan::message_layout!`], together with
+//! - constants for the CAN identifier and payload length,
+//! - a `filter` function returning a filter that accepts the message,
+//! - a `message_builder` function encoding the message for transmission, and
+//! - a `TryFrom<&rx::Message<N>>` implementation decoding received messages.
+//!
+//! ```no_run
+//! // build.rs
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("messages.rs");
+//! mcan_dbc::generate_file("vehicle.dbc", out).unwrap();
+//! ```
+//!
+//! ```ignore
+//! // src/main.rs
+//! mod messages {
+//!     include!(concat!(env!("OUT_DIR"), "/messages.rs"));
+//! }
+//! ```
+//!
+//! Multiplexed signals are not supported.
+//!
+//! [`mcan::message_layout!`]: https://docs.rs/mcan/latest/mcan/macro.message_layout.html
+
+use std::fmt::{self, Write as _};
+use std::path::Path;
+
+/// ID of the pseudo message holding signals that are not assigned to any
+/// message
+const INDEPENDENT_SIGNALS_ID: u32 = 0xc000_0000;
+/// Flag marking extended IDs in DBC files
+const EXTENDED_FLAG: u32 = 1 << 31;
+
+/// Errors of generating code from a DBC file
+#[derive(Debug)]
+pub enum Error {
+    /// The DBC file is malformed
+    Syntax {
+        /// Line number, starting at 1
+        line: usize,
+        /// What is wrong
+        message: &'static str,
+    },
+    /// The DBC file uses a feature that is not supported
+    Unsupported {
+        /// Line number, starting at 1
+        line: usize,
+        /// Which feature
+        message: &'static str,
+    },
+    /// Reading the DBC file or writing the generated code failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            Self::Unsupported { line, message } => {
+                write!(f, "line {line}: unsupported: {message}")
+            }
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    name: String,
+    id: u32,
+    extended: bool,
+    len: usize,
+    signals: Vec<Signal>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Signal {
+    name: String,
+    start_bit: u16,
+    length: u8,
+    little_endian: bool,
+    signed: bool,
+    factor: f64,
+    offset: f64,
+    unit: String,
+}
+
+fn parse(dbc: &str) -> Result<Vec<Message>, Error> {
+    let mut messages: Vec<Message> = Vec::new();
+    let mut in_independent_signals = false;
+    for (index, line) in dbc.lines().enumerate() {
+        let line_number = index + 1;
+        let syntax = |message| Error::Syntax {
+            line: line_number,
+            message,
+        };
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("BO_ ") {
+            let (id, rest) = rest
+                .trim()
+                .split_once(' ')
+                .ok_or(syntax("expected message ID"))?;
+            let id: u32 = id.parse().map_err(|_| syntax("invalid message ID"))?;
+            let (name, rest) = rest
+                .split_once(':')
+                .ok_or(syntax("expected ':' after message name"))?;
+            let len = rest
+                .split_whitespace()
+                .next()
+                .and_then(|len| len.parse().ok())
+                .ok_or(syntax("invalid message length"))?;
+            in_independent_signals = id == INDEPENDENT_SIGNALS_ID;
+            if in_independent_signals {
+                continue;
+            }
+            let extended = id & EXTENDED_FLAG != 0;
+            let id = id & !EXTENDED_FLAG;
+            if (!extended && id > 0x7ff) || id > 0x1fff_ffff {
+                return Err(syntax("message ID out of range"));
+            }
+            if len > 64 {
+                return Err(syntax("message longer than 64 bytes"));
+            }
+            messages.push(Message {
+                name: name.trim().to_owned(),
+                id,
+                extended,
+                len,
+                signals: Vec::new(),
+            });
+        } else if let Some(rest) = line.strip_prefix("SG_ ") {
+            if in_independent_signals {
+                continue;
+            }
+            let message = messages
+                .last_mut()
+                .ok_or(syntax("signal outside of a message"))?;
+            message.signals.push(parse_signal(rest, line_number)?);
+        }
+    }
+    Ok(messages)
+}
+
+fn parse_signal(signal: &str, line: usize) -> Result<Signal, Error> {
+    let syntax = |message| Error::Syntax { line, message };
+    let (names, rest) = signal
+        .split_once(':')
+        .ok_or(syntax("expected ':' after signal name"))?;
+    let mut names = names.split_whitespace();
+    let name = names.next().ok_or(syntax("expected signal name"))?;
+    if names.next().is_some() {
+        return Err(Error::Unsupported {
+            line,
+            message: "multiplexed signal",
+        });
+    }
+    let rest = rest.trim_start();
+    let (layout, rest) = rest
+        .split_once(' ')
+        .ok_or(syntax("expected signal layout"))?;
+    let (start_bit, layout) = layout.split_once('|').ok_or(syntax("expected '|'"))?;
+    let (length, layout) = layout.split_once('@').ok_or(syntax("expected '@'"))?;
+    let start_bit = start_bit.parse().map_err(|_| syntax("invalid start bit"))?;
+    let length: u8 = length
+        .parse()
+        .map_err(|_| syntax("invalid signal length"))?;
+    if !(1..=64).contains(&length) {
+        return Err(syntax("signal length out of range"));
+    }
+    let little_endian = match layout.get(..1) {
+        Some("1") => true,
+        Some("0") => false,
+        _ => return Err(syntax("invalid byte order")),
+    };
+    let signed = match layout.get(1..) {
+        Some("+") => false,
+        Some("-") => true,
+        _ => return Err(syntax("invalid value type")),
+    };
+    let rest = rest.trim_start();
+    let (scaling, rest) = rest
+        .strip_prefix('(')
+        .and_then(|rest| rest.split_once(')'))
+        .ok_or(syntax("expected (factor,offset)"))?;
+    let (factor, offset) = scaling.split_once(',').ok_or(syntax("expected ','"))?;
+    let factor = factor
+        .trim()
+        .parse()
+        .map_err(|_| syntax("invalid factor"))?;
+    let offset = offset
+        .trim()
+        .parse()
+        .map_err(|_| syntax("invalid offset"))?;
+    let unit = rest
+        .split_once('"')
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(unit, _)| unit.to_owned())
+        .unwrap_or_default();
+    Ok(Signal {
+        name: name.to_owned(),
+        start_bit,
+        length,
+        little_endian,
+        signed,
+        factor,
+        offset,
+        unit,
+    })
+}
+
+/// Converts `name` to `UpperCamelCase`
+fn type_name(name: &str) -> String {
+    let mut result = String::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            result.extend(first.to_uppercase());
+            result.extend(chars.map(|c| c.to_ascii_lowercase()));
+        }
+    }
+    if result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert(0, 'M');
+    }
+    // `Self` cannot be used as a raw identifier
+    if result == "Self" {
+        result.push('_');
+    }
+    result
+}
+
+/// Converts `name` to `snake_case`
+fn field_name(name: &str) -> String {
+    let mut result = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_ascii_uppercase()
+            && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            result.push('_');
+        }
+        result.push(c.to_ascii_lowercase());
+        previous = Some(c);
+    }
+    if result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert(0, '_');
+    }
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "continue", "else", "enum", "extern", "false", "fn", "for", "if",
+        "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while", "async",
+        "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+        "typeof", "unsized", "virtual", "yield", "try",
+    ];
+    // Keywords that cannot be used as raw identifiers
+    const PATH_KEYWORDS: &[&str] = &["crate", "self", "super"];
+    if KEYWORDS.contains(&result.as_str()) {
+        result.insert_str(0, "r#");
+    } else if PATH_KEYWORDS.contains(&result.as_str()) {
+        result.push('_');
+    }
+    result
+}
+
+fn write_message(out: &mut String, message: &Message) -> fmt::Result {
+    let name = type_name(&message.name);
+    let (kind, id_type, filter_type) = if message.extended {
+        ("Extended", "ExtendedId", "ExtFilter")
+    } else {
+        ("Standard", "StandardId", "Filter")
+    };
+    let digits = if message.extended { 8 } else { 3 };
+
+    writeln!(out, "mcan::message_layout! {{")?;
+    writeln!(
+        out,
+        "    /// `{}`, {} ID `{:#0width$x}`",
+        message.name,
+        kind.to_lowercase(),
+        message.id,
+        width = digits + 2
+    )?;
+    writeln!(out, "    #[derive(Clone, Debug, PartialEq)]")?;
+    writeln!(out, "    pub struct {name} {{")?;
+    for signal in &message.signals {
+        if signal.unit.is_empty() {
+            writeln!(out, "        /// `{}`", signal.name)?;
+        } else {
+            writeln!(out, "        /// `{}` in {}", signal.name, signal.unit)?;
+        }
+        let byte_order = if signal.little_endian {
+            "LittleEndian"
+        } else {
+            "BigEndian"
+        };
+        write!(
+            out,
+            "        {}: mcan::signals::Signal::new({}, {}, mcan::signals::ByteOrder::{byte_order})",
+            field_name(&signal.name),
+            signal.start_bit,
+            signal.length
+        )?;
+        if signal.signed {
+            write!(out, ".signed()")?;
+        }
+        writeln!(out, ".scaled({:?}, {:?}),", signal.factor, signal.offset)?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    let raw_id = format!("{:#0width$x}", message.id, width = digits + 2);
+    let id = format!(
+        "match mcan::embedded_can::{id_type}::new({raw_id}) {{ Some(id) => id, None => panic!() }}"
+    );
+    writeln!(out, "impl {name} {{")?;
+    writeln!(out, "    /// CAN identifier of the message")?;
+    writeln!(
+        out,
+        "    pub const ID: mcan::embedded_can::Id = mcan::embedded_can::Id::{kind}({id});"
+    )?;
+    writeln!(out, "    /// Length of the payload in bytes")?;
+    writeln!(out, "    pub const LEN: usize = {};", message.len)?;
+    writeln!(out)?;
+    writeln!(out, "    /// Filter accepting the message")?;
+    writeln!(
+        out,
+        "    pub fn filter(action: mcan::filter::Action) -> mcan::filter::{filter_type} {{"
+    )?;
+    writeln!(out, "        mcan::filter::{filter_type}::Classic {{")?;
+    writeln!(out, "            action,")?;
+    writeln!(out, "            filter: {id},")?;
+    writeln!(out, "            mask: mcan::embedded_can::{id_type}::MAX,")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// Encodes the message into `buffer` and describes it for transmission"
+    )?;
+    writeln!(out, "    pub fn message_builder<'a>(")?;
+    writeln!(out, "        &self,")?;
+    writeln!(out, "        buffer: &'a mut [u8; Self::LEN],")?;
+    writeln!(
+        out,
+        "    ) -> Result<mcan::message::tx::MessageBuilder<'a>, mcan::signals::SignalError> {{"
+    )?;
+    writeln!(out, "        self.encode(buffer)?;")?;
+    writeln!(out, "        Ok(mcan::message::tx::MessageBuilder {{")?;
+    writeln!(out, "            id: Self::ID,")?;
+    if message.len > 8 {
+        writeln!(
+            out,
+            "            frame_type: mcan::message::tx::FrameType::FlexibleDatarate {{"
+        )?;
+        writeln!(out, "                payload: buffer,")?;
+        writeln!(out, "                bit_rate_switching: false,")?;
+        writeln!(out, "                force_error_state_indicator: false,")?;
+        writeln!(out, "            }},")?;
+    } else {
+        writeln!(
+            out,
+            "            frame_type: mcan::message::tx::FrameType::Classic("
+        )?;
+        writeln!(
+            out,
+            "                mcan::message::tx::ClassicFrameType::Data(buffer),"
+        )?;
+        writeln!(out, "            ),")?;
+    }
+    writeln!(out, "            store_tx_event: None,")?;
+    writeln!(out, "        }})")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "impl<const N: usize> TryFrom<&mcan::message::rx::Message<N>> for {name} {{"
+    )?;
+    writeln!(out, "    type Error = ();")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    fn try_from(message: &mcan::message::rx::Message<N>) -> Result<Self, ()> {{"
+    )?;
+    writeln!(out, "        use mcan::message::Raw as _;")?;
+    writeln!(out, "        if message.id() != Self::ID {{")?;
+    writeln!(out, "            return Err(());")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        Self::decode(message.data()).ok_or(())")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Generates Rust code for all messages in the DBC file contents `dbc`
+pub fn generate(dbc: &str) -> Result<String, Error> {
+    let messages = parse(dbc)?;
+    let mut out = String::from("// Generated by mcan-dbc, do not edit\n");
+    for message in &messages {
+        out.push('\n');
+        write_message(&mut out, message).expect("writing to a String never fails");
+    }
+    Ok(out)
+}
+
+/// Generates Rust code for all messages in the DBC file `input` and writes it
+/// to `output`. Meant to be called from build scripts, instructs Cargo to
+/// rerun the build script when `input` changes.
+pub fn generate_file(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), Error> {
+    let input = input.as_ref();
+    println!("cargo:rerun-if-changed={}", input.display());
+    let code = generate(&std::fs::read_to_string(input)?)?;
+    std::fs::write(output, code)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_messages_and_signals() {
+        let messages = parse(
+            r#"
+VERSION ""
+
+BO_ 2364540158 EEC1: 8 Engine
+ SG_ EngineSpeed : 24|16@1+ (0.125,0) [0|8031.875] "rpm" Vector__XXX
+ SG_ Temperature : 7|8@0- (1,-40) [-40|210] "" Vector__XXX
+
+BO_ 3221225472 VECTOR__INDEPENDENT_SIG_MSG: 0 Vector__XXX
+ SG_ Orphan : 0|8@1+ (1,0) [0|0] "" Vector__XXX
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            messages,
+            [Message {
+                name: "EEC1".into(),
+                id: 0x0cf0_04fe,
+                extended: true,
+                len: 8,
+                signals: vec![
+                    Signal {
+                        name: "EngineSpeed".into(),
+                        start_bit: 24,
+                        length: 16,
+                        little_endian: true,
+                        signed: false,
+                        factor: 0.125,
+                        offset: 0.0,
+                        unit: "rpm".into(),
+                    },
+                    Signal {
+                        name: "Temperature".into(),
+                        start_bit: 7,
+                        length: 8,
+                        little_endian: false,
+                        signed: true,
+                        factor: 1.0,
+                        offset: -40.0,
+                        unit: "".into(),
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_multiplexed_signals() {
+        let result = parse("BO_ 100 Mux: 8 Node\n SG_ Selector M : 0|8@1+ (1,0) [0|0] \"\" Node\n");
+        assert!(matches!(result, Err(Error::Unsupported { line: 2, .. })));
+    }
+
+    #[test]
+    fn converts_names() {
+        assert_eq!(type_name("EEC1"), "Eec1");
+        assert_eq!(type_name("wheel_speeds"), "WheelSpeeds");
+        assert_eq!(field_name("EngineSpeed"), "engine_speed");
+        assert_eq!(field_name("Type"), "r#type");
+        assert_eq!(field_name("Self"), "self_");
+        assert_eq!(field_name("crate"), "crate_");
+        assert_eq!(type_name("SELF"), "Self_");
+    }
+}
@@ -0,0 +1,70 @@
+//! Checks that the generated code is up to date and compiles
+
+mod messages {
+    include!("generated/example.rs");
+}
+
+use mcan::embedded_can::{ExtendedId, Id, StandardId};
+use mcan::filter::{Action, ExtFilter, Filter};
+use mcan::message::tx::{ClassicFrameType, FrameType};
+use messages::{Climate, Eec1};
+
+#[test]
+fn generated_code_is_up_to_date() {
+    let generated = mcan_dbc::generate(include_str!("generated/example.dbc")).unwrap();
+    assert_eq!(generated, include_str!("generated/example.rs"));
+}
+
+#[test]
+fn encodes_messages() {
+    let eec1 = Eec1 {
+        engine_speed: 621.0,
+        actual_torque: 0.0,
+    };
+    let mut buffer = [0; Eec1::LEN];
+    let builder = eec1.message_builder(&mut buffer).unwrap();
+    assert_eq!(builder.id, Eec1::ID);
+    assert_eq!(buffer, [0, 0, 0x7d, 0x68, 0x13, 0, 0, 0]);
+    assert_eq!(Eec1::decode(&buffer), Some(eec1));
+
+    let climate = Climate {
+        temperature: -1.5,
+        fan: 3.0,
+    };
+    let mut buffer = [0; Climate::LEN];
+    climate.encode(&mut buffer).unwrap();
+    assert_eq!(buffer, [0xfd, 0x30]);
+}
+
+#[test]
+fn filters_and_describes_messages() {
+    assert!(matches!(
+        Eec1::filter(Action::StoreFifo1),
+        ExtFilter::Classic {
+            action: Action::StoreFifo1,
+            filter,
+            mask: ExtendedId::MAX,
+        } if Id::Extended(filter) == Eec1::ID
+    ));
+    assert!(matches!(
+        Climate::filter(Action::StoreFifo0),
+        Filter::Classic {
+            action: Action::StoreFifo0,
+            filter,
+            mask: StandardId::MAX,
+        } if Id::Standard(filter) == Climate::ID
+    ));
+
+    let climate = Climate {
+        temperature: 20.0,
+        fan: 1.0,
+    };
+    let mut buffer = [0; Climate::LEN];
+    let builder = climate.message_builder(&mut buffer).unwrap();
+    assert_eq!(builder.id, Climate::ID);
+    assert_eq!(builder.store_tx_event, None);
+    assert!(matches!(
+        builder.frame_type,
+        FrameType::Classic(ClassicFrameType::Data(&[40, 0x10]))
+    ));
+}
@@ -0,0 +1,109 @@
+// Generated by mcan-dbc, do not edit
+
+mcan::message_layout! {
+    /// `EEC1`, extended ID `0x0cf004fe`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Eec1 {
+        /// `EngineSpeed` in rpm
+        engine_speed: mcan::signals::Signal::new(24, 16, mcan::signals::ByteOrder::LittleEndian).scaled(0.125, 0.0),
+        /// `ActualTorque` in %
+        actual_torque: mcan::signals::Signal::new(16, 8, mcan::signals::ByteOrder::LittleEndian).scaled(1.0, -125.0),
+    }
+}
+
+impl Eec1 {
+    /// CAN identifier of the message
+    pub const ID: mcan::embedded_can::Id = mcan::embedded_can::Id::Extended(match mcan::embedded_can::ExtendedId::new(0x0cf004fe) { Some(id) => id, None => panic!() });
+    /// Length of the payload in bytes
+    pub const LEN: usize = 8;
+
+    /// Filter accepting the message
+    pub fn filter(action: mcan::filter::Action) -> mcan::filter::ExtFilter {
+        mcan::filter::ExtFilter::Classic {
+            action,
+            filter: match mcan::embedded_can::ExtendedId::new(0x0cf004fe) { Some(id) => id, None => panic!() },
+            mask: mcan::embedded_can::ExtendedId::MAX,
+        }
+    }
+
+    /// Encodes the message into `buffer` and describes it for transmission
+    pub fn message_builder<'a>(
+        &self,
+        buffer: &'a mut [u8; Self::LEN],
+    ) -> Result<mcan::message::tx::MessageBuilder<'a>, mcan::signals::SignalError> {
+        self.encode(buffer)?;
+        Ok(mcan::message::tx::MessageBuilder {
+            id: Self::ID,
+            frame_type: mcan::message::tx::FrameType::Classic(
+                mcan::message::tx::ClassicFrameType::Data(buffer),
+            ),
+            store_tx_event: None,
+        })
+    }
+}
+
+impl<const N: usize> TryFrom<&mcan::message::rx::Message<N>> for Eec1 {
+    type Error = ();
+
+    fn try_from(message: &mcan::message::rx::Message<N>) -> Result<Self, ()> {
+        use mcan::message::Raw as _;
+        if message.id() != Self::ID {
+            return Err(());
+        }
+        Self::decode(message.data()).ok_or(())
+    }
+}
+
+mcan::message_layout! {
+    /// `Climate`, standard ID `0x123`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Climate {
+        /// `Temperature` in degC
+        temperature: mcan::signals::Signal::new(7, 8, mcan::signals::ByteOrder::BigEndian).signed().scaled(0.5, 0.0),
+        /// `Fan`
+        fan: mcan::signals::Signal::new(15, 4, mcan::signals::ByteOrder::BigEndian).scaled(1.0, 0.0),
+    }
+}
+
+impl Climate {
+    /// CAN identifier of the message
+    pub const ID: mcan::embedded_can::Id = mcan::embedded_can::Id::Standard(match mcan::embedded_can::StandardId::new(0x123) { Some(id) => id, None => panic!() });
+    /// Length of the payload in bytes
+    pub const LEN: usize = 2;
+
+    /// Filter accepting the message
+    pub fn filter(action: mcan::filter::Action) -> mcan::filter::Filter {
+        mcan::filter::Filter::Classic {
+            action,
+            filter: match mcan::embedded_can::StandardId::new(0x123) { Some(id) => id, None => panic!() },
+            mask: mcan::embedded_can::StandardId::MAX,
+        }
+    }
+
+    /// Encodes the message into `buffer` and describes it for transmission
+    pub fn message_builder<'a>(
+        &self,
+        buffer: &'a mut [u8; Self::LEN],
+    ) -> Result<mcan::message::tx::MessageBuilder<'a>, mcan::signals::SignalError> {
+        self.encode(buffer)?;
+        Ok(mcan::message::tx::MessageBuilder {
+            id: Self::ID,
+            frame_type: mcan::message::tx::FrameType::Classic(
+                mcan::message::tx::ClassicFrameType::Data(buffer),
+            ),
+            store_tx_event: None,
+        })
+    }
+}
+
+impl<const N: usize> TryFrom<&mcan::message::rx::Message<N>> for Climate {
+    type Error = ();
+
+    fn try_from(message: &mcan::message::rx::Message<N>) -> Result<Self, ()> {
+        use mcan::message::Raw as _;
+        if message.id() != Self::ID {
+            return Err(());
+        }
+        Self::decode(message.data()).ok_or(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Switching between per-message and watermark interrupts of an RX FIFO
+//!
+//! An interrupt per received message keeps latency low, but at high traffic
+//! the interrupt rate becomes a burden. The watermark interrupt fires only
+//! once the FIFO has filled up to its configured watermark, so many messages
+//! are handled per interrupt. [`AdaptiveDrain`] switches between the two based
+//! on the number of messages found in the FIFO on each interrupt.
+//!
+//! While the watermark interrupt is used, messages below the watermark are
+//! only delivered with the next interrupt. To bound their latency when traffic
+//! suddenly stops, call [`AdaptiveDrain::poll`] periodically, e.g. from a timer
+//! task.
+//!
+//! The watermark is configured with [`RxFifoConfig::watermark`] and must be
+//! non-zero. Watermark interrupts only fire once the FIFO holds at least
+//! watermark messages, so interrupts only switch back to per-message
+//! interrupts if the lower threshold exceeds the watermark. With a lower
+//! threshold, only [`AdaptiveDrain::poll`] switches back.
+//!
+//! ```no_run
+//! use mcan::adaptive_drain::AdaptiveDrain;
+//! use mcan::interrupt::{Interrupt, InterruptConfiguration, InterruptLine, OwnedInterruptSet};
+//! use mcan::rx_fifo::DynRxFifo;
+//!
+//! fn setup<Id: mcan::core::CanId>(
+//!     interrupts: &mut OwnedInterruptSet<Id, mcan::interrupt::state::Disabled>,
+//!     config: &mut InterruptConfiguration<Id>,
+//! ) -> AdaptiveDrain<Id> {
+//!     let new_message = interrupts.split(Interrupt::RxFifo0NewMessage.into()).unwrap();
+//!     let watermark = interrupts.split(Interrupt::RxFifo0WatermarkReached.into()).unwrap();
+//!     // Use watermark interrupts from 4 messages per interrupt on, go back to
+//!     // per-message interrupts below 3, with the FIFO watermark set to 2
+//!     AdaptiveDrain::new(config, new_message, watermark, InterruptLine::Line0, 4, 3)
+//! }
+//!
+//! fn on_interrupt<Id: mcan::core::CanId>(
+//!     drain: &mut AdaptiveDrain<Id>,
+//!     config: &mut InterruptConfiguration<Id>,
+//!     fifo: &mut impl DynRxFifo,
+//! ) {
+//!     drain.drain(config, fifo, |message| {
+//!         // ...
+//!     });
+//! }
+//! ```
+//!
+//! [`RxFifoConfig::watermark`]: crate::config::RxFifoConfig::watermark
+
+use crate::interrupt::{state, InterruptConfiguration, InterruptLine, OwnedInterruptSet};
+use crate::rx_fifo::DynRxFifo;
+
+/// Interrupt currently used to signal received messages
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrainMode {
+    /// Interrupt on every new message
+    PerMessage,
+    /// Interrupt when the watermark is reached
+    Watermark,
+}
+
+/// Switches an RX FIFO between per-message and watermark interrupts
+pub struct AdaptiveDrain<Id> {
+    new_message: OwnedInterruptSet<Id>,
+    watermark: OwnedInterruptSet<Id>,
+    line: InterruptLine,
+    thresholds: Thresholds,
+}
+
+/// Load thresholds deciding on the interrupt to use
+#[derive(Copy, Clone, Debug)]
+struct Thresholds {
+    mode: DrainMode,
+    high: usize,
+    low: usize,
+}
+
+impl Thresholds {
+    /// Returns the mode to switch to after an interrupt found `received`
+    /// messages
+    fn after_interrupt(&self, received: usize) -> Option<DrainMode> {
+        match self.mode {
+            DrainMode::PerMessage if received >= self.high => Some(DrainMode::Watermark),
+            DrainMode::Watermark if received < self.low => Some(DrainMode::PerMessage),
+            _ => None,
+        }
+    }
+
+    /// Returns the mode to switch to after a poll found `received` messages
+    fn after_poll(&self, received: usize) -> Option<DrainMode> {
+        (received > 0 && self.mode == DrainMode::Watermark).then_some(DrainMode::PerMessage)
+    }
+}
+
+impl<Id: mcan_core::CanId> AdaptiveDrain<Id> {
+    /// Starts in [`DrainMode::PerMessage`] on interrupt `line`. Switches to
+    /// watermark interrupts once an interrupt finds at least `high` messages in
+    /// the FIFO, and back to per-message interrupts once an interrupt finds
+    /// fewer than `low` messages.
+    ///
+    /// `low` must exceed the watermark of the FIFO for interrupts to switch
+    /// back to per-message interrupts, see the [module](self) documentation.
+    pub fn new(
+        config: &mut InterruptConfiguration<Id>,
+        new_message: OwnedInterruptSet<Id, state::Disabled>,
+        watermark: OwnedInterruptSet<Id, state::Disabled>,
+        line: InterruptLine,
+        high: usize,
+        low: usize,
+    ) -> Self {
+        Self {
+            new_message: config.enable(new_message, line),
+            watermark: watermark.into(),
+            line,
+            thresholds: Thresholds {
+                mode: DrainMode::PerMessage,
+                high,
+                low,
+            },
+        }
+    }
+
+    /// Returns the interrupt currently in use
+    pub fn mode(&self) -> DrainMode {
+        self.thresholds.mode
+    }
+
+    /// Handles an interrupt: clears the flags, receives all messages from
+    /// `fifo` into `f` and switches the interrupt if the load changed. Returns
+    /// the number of messages received.
+    pub fn drain<F: DynRxFifo>(
+        &mut self,
+        config: &mut InterruptConfiguration<Id>,
+        fifo: &mut F,
+        f: impl FnMut(F::Message),
+    ) -> usize {
+        self.clear_flags();
+        let received = receive_all(|| fifo.receive().ok(), f);
+        if let Some(mode) = self.thresholds.after_interrupt(received) {
+            self.switch(config, mode);
+        }
+        received
+    }
+
+    /// Receives messages left below the watermark. Switches back to
+    /// per-message interrupts if messages were waiting, since traffic has
+    /// evidently dropped. Returns the number of messages received.
+    pub fn poll<F: DynRxFifo>(
+        &mut self,
+        config: &mut InterruptConfiguration<Id>,
+        fifo: &mut F,
+        f: impl FnMut(F::Message),
+    ) -> usize {
+        let received = receive_all(|| fifo.receive().ok(), f);
+        if let Some(mode) = self.thresholds.after_poll(received) {
+            self.switch(config, mode);
+        }
+        received
+    }
+
+    /// Disables both interrupts and returns them
+    pub fn release(
+        self,
+        config: &mut InterruptConfiguration<Id>,
+    ) -> (
+        OwnedInterruptSet<Id, state::Disabled>,
+        OwnedInterruptSet<Id, state::Disabled>,
+    ) {
+        (
+            config.disable(self.new_message),
+            config.disable(self.watermark),
+        )
+    }
+
+    fn clear_flags(&self) {
+        self.new_message
+            .clear_interrupts(self.new_message.interrupt_flags());
+        self.watermark
+            .clear_interrupts(self.watermark.interrupt_flags());
+    }
+
+    fn switch(&mut self, config: &mut InterruptConfiguration<Id>, mode: DrainMode) {
+        let (enable, disable) = match mode {
+            DrainMode::PerMessage => (&mut self.new_message, &mut self.watermark),
+            DrainMode::Watermark => (&mut self.watermark, &mut self.new_message),
+        };
+        *enable = config.enable(core::mem::take(enable), self.line);
+        *disable = config.disable(core::mem::take(disable)).into();
+        self.thresholds.mode = mode;
+    }
+}
+
+fn receive_all<M>(mut receive: impl FnMut() -> Option<M>, mut f: impl FnMut(M)) -> usize {
+    let mut received = 0;
+    while let Some(message) = receive() {
+        f(message);
+        received += 1;
+    }
+    received
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn interrupt(thresholds: &mut Thresholds, messages: &[u32]) -> Vec<u32> {
+        let mut fifo = messages.iter().copied();
+        let mut handled = Vec::new();
+        let received = receive_all(|| fifo.next(), |message| handled.push(message));
+        assert_eq!(received, messages.len());
+        if let Some(mode) = thresholds.after_interrupt(received) {
+            thresholds.mode = mode;
+        }
+        handled
+    }
+
+    #[test]
+    fn load_switches_between_interrupts() {
+        let mut thresholds = Thresholds {
+            mode: DrainMode::PerMessage,
+            high: 4,
+            low: 3,
+        };
+        assert_eq!(interrupt(&mut thresholds, &[1, 2, 3]), [1, 2, 3]);
+        assert_eq!(thresholds.mode, DrainMode::PerMessage);
+        assert_eq!(interrupt(&mut thresholds, &[1, 2, 3, 4]), [1, 2, 3, 4]);
+        assert_eq!(thresholds.mode, DrainMode::Watermark);
+        // Between the thresholds the mode is kept
+        interrupt(&mut thresholds, &[1, 2, 3]);
+        assert_eq!(thresholds.mode, DrainMode::Watermark);
+        interrupt(&mut thresholds, &[1, 2]);
+        assert_eq!(thresholds.mode, DrainMode::PerMessage);
+        interrupt(&mut thresholds, &[1, 2, 3]);
+        assert_eq!(thresholds.mode, DrainMode::PerMessage);
+    }
+
+    #[test]
+    fn poll_switches_back_once_messages_wait() {
+        let mut thresholds = Thresholds {
+            mode: DrainMode::Watermark,
+            high: 4,
+            low: 1,
+        };
+        assert_eq!(thresholds.after_poll(0), None);
+        assert_eq!(thresholds.after_poll(2), Some(DrainMode::PerMessage));
+        thresholds.mode = DrainMode::PerMessage;
+        assert_eq!(thresholds.after_poll(2), None);
+    }
+}
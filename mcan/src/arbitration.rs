@@ -0,0 +1,122 @@
+//! Detection of lost arbitration
+//!
+//! A frame requested while the bus is busy waits at most for the frame in
+//! progress to end. If it starts later than one frame time after it was
+//! requested, it has lost arbitration against frames of higher priority.
+//! Frequent losses of a frame point to a priority that is too low for its
+//! latency requirements.
+//!
+//! [`ArbitrationTracker`] records the timestamp of each request by the
+//! message marker of the frame, and compares it with the timestamp of the
+//! start of frame in its TX event. Delays are reported to
+//! [`DynMonitor::on_arbitration_loss`] and thus counted in
+//! [`Stats::arbitration_losses`]. Frames requested while the node itself is
+//! transmitting wait behind its own frames rather than lost arbitration, so
+//! they are not measured.
+//!
+//! The timestamp counter has to be running, and frames have to be sent with
+//! TX events whose markers are below the capacity of the tracker.
+//!
+//! ```no_run
+//! use mcan::arbitration::ArbitrationTracker;
+//! use mcan::message::{tx, TxEvent};
+//! use mcan::monitor::DynMonitor;
+//! use mcan::tx_buffers::DynTx;
+//!
+//! fn send(
+//!     tracker: &mut ArbitrationTracker<32>,
+//!     tx: &mut impl DynTx<Message = tx::Message<8>>,
+//!     monitor: &impl DynMonitor,
+//!     message: tx::Message<8>,
+//!     marker: u8,
+//! ) {
+//!     tracker.on_request(marker, monitor.timestamp(), &monitor.protocol_status());
+//!     let _ = tx.transmit_queued(message);
+//! }
+//!
+//! fn on_tx_event(tracker: &mut ArbitrationTracker<32>, monitor: &impl DynMonitor, event: TxEvent) {
+//!     tracker.on_tx_event(&event, monitor);
+//! }
+//! ```
+//!
+//! [`Stats::arbitration_losses`]: crate::stats::Stats::arbitration_losses
+
+use crate::bus::ProtocolStatus;
+use crate::message::TxEvent;
+use crate::monitor::DynMonitor;
+
+/// Compares request and start of frame timestamps of frames with message
+/// markers below `N`
+pub struct ArbitrationTracker<const N: usize> {
+    requests: [Option<u16>; N],
+    frame_time: u16,
+}
+
+impl<const N: usize> ArbitrationTracker<N> {
+    /// Creates a tracker counting frames delayed by more than `frame_time`
+    /// ticks of the timestamp counter, usually the duration of the longest
+    /// frame on the bus
+    pub const fn new(frame_time: u16) -> Self {
+        Self {
+            requests: [None; N],
+            frame_time,
+        }
+    }
+
+    /// Records that the frame with `marker` was requested at timestamp `now`.
+    /// `status` is the protocol status read at the same time.
+    ///
+    /// Reading the protocol status clears its error fields, so it should be
+    /// read once and shared with other users.
+    pub fn on_request(&mut self, marker: u8, now: u16, status: &ProtocolStatus) {
+        self.request(marker, now, status.act().is_tx());
+    }
+
+    /// Compares the start of frame in `event` with the request of its frame,
+    /// calling [`DynMonitor::on_arbitration_loss`] if it was delayed. Returns
+    /// the delay in ticks of the timestamp counter, if the frame was measured.
+    pub fn on_tx_event<M: DynMonitor>(&mut self, event: &TxEvent, monitor: &M) -> Option<u16> {
+        let (delay, lost) = self.complete(event.message_marker(), event.timestamp())?;
+        if lost {
+            monitor.on_arbitration_loss();
+        }
+        Some(delay)
+    }
+
+    /// Forgets all pending requests, e.g. after the transmissions were
+    /// cancelled
+    pub fn clear(&mut self) {
+        self.requests = [None; N];
+    }
+
+    fn request(&mut self, marker: u8, now: u16, own_transmission: bool) {
+        if let Some(request) = self.requests.get_mut(usize::from(marker)) {
+            *request = (!own_transmission).then_some(now);
+        }
+    }
+
+    fn complete(&mut self, marker: u8, start_of_frame: u16) -> Option<(u16, bool)> {
+        let requested = self.requests.get_mut(usize::from(marker))?.take()?;
+        let delay = start_of_frame.wrapping_sub(requested);
+        Some((delay, delay > self.frame_time))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_frames_delayed_beyond_a_frame_time() {
+        let mut tracker = ArbitrationTracker::<4>::new(100);
+        tracker.request(0, 1000, false);
+        tracker.request(1, u16::MAX - 10, false);
+        tracker.request(2, 1000, true);
+        tracker.request(7, 1000, false);
+        assert_eq!(tracker.complete(0, 1100), Some((100, false)));
+        assert_eq!(tracker.complete(1, 120), Some((131, true)));
+        assert_eq!(tracker.complete(2, 1500), None);
+        assert_eq!(tracker.complete(7, 1500), None);
+        assert_eq!(tracker.complete(0, 1500), None);
+    }
+}
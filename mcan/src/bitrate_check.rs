@@ -0,0 +1,186 @@
+//! Verification of the bitrate against another node
+//!
+//! A node configured with a bitrate different from the rest of the bus
+//! disturbs the traffic with error frames as soon as it transmits. Devices
+//! joining a bus of unknown configuration can avoid that by sending a single
+//! probe frame before enabling application traffic and checking its outcome
+//! with a [`BitrateCheck`]:
+//!
+//! - The probe is acknowledged by another node. The peripheral stores its TX
+//!   event, and the bitrates match.
+//! - The transmission fails with acknowledgement errors. No other node is
+//!   listening at this bitrate, or no other node is present at all.
+//! - The transmission fails with other errors, e.g. bit or form errors. Nodes
+//!   on the bus use a different bitrate.
+//!
+//! The check only observes the probe. On failure, the pending transmission
+//! has to be cancelled by the application, since the peripheral retries it
+//! otherwise. Error passive nodes do not count acknowledgement errors, so the
+//! check is bounded by a timeout of the application as well.
+//!
+//! ```no_run
+//! use mcan::bitrate_check::BitrateCheck;
+//! use mcan::message::tx;
+//! use mcan::monitor::DynMonitor;
+//! use mcan::tx_buffers::DynTx;
+//! use mcan::tx_event_fifo::DynTxEventFifo;
+//!
+//! fn verify(
+//!     tx: &mut impl DynTx<Message = tx::Message<8>>,
+//!     tx_events: &mut impl DynTxEventFifo,
+//!     monitor: &impl DynMonitor,
+//!     probe: tx::Message<8>,
+//! ) -> bool {
+//!     let mut check = BitrateCheck::new(monitor, 0xa5, 2);
+//!     tx.transmit_queued(check.probe(probe)).unwrap();
+//!     nb::block!(check.poll(monitor, tx_events, |_other| {})).is_ok()
+//! }
+//! ```
+
+use crate::message::{tx, TxEvent};
+use crate::monitor::DynMonitor;
+use crate::status_event::Lec;
+use crate::tx_event_fifo::DynTxEventFifo;
+
+/// Transmission increments the transmit error counter by this much per error
+const TEC_PER_ERROR: u8 = 8;
+
+/// The probe frame of a [`BitrateCheck`] was not acknowledged
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitrateMismatch {
+    /// Only acknowledgement errors occurred, no other node is listening at
+    /// this bitrate
+    NoAcknowledge,
+    /// Other errors occurred, nodes on the bus likely use another bitrate
+    BusErrors {
+        /// Last error observed, other than acknowledgement errors
+        last_error: Lec,
+    },
+}
+
+/// Observes the outcome of a probe frame, see the [module level
+/// documentation](self)
+pub struct BitrateCheck {
+    marker: u8,
+    /// Transmit error counter when the check started
+    initial_tec: u8,
+    max_errors: u8,
+    /// Last error other than an acknowledgement error, if any
+    last_error: Option<Lec>,
+}
+
+impl BitrateCheck {
+    /// Starts a check of a probe frame with TX event marker `marker`, failing
+    /// after `max_errors` failed transmission attempts. `max_errors` is at
+    /// least one.
+    pub fn new(monitor: &impl DynMonitor, marker: u8, max_errors: u8) -> Self {
+        Self {
+            marker,
+            initial_tec: monitor.error_counters().tec().bits(),
+            max_errors: max_errors.max(1),
+            last_error: None,
+        }
+    }
+
+    /// Returns `message` prepared as the probe frame, storing a TX event with
+    /// the marker of the check
+    pub fn probe<const L: usize>(&self, mut message: tx::Message<L>) -> tx::Message<L> {
+        message.set_tx_event_marker(Some(self.marker));
+        message
+    }
+
+    /// Checks the outcome of the probe. Returns `Ok(())` once it was
+    /// acknowledged and [`nb::Error::WouldBlock`] while it is pending.
+    ///
+    /// TX events of other frames are passed to `others`. Reading the protocol
+    /// status clears its error codes; see [`DynMonitor::protocol_status`].
+    pub fn poll(
+        &mut self,
+        monitor: &impl DynMonitor,
+        tx_events: &mut impl DynTxEventFifo,
+        mut others: impl FnMut(TxEvent),
+    ) -> nb::Result<(), BitrateMismatch> {
+        while let Some(event) = tx_events.pop() {
+            if event.message_marker() == self.marker {
+                return Ok(());
+            }
+            others(event);
+        }
+        let status = monitor.protocol_status();
+        let tec = monitor.error_counters().tec().bits();
+        self.evaluate(
+            status.lec().variant().into(),
+            tec,
+            status.ep().bit() || status.bo().bit(),
+        )
+    }
+
+    fn evaluate(
+        &mut self,
+        lec: Lec,
+        tec: u8,
+        error_passive: bool,
+    ) -> nb::Result<(), BitrateMismatch> {
+        match lec {
+            Lec::None | Lec::NoChange | Lec::Ack => {}
+            error => self.last_error = Some(error),
+        }
+        let errors = tec.saturating_sub(self.initial_tec) / TEC_PER_ERROR;
+        if errors < self.max_errors && !error_passive {
+            return Err(nb::Error::WouldBlock);
+        }
+        Err(nb::Error::Other(match self.last_error {
+            Some(last_error) => BitrateMismatch::BusErrors { last_error },
+            None => BitrateMismatch::NoAcknowledge,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn started_at(initial_tec: u8) -> BitrateCheck {
+        BitrateCheck {
+            marker: 0,
+            initial_tec,
+            max_errors: 2,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn missing_acknowledgement_is_told_apart_from_bus_errors() {
+        let mut check = started_at(16);
+        assert_eq!(
+            check.evaluate(Lec::Ack, 24, false),
+            Err(nb::Error::WouldBlock)
+        );
+        assert_eq!(
+            check.evaluate(Lec::Ack, 32, false),
+            Err(nb::Error::Other(BitrateMismatch::NoAcknowledge))
+        );
+
+        let mut check = started_at(0);
+        assert_eq!(
+            check.evaluate(Lec::Bit0, 8, false),
+            Err(nb::Error::WouldBlock)
+        );
+        // The error code was cleared by the previous read
+        assert_eq!(
+            check.evaluate(Lec::NoChange, 16, false),
+            Err(nb::Error::Other(BitrateMismatch::BusErrors {
+                last_error: Lec::Bit0
+            }))
+        );
+    }
+
+    #[test]
+    fn error_passive_ends_the_check() {
+        let mut check = started_at(120);
+        assert_eq!(
+            check.evaluate(Lec::Ack, 128, true),
+            Err(nb::Error::Other(BitrateMismatch::NoAcknowledge))
+        );
+    }
+}
@@ -1,25 +1,39 @@
 //! Pad declarations for the CAN buses
 
-use crate::config::{BitTimingError, DATA_BIT_TIMING_RANGES, NOMINAL_BIT_TIMING_RANGES};
-use crate::filter::{FiltersExtended, FiltersStandard};
+use crate::config::{
+    AnyFormat, BitTiming, BitTimingError, ClassicOnly, FrameFormat, GlobalFilter,
+    NonMatchingFrames, DATA_BIT_TIMING_RANGES, NOMINAL_BIT_TIMING_RANGES,
+};
+use crate::controller_state::ControllerState;
+use crate::filter::{
+    Action, FiltersExtended, FiltersStandard, IdMatch, StagedFiltersExtended,
+    StagedFiltersStandard, TooManyFilters,
+};
+use crate::init_mode::InitModeGuard;
 use crate::interrupt::{state, InterruptConfiguration, OwnedInterruptSet};
+use crate::message::data_field_size;
 use crate::messageram::SharedMemoryInner;
+use crate::monitor::Monitor;
+use crate::provisioning::Provisioned;
 use crate::reg::{ecr::R as ECR, psr::R as PSR};
+use crate::report::{AppliedTiming, InitReport};
 use crate::rx_dedicated_buffers::RxDedicatedBuffer;
 use crate::rx_fifo::{Fifo0, Fifo1, RxFifo};
+use crate::test_mode::TestMode;
+use crate::tolerance::{clock_tolerance, ToleranceCondition};
 use crate::tx_buffers::Tx;
 use crate::tx_event_fifo::TxEventFifo;
 use core::convert::From;
 use core::fmt::{self, Debug};
-use core::ops::Deref;
+use core::ops::{Deref, RangeInclusive};
 
 use super::{
-    config::{CanConfig, Mode},
+    config::{CanConfig, Mode, TimeStampSelect},
     message::AnyMessage,
     messageram::{Capacities, SharedMemory},
 };
 use fugit::HertzU32;
-use generic_array::typenum::Unsigned;
+use generic_array::typenum::{NonZero, Unsigned};
 
 /// Wrapper for the protocol status register
 pub struct ProtocolStatus(PSR);
@@ -84,31 +98,255 @@ impl Debug for ErrorCounters {
     }
 }
 
+/// Section of the message RAM holding messages
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageRamSection {
+    /// Dedicated receive buffers
+    RxBuffers,
+    /// Receive FIFO 0
+    RxFifo0,
+    /// Receive FIFO 1
+    RxFifo1,
+    /// Transmit buffers
+    TxBuffers,
+}
+
+impl fmt::Display for MessageRamSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RxBuffers => write!(f, "RX buffers"),
+            Self::RxFifo0 => write!(f, "RX FIFO 0"),
+            Self::RxFifo1 => write!(f, "RX FIFO 1"),
+            Self::TxBuffers => write!(f, "TX buffers"),
+        }
+    }
+}
+
 /// Errors that may occur during configuration
 #[derive(Debug)]
 pub enum ConfigurationError {
-    /// Problems with the bit timing configuration
-    BitTiming(BitTimingError),
-    /// Time stamp prescaler value is not in the range [1, 16]
-    InvalidTimeStampPrescaler,
+    /// Problems with [`CanConfig::nominal_timing`]
+    ///
+    /// [`CanConfig::nominal_timing`]: crate::config::CanConfig::nominal_timing
+    NominalBitTiming(BitTimingError),
+    /// Problems with the `data_phase_timing` of [`Mode::Fd`]
+    DataBitTiming(BitTimingError),
+    /// [`Timestamp::prescaler`] is outside of `valid`
+    ///
+    /// [`Timestamp::prescaler`]: crate::config::Timestamp::prescaler
+    InvalidTimeStampPrescaler {
+        /// Configured prescaler
+        prescaler: u8,
+        /// Range of valid prescalers
+        valid: RangeInclusive<u8>,
+    },
+    /// The internal timestamp counter is selected in [`Mode::Fd`]. CAN FD
+    /// requires an external counter for timestamp generation.
+    InternalTimestampWithFd,
+    /// [`RxFifoConfig::watermark`] of RX FIFO 0 can never be reached
+    ///
+    /// [`RxFifoConfig::watermark`]: crate::config::RxFifoConfig::watermark
+    RxFifo0WatermarkExceedsCapacity {
+        /// Configured watermark
+        watermark: u8,
+        /// Capacity of the FIFO
+        capacity: usize,
+    },
+    /// [`RxFifoConfig::watermark`] of RX FIFO 1 can never be reached
+    ///
+    /// [`RxFifoConfig::watermark`]: crate::config::RxFifoConfig::watermark
+    RxFifo1WatermarkExceedsCapacity {
+        /// Configured watermark
+        watermark: u8,
+        /// Capacity of the FIFO
+        capacity: usize,
+    },
+    /// [`TxConfig::tx_event_fifo_watermark`] can never be reached
+    ///
+    /// [`TxConfig::tx_event_fifo_watermark`]: crate::config::TxConfig::tx_event_fifo_watermark
+    TxEventFifoWatermarkExceedsCapacity {
+        /// Configured watermark
+        watermark: u8,
+        /// Capacity of the FIFO
+        capacity: usize,
+    },
+    /// [`Mode::Fd`] is selected for a bus restricted to classic frames by
+    /// [`CanConfigurable::classic_only`]
+    FdOnClassicOnlyBus,
+    /// The data field size of a message RAM section, as configured in RXESC
+    /// or TXESC, does not match the message type selected in [`Capacities`].
+    /// Elements would be read truncated or misaligned. This only happens if
+    /// the registers were modified through raw access.
+    ElementSizeMismatch {
+        /// Section with the mismatch
+        section: MessageRamSection,
+        /// Data bytes per element configured in the peripheral
+        configured: usize,
+        /// Data bytes per element of the message type
+        expected: usize,
+    },
+    /// The bit timing does not tolerate the oscillator deviation given in
+    /// [`CanConfig::clock_tolerance_ppm`]
+    ///
+    /// [`CanConfig::clock_tolerance_ppm`]: crate::config::CanConfig::clock_tolerance_ppm
+    InsufficientClockTolerance {
+        /// Deviation tolerated by the bit timing
+        allowed_ppm: u32,
+        /// Deviation of the oscillator
+        required_ppm: u32,
+        /// Condition of the bit timing that limits the tolerance
+        limiting: ToleranceCondition,
+    },
 }
 
-/// Error that may occur during construction
+impl fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NominalBitTiming(e) => write!(f, "nominal bit timing: {}", e),
+            Self::DataBitTiming(e) => write!(f, "data bit timing: {}", e),
+            Self::InvalidTimeStampPrescaler { prescaler, valid } => write!(
+                f,
+                "time stamp prescaler {} outside of {}..={}",
+                prescaler,
+                valid.start(),
+                valid.end()
+            ),
+            Self::InternalTimestampWithFd => {
+                write!(f, "internal timestamp counter selected with CAN FD")
+            }
+            Self::RxFifo0WatermarkExceedsCapacity {
+                watermark,
+                capacity,
+            } => write!(
+                f,
+                "RX FIFO 0 watermark {} exceeds capacity {}",
+                watermark, capacity
+            ),
+            Self::RxFifo1WatermarkExceedsCapacity {
+                watermark,
+                capacity,
+            } => write!(
+                f,
+                "RX FIFO 1 watermark {} exceeds capacity {}",
+                watermark, capacity
+            ),
+            Self::TxEventFifoWatermarkExceedsCapacity {
+                watermark,
+                capacity,
+            } => write!(
+                f,
+                "TX event FIFO watermark {} exceeds capacity {}",
+                watermark, capacity
+            ),
+            Self::FdOnClassicOnlyBus => write!(f, "CAN FD selected for a classic only bus"),
+            Self::ElementSizeMismatch {
+                section,
+                configured,
+                expected,
+            } => write!(
+                f,
+                "{} configured for {} data bytes instead of {}",
+                section, configured, expected
+            ),
+            Self::InsufficientClockTolerance {
+                allowed_ppm,
+                required_ppm,
+                limiting,
+            } => write!(
+                f,
+                "bit timing tolerates {} ppm of clock deviation instead of {} ppm, limited by {:?}",
+                allowed_ppm, required_ppm, limiting
+            ),
+        }
+    }
+}
+
+/// All problems found when validating the configuration, see
+/// [`CanConfigurable::validate`]
+///
+/// The list has room for every error that can be reported at once. It is
+/// returned by value since configuration happens once during startup.
 #[derive(Debug)]
-pub struct MemoryNotAddressableError;
+pub struct ConfigurationErrors {
+    errors: [Option<ConfigurationError>; 13],
+}
+
+impl ConfigurationErrors {
+    fn new() -> Self {
+        Self {
+            errors: Default::default(),
+        }
+    }
+
+    fn push(&mut self, error: ConfigurationError) {
+        if let Some(slot) = self.errors.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(error);
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
 
-impl From<BitTimingError> for ConfigurationError {
-    fn from(value: BitTimingError) -> Self {
-        Self::BitTiming(value)
+    /// An iterator over the errors in the order they were found
+    pub fn iter(&self) -> impl Iterator<Item = &ConfigurationError> {
+        self.errors.iter().map_while(Option::as_ref)
+    }
+
+    /// Returns the number of errors
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if there are no errors
+    pub fn is_empty(&self) -> bool {
+        self.errors[0].is_none()
     }
 }
 
+impl From<ConfigurationError> for ConfigurationErrors {
+    fn from(value: ConfigurationError) -> Self {
+        let mut errors = Self::new();
+        errors.push(value);
+        errors
+    }
+}
+
+impl fmt::Display for ConfigurationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.iter().enumerate() {
+            if i != 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Valid values of [`Timestamp::prescaler`]
+///
+/// [`Timestamp::prescaler`]: crate::config::Timestamp::prescaler
+const VALID_TIMESTAMP_PRESCALERS: RangeInclusive<u8> = 1..=16;
+
+/// Error that may occur during construction
+#[derive(Debug)]
+pub struct MemoryNotAddressableError;
+
 /// A CAN bus that is not in configuration mode (CCE=0)
 ///
 /// Some errors (including Bus_Off) can asynchronously stop bus operation
 /// (INIT=1), which will require user intervention to reactivate the bus to
 /// resume sending and receiving messages.
-pub struct Can<'a, Id, D, C: Capacities> {
+///
+/// The [`FrameFormat`] `F` restricts the frames transmitted, see
+/// [`CanConfigurable::classic_only`].
+pub struct Can<'a, Id, D, C: Capacities, F = AnyFormat> {
     /// Controls enabling and line selection of interrupts.
     pub interrupt_configuration: InterruptConfiguration<Id>,
     /// Initial set of interrupts in a disabled state.
@@ -120,11 +358,13 @@ pub struct Can<'a, Id, D, C: Capacities> {
     /// Dedicated receive buffers
     pub rx_dedicated_buffers: RxDedicatedBuffer<'a, Id, C::RxBufferMessage>,
     /// Message transmission
-    pub tx: Tx<'a, Id, C>,
+    pub tx: Tx<'a, Id, C, F>,
     /// Events for successfully transmitted messages
     pub tx_event_fifo: TxEventFifo<'a, Id>,
     /// Auxiliary bits and bobs
     pub aux: Aux<'a, Id, D>,
+    /// Status of the peripheral
+    pub monitor: Monitor<Id>,
 }
 
 /// Auxiliary struct
@@ -187,24 +427,220 @@ pub trait DynAux {
     /// Returns `true` if the peripheral is in "Normal Operation" mode.
     fn is_operational(&self) -> bool;
 
-    /// Access the error counters register value
-    fn error_counters(&self) -> ErrorCounters;
-
-    /// Access the protocol status register value
+    /// Returns the operating state of the controller, see
+    /// [`crate::controller_state`]. `status` is the protocol status read
+    /// through [`DynMonitor::protocol_status`], which clears its error codes
+    /// and should therefore be read once and shared with other users.
     ///
-    /// Reading the register clears fields: PXE, RFDF, RBRS, RESI, DLEC, LEC.
-    fn protocol_status(&self) -> ProtocolStatus;
+    /// [`DynMonitor::protocol_status`]: crate::monitor::DynMonitor::protocol_status
+    fn controller_state(&self, status: &ProtocolStatus) -> ControllerState;
+}
 
-    /// Current value of the timestamp counter
-    ///
-    /// If timestamping is disabled, its value is zero.
-    fn timestamp(&self) -> u16;
+/// Writes NBTP. `prescaler` must have been computed from `timing`, which
+/// checks it.
+fn write_nominal_timing<Id: mcan_core::CanId>(
+    reg: &crate::reg::Can<Id>,
+    timing: &BitTiming,
+    prescaler: u16,
+) {
+    // Safety: The configuration is checked to be valid when computing the prescaler
+    reg.nbtp.write(|w| unsafe {
+        w.nsjw()
+            .bits(timing.sjw - 1)
+            .ntseg1()
+            .bits(timing.phase_seg_1 - 1)
+            .ntseg2()
+            .bits(timing.phase_seg_2 - 1)
+            .nbrp()
+            .bits(prescaler - 1)
+    });
+}
+
+/// Writes DBTP. `prescaler` must have been computed from `timing`, which
+/// checks it.
+fn write_data_timing<Id: mcan_core::CanId>(
+    reg: &crate::reg::Can<Id>,
+    timing: &BitTiming,
+    prescaler: u16,
+) {
+    // Safety: The configuration is checked to be valid when computing the prescaler
+    reg.dbtp.write(|w| unsafe {
+        w.dsjw()
+            .bits(timing.sjw - 1)
+            .dtseg1()
+            .bits(timing.phase_seg_1 - 1)
+            .dtseg2()
+            .bits(timing.phase_seg_2 - 1)
+            .dbrp()
+            .bits((prescaler - 1) as u8)
+    });
 }
 
 impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>> Aux<'a, Id, D> {
     fn configuration_mode(&self) {
         self.reg.configuration_mode()
     }
+
+    /// Switches the nominal bit timing, and the data phase bit timing if
+    /// `data_phase_timing` is given, while operating, e.g. after a bitrate
+    /// negotiation as in CANopen LSS.
+    ///
+    /// Fails with [`nb::Error::WouldBlock`] unless the bus is idle, so that no
+    /// frame is cut off. Otherwise the peripheral passes through
+    /// configuration mode, which drops pending transmissions and empties the
+    /// receive FIFOs, applies both timings and returns to the mode it was in.
+    /// Invalid timings are rejected without changing anything.
+    ///
+    /// `status` is the protocol status read through
+    /// [`DynMonitor::protocol_status`] right before the call, so that the
+    /// error codes cleared by reading it are not lost to the monitor.
+    ///
+    /// [`DynMonitor::protocol_status`]: crate::monitor::DynMonitor::protocol_status
+    pub fn change_bitrate(
+        &mut self,
+        timing: BitTiming,
+        data_phase_timing: Option<BitTiming>,
+        status: &ProtocolStatus,
+    ) -> nb::Result<(), ConfigurationError> {
+        let can_clock = self.dependencies.can_clock();
+        let prescaler = timing
+            .prescaler(can_clock, &NOMINAL_BIT_TIMING_RANGES)
+            .map_err(ConfigurationError::NominalBitTiming)?;
+        let data_phase = data_phase_timing
+            .map(|data_timing| {
+                data_timing
+                    .prescaler(can_clock, &DATA_BIT_TIMING_RANGES)
+                    .map(|data_prescaler| (data_timing, data_prescaler))
+            })
+            .transpose()
+            .map_err(ConfigurationError::DataBitTiming)?;
+        if !status.act().is_idle() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let init = InitModeGuard::enter(&self.reg);
+        write_nominal_timing(&self.reg, &timing, prescaler);
+        if let Some((data_timing, data_prescaler)) = &data_phase {
+            write_data_timing(&self.reg, data_timing, *data_prescaler);
+        }
+        init.exit();
+        self.config.nominal_timing = timing;
+        if let (
+            Mode::Fd {
+                data_phase_timing, ..
+            },
+            Some((data_timing, _)),
+        ) = (&mut self.config.mode, data_phase)
+        {
+            *data_phase_timing = data_timing;
+        }
+        Ok(())
+    }
+
+    /// Replaces both filter lists with staged filters at once, so that no
+    /// frame is filtered by a partially applied configuration.
+    ///
+    /// Fails with [`nb::Error::WouldBlock`] unless the bus is idle, so that no
+    /// frame is cut off. Otherwise reception is paused in initialization mode
+    /// while the lists are written; pending transmissions and the receive
+    /// FIFOs are kept. Staged filters that do not fit are rejected without
+    /// changing anything.
+    ///
+    /// `status` is the protocol status read through
+    /// [`DynMonitor::protocol_status`] right before the call, so that the
+    /// error codes cleared by reading it are not lost to the monitor.
+    ///
+    /// [`DynMonitor::protocol_status`]: crate::monitor::DynMonitor::protocol_status
+    pub fn swap_filters<const S: usize, const E: usize>(
+        &mut self,
+        standard: &StagedFiltersStandard<S>,
+        extended: &StagedFiltersExtended<E>,
+        status: &ProtocolStatus,
+    ) -> nb::Result<(), TooManyFilters> {
+        self.filters_standard.check(standard)?;
+        self.filters_extended.check(extended)?;
+        if !status.act().is_idle() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let operational = self.is_operational();
+        self.initialization_mode();
+        self.filters_standard.replace(standard)?;
+        self.filters_extended.replace(extended)?;
+        if operational {
+            self.operational_mode();
+        }
+        Ok(())
+    }
+
+    /// Enters test mode, giving control over the transmit pin and observation
+    /// of the receive pin. Test mode is left when the returned handle is
+    /// dropped.
+    ///
+    /// Entering and leaving test mode passes through configuration mode, which
+    /// drops pending transmissions and empties the receive FIFOs.
+    pub fn enter_test_mode(&mut self) -> TestMode<'_, Id> {
+        TestMode::enter(&self.reg, self.config.loopback)
+    }
+
+    /// Returns the nominal bit timing as configured in the peripheral, with
+    /// the bitrate and sample point computed from the clock of the
+    /// peripheral. Unlike [`Can::init_report`], this reflects changes made
+    /// after initialization, e.g. by [`Self::change_bitrate`] or through raw
+    /// register access.
+    pub fn nominal_bitrate(&self) -> AppliedTiming {
+        let nbtp = self.reg.nbtp.read();
+        AppliedTiming::from_registers(
+            self.dependencies.can_clock(),
+            nbtp.nbrp().bits(),
+            nbtp.ntseg1().bits(),
+            nbtp.ntseg2().bits(),
+            nbtp.nsjw().bits(),
+        )
+    }
+
+    /// Returns the data phase bit timing as configured in the peripheral,
+    /// like [`Self::nominal_bitrate`]. `None` unless CAN FD is enabled.
+    pub fn data_bitrate(&self) -> Option<AppliedTiming> {
+        if self.reg.cccr.read().fdoe().bit_is_clear() {
+            return None;
+        }
+        let dbtp = self.reg.dbtp.read();
+        Some(AppliedTiming::from_registers(
+            self.dependencies.can_clock(),
+            dbtp.dbrp().bits().into(),
+            dbtp.dtseg1().bits(),
+            dbtp.dtseg2().bits(),
+            dbtp.dsjw().bits(),
+        ))
+    }
+
+    /// Enters configuration mode, with CCCR.INIT and CCCR.CCE set, until the
+    /// returned guard is dropped, see [`crate::init_mode`].
+    ///
+    /// Entering configuration mode drops pending transmissions and empties
+    /// the receive FIFOs.
+    pub fn enter_init_mode(&mut self) -> InitModeGuard<'_, Id> {
+        InitModeGuard::enter(&self.reg)
+    }
+
+    /// Restarts the timeout counter from [`TimeoutCounter::period`]. Needed
+    /// in [`TimeoutSelect::CONT`] mode, where nothing else restarts it.
+    ///
+    /// [`TimeoutCounter::period`]: crate::config::TimeoutCounter::period
+    /// [`TimeoutSelect::CONT`]: crate::config::TimeoutSelect::CONT
+    pub fn restart_timeout_counter(&self) {
+        // Any write presets the counter.
+        self.reg.tocv.write(|w| w);
+    }
+
+    /// Current value of the timeout counter
+    pub fn timeout_counter(&self) -> u16 {
+        self.reg.tocv.read().toc().bits()
+    }
+
+    /// Runs `f` in test mode; see [`Self::enter_test_mode`].
+    pub fn with_test_mode<R>(&mut self, f: impl FnOnce(&mut TestMode<'_, Id>) -> R) -> R {
+        f(&mut self.enter_test_mode())
+    }
 }
 
 impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>> DynAux for Aux<'a, Id, D> {
@@ -223,10 +659,6 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>> DynAux for Aux<'a
         self.reg.is_operational()
     }
 
-    fn error_counters(&self) -> ErrorCounters {
-        ErrorCounters(self.reg.ecr.read())
-    }
-
     fn power_down_mode(&self) {
         self.reg.cccr.write(|w| w.csr().set_bit());
     }
@@ -235,24 +667,138 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>> DynAux for Aux<'a
         self.reg.cccr.read().csa().bit_is_set()
     }
 
-    fn protocol_status(&self) -> ProtocolStatus {
-        ProtocolStatus(self.reg.psr.read())
-    }
-
-    fn timestamp(&self) -> u16 {
-        self.reg.tscv.read().tsc().bits()
+    fn controller_state(&self, status: &ProtocolStatus) -> ControllerState {
+        ControllerState::from_bits(self.reg.cccr.read().bits(), status.bits())
     }
 }
 
 /// A CAN bus in configuration mode. Before messages can be sent and received,
 /// it needs to be [`Self::finalize`]d.
-pub struct CanConfigurable<'a, Id, D, C: Capacities>(
+pub struct CanConfigurable<'a, Id, D, C: Capacities, F = AnyFormat>(
     /// The type invariant of CCE=0 is broken while this is wrapped.
-    Can<'a, Id, D, C>,
+    Can<'a, Id, D, C, F>,
 );
 
 impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
     CanConfigurable<'a, Id, D, C>
+{
+    /// Evaluated when [`Self::new`] is instantiated, turning capacities that
+    /// exceed the message RAM of the peripheral into a compile-time error.
+    const ASSERT_FITS_MESSAGE_RAM: () = assert!(
+        SharedMemory::<C>::fits_in(Id::MESSAGE_RAM_SIZE),
+        "Capacities exceed the message RAM available to the peripheral"
+    );
+
+    /// Create new can peripheral.
+    ///
+    /// The hardware requires that SharedMemory is contained within the first
+    /// 64K of system RAM. If this condition is not fulfilled, an error is
+    /// returned.
+    ///
+    /// Capacities exceeding [`CanId::MESSAGE_RAM_SIZE`] fail to compile.
+    ///
+    /// [`CanId::MESSAGE_RAM_SIZE`]: mcan_core::CanId::MESSAGE_RAM_SIZE
+    ///
+    /// The returned peripheral is not operational; use [`Self::finalize`] to
+    /// finish configuration and start transmitting and receiving.
+    pub fn new(
+        bitrate: HertzU32,
+        dependencies: D,
+        memory: &'a mut SharedMemory<C>,
+    ) -> Result<Self, MemoryNotAddressableError> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::ASSERT_FITS_MESSAGE_RAM;
+
+        // Safety:
+        // Since `dependencies` field implies ownership of the HW register pointed to by
+        // `Id: CanId`, `can` has a unique access to it
+        let reg = unsafe { crate::reg::Can::<Id>::new() };
+
+        reg.configuration_mode();
+
+        // Contract:
+        // `mcan_core::Dependencies::eligible_message_ram_start` contract guarantees
+        // `u16::MAX + 1` alignment and points to the beginning of the allocatable CAN
+        // memory region.
+        if !memory.is_addressable(dependencies.eligible_message_ram_start()) {
+            return Err(MemoryNotAddressableError);
+        }
+
+        let memory = memory.init();
+        Self::apply_ram_config(&reg, memory);
+
+        let config = CanConfig::new(bitrate);
+
+        // Safety: Since `Can::new` takes a PAC singleton, it can only be called once.
+        // Then no duplicates will be constructed. The registers that are
+        // delegated to these components should not be touched by any other
+        // code. This has to be upheld by all code that has access to the
+        // register block.
+
+        let (interrupt_configuration, interrupts) = unsafe { InterruptConfiguration::new() };
+
+        let can = CanConfigurable(Can {
+            interrupt_configuration,
+            interrupts,
+            rx_fifo_0: unsafe { RxFifo::new(&mut memory.rx_fifo_0) },
+            rx_fifo_1: unsafe { RxFifo::new(&mut memory.rx_fifo_1) },
+            rx_dedicated_buffers: unsafe {
+                RxDedicatedBuffer::new(&mut memory.rx_dedicated_buffers)
+            },
+            tx: unsafe { Tx::new(&mut memory.tx_buffers, config.mode) },
+            tx_event_fifo: unsafe { TxEventFifo::new(&mut memory.tx_event_fifo) },
+            aux: Aux {
+                reg,
+                dependencies,
+                config,
+                // Safety: The memory is zeroed by `memory.init`, so all filters are initially
+                // disabled.
+                filters_standard: unsafe { FiltersStandard::new(&mut memory.filters_standard) },
+                filters_extended: unsafe { FiltersExtended::new(&mut memory.filters_extended) },
+            },
+            // Safety: The status registers are only read through the monitor.
+            monitor: unsafe { Monitor::new() },
+        });
+
+        Ok(can)
+    }
+
+    /// Rules out CAN FD at the type level: the mode is set to
+    /// [`Mode::Classic`], which [`Self::validate`] enforces from then on, and
+    /// [`Tx`] only accepts [`ClassicMessage`]s.
+    ///
+    /// [`ClassicMessage`]: crate::message::tx::ClassicMessage
+    pub fn classic_only(self) -> CanConfigurable<'a, Id, D, C, ClassicOnly> {
+        let Can {
+            interrupt_configuration,
+            interrupts,
+            rx_fifo_0,
+            rx_fifo_1,
+            rx_dedicated_buffers,
+            tx,
+            tx_event_fifo,
+            mut aux,
+            monitor,
+        } = self.0;
+        aux.config.mode = Mode::Classic;
+        let mut tx = tx.into_format();
+        tx.mode = Mode::Classic;
+        CanConfigurable(Can {
+            interrupt_configuration,
+            interrupts,
+            rx_fifo_0,
+            rx_fifo_1,
+            rx_dedicated_buffers,
+            tx,
+            tx_event_fifo,
+            aux,
+            monitor,
+        })
+    }
+}
+
+impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities, F: FrameFormat>
+    CanConfigurable<'a, Id, D, C, F>
 {
     /// Raw access to the registers.
     ///
@@ -265,12 +811,24 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
     }
 
     /// Allows reconfiguring the acceptance filters for standard IDs.
-    pub fn filters_standard(&mut self) -> &mut FiltersStandard<'a, Id> {
+    ///
+    /// Not available without capacity for standard ID filters, see
+    /// [`Capacities::StandardFilters`].
+    pub fn filters_standard(&mut self) -> &mut FiltersStandard<'a, Id>
+    where
+        C::StandardFilters: NonZero,
+    {
         &mut self.0.aux.filters_standard
     }
 
     /// Allows reconfiguring the acceptance filters for extended IDs.
-    pub fn filters_extended(&mut self) -> &mut FiltersExtended<'a, Id> {
+    ///
+    /// Not available without capacity for extended ID filters, see
+    /// [`Capacities::ExtendedFilters`].
+    pub fn filters_extended(&mut self) -> &mut FiltersExtended<'a, Id>
+    where
+        C::ExtendedFilters: NonZero,
+    {
         &mut self.0.aux.filters_extended
     }
 
@@ -290,30 +848,67 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         &mut self.0.aux.config
     }
 
+    /// Applies a persisted configuration, replacing the config and both
+    /// filter lists. Fails without changing anything if the filters do not
+    /// fit into the lists.
+    pub fn provision<const S: usize, const E: usize>(
+        &mut self,
+        provisioned: &Provisioned<S, E>,
+    ) -> Result<(), TooManyFilters> {
+        let aux = &mut self.0.aux;
+        aux.filters_standard.check(&provisioned.standard)?;
+        aux.filters_extended.check(&provisioned.extended)?;
+        aux.filters_standard.replace(&provisioned.standard)?;
+        aux.filters_extended.replace(&provisioned.extended)?;
+        aux.config = provisioned.config;
+        Ok(())
+    }
+
+    /// Routes frames in one call: frames matching any of `matches` are
+    /// handled by `action`, all other frames as `others`. For example,
+    /// diagnostic frames can be stored in RX FIFO 1 and everything else in RX
+    /// FIFO 0.
+    ///
+    /// Appends a filter per match and sets [`CanConfig::global_filter`] for
+    /// both standard and extended IDs. Fails with the first match not fitting
+    /// into the filter lists.
+    pub fn route(
+        &mut self,
+        matches: &[IdMatch],
+        action: Action,
+        others: NonMatchingFrames,
+    ) -> Result<(), IdMatch> {
+        let aux = &mut self.0.aux;
+        crate::filter::route(
+            &mut aux.filters_standard,
+            &mut aux.filters_extended,
+            matches,
+            action,
+        )?;
+        aux.config.global_filter = GlobalFilter {
+            standard: others,
+            extended: others,
+        };
+        Ok(())
+    }
+
     /// Apply parameters from a bus config struct
     fn apply_configuration(&mut self) -> Result<(), ConfigurationError> {
         let reg = &self.0.aux.reg;
         let config = &self.0.aux.config;
         let dependencies = &self.0.aux.dependencies;
-        if !(1..=16).contains(&config.timestamp.prescaler) {
-            return Err(ConfigurationError::InvalidTimeStampPrescaler);
+        if !VALID_TIMESTAMP_PRESCALERS.contains(&config.timestamp.prescaler) {
+            return Err(ConfigurationError::InvalidTimeStampPrescaler {
+                prescaler: config.timestamp.prescaler,
+                valid: VALID_TIMESTAMP_PRESCALERS,
+            });
         }
 
         let nominal_prescaler = config
             .nominal_timing
-            .prescaler(dependencies.can_clock(), &NOMINAL_BIT_TIMING_RANGES)?;
-
-        // Safety: The configuration is checked to be valid when computing the prescaler
-        reg.nbtp.write(|w| unsafe {
-            w.nsjw()
-                .bits(config.nominal_timing.sjw - 1)
-                .ntseg1()
-                .bits(config.nominal_timing.phase_seg_1 - 1)
-                .ntseg2()
-                .bits(config.nominal_timing.phase_seg_2 - 1)
-                .nbrp()
-                .bits(nominal_prescaler - 1)
-        });
+            .prescaler(dependencies.can_clock(), &NOMINAL_BIT_TIMING_RANGES)
+            .map_err(ConfigurationError::NominalBitTiming)?;
+        write_nominal_timing(reg, &config.nominal_timing, nominal_prescaler);
 
         // Safety: Every bit pattern of TCP is valid.
         reg.tscc.write(|w| unsafe {
@@ -333,31 +928,39 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
                 reg.cccr
                     .modify(|_, w| w.fdoe().set_bit().brse().bit(allow_bit_rate_switching));
                 let data_prescaler = data_phase_timing
-                    .prescaler(dependencies.can_clock(), &DATA_BIT_TIMING_RANGES)?;
-                // Safety: The configuration is checked to be valid when computing the prescaler
-                reg.dbtp.write(|w| unsafe {
-                    w.dsjw()
-                        .bits(data_phase_timing.sjw - 1)
-                        .dtseg1()
-                        .bits(data_phase_timing.phase_seg_1 - 1)
-                        .dtseg2()
-                        .bits(data_phase_timing.phase_seg_2 - 1)
-                        .dbrp()
-                        .bits((data_prescaler - 1) as u8)
-                });
+                    .prescaler(dependencies.can_clock(), &DATA_BIT_TIMING_RANGES)
+                    .map_err(ConfigurationError::DataBitTiming)?;
+                write_data_timing(reg, &data_phase_timing, data_prescaler);
             }
         };
         // Repopulate mode configuration in `tx`
         self.0.tx.mode = config.mode;
 
+        // Safety: Every bit pattern of TOP is valid.
+        reg.tocc.write(|w| unsafe {
+            w.etoc()
+                .bit(config.timeout.enabled)
+                .tos()
+                .variant(config.timeout.select)
+                .top()
+                .bits(config.timeout.period)
+        });
+
         // Global filter configuration
-        // This setting is redundant and the same behaviour is achievable through main
-        // filter API
         reg.gfc.write(|w| {
+            use crate::reg::gfc::{ANFESELECT_A, ANFSSELECT_A};
             w.anfs()
-                .variant(crate::reg::gfc::ANFSSELECT_A::REJECT)
+                .variant(match config.global_filter.standard {
+                    NonMatchingFrames::Reject => ANFSSELECT_A::REJECT,
+                    NonMatchingFrames::Fifo0 => ANFSSELECT_A::RXF0,
+                    NonMatchingFrames::Fifo1 => ANFSSELECT_A::RXF1,
+                })
                 .anfe()
-                .variant(crate::reg::gfc::ANFESELECT_A::REJECT)
+                .variant(match config.global_filter.extended {
+                    NonMatchingFrames::Reject => ANFESELECT_A::REJECT,
+                    NonMatchingFrames::Fifo0 => ANFESELECT_A::RXF0,
+                    NonMatchingFrames::Fifo1 => ANFESELECT_A::RXF1,
+                })
         });
 
         // Configure test/loopback mode
@@ -415,6 +1018,12 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
     /// memory RAM is largely unchecked and an improperly configured linker
     /// script could interfere with bus operations.
     fn apply_ram_config(reg: &crate::reg::Can<Id>, mem: &SharedMemoryInner<C>) {
+        // Offsets and lengths are constants, leaving only the base address to be
+        // determined at runtime. The peripheral only considers the lower 16 bits.
+        let layout = SharedMemory::<C>::LAYOUT;
+        let base = mem as *const _ as u16;
+        let address = |offset: usize| base.wrapping_add(offset as u16);
+
         // Standard id
         //
         // Safety:
@@ -422,9 +1031,9 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         // - Length is checked at compile-time on the `Capacities` constraints level
         reg.sidfc.write(|w| unsafe {
             w.flssa()
-                .bits(&mem.filters_standard as *const _ as u16)
+                .bits(address(layout.filters_standard))
                 .lss()
-                .bits(mem.filters_standard.len() as u8)
+                .bits(<C::StandardFilters as Unsigned>::U8)
         });
 
         // Extended id
@@ -434,9 +1043,9 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         // - Length is checked at compile-time on the `Capacities` constraints level
         reg.xidfc.write(|w| unsafe {
             w.flesa()
-                .bits(&mem.filters_extended as *const _ as u16)
+                .bits(address(layout.filters_extended))
                 .lse()
-                .bits(mem.filters_extended.len() as u8)
+                .bits(<C::ExtendedFilters as Unsigned>::U8)
         });
 
         // RX buffers
@@ -444,7 +1053,7 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         // Safety:
         // - Pointer is valid assuming SharedMemory location is within first 64K of RAM
         reg.rxbc
-            .write(|w| unsafe { w.rbsa().bits(&mem.rx_dedicated_buffers as *const _ as u16) });
+            .write(|w| unsafe { w.rbsa().bits(address(layout.rx_dedicated_buffers)) });
 
         // Data field size for buffers and FIFOs
         reg.rxesc.write(|w| {
@@ -463,9 +1072,9 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         // - Length is checked at compile-time on the `Capacities` constraints level
         reg.rxf0.c.write(|w| unsafe {
             w.fsa()
-                .bits(&mem.rx_fifo_0 as *const _ as u16)
+                .bits(address(layout.rx_fifo_0))
                 .fs()
-                .bits(mem.rx_fifo_0.len() as u8)
+                .bits(<C::RxFifo0 as Unsigned>::U8)
         });
 
         // RX FIFO 1
@@ -475,9 +1084,9 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         // - Length is checked at compile-time on the `Capacities` constraints level
         reg.rxf1.c.write(|w| unsafe {
             w.fsa()
-                .bits(&mem.rx_fifo_1 as *const _ as u16)
+                .bits(address(layout.rx_fifo_1))
                 .fs()
-                .bits(mem.rx_fifo_1.len() as u8)
+                .bits(<C::RxFifo1 as Unsigned>::U8)
         });
 
         // TX buffers
@@ -491,7 +1100,7 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
                 .ndtb()
                 .bits(<C::DedicatedTxBuffers as Unsigned>::U8)
                 .tbsa()
-                .bits(&mem.tx_buffers as *const _ as u16)
+                .bits(address(layout.tx_buffers))
         });
 
         // TX element size config
@@ -504,79 +1113,133 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
         // - Lengths are checked at compile-time on the `Capacities` constraints level
         reg.txefc.write(|w| unsafe {
             w.efsa()
-                .bits(&mem.tx_event_fifo as *const _ as u16)
+                .bits(address(layout.tx_event_fifo))
                 .efs()
-                .bits(mem.tx_event_fifo.len() as u8)
+                .bits(<C::TxEventFifo as Unsigned>::U8)
         });
     }
 
-    /// Create new can peripheral.
-    ///
-    /// The hardware requires that SharedMemory is contained within the first
-    /// 64K of system RAM. If this condition is not fulfilled, an error is
-    /// returned.
-    ///
-    /// The returned peripheral is not operational; use [`Self::finalize`] to
-    /// finish configuration and start transmitting and receiving.
-    pub fn new(
-        bitrate: HertzU32,
-        dependencies: D,
-        memory: &'a mut SharedMemory<C>,
-    ) -> Result<Self, MemoryNotAddressableError> {
-        // Safety:
-        // Since `dependencies` field implies ownership of the HW register pointed to by
-        // `Id: CanId`, `can` has a unique access to it
-        let reg = unsafe { crate::reg::Can::<Id>::new() };
-
-        reg.configuration_mode();
-
-        // Contract:
-        // `mcan_core::Dependencies::eligible_message_ram_start` contract guarantees
-        // `u16::MAX + 1` alignment and points to the beginning of the allocatable CAN
-        // memory region.
-        if !memory.is_addressable(dependencies.eligible_message_ram_start()) {
-            return Err(MemoryNotAddressableError);
+    /// Checks the configuration, including the interaction between settings,
+    /// and returns every problem found. The element sizes configured in the
+    /// peripheral are checked against [`Capacities`] as well. The checks are
+    /// also performed by [`Self::finalize`] and [`Self::finalize_initialized`].
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self) -> Result<(), ConfigurationErrors> {
+        let config = &self.0.aux.config;
+        let can_clock = self.0.aux.dependencies.can_clock();
+        let mut errors = ConfigurationErrors::new();
+
+        if !VALID_TIMESTAMP_PRESCALERS.contains(&config.timestamp.prescaler) {
+            errors.push(ConfigurationError::InvalidTimeStampPrescaler {
+                prescaler: config.timestamp.prescaler,
+                valid: VALID_TIMESTAMP_PRESCALERS,
+            });
+        }
+        if let Err(e) = config
+            .nominal_timing
+            .prescaler(can_clock, &NOMINAL_BIT_TIMING_RANGES)
+        {
+            errors.push(ConfigurationError::NominalBitTiming(e));
+        }
+        if let Mode::Fd {
+            data_phase_timing, ..
+        } = config.mode
+        {
+            if let Err(e) = data_phase_timing.prescaler(can_clock, &DATA_BIT_TIMING_RANGES) {
+                errors.push(ConfigurationError::DataBitTiming(e));
+            }
+            if config.timestamp.select == TimeStampSelect::INC {
+                errors.push(ConfigurationError::InternalTimestampWithFd);
+            }
         }
 
-        let memory = memory.init();
-        Self::apply_ram_config(&reg, memory);
-
-        let config = CanConfig::new(bitrate);
-
-        // Safety: Since `Can::new` takes a PAC singleton, it can only be called once.
-        // Then no duplicates will be constructed. The registers that are
-        // delegated to these components should not be touched by any other
-        // code. This has to be upheld by all code that has access to the
-        // register block.
+        // Watermarks above the maximum disable the interrupt, like 0 does
+        let unreachable = |watermark: u8, max: u8, capacity: usize| {
+            (1..=max).contains(&watermark) && usize::from(watermark) > capacity
+        };
+        let watermark = config.rx_fifo_0.watermark;
+        let capacity = C::RxFifo0::USIZE;
+        if unreachable(watermark, 64, capacity) {
+            errors.push(ConfigurationError::RxFifo0WatermarkExceedsCapacity {
+                watermark,
+                capacity,
+            });
+        }
+        let watermark = config.rx_fifo_1.watermark;
+        let capacity = C::RxFifo1::USIZE;
+        if unreachable(watermark, 64, capacity) {
+            errors.push(ConfigurationError::RxFifo1WatermarkExceedsCapacity {
+                watermark,
+                capacity,
+            });
+        }
+        let watermark = config.tx.tx_event_fifo_watermark;
+        let capacity = C::TxEventFifo::USIZE;
+        if unreachable(watermark, 32, capacity) {
+            errors.push(ConfigurationError::TxEventFifoWatermarkExceedsCapacity {
+                watermark,
+                capacity,
+            });
+        }
 
-        let (interrupt_configuration, interrupts) = unsafe { InterruptConfiguration::new() };
+        if F::CLASSIC_ONLY && matches!(config.mode, Mode::Fd { .. }) {
+            errors.push(ConfigurationError::FdOnClassicOnlyBus);
+        }
+        // Invalid bit timings are reported above
+        if let (Some(required_ppm), Ok(report)) = (
+            config.clock_tolerance_ppm,
+            clock_tolerance(config, can_clock),
+        ) {
+            if report.margin_ppm(required_ppm) < 0 {
+                errors.push(ConfigurationError::InsufficientClockTolerance {
+                    allowed_ppm: report.allowed_ppm,
+                    required_ppm,
+                    limiting: report.limiting,
+                });
+            }
+        }
 
-        let can = CanConfigurable(Can {
-            interrupt_configuration,
-            interrupts,
-            rx_fifo_0: unsafe { RxFifo::new(&mut memory.rx_fifo_0) },
-            rx_fifo_1: unsafe { RxFifo::new(&mut memory.rx_fifo_1) },
-            rx_dedicated_buffers: unsafe {
-                RxDedicatedBuffer::new(&mut memory.rx_dedicated_buffers)
-            },
-            tx: unsafe { Tx::new(&mut memory.tx_buffers, config.mode) },
-            tx_event_fifo: unsafe { TxEventFifo::new(&mut memory.tx_event_fifo) },
-            aux: Aux {
-                reg,
-                dependencies,
-                config,
-                // Safety: The memory is zeroed by `memory.init`, so all filters are initially
-                // disabled.
-                filters_standard: unsafe { FiltersStandard::new(&mut memory.filters_standard) },
-                filters_extended: unsafe { FiltersExtended::new(&mut memory.filters_extended) },
-            },
-        });
+        let rxesc = self.0.aux.reg.rxesc.read();
+        let txesc = self.0.aux.reg.txesc.read();
+        let element_sizes = [
+            (
+                MessageRamSection::RxBuffers,
+                rxesc.rbds().bits(),
+                C::RxBufferMessage::REG,
+            ),
+            (
+                MessageRamSection::RxFifo0,
+                rxesc.f0ds().bits(),
+                C::RxFifo0Message::REG,
+            ),
+            (
+                MessageRamSection::RxFifo1,
+                rxesc.f1ds().bits(),
+                C::RxFifo1Message::REG,
+            ),
+            (
+                MessageRamSection::TxBuffers,
+                txesc.tbds().bits(),
+                C::TxMessage::REG,
+            ),
+        ];
+        for (section, configured, expected) in element_sizes {
+            if configured != expected {
+                errors.push(ConfigurationError::ElementSizeMismatch {
+                    section,
+                    configured: data_field_size(configured),
+                    expected: data_field_size(expected),
+                });
+            }
+        }
 
-        Ok(can)
+        errors.into_result()
     }
 
     /// Locks the configuration and enters initialization mode.
-    pub fn finalize_initialized(mut self) -> Result<Can<'a, Id, D, C>, ConfigurationError> {
+    #[allow(clippy::result_large_err)]
+    pub fn finalize_initialized(mut self) -> Result<Can<'a, Id, D, C, F>, ConfigurationErrors> {
+        self.validate()?;
         self.apply_configuration()?;
 
         let can = self.0;
@@ -586,7 +1249,11 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
     }
 
     /// Locks the configuration and enters normal operation.
-    pub fn finalize(mut self) -> Result<Can<'a, Id, D, C>, ConfigurationError> {
+    ///
+    /// The applied configuration is summarized by [`Can::init_report`].
+    #[allow(clippy::result_large_err)]
+    pub fn finalize(mut self) -> Result<Can<'a, Id, D, C, F>, ConfigurationErrors> {
+        self.validate()?;
         self.apply_configuration()?;
 
         let can = self.0;
@@ -604,7 +1271,9 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities>
     }
 }
 
-impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities> Can<'a, Id, D, C> {
+impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities, F: FrameFormat>
+    Can<'a, Id, D, C, F>
+{
     /// Raw access to the registers.
     ///
     /// # Safety
@@ -614,10 +1283,25 @@ impl<'a, Id: mcan_core::CanId, D: mcan_core::Dependencies<Id>, C: Capacities> Ca
         &self.aux.reg
     }
 
+    /// Summarizes the configuration applied to the peripheral, e.g. to log it
+    /// after [`CanConfigurable::finalize`]
+    pub fn init_report(&self) -> InitReport {
+        InitReport::new(
+            &self.aux.config,
+            self.aux.dependencies.can_clock(),
+            SharedMemory::<C>::LAYOUT,
+            SharedMemory::<C>::SIZE,
+            (
+                self.aux.filters_standard.len(),
+                self.aux.filters_extended.len(),
+            ),
+        )
+    }
+
     /// Return to configuration mode. This resets some status registers, which
     /// effectively clears received messages, messages pending transmission and
     /// tranmit events.
-    pub fn configure(self) -> CanConfigurable<'a, Id, D, C> {
+    pub fn configure(self) -> CanConfigurable<'a, Id, D, C, F> {
         self.aux.configuration_mode();
         CanConfigurable(self)
     }
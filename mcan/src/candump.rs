@@ -0,0 +1,149 @@
+//! Export of captured frames in the candump log format
+//!
+//! The log format of `candump -l` from can-utils is understood by most CAN
+//! analysis tools, either directly or after conversion with `log2asc` and
+//! similar tools. Each frame is written as one line:
+//!
+//! ```text
+//! (1436509052.249713) can0 123#DEADBEEF
+//! (1436509052.250117) can0 1F334455#R
+//! (1436509052.251296) can0 0C0##3112233445566778899AABB
+//! ```
+//!
+//! Standard IDs are written with 3 and extended IDs with 8 hexadecimal
+//! digits. CAN FD frames use `##` followed by a flags digit (bit rate switch
+//! 1, error state indicator 2).
+//!
+//! ```
+//! use mcan::candump::CandumpWriter;
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::message::tx;
+//!
+//! let message: tx::Message<8> = tx::MessageBuilder {
+//!     id: Id::Standard(StandardId::new(0x123).unwrap()),
+//!     frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&[0xde, 0xad])),
+//!     store_tx_event: None,
+//! }
+//! .build()
+//! .unwrap();
+//!
+//! let mut log = CandumpWriter::new(String::new(), "can0");
+//! log.record(1_436_509_052_249_713, &message).unwrap();
+//! assert_eq!(log.into_inner(), "(1436509052.249713) can0 123#DEAD\n");
+//! ```
+
+use crate::message::Raw;
+use core::fmt::{self, Write};
+use embedded_can::Id;
+
+/// Writes one candump log record for `message` captured at `timestamp_us`
+/// microseconds since the epoch of the capture, e.g. the Unix epoch, on
+/// `interface`
+pub fn write_record<W: Write, M: Raw>(
+    out: &mut W,
+    timestamp_us: u64,
+    interface: &str,
+    message: &M,
+) -> fmt::Result {
+    write!(
+        out,
+        "({}.{:06}) {} ",
+        timestamp_us / 1_000_000,
+        timestamp_us % 1_000_000,
+        interface
+    )?;
+    match message.id() {
+        Id::Standard(id) => write!(out, "{:03X}", id.as_raw())?,
+        Id::Extended(id) => write!(out, "{:08X}", id.as_raw())?,
+    }
+    if message.fd_format() {
+        let flags = u8::from(message.bit_rate_switching())
+            | u8::from(message.is_transmitter_error_passive()) << 1;
+        write!(out, "##{flags:X}")?;
+    } else if message.is_remote_frame() {
+        out.write_str("#R")?;
+        if message.decoded_dlc() != 0 {
+            write!(out, "{}", message.decoded_dlc())?;
+        }
+        return out.write_char('\n');
+    } else {
+        out.write_char('#')?;
+    }
+    for byte in message.data() {
+        write!(out, "{byte:02X}")?;
+    }
+    out.write_char('\n')
+}
+
+/// Writer of candump log records for a single interface
+pub struct CandumpWriter<'i, W> {
+    out: W,
+    interface: &'i str,
+}
+
+impl<'i, W: Write> CandumpWriter<'i, W> {
+    /// Creates a writer of records for frames on `interface` into `out`
+    pub fn new(out: W, interface: &'i str) -> Self {
+        Self { out, interface }
+    }
+
+    /// Writes the record of `message` captured at `timestamp_us`, see
+    /// [`write_record`]
+    pub fn record<M: Raw>(&mut self, timestamp_us: u64, message: &M) -> fmt::Result {
+        write_record(&mut self.out, timestamp_us, self.interface, message)
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::message::tx;
+    use embedded_can::{ExtendedId, StandardId};
+    use std::string::String;
+
+    fn record<const N: usize>(message: tx::MessageBuilder) -> String {
+        let message: tx::Message<N> = message.build().unwrap();
+        let mut out = String::new();
+        write_record(&mut out, 12_000_042, "can1", &message).unwrap();
+        out
+    }
+
+    #[test]
+    fn writes_frame_types() {
+        assert_eq!(
+            record::<8>(tx::MessageBuilder {
+                id: Id::Extended(ExtendedId::new(0x1f334455).unwrap()),
+                frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Remote { desired_len: 0 }),
+                store_tx_event: None,
+            }),
+            "(12.000042) can1 1F334455#R\n"
+        );
+        assert_eq!(
+            record::<8>(tx::MessageBuilder {
+                id: Id::Standard(StandardId::new(0x7).unwrap()),
+                frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Remote { desired_len: 3 }),
+                store_tx_event: None,
+            }),
+            "(12.000042) can1 007#R3\n"
+        );
+        assert_eq!(
+            record::<12>(tx::MessageBuilder {
+                id: Id::Standard(StandardId::new(0xc0).unwrap()),
+                frame_type: tx::FrameType::FlexibleDatarate {
+                    payload: &[0x11; 12],
+                    bit_rate_switching: true,
+                    force_error_state_indicator: false,
+                },
+                store_tx_event: None,
+            }),
+            "(12.000042) can1 0C0##1111111111111111111111111\n"
+        );
+    }
+}
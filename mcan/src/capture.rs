@@ -0,0 +1,246 @@
+//! One-shot capture of received frames
+//!
+//! A [`Capture`] collects received frames into a caller buffer until it is
+//! full or, optionally, a time window has passed, and then disables its
+//! interrupts. This takes a snapshot of the bus around a trigger for
+//! diagnostics, without further load on the system once it is taken.
+//!
+//! The window is measured in ticks of the timestamp counter, see
+//! [`Timestamp::tick_duration`]. As the counter is only 16 bits wide,
+//! [`Capture::poll`] has to be called at least once per window to end it when
+//! no frames arrive.
+//!
+//! ```no_run
+//! use mcan::capture::Capture;
+//! use mcan::interrupt::{Interrupt, InterruptConfiguration, InterruptLine, OwnedInterruptSet};
+//! use mcan::message::rx;
+//! use mcan::rx_fifo::DynRxFifo;
+//!
+//! fn on_trigger<'b, Id: mcan::core::CanId>(
+//!     interrupts: &mut OwnedInterruptSet<Id, mcan::interrupt::state::Disabled>,
+//!     config: &mut InterruptConfiguration<Id>,
+//!     buffer: &'b mut [rx::Message<8>],
+//! ) -> Capture<'b, Id, rx::Message<8>> {
+//!     let new_message = interrupts.split(Interrupt::RxFifo0NewMessage.into()).unwrap();
+//!     Capture::arm(config, new_message, InterruptLine::Line1, buffer)
+//! }
+//!
+//! fn on_interrupt<Id: mcan::core::CanId>(
+//!     capture: &mut Capture<'_, Id, rx::Message<8>>,
+//!     config: &mut InterruptConfiguration<Id>,
+//!     fifo: &mut impl DynRxFifo<Message = rx::Message<8>>,
+//! ) {
+//!     if capture.handle(config, fifo) {
+//!         let frames = capture.frames();
+//!         // ...
+//! #       let _ = frames;
+//!     }
+//! }
+//! ```
+//!
+//! [`Timestamp::tick_duration`]: crate::config::Timestamp::tick_duration
+
+use crate::interrupt::{state, InterruptConfiguration, InterruptLine, OwnedInterruptSet};
+use crate::message::rx;
+use crate::rx_fifo::DynRxFifo;
+
+/// Collects received frames into a buffer, then disables its interrupts
+pub struct Capture<'b, Id, M> {
+    interrupts: OwnedInterruptSet<Id>,
+    collector: Collector<'b, M>,
+}
+
+impl<'b, Id: mcan_core::CanId, M: rx::AnyMessage> Capture<'b, Id, M> {
+    /// Starts capturing as many frames as `buffer` holds. `interrupts`, which
+    /// should announce new messages in the FIFO to capture from, are enabled
+    /// on `line`.
+    pub fn arm(
+        config: &mut InterruptConfiguration<Id>,
+        interrupts: OwnedInterruptSet<Id, state::Disabled>,
+        line: InterruptLine,
+        buffer: &'b mut [M],
+    ) -> Self {
+        Self {
+            interrupts: config.enable(interrupts, line),
+            collector: Collector::new(buffer),
+        }
+    }
+
+    /// Also ends the capture once `ticks` timestamp ticks have passed since
+    /// timestamp `now`. Frames received after the window are left in the
+    /// FIFO.
+    pub fn with_window(mut self, now: u16, ticks: u16) -> Self {
+        self.collector.window = Some((now, ticks));
+        self
+    }
+
+    /// Handles an interrupt: moves frames from `fifo` into the buffer. Returns
+    /// `true` once the capture is complete, at which point its interrupts are
+    /// disabled and frames are left in `fifo`.
+    pub fn handle<F: DynRxFifo<Message = M>>(
+        &mut self,
+        config: &mut InterruptConfiguration<Id>,
+        fifo: &mut F,
+    ) -> bool {
+        if self.collector.complete {
+            return true;
+        }
+        self.interrupts
+            .clear_interrupts(self.interrupts.interrupt_flags());
+        let complete = self.collector.collect(
+            fifo,
+            |fifo| fifo.peek().ok().map(|next| next.timestamp()),
+            |fifo| fifo.receive().ok(),
+        );
+        if complete {
+            self.finish(config);
+        }
+        complete
+    }
+
+    /// Ends the capture if its window has passed at timestamp `now`. Returns
+    /// `true` if the capture is complete.
+    pub fn poll(&mut self, config: &mut InterruptConfiguration<Id>, now: u16) -> bool {
+        if !self.collector.complete && self.collector.expired(now) {
+            self.finish(config);
+        }
+        self.collector.complete
+    }
+
+    /// Returns `true` once the buffer is full or the window has passed
+    pub fn is_complete(&self) -> bool {
+        self.collector.complete
+    }
+
+    /// Returns the frames captured so far
+    pub fn frames(&self) -> &[M] {
+        self.collector.frames()
+    }
+
+    /// Disables the interrupts and returns them, along with the captured
+    /// frames
+    pub fn release(
+        self,
+        config: &mut InterruptConfiguration<Id>,
+    ) -> (OwnedInterruptSet<Id, state::Disabled>, &'b mut [M]) {
+        (config.disable(self.interrupts), self.collector.release())
+    }
+
+    fn finish(&mut self, config: &mut InterruptConfiguration<Id>) {
+        self.collector.complete = true;
+        self.interrupts = config.disable(core::mem::take(&mut self.interrupts)).into();
+    }
+}
+
+/// Buffer and window of a [`Capture`]
+struct Collector<'b, M> {
+    buffer: &'b mut [M],
+    len: usize,
+    /// Start and length of the window in timestamp ticks
+    window: Option<(u16, u16)>,
+    complete: bool,
+}
+
+impl<'b, M> Collector<'b, M> {
+    fn new(buffer: &'b mut [M]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            window: None,
+            complete: false,
+        }
+    }
+
+    /// Moves frames from `fifo` into the buffer, using `peek` for the
+    /// timestamp of the next frame and `receive` to take it. Returns `true`
+    /// once the buffer is full or the window has passed.
+    fn collect<F>(
+        &mut self,
+        fifo: &mut F,
+        peek: impl Fn(&F) -> Option<u16>,
+        mut receive: impl FnMut(&mut F) -> Option<M>,
+    ) -> bool {
+        while self.len < self.buffer.len() {
+            // Frames received after the window are left in the FIFO.
+            let Some(timestamp) = peek(fifo) else {
+                break;
+            };
+            if self.expired(timestamp) {
+                break;
+            }
+            let Some(message) = receive(fifo) else {
+                break;
+            };
+            if let Some(slot) = self.buffer.get_mut(self.len) {
+                *slot = message;
+                self.len += 1;
+            }
+        }
+        if self.len >= self.buffer.len() {
+            self.complete = true;
+        }
+        self.complete
+    }
+
+    /// Returns `true` if the window has passed at `timestamp`, marking the
+    /// capture complete
+    fn expired(&mut self, timestamp: u16) -> bool {
+        if let Some((start, ticks)) = self.window {
+            if timestamp.wrapping_sub(start) >= ticks {
+                self.complete = true;
+            }
+        }
+        self.complete
+    }
+
+    fn frames(&self) -> &[M] {
+        self.buffer.get(..self.len).unwrap_or(&[])
+    }
+
+    fn release(self) -> &'b mut [M] {
+        let len = self.len.min(self.buffer.len());
+        let (frames, _) = self.buffer.split_at_mut(len);
+        frames
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use std::collections::VecDeque;
+
+    fn collect(collector: &mut Collector<'_, u16>, fifo: &mut VecDeque<u16>) -> bool {
+        // The frames are represented by their timestamps
+        collector.collect(fifo, |fifo| fifo.front().copied(), VecDeque::pop_front)
+    }
+
+    #[test]
+    fn frames_after_the_window_stay_in_the_fifo() {
+        let mut buffer = [0; 8];
+        let mut collector = Collector::new(&mut buffer);
+        // The window wraps around with the timestamp counter
+        collector.window = Some((0xfff0, 0x20));
+        let mut fifo = VecDeque::from([0xfff0, 0xffff, 0x0005]);
+        assert!(!collect(&mut collector, &mut fifo));
+        assert!(fifo.is_empty());
+        fifo.extend([0x000f, 0x0010, 0x0011]);
+        assert!(collect(&mut collector, &mut fifo));
+        assert_eq!(collector.frames(), [0xfff0, 0xffff, 0x0005, 0x000f]);
+        assert_eq!(fifo, [0x0010, 0x0011]);
+        // Nothing is taken once complete
+        assert!(collect(&mut collector, &mut fifo));
+        assert_eq!(fifo.len(), 2);
+    }
+
+    #[test]
+    fn capture_completes_when_the_buffer_is_full() {
+        let mut buffer = [0; 2];
+        let mut collector = Collector::new(&mut buffer);
+        let mut fifo = VecDeque::from([1, 2, 3]);
+        assert!(collect(&mut collector, &mut fifo));
+        assert_eq!(fifo, [3]);
+        assert_eq!(collector.release(), [1, 2]);
+    }
+}
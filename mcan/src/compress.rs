@@ -0,0 +1,467 @@
+//! Compressed frame logs for on-device storage
+//!
+//! Black-box recorders store as many frames as possible in little flash.
+//! [`Encoder`] packs consecutive frames into a byte buffer, exploiting the
+//! redundancy of typical CAN traffic:
+//!
+//! - timestamps are stored as the difference to the previous frame
+//! - IDs are stored as the difference to the previous frame, or not at all if
+//!   they repeat
+//! - payloads equal to that of the previous frame are not stored again
+//!
+//! Classic data frames take a header byte, the variable length timestamp and
+//! ID differences, and their payload. Each buffer starts from scratch, so that
+//! blocks of storage can be decoded independently.
+//!
+//! With the `std` feature, `Decoder` restores the frames of a buffer, e.g. on
+//! the host side after reading out the storage.
+//!
+//! # Format
+//!
+//! Every frame starts with a header byte:
+//!
+//! | Bits | Content                                                |
+//! |------|--------------------------------------------------------|
+//! | 0..4 | DLC                                                    |
+//! | 4    | extended ID                                            |
+//! | 5    | ID equal to the previous frame's, no ID difference stored |
+//! | 6    | payload equal to the previous frame's, no payload stored |
+//! | 7    | flags byte follows: FD format (bit 0), bit rate switching (bit 1), error state indicator (bit 2), remote frame (bit 3) |
+//!
+//! It is followed by the flags byte, the timestamp difference as unsigned
+//! LEB128, the ID difference as zigzag encoded signed LEB128, and the
+//! payload. The first frame of a buffer is stored relative to timestamp and ID
+//! zero.
+//!
+//! ```
+//! use mcan::compress::Encoder;
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::message::tx;
+//!
+//! let message: tx::Message<8> = tx::MessageBuilder {
+//!     id: Id::Standard(StandardId::new(0x123).unwrap()),
+//!     frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&[1, 2, 3, 4])),
+//!     store_tx_event: None,
+//! }
+//! .build()
+//! .unwrap();
+//!
+//! let mut storage = [0; 64];
+//! let mut encoder = Encoder::new(&mut storage);
+//! encoder.encode(1000, &message).unwrap();
+//! encoder.encode(2000, &message).unwrap();
+//! // The repeated frame only takes the header and the timestamp difference
+//! assert_eq!(encoder.bytes().len(), 9 + 3);
+//! ```
+
+use crate::message::Raw;
+use embedded_can::Id;
+
+const EXTENDED: u8 = 1 << 4;
+const SAME_ID: u8 = 1 << 5;
+const SAME_PAYLOAD: u8 = 1 << 6;
+const HAS_FLAGS: u8 = 1 << 7;
+
+const FD_FORMAT: u8 = 1 << 0;
+const BIT_RATE_SWITCHING: u8 = 1 << 1;
+const ERROR_PASSIVE: u8 = 1 << 2;
+const REMOTE: u8 = 1 << 3;
+
+/// The buffer of an [`Encoder`] has no space for the frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BufferFull;
+
+fn bit(value: bool, mask: u8) -> u8 {
+    if value {
+        mask
+    } else {
+        0
+    }
+}
+
+fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw().into(),
+        Id::Extended(id) => id.as_raw(),
+    }
+}
+
+/// Frame state the next frame is encoded relative to
+#[derive(Copy, Clone)]
+struct Previous {
+    timestamp: u64,
+    id: u32,
+    payload: [u8; 64],
+    len: usize,
+}
+
+impl Previous {
+    const INITIAL: Self = Self {
+        timestamp: 0,
+        id: 0,
+        payload: [0; 64],
+        len: 0,
+    };
+
+    fn payload(&self) -> &[u8] {
+        self.payload.get(..self.len).unwrap_or(&[])
+    }
+}
+
+/// Compresses frames into a byte buffer
+pub struct Encoder<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+    previous: Previous,
+}
+
+impl<'b> Encoder<'b> {
+    /// Encodes frames into `buffer`
+    pub fn new(buffer: &'b mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            previous: Previous::INITIAL,
+        }
+    }
+
+    /// Appends `message`, captured at `timestamp` in ticks of any monotonic
+    /// clock. Fails without changing the buffer if it has no space left.
+    /// Returns the number of bytes used.
+    pub fn encode<M: Raw>(&mut self, timestamp: u64, message: &M) -> Result<usize, BufferFull> {
+        let id = raw_id(message.id());
+        let remote = message.is_remote_frame();
+        let len = if remote {
+            0
+        } else {
+            message.dlc().len(message.fd_format())
+        };
+        // Pad payloads truncated by the size of the message type
+        let mut payload = [0; 64];
+        for (slot, byte) in payload.iter_mut().zip(message.data()) {
+            *slot = *byte;
+        }
+        let payload = payload.get(..len).unwrap_or(&[]);
+
+        let flags = bit(message.fd_format(), FD_FORMAT)
+            | bit(message.bit_rate_switching(), BIT_RATE_SWITCHING)
+            | bit(message.is_transmitter_error_passive(), ERROR_PASSIVE)
+            | bit(remote, REMOTE);
+        let same_id = id == self.previous.id;
+        let same_payload = !payload.is_empty() && payload == self.previous.payload();
+        let header = message.dlc().code()
+            | bit(message.is_extended(), EXTENDED)
+            | bit(same_id, SAME_ID)
+            | bit(same_payload, SAME_PAYLOAD)
+            | bit(flags != 0, HAS_FLAGS);
+
+        let mut writer = Writer {
+            buffer: self.buffer.get_mut(self.len..).unwrap_or(&mut []),
+            len: 0,
+        };
+        writer.byte(header)?;
+        if flags != 0 {
+            writer.byte(flags)?;
+        }
+        writer.varint(timestamp.wrapping_sub(self.previous.timestamp))?;
+        if !same_id {
+            let delta = i64::from(id) - i64::from(self.previous.id);
+            writer.varint(((delta << 1) ^ (delta >> 63)) as u64)?;
+        }
+        if !same_payload {
+            writer.bytes(payload)?;
+        }
+        let written = writer.len;
+
+        self.len += written;
+        self.previous.timestamp = timestamp;
+        self.previous.id = id;
+        if !same_payload {
+            self.previous.payload = [0; 64];
+            for (slot, byte) in self.previous.payload.iter_mut().zip(payload) {
+                *slot = *byte;
+            }
+            self.previous.len = payload.len();
+        }
+        Ok(written)
+    }
+
+    /// Returns the encoded frames
+    pub fn bytes(&self) -> &[u8] {
+        self.buffer.get(..self.len).unwrap_or(&[])
+    }
+
+    /// Empties the buffer, e.g. after it was stored. Frames encoded
+    /// afterwards are decoded independently of earlier ones.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.previous = Previous::INITIAL;
+    }
+
+    /// Returns the buffer and the number of bytes used
+    pub fn release(self) -> (&'b mut [u8], usize) {
+        (self.buffer, self.len)
+    }
+}
+
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn byte(&mut self, byte: u8) -> Result<(), BufferFull> {
+        let slot = self.buffer.get_mut(self.len).ok_or(BufferFull)?;
+        *slot = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), BufferFull> {
+        bytes.iter().try_for_each(|&byte| self.byte(byte))
+    }
+
+    fn varint(&mut self, mut value: u64) -> Result<(), BufferFull> {
+        loop {
+            let low = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return self.byte(low);
+            }
+            self.byte(low | 0x80)?;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use decoder::{DecodeError, DecodedFrame, Decoder};
+
+#[cfg(feature = "std")]
+mod decoder {
+    use super::*;
+    use crate::message::Dlc;
+    use embedded_can::{ExtendedId, StandardId};
+    use std::vec::Vec;
+
+    /// Frame restored by a [`Decoder`]
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct DecodedFrame {
+        /// Timestamp the frame was encoded with
+        pub timestamp: u64,
+        /// CAN identifier
+        pub id: Id,
+        /// Data length code
+        pub dlc: Dlc,
+        /// CAN FD format
+        pub fd_format: bool,
+        /// Bit rate switching
+        pub bit_rate_switching: bool,
+        /// Error state indicator of the transmitter
+        pub error_passive: bool,
+        /// Remote frame
+        pub remote: bool,
+        /// Payload, padded to the length given by the DLC
+        pub data: Vec<u8>,
+    }
+
+    /// Encoded frames are corrupt or truncated
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum DecodeError {
+        /// The buffer ends within a frame
+        Truncated,
+        /// An ID is out of range of its ID space
+        InvalidId,
+        /// A variable length integer is too long
+        Overlong,
+    }
+
+    /// Restores the frames compressed by an [`Encoder`]
+    pub struct Decoder<'b> {
+        bytes: &'b [u8],
+        previous_timestamp: u64,
+        previous_id: u32,
+        previous_payload: Vec<u8>,
+    }
+
+    impl<'b> Decoder<'b> {
+        /// Decodes the frames in `bytes`, as returned by [`Encoder::bytes`]
+        pub fn new(bytes: &'b [u8]) -> Self {
+            Self {
+                bytes,
+                previous_timestamp: 0,
+                previous_id: 0,
+                previous_payload: Vec::new(),
+            }
+        }
+
+        fn byte(&mut self) -> Result<u8, DecodeError> {
+            let (&byte, rest) = self.bytes.split_first().ok_or(DecodeError::Truncated)?;
+            self.bytes = rest;
+            Ok(byte)
+        }
+
+        fn varint(&mut self) -> Result<u64, DecodeError> {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.byte()?;
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(DecodeError::Overlong)
+        }
+
+        fn frame(&mut self) -> Result<DecodedFrame, DecodeError> {
+            let header = self.byte()?;
+            let flags = if header & HAS_FLAGS != 0 {
+                self.byte()?
+            } else {
+                0
+            };
+            let timestamp = self.previous_timestamp.wrapping_add(self.varint()?);
+            let raw = if header & SAME_ID != 0 {
+                self.previous_id
+            } else {
+                let zigzag = self.varint()?;
+                let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+                i64::from(self.previous_id)
+                    .checked_add(delta)
+                    .and_then(|id| u32::try_from(id).ok())
+                    .ok_or(DecodeError::InvalidId)?
+            };
+            let id = if header & EXTENDED != 0 {
+                ExtendedId::new(raw).map(Id::Extended)
+            } else {
+                u16::try_from(raw)
+                    .ok()
+                    .and_then(StandardId::new)
+                    .map(Id::Standard)
+            }
+            .ok_or(DecodeError::InvalidId)?;
+            let dlc = Dlc::from_bits(header);
+            let fd_format = flags & FD_FORMAT != 0;
+            let remote = flags & REMOTE != 0;
+            let data = if remote {
+                Vec::new()
+            } else if header & SAME_PAYLOAD != 0 {
+                self.previous_payload.clone()
+            } else {
+                let len = dlc.len(fd_format);
+                if self.bytes.len() < len {
+                    return Err(DecodeError::Truncated);
+                }
+                let (data, rest) = self.bytes.split_at(len);
+                self.bytes = rest;
+                self.previous_payload = data.to_vec();
+                data.to_vec()
+            };
+            self.previous_timestamp = timestamp;
+            self.previous_id = raw;
+            Ok(DecodedFrame {
+                timestamp,
+                id,
+                dlc,
+                fd_format,
+                bit_rate_switching: flags & BIT_RATE_SWITCHING != 0,
+                error_passive: flags & ERROR_PASSIVE != 0,
+                remote,
+                data,
+            })
+        }
+    }
+
+    impl Iterator for Decoder<'_> {
+        type Item = Result<DecodedFrame, DecodeError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.bytes.is_empty() {
+                return None;
+            }
+            let frame = self.frame();
+            if frame.is_err() {
+                // Nothing after corrupt data can be decoded
+                self.bytes = &[];
+            }
+            Some(frame)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::message::tx;
+    use embedded_can::{ExtendedId, StandardId};
+
+    fn message(id: Id, data: &[u8]) -> tx::Message<64> {
+        tx::MessageBuilder {
+            id,
+            frame_type: if data.len() > 8 {
+                tx::FrameType::FlexibleDatarate {
+                    payload: data,
+                    bit_rate_switching: true,
+                    force_error_state_indicator: false,
+                }
+            } else {
+                tx::FrameType::Classic(tx::ClassicFrameType::Data(data))
+            },
+            store_tx_event: None,
+        }
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn frames_survive_a_round_trip() {
+        let standard = Id::Standard(StandardId::new(0x7ff).unwrap());
+        let extended = Id::Extended(ExtendedId::new(0x1234_5678).unwrap());
+        let frames = [
+            (10, message(standard, &[1, 2, 3])),
+            (25, message(standard, &[1, 2, 3])),
+            (u64::MAX, message(extended, &[0x55; 20])),
+            (3, message(standard, &[])),
+        ];
+        let mut buffer = [0; 256];
+        let mut encoder = Encoder::new(&mut buffer);
+        for (timestamp, frame) in &frames {
+            encoder.encode(*timestamp, frame).unwrap();
+        }
+        let decoded: std::vec::Vec<_> = Decoder::new(encoder.bytes()).map(Result::unwrap).collect();
+        assert_eq!(decoded.len(), frames.len());
+        for (decoded, (timestamp, frame)) in decoded.iter().zip(&frames) {
+            assert_eq!(decoded.timestamp, *timestamp);
+            assert_eq!(decoded.id, frame.id());
+            assert_eq!(decoded.fd_format, frame.fd_format());
+            assert_eq!(decoded.data, frame.data());
+        }
+    }
+
+    #[test]
+    fn full_buffer_is_left_unchanged() {
+        let id = Id::Standard(StandardId::new(0x100).unwrap());
+        let mut buffer = [0; 8];
+        let mut encoder = Encoder::new(&mut buffer);
+        assert_eq!(encoder.encode(1, &message(id, &[1, 2])), Ok(6));
+        assert_eq!(encoder.encode(2, &message(id, &[3, 4, 5])), Err(BufferFull));
+        assert_eq!(encoder.bytes().len(), 6);
+        assert_eq!(Decoder::new(encoder.bytes()).count(), 1);
+    }
+
+    #[test]
+    fn corrupt_id_difference_is_rejected() {
+        // Standard ID 1
+        let mut bytes = std::vec![0x00, 0x00, 0x02];
+        // Followed by an ID difference of `i64::MAX`
+        bytes.extend([0x00, 0x00, 0xfe]);
+        bytes.extend([0xff; 8]);
+        bytes.push(0x01);
+        let mut decoder = Decoder::new(&bytes);
+        assert_eq!(
+            decoder.next().unwrap().unwrap().id,
+            Id::Standard(StandardId::new(1).unwrap())
+        );
+        assert_eq!(decoder.next(), Some(Err(DecodeError::InvalidId)));
+        assert_eq!(decoder.next(), None);
+    }
+}
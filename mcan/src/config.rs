@@ -1,8 +1,12 @@
 //! CAN bus configuration
 
-pub use crate::reg::{self, tscc::TSSSELECT_A as TimeStampSelect};
+use crate::message::tx;
+pub use crate::reg::{
+    self, tocc::TOSSELECT_A as TimeoutSelect, tscc::TSSSELECT_A as TimeStampSelect,
+};
+use core::fmt;
 use core::ops::RangeInclusive;
-use fugit::HertzU32;
+use fugit::{HertzU32, NanosDurationU32};
 
 /// Configuration for the CAN bus
 #[derive(Copy, Clone)]
@@ -16,12 +20,44 @@ pub struct CanConfig {
     pub nominal_timing: BitTiming,
     /// Timestamp configuration
     pub timestamp: Timestamp,
+    /// Timeout counter configuration
+    pub timeout: TimeoutCounter,
     /// RX Fifo 0
     pub rx_fifo_0: RxFifoConfig,
     /// RX Fifo 1
     pub rx_fifo_1: RxFifoConfig,
     /// Tx configuration
     pub tx: TxConfig,
+    /// Handling of frames matching none of the filters
+    pub global_filter: GlobalFilter,
+    /// Worst case deviation of the oscillator from its nominal frequency in
+    /// parts per million, including temperature drift and aging. If set,
+    /// configurations whose bit timing does not tolerate it are rejected, see
+    /// [`crate::tolerance`].
+    pub clock_tolerance_ppm: Option<u32>,
+}
+
+/// Handling of received frames that match none of the filters
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NonMatchingFrames {
+    /// Discard the frames
+    #[default]
+    Reject,
+    /// Store the frames in RX FIFO 0
+    Fifo0,
+    /// Store the frames in RX FIFO 1
+    Fifo1,
+}
+
+/// Global filter configuration
+///
+/// By default, frames matching none of the filters are rejected.
+#[derive(Default, Copy, Clone)]
+pub struct GlobalFilter {
+    /// Handling of frames with standard IDs matching no filter
+    pub standard: NonMatchingFrames,
+    /// Handling of frames with extended IDs matching no filter
+    pub extended: NonMatchingFrames,
 }
 
 /// Denotes a TX related configuration
@@ -96,6 +132,14 @@ pub struct Timestamp {
     pub prescaler: u8,
 }
 
+impl Timestamp {
+    /// Returns the duration of one tick of the internal timestamp counter when
+    /// running at `bit_timing`
+    pub fn tick_duration(&self, bit_timing: &BitTiming) -> NanosDurationU32 {
+        bit_timing.bit_time() * u32::from(self.prescaler)
+    }
+}
+
 impl Default for Timestamp {
     fn default() -> Self {
         Self {
@@ -105,6 +149,36 @@ impl Default for Timestamp {
     }
 }
 
+/// Timeout counter configuration
+///
+/// The timeout counter counts down from `period` in ticks of the timestamp
+/// counter prescaler, see [`Timestamp::tick_duration`].
+/// [`Interrupt::TimeoutOccured`] is raised when it reaches zero.
+///
+/// [`Interrupt::TimeoutOccured`]: crate::interrupt::Interrupt::TimeoutOccured
+#[derive(Copy, Clone)]
+pub struct TimeoutCounter {
+    /// Enables the counter
+    pub enabled: bool,
+    /// Selects what restarts the counter. In [`TimeoutSelect::CONT`] mode, it
+    /// is restarted by [`Aux::restart_timeout_counter`].
+    ///
+    /// [`Aux::restart_timeout_counter`]: crate::bus::Aux::restart_timeout_counter
+    pub select: TimeoutSelect,
+    /// Start value of the counter
+    pub period: u16,
+}
+
+impl Default for TimeoutCounter {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            select: TimeoutSelect::CONT,
+            period: u16::MAX,
+        }
+    }
+}
+
 /// Misconfigurations of [`BitTiming`].
 #[derive(Debug)]
 pub enum BitTimingError {
@@ -121,7 +195,8 @@ pub enum BitTimingError {
     /// No valid prescaler could be found
     ///
     /// The following requirement must be met:
-    /// - `can_clock` must be divisible by `bitrate * bit_time_quanta`
+    /// - `can_clock` must be divisible by `bitrate * bit_time_quanta`, which
+    ///   must not be zero or exceed `u32::MAX` Hz
     NoValidPrescaler {
         /// Provided peripheral clock
         can_clock: HertzU32,
@@ -132,6 +207,38 @@ pub enum BitTimingError {
     },
 }
 
+impl fmt::Display for BitTimingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (field, valid) = match self {
+            Self::SynchronizationJumpWidthOutOfRange(valid) => ("sjw", valid),
+            Self::PhaseSeg1OutOfRange(valid) => ("phase_seg_1", valid),
+            Self::PhaseSeg2OutOfRange(valid) => ("phase_seg_2", valid),
+            Self::BitTimeOutOfRange(valid) => ("time quanta per bit", valid),
+            Self::PrescalerOutOfRange(valid) => ("prescaler", valid),
+            Self::NoValidPrescaler {
+                can_clock,
+                bitrate,
+                bit_time_quanta,
+            } => {
+                return write!(
+                    f,
+                    "no prescaler divides {} Hz into {} Hz with {} time quanta per bit",
+                    can_clock.to_Hz(),
+                    bitrate.to_Hz(),
+                    bit_time_quanta
+                )
+            }
+        };
+        write!(
+            f,
+            "{} outside of {}..={}",
+            field,
+            valid.start(),
+            valid.end()
+        )
+    }
+}
+
 /// Valid values of a BitTiming struct
 #[derive(Clone)]
 pub(crate) struct BitTimingRanges {
@@ -164,6 +271,46 @@ impl BitTiming {
         1 + u32::from(self.phase_seg_1) + u32::from(self.phase_seg_2)
     }
 
+    /// Returns the duration of one bit, rounded down to whole nanoseconds. Zero
+    /// if `bitrate` is zero.
+    pub fn bit_time(&self) -> NanosDurationU32 {
+        NanosDurationU32::from_ticks(
+            1_000_000_000_u32
+                .checked_div(self.bitrate.to_Hz())
+                .unwrap_or(0),
+        )
+    }
+
+    /// Returns the position of the sample point, in per mille of the bit time
+    /// counted from the start of the bit
+    pub fn sample_point_permille(&self) -> u16 {
+        ((1 + u32::from(self.phase_seg_1)) * 1000 / self.time_quanta_per_bit()) as u16
+    }
+
+    /// Returns the time from the start of a bit to its sample point, rounded
+    /// down to whole nanoseconds
+    pub fn sample_point(&self) -> NanosDurationU32 {
+        let bit_time = u64::from(self.bit_time().ticks());
+        let sample_point =
+            bit_time * (1 + u64::from(self.phase_seg_1)) / u64::from(self.time_quanta_per_bit());
+        NanosDurationU32::from_ticks(sample_point as u32)
+    }
+
+    /// Returns the number of whole bit times that fit in `duration`,
+    /// saturating at `u32::MAX`
+    ///
+    /// Useful for peripheral settings counted in bit times, like
+    /// [`Timestamp::prescaler`].
+    pub fn bit_times_in<const NOM: u32, const DENOM: u32>(
+        &self,
+        duration: fugit::Duration<u32, NOM, DENOM>,
+    ) -> u32 {
+        let bit_times =
+            u64::from(duration.ticks()) * u64::from(NOM) * u64::from(self.bitrate.to_Hz())
+                / u64::from(DENOM);
+        bit_times.try_into().unwrap_or(u32::MAX)
+    }
+
     fn check(&self, valid: &BitTimingRanges) -> Result<(), BitTimingError> {
         if !valid.sjw.contains(&self.sjw.into()) {
             Err(BitTimingError::SynchronizationJumpWidthOutOfRange(
@@ -197,9 +344,12 @@ impl BitTiming {
         self.check(valid)?;
         let f_out = self.bitrate;
         let bit_time_quanta = self.time_quanta_per_bit();
-        let f_q = f_out * bit_time_quanta;
-        if let Some(0) = f_can.to_Hz().checked_rem(f_q.to_Hz()) {
-            let prescaler = f_can / f_q;
+        let f_q = f_out.to_Hz().checked_mul(bit_time_quanta);
+        let prescaler = f_q.and_then(|f_q| match f_can.to_Hz().checked_rem(f_q) {
+            Some(0) => f_can.to_Hz().checked_div(f_q),
+            _ => None,
+        });
+        if let Some(prescaler) = prescaler {
             if !valid.prescaler.contains(&prescaler) {
                 Err(BitTimingError::PrescalerOutOfRange(valid.prescaler.clone()))
             } else {
@@ -215,6 +365,66 @@ impl BitTiming {
     }
 }
 
+/// Frame formats a bus is restricted to at the type level
+///
+/// [`AnyFormat`] leaves the choice to [`CanConfig::mode`] and transmit
+/// attempts of CAN FD frames on a classic bus fail at runtime.
+/// [`ClassicOnly`], selected with [`CanConfigurable::classic_only`], rules out
+/// CAN FD entirely: [`Tx`] then only accepts [`ClassicMessage`]s, so CAN FD
+/// frames cannot be transmitted, and 8 byte elements suffice in
+/// [`Capacities`].
+///
+/// [`CanConfigurable::classic_only`]: crate::bus::CanConfigurable::classic_only
+/// [`Tx`]: crate::tx_buffers::Tx
+/// [`ClassicMessage`]: crate::message::tx::ClassicMessage
+/// [`Capacities`]: crate::messageram::Capacities
+pub trait FrameFormat: private::Sealed {
+    /// Message type accepted by [`Tx`] for elements of type `M`
+    ///
+    /// [`Tx`]: crate::tx_buffers::Tx
+    type Message<M: tx::AnyMessage>;
+
+    /// `true` if CAN FD is ruled out
+    const CLASSIC_ONLY: bool;
+
+    #[doc(hidden)]
+    fn into_message<M: tx::AnyMessage>(message: Self::Message<M>) -> M;
+}
+
+/// Frame format chosen at runtime by [`CanConfig::mode`]
+#[derive(Copy, Clone, Debug)]
+pub struct AnyFormat;
+
+/// Only classic CAN frames, see [`FrameFormat`]
+#[derive(Copy, Clone, Debug)]
+pub struct ClassicOnly;
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for super::AnyFormat {}
+    impl Sealed for super::ClassicOnly {}
+}
+
+impl FrameFormat for AnyFormat {
+    type Message<M: tx::AnyMessage> = M;
+
+    const CLASSIC_ONLY: bool = false;
+
+    fn into_message<M: tx::AnyMessage>(message: M) -> M {
+        message
+    }
+}
+
+impl FrameFormat for ClassicOnly {
+    type Message<M: tx::AnyMessage> = tx::ClassicMessage<M>;
+
+    const CLASSIC_ONLY: bool = true;
+
+    fn into_message<M: tx::AnyMessage>(message: tx::ClassicMessage<M>) -> M {
+        message.into_message()
+    }
+}
+
 /// Enable/disable CAN-FD and related features
 #[derive(Default, Copy, Clone)]
 pub enum Mode {
@@ -249,9 +459,12 @@ impl CanConfig {
             loopback: Default::default(),
             nominal_timing: BitTiming::new(bitrate),
             timestamp: Default::default(),
+            timeout: Default::default(),
             rx_fifo_0: Default::default(),
             rx_fifo_1: Default::default(),
             tx: Default::default(),
+            global_filter: Default::default(),
+            clock_tolerance_ppm: None,
         }
     }
 }
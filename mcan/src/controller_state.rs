@@ -0,0 +1,163 @@
+//! Operating state of the controller
+//!
+//! The state of the peripheral is spread over several bits of the CC control
+//! register (CCCR) and the protocol status register (PSR).
+//! [`ControllerState`] combines them into the state the controller is in, as
+//! read by [`DynAux::controller_state`]. [`StateTracker`] turns a sequence of
+//! observed states into transitions, e.g. for logging or for driving
+//! application state machines.
+//!
+//! ```no_run
+//! use mcan::bus::DynAux;
+//! use mcan::controller_state::{ControllerState, StateTracker};
+//! use mcan::monitor::DynMonitor;
+//!
+//! fn poll(aux: &impl DynAux, monitor: &impl DynMonitor, tracker: &mut StateTracker) {
+//!     let status = monitor.protocol_status();
+//!     if let Some(transition) = tracker.poll(aux, &status) {
+//!         if transition.to == ControllerState::BusOffRecovery {
+//!             // Stop queueing frames until the node is back
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`DynAux::controller_state`]: crate::bus::DynAux::controller_state
+
+use crate::bus::{DynAux, ProtocolStatus};
+
+const CCCR_INIT: u32 = 1 << 0;
+const CCCR_ASM: u32 = 1 << 2;
+const CCCR_CSA: u32 = 1 << 3;
+const CCCR_MON: u32 = 1 << 5;
+const PSR_BO: u32 = 1 << 7;
+
+/// Operating state of the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ControllerState {
+    /// Software initialization (CCCR.INIT set). Entered by software, and by
+    /// the peripheral when it goes bus off.
+    Init,
+    /// Normal operation, transmitting and receiving frames
+    Normal,
+    /// Restricted operation (CCCR.ASM set). Frames are received and
+    /// acknowledged, but the node does not transmit nor send error frames.
+    Restricted,
+    /// Bus monitoring (CCCR.MON set). Frames are received, but the node
+    /// neither transmits nor acknowledges frames.
+    BusMonitoring,
+    /// Power down (CCCR.CSA set). The clocks of the controller may be
+    /// stopped.
+    Sleep,
+    /// Bus off (PSR.BO set) after software left initialization. The node
+    /// waits for 129 occurrences of 11 recessive bits before returning to
+    /// normal operation.
+    BusOffRecovery,
+}
+
+impl ControllerState {
+    /// Decodes the state from the values of CCCR and PSR
+    pub(crate) fn from_bits(cccr: u32, psr: u32) -> Self {
+        if cccr & CCCR_CSA != 0 {
+            Self::Sleep
+        } else if cccr & CCCR_INIT != 0 {
+            Self::Init
+        } else if psr & PSR_BO != 0 {
+            Self::BusOffRecovery
+        } else if cccr & CCCR_MON != 0 {
+            Self::BusMonitoring
+        } else if cccr & CCCR_ASM != 0 {
+            Self::Restricted
+        } else {
+            Self::Normal
+        }
+    }
+
+    /// Returns `true` if the node takes part in the traffic on the bus by
+    /// transmitting frames
+    pub fn transmits(&self) -> bool {
+        matches!(self, Self::Normal)
+    }
+
+    /// Returns `true` if the node receives frames
+    pub fn receives(&self) -> bool {
+        matches!(self, Self::Normal | Self::Restricted | Self::BusMonitoring)
+    }
+}
+
+/// Change of the [`ControllerState`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StateTransition {
+    /// State before the change
+    pub from: ControllerState,
+    /// State after the change
+    pub to: ControllerState,
+}
+
+/// Reports transitions between observed [`ControllerState`]s
+///
+/// States are sampled when polled, so states lasting shorter than the
+/// polling period may be missed.
+#[derive(Copy, Clone, Debug)]
+pub struct StateTracker {
+    state: ControllerState,
+}
+
+impl StateTracker {
+    /// Starts tracking from `state`
+    pub fn new(state: ControllerState) -> Self {
+        Self { state }
+    }
+
+    /// Returns the state observed last
+    pub fn state(&self) -> ControllerState {
+        self.state
+    }
+
+    /// Records the observed `state`. Returns the transition if it differs
+    /// from the previous one.
+    pub fn update(&mut self, state: ControllerState) -> Option<StateTransition> {
+        let from = core::mem::replace(&mut self.state, state);
+        (from != state).then_some(StateTransition { from, to: state })
+    }
+
+    /// Reads the state of the controller given the protocol `status` and
+    /// records it, see [`Self::update`] and [`DynAux::controller_state`]
+    pub fn poll(&mut self, aux: &impl DynAux, status: &ProtocolStatus) -> Option<StateTransition> {
+        self.update(aux.controller_state(status))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn state_is_decoded_by_precedence() {
+        use ControllerState::*;
+        assert_eq!(ControllerState::from_bits(0, 0), Normal);
+        assert_eq!(ControllerState::from_bits(CCCR_ASM, 0), Restricted);
+        assert_eq!(
+            ControllerState::from_bits(CCCR_MON | CCCR_ASM, 0),
+            BusMonitoring
+        );
+        assert_eq!(ControllerState::from_bits(CCCR_ASM, PSR_BO), BusOffRecovery);
+        assert_eq!(ControllerState::from_bits(CCCR_INIT, PSR_BO), Init);
+        assert_eq!(ControllerState::from_bits(CCCR_INIT | CCCR_CSA, 0), Sleep);
+        assert!(Restricted.receives() && !Restricted.transmits());
+    }
+
+    #[test]
+    fn tracker_reports_changes_only() {
+        let mut tracker = StateTracker::new(ControllerState::Init);
+        assert_eq!(tracker.update(ControllerState::Init), None);
+        assert_eq!(
+            tracker.update(ControllerState::Normal),
+            Some(StateTransition {
+                from: ControllerState::Init,
+                to: ControllerState::Normal,
+            })
+        );
+        assert_eq!(tracker.state(), ControllerState::Normal);
+    }
+}
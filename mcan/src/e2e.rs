@@ -0,0 +1,280 @@
+//! End-to-end protection of payloads
+//!
+//! Safety related messages commonly carry a rolling counter and a checksum,
+//! so that receivers detect lost, repeated and corrupted messages beyond what
+//! the CRC of the bus covers, e.g. faults in gateways or in the software of
+//! the sender. This module implements the pattern of the AUTOSAR E2E profile
+//! 1:
+//!
+//! - a 4-bit counter in the low nibble of one byte, counting from 0 to 14
+//! - a CRC-8 (SAE J1850) in another byte, computed over a 16-bit data ID
+//!   identifying the message and the remaining payload
+//!
+//! [`E2eProtector`] inserts both on the TX path and [`E2eChecker`] validates
+//! them on the RX path, each keeping the counters of the IDs it is
+//! configured for.
+//!
+//! ```no_run
+//! use mcan::e2e::{E2eConfig, E2eProtector};
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::message::tx;
+//!
+//! let id = Id::Standard(StandardId::new(0x120).unwrap());
+//! let mut protector = E2eProtector::new([(id, E2eConfig::new(0x0120))]).unwrap();
+//!
+//! let mut payload = [0, 0, 0x12, 0x34, 0, 0, 0, 0];
+//! protector.protect(id, &mut payload).unwrap();
+//! let message = tx::MessageBuilder {
+//!     id,
+//!     frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&payload)),
+//!     store_tx_event: None,
+//! }
+//! .build::<8>();
+//! ```
+
+use crate::id_map::IdMap;
+use crate::message::Raw;
+use embedded_can::Id;
+
+/// Number of values of the counter
+const COUNTER_MODULO: u8 = 15;
+
+/// Placement and identification of the protection of one message
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct E2eConfig {
+    /// Identifies the message in the CRC, so that messages of the same
+    /// layout but different meaning are not mistaken for each other
+    pub data_id: u16,
+    /// Byte holding the CRC
+    pub crc_byte: usize,
+    /// Byte holding the counter in its low nibble. The high nibble is left
+    /// to the application.
+    pub counter_byte: usize,
+    /// Number of messages that may be lost in a row before a reception is
+    /// reported as [`E2eStatus::WrongSequence`]
+    pub max_lost: u8,
+}
+
+impl E2eConfig {
+    /// CRC in byte 0, counter in byte 1, no lost messages tolerated
+    pub const fn new(data_id: u16) -> Self {
+        Self {
+            data_id,
+            crc_byte: 0,
+            counter_byte: 1,
+            max_lost: 0,
+        }
+    }
+
+    fn crc(&self, data: &[u8]) -> u8 {
+        let [low, high] = self.data_id.to_le_bytes();
+        let payload = data
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| index != self.crc_byte)
+            .map(|(_, byte)| *byte);
+        ![low, high].into_iter().chain(payload).fold(0xff, crc8_step)
+    }
+}
+
+/// CRC-8 SAE J1850: polynomial 0x1d
+fn crc8_step(crc: u8, byte: u8) -> u8 {
+    (0..8).fold(crc ^ byte, |crc, _| {
+        if crc & 0x80 != 0 {
+            (crc << 1) ^ 0x1d
+        } else {
+            crc << 1
+        }
+    })
+}
+
+/// The payload does not contain the bytes of the protection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PayloadTooShort;
+
+#[derive(Copy, Clone, Debug)]
+struct Channel {
+    config: E2eConfig,
+    counter: Option<u8>,
+}
+
+/// Inserts counters and CRCs into the payloads of up to `N` IDs
+pub struct E2eProtector<const N: usize> {
+    channels: IdMap<Channel, N>,
+}
+
+impl<const N: usize> E2eProtector<N> {
+    /// Creates a protector for the given IDs, which may be given in any
+    /// order. Fails with the offending ID if an ID occurs more than once.
+    pub fn new(configs: [(Id, E2eConfig); N]) -> Result<Self, Id> {
+        Ok(Self {
+            channels: IdMap::new(configs.map(|(id, config)| {
+                (
+                    id,
+                    Channel {
+                        config,
+                        counter: None,
+                    },
+                )
+            }))?,
+        })
+    }
+
+    /// Advances the counter of `id` and writes it and the CRC into `data`.
+    /// Returns `Ok(false)` and leaves `data` untouched if `id` is not
+    /// protected.
+    pub fn protect(&mut self, id: Id, data: &mut [u8]) -> Result<bool, PayloadTooShort> {
+        let Some(channel) = self.channels.get_mut(id) else {
+            return Ok(false);
+        };
+        let config = channel.config;
+        let counter = channel.counter.map_or(0, |c| (c + 1) % COUNTER_MODULO);
+        let counter_byte = data.get_mut(config.counter_byte).ok_or(PayloadTooShort)?;
+        *counter_byte = (*counter_byte & 0xf0) | counter;
+        let crc = config.crc(data);
+        *data.get_mut(config.crc_byte).ok_or(PayloadTooShort)? = crc;
+        channel.counter = Some(counter);
+        Ok(true)
+    }
+
+    /// Returns the counter last written for `id`
+    pub fn counter(&self, id: Id) -> Option<u8> {
+        self.channels.get(id)?.counter
+    }
+}
+
+/// Result of checking a received payload
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum E2eStatus {
+    /// The ID is not protected
+    Unprotected,
+    /// First message received for the ID, its counter is accepted as is
+    Initial,
+    /// The counter follows the previous one, possibly after up to
+    /// [`E2eConfig::max_lost`] lost messages
+    Ok {
+        /// Number of messages lost since the previous one
+        lost: u8,
+    },
+    /// The counter equals the previous one
+    Repeated,
+    /// More messages were lost than tolerated, or the counter jumped
+    /// backwards. The new counter is accepted as the reference for the next
+    /// message.
+    WrongSequence,
+    /// The CRC does not match, the counter is ignored
+    WrongCrc,
+    /// The payload does not contain the bytes of the protection
+    PayloadTooShort,
+}
+
+impl E2eStatus {
+    /// Returns `true` if the payload can be used
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Unprotected | Self::Initial | Self::Ok { .. })
+    }
+}
+
+/// Validates counters and CRCs of received payloads of up to `N` IDs
+pub struct E2eChecker<const N: usize> {
+    channels: IdMap<Channel, N>,
+}
+
+impl<const N: usize> E2eChecker<N> {
+    /// Creates a checker for the given IDs, which may be given in any order.
+    /// Fails with the offending ID if an ID occurs more than once.
+    pub fn new(configs: [(Id, E2eConfig); N]) -> Result<Self, Id> {
+        E2eProtector::new(configs).map(|p| Self {
+            channels: p.channels,
+        })
+    }
+
+    /// Checks `data` received with `id`
+    pub fn check(&mut self, id: Id, data: &[u8]) -> E2eStatus {
+        let Some(channel) = self.channels.get_mut(id) else {
+            return E2eStatus::Unprotected;
+        };
+        let config = channel.config;
+        let (Some(&crc), Some(&counter_byte)) =
+            (data.get(config.crc_byte), data.get(config.counter_byte))
+        else {
+            return E2eStatus::PayloadTooShort;
+        };
+        let counter = counter_byte & 0x0f;
+        if crc != config.crc(data) || counter >= COUNTER_MODULO {
+            return E2eStatus::WrongCrc;
+        }
+        let previous = channel.counter.replace(counter);
+        let Some(previous) = previous else {
+            return E2eStatus::Initial;
+        };
+        match (counter + COUNTER_MODULO - previous) % COUNTER_MODULO {
+            0 => E2eStatus::Repeated,
+            delta if delta <= config.max_lost.saturating_add(1) => {
+                E2eStatus::Ok { lost: delta - 1 }
+            }
+            _ => E2eStatus::WrongSequence,
+        }
+    }
+
+    /// Checks the payload of `message`
+    pub fn check_message<M: Raw>(&mut self, message: &M) -> E2eStatus {
+        self.check(message.id(), message.data())
+    }
+
+    /// Forgets the counter of `id`, so that its next message is accepted as
+    /// [`E2eStatus::Initial`], e.g. after the sender restarted
+    pub fn reset(&mut self, id: Id) {
+        if let Some(channel) = self.channels.get_mut(id) {
+            channel.counter = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::StandardId;
+
+    fn id(raw: u16) -> Id {
+        Id::Standard(StandardId::new(raw).unwrap())
+    }
+
+    #[test]
+    fn crc_matches_sae_j1850() {
+        assert_eq!(!b"123456789".iter().copied().fold(0xff, crc8_step), 0x4b);
+    }
+
+    #[test]
+    fn checker_accepts_protected_sequence() {
+        let config = E2eConfig {
+            max_lost: 1,
+            ..E2eConfig::new(0x1234)
+        };
+        let mut protector = E2eProtector::new([(id(0x10), config)]).unwrap();
+        let mut checker = E2eChecker::new([(id(0x10), config)]).unwrap();
+        let mut data = [0, 0xa0, 1, 2, 3];
+        let mut send = || {
+            assert!(protector.protect(id(0x10), &mut data).unwrap());
+            assert_eq!(data[1] & 0xf0, 0xa0);
+            data
+        };
+        assert_eq!(checker.check(id(0x10), &send()), E2eStatus::Initial);
+        for _ in 0..20 {
+            assert_eq!(checker.check(id(0x10), &send()), E2eStatus::Ok { lost: 0 });
+        }
+        send();
+        let data = send();
+        assert_eq!(checker.check(id(0x10), &data), E2eStatus::Ok { lost: 1 });
+        assert_eq!(checker.check(id(0x10), &data), E2eStatus::Repeated);
+        send();
+        send();
+        let mut data = send();
+        assert_eq!(checker.check(id(0x10), &data), E2eStatus::WrongSequence);
+
+        data[3] ^= 1;
+        assert_eq!(checker.check(id(0x10), &data), E2eStatus::WrongCrc);
+        assert_eq!(checker.check(id(0x11), &data), E2eStatus::Unprotected);
+        assert_eq!(checker.check(id(0x10), &[0]), E2eStatus::PayloadTooShort);
+    }
+}
@@ -0,0 +1,274 @@
+//! Observation of frames transmitted by the node itself
+//!
+//! Logging and conformance-test applications need to reconstruct the complete
+//! traffic on the bus, including the frames the node transmits itself. The
+//! peripheral does not receive its own frames, so an [`EchoTracker`] keeps a
+//! copy of every tracked frame until it is confirmed by one of two
+//! [`EchoSource`]s:
+//!
+//! - [`EchoSource::TxEventFifo`]: echoes are synthesized from entries of the
+//!   TX event FIFO, which also carry the time of transmission. Frames are
+//!   matched to events by their message marker.
+//! - [`EchoSource::Loopback`]: with [`CanConfig::loopback`] enabled, the
+//!   peripheral receives its own frames while still transmitting them on the
+//!   bus. Received frames are matched against tracked frames by ID and data.
+//!
+//! Either way, the receive path hands out [`Traffic`], which marks echoes of
+//! own frames.
+//!
+//! [`CanConfig::loopback`]: crate::config::CanConfig::loopback
+
+use crate::message::{rx, tx, Dlc, Raw, TxEvent};
+use embedded_can::Id;
+
+/// Where confirmations of transmitted frames come from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EchoSource {
+    /// Events in the TX event FIFO, see [`EchoTracker::on_tx_event`]
+    TxEventFifo,
+    /// Frames received through external loopback, see
+    /// [`EchoTracker::classify`]
+    Loopback,
+}
+
+/// Frame transmitted by the node itself
+#[derive(Copy, Clone, Debug)]
+pub struct Echo<const L: usize> {
+    message: tx::Message<L>,
+    timestamp: u16,
+}
+
+impl<const L: usize> Echo<L> {
+    /// The transmitted message
+    pub fn message(&self) -> &tx::Message<L> {
+        &self.message
+    }
+
+    /// Value of the timestamp counter when the frame was transmitted
+    pub fn timestamp(&self) -> u16 {
+        self.timestamp
+    }
+}
+
+impl<const L: usize> Raw for Echo<L> {
+    fn id(&self) -> Id {
+        self.message.id()
+    }
+    fn decoded_dlc(&self) -> usize {
+        self.message.decoded_dlc()
+    }
+    fn dlc(&self) -> Dlc {
+        self.message.dlc()
+    }
+    fn fd_format(&self) -> bool {
+        self.message.fd_format()
+    }
+    fn is_remote_frame(&self) -> bool {
+        self.message.is_remote_frame()
+    }
+    fn data(&self) -> &[u8] {
+        self.message.data()
+    }
+    fn is_extended(&self) -> bool {
+        self.message.is_extended()
+    }
+    fn is_transmitter_error_passive(&self) -> bool {
+        self.message.is_transmitter_error_passive()
+    }
+    fn bit_rate_switching(&self) -> bool {
+        self.message.bit_rate_switching()
+    }
+}
+
+/// Frame observed on the receive path
+#[derive(Copy, Clone, Debug)]
+pub enum Traffic<M, const L: usize> {
+    /// Frame transmitted by another node
+    Received(M),
+    /// Frame transmitted by this node
+    Echo(Echo<L>),
+}
+
+/// The tracker has no free slot for another frame
+#[derive(Debug)]
+pub struct TrackerFull<const L: usize>(pub tx::Message<L>);
+
+/// Tracks up to `N` transmitted frames with up to `L` bytes of data until
+/// their echo is observed
+pub struct EchoTracker<const L: usize, const N: usize> {
+    source: EchoSource,
+    pending: [Option<tx::Message<L>>; N],
+}
+
+impl<const L: usize, const N: usize> EchoTracker<L, N> {
+    const MARKERS_SUFFICE: () = assert!(N <= 256, "message markers only identify 256 frames");
+
+    /// Creates a tracker confirming frames from `source`
+    pub fn new(source: EchoSource) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::MARKERS_SUFFICE;
+        Self {
+            source,
+            pending: [None; N],
+        }
+    }
+
+    /// Returns the source frames are confirmed from
+    pub fn source(&self) -> EchoSource {
+        self.source
+    }
+
+    /// Tracks `message` and returns it ready for transmission. With
+    /// [`EchoSource::TxEventFifo`], the message is changed to store an event
+    /// with a marker identifying it.
+    ///
+    /// Fails if `N` frames are already awaiting their echo.
+    pub fn track(&mut self, mut message: tx::Message<L>) -> Result<tx::Message<L>, TrackerFull<L>> {
+        let Some((index, slot)) = self
+            .pending
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+        else {
+            return Err(TrackerFull(message));
+        };
+        if self.source == EchoSource::TxEventFifo {
+            message.set_tx_event_marker(Some(index as u8));
+        }
+        *slot = Some(message);
+        Ok(message)
+    }
+
+    /// Synthesizes the echo of the frame confirmed by `event`. Returns `None`
+    /// for events of untracked frames, including frames sent with the marker
+    /// of a tracked frame but a different ID or data length.
+    pub fn on_tx_event(&mut self, event: &TxEvent) -> Option<Echo<L>> {
+        let slot = self.pending.get_mut(usize::from(event.message_marker()))?;
+        let pending = slot.as_ref()?;
+        if pending.id() != event.id()
+            || pending.dlc() != event.dlc()
+            || pending.is_remote_frame() != event.is_remote_frame()
+        {
+            return None;
+        }
+        let message = slot.take()?;
+        Some(Echo {
+            message,
+            timestamp: event.timestamp(),
+        })
+    }
+
+    /// Marks `message` as an echo if it matches a tracked frame. Only frames
+    /// received through external loopback can be echoes.
+    pub fn classify<M: rx::AnyMessage>(&mut self, message: M) -> Traffic<M, L> {
+        let echo = self.pending.iter_mut().find(|pending| {
+            pending.as_ref().is_some_and(|pending| {
+                pending.id() == message.id()
+                    && pending.is_remote_frame() == message.is_remote_frame()
+                    && pending.data() == message.data()
+            })
+        });
+        match echo.and_then(Option::take) {
+            Some(pending) => Traffic::Echo(Echo {
+                message: pending,
+                timestamp: message.timestamp(),
+            }),
+            None => Traffic::Received(message),
+        }
+    }
+
+    /// Returns the number of frames awaiting their echo
+    pub fn len(&self) -> usize {
+        self.pending.iter().filter(|p| p.is_some()).count()
+    }
+
+    /// Returns `true` if no frame is awaiting its echo
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stops tracking all frames, e.g. after their transmission was
+    /// cancelled
+    pub fn clear(&mut self) {
+        self.pending = [None; N];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::StandardId;
+
+    fn message(id: u16, data: &[u8]) -> tx::Message<8> {
+        tx::MessageBuilder {
+            id: Id::Standard(StandardId::new(id).unwrap()),
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(data)),
+            store_tx_event: None,
+        }
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn assigns_free_markers() {
+        let mut tracker = EchoTracker::<8, 2>::new(EchoSource::TxEventFifo);
+        let first = tracker.track(message(0x10, &[1])).unwrap();
+        let second = tracker.track(message(0x20, &[2])).unwrap();
+        assert_eq!(first.tx_event_marker(), Some(0));
+        assert_eq!(second.tx_event_marker(), Some(1));
+        assert!(tracker.track(message(0x30, &[3])).is_err());
+        assert_eq!(tracker.len(), 2);
+        tracker.clear();
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn tx_events_confirm_matching_frames_only() {
+        let mut tracker = EchoTracker::<8, 2>::new(EchoSource::TxEventFifo);
+        let tracked = tracker.track(message(0x10, &[1])).unwrap();
+        let echo = tracker.on_tx_event(&tracked.event(500)).unwrap();
+        assert_eq!(echo.id(), tracked.id());
+        assert_eq!(echo.timestamp(), 500);
+        assert!(tracker.is_empty());
+        // Confirmed frames are not confirmed again
+        assert!(tracker.on_tx_event(&tracked.event(600)).is_none());
+
+        // An untracked frame reusing the marker of a tracked one
+        let tracked = tracker.track(message(0x10, &[1])).unwrap();
+        let other = message(0x20, &[1]).with_tx_event(0);
+        assert!(tracker.on_tx_event(&other.event(700)).is_none());
+        let longer = message(0x10, &[1, 2]).with_tx_event(0);
+        assert!(tracker.on_tx_event(&longer.event(700)).is_none());
+        // Markers beyond the tracker
+        let untracked = message(0x10, &[1]).with_tx_event(5);
+        assert!(tracker.on_tx_event(&untracked.event(700)).is_none());
+        assert_eq!(tracker.len(), 1);
+        assert!(tracker.on_tx_event(&tracked.event(800)).is_some());
+    }
+
+    #[test]
+    fn loopback_echoes_are_told_apart_from_foreign_frames() {
+        let mut tracker = EchoTracker::<8, 2>::new(EchoSource::Loopback);
+        let tracked = tracker.track(message(0x10, &[1, 2])).unwrap();
+        assert_eq!(tracked.tx_event_marker(), None);
+        // Same ID, different data
+        let foreign = message(0x10, &[1, 3]).received(100);
+        assert!(matches!(tracker.classify(foreign), Traffic::Received(_)));
+        let foreign = message(0x11, &[1, 2]).received(100);
+        assert!(matches!(tracker.classify(foreign), Traffic::Received(_)));
+        assert_eq!(tracker.len(), 1);
+        match tracker.classify(tracked.received(200)) {
+            Traffic::Echo(echo) => {
+                assert_eq!(echo.data(), [1, 2]);
+                assert_eq!(echo.timestamp(), 200);
+            }
+            Traffic::Received(_) => panic!("echo not recognized"),
+        }
+        assert!(tracker.is_empty());
+        // A second copy is a frame of another node
+        assert!(matches!(
+            tracker.classify(tracked.received(300)),
+            Traffic::Received(_)
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+//! Posting interrupts as events to RTOS queues
+//!
+//! Without async, the usual way to hand work from an interrupt handler to a
+//! task is a message queue of the RTOS. [`EventAdapter`] clears the flagged
+//! interrupts of an [`OwnedInterruptSet`] and posts an event for each of them
+//! to a queue implementing [`EventQueue`], counting events that did not fit.
+//!
+//! [`EventQueue`] is implemented for [`spillover::Producer`] and, with the
+//! `heapless` feature, for `heapless::spsc::Producer`. Wrappers around RTOS
+//! queues implement it with a non-blocking send.
+//!
+//! ```no_run
+//! use mcan::event_queue::{EventAdapter, EventQueue};
+//! use mcan::interrupt::{state, Interrupt, OwnedInterruptSet};
+//!
+//! #[derive(Debug)]
+//! enum CanEvent {
+//!     Received,
+//!     BusOff,
+//! }
+//!
+//! fn to_event(interrupt: Interrupt) -> Option<CanEvent> {
+//!     match interrupt {
+//!         Interrupt::RxFifo0NewMessage => Some(CanEvent::Received),
+//!         Interrupt::BusOff => Some(CanEvent::BusOff),
+//!         _ => None,
+//!     }
+//! }
+//!
+//! fn on_interrupt<Id: mcan::core::CanId, Q: EventQueue<CanEvent>>(
+//!     adapter: &mut EventAdapter<Q, fn(Interrupt) -> Option<CanEvent>>,
+//!     interrupts: &OwnedInterruptSet<Id, state::EnabledLine0>,
+//! ) {
+//!     adapter.handle(interrupts);
+//! }
+//! ```
+//!
+//! [`spillover::Producer`]: crate::spillover::Producer
+
+use crate::interrupt::{state, Interrupt, InterruptSet, OwnedInterruptSet};
+use crate::spillover;
+
+/// Queue events are posted to from an interrupt handler
+pub trait EventQueue<E> {
+    /// Posts `event` without blocking. Hands the event back if the queue is
+    /// full.
+    fn post(&mut self, event: E) -> Result<(), E>;
+}
+
+impl<E, const N: usize> EventQueue<E> for spillover::Producer<'_, E, N> {
+    fn post(&mut self, event: E) -> Result<(), E> {
+        self.push(event)
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<E, const N: usize> EventQueue<E> for heapless::spsc::Producer<'_, E, N> {
+    fn post(&mut self, event: E) -> Result<(), E> {
+        self.enqueue(event)
+    }
+}
+
+/// Posts events for flagged interrupts to an [`EventQueue`]
+pub struct EventAdapter<Q, F> {
+    queue: Q,
+    map: F,
+    dropped: usize,
+}
+
+impl<Q, F> EventAdapter<Q, F> {
+    /// Posts to `queue` the events `map` returns for flagged interrupts.
+    /// Interrupts mapped to `None` are cleared without posting anything.
+    pub fn new(queue: Q, map: F) -> Self {
+        Self {
+            queue,
+            map,
+            dropped: 0,
+        }
+    }
+
+    /// Clears the flagged interrupts of `interrupts` and posts their events.
+    /// Returns the number of events posted.
+    pub fn handle<E, Id: mcan_core::CanId, State: state::MaybeEnabled>(
+        &mut self,
+        interrupts: &OwnedInterruptSet<Id, State>,
+    ) -> usize
+    where
+        Q: EventQueue<E>,
+        F: FnMut(Interrupt) -> Option<E>,
+    {
+        let flags = interrupts.interrupt_flags();
+        interrupts.clear_interrupts(flags);
+        self.post(flags)
+    }
+
+    /// Posts the events of the interrupts in `flags`. Returns the number of
+    /// events posted.
+    pub fn post<E>(&mut self, flags: InterruptSet) -> usize
+    where
+        Q: EventQueue<E>,
+        F: FnMut(Interrupt) -> Option<E>,
+    {
+        let mut posted = 0;
+        for event in flags.iter().filter_map(&mut self.map) {
+            if self.queue.post(event).is_ok() {
+                posted += 1;
+            } else {
+                self.dropped = self.dropped.wrapping_add(1);
+            }
+        }
+        posted
+    }
+
+    /// Returns the number of events dropped because the queue was full. The
+    /// counter wraps around on overflow.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Returns the queue
+    pub fn release(self) -> Q {
+        self.queue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spillover::Spillover;
+
+    #[test]
+    fn posts_mapped_events_and_counts_drops() {
+        let mut queue = Spillover::<u8, 2>::new();
+        let (producer, mut consumer) = queue.split();
+        let mut adapter = EventAdapter::new(producer, |interrupt| match interrupt {
+            Interrupt::BusOff => None,
+            other => Some(other as u8),
+        });
+        let flags = [
+            Interrupt::RxFifo0NewMessage,
+            Interrupt::BusOff,
+            Interrupt::RxFifo1NewMessage,
+            Interrupt::TimeoutOccured,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(adapter.post(flags), 2);
+        assert_eq!(adapter.dropped(), 1);
+        assert_eq!(consumer.pop(), Some(Interrupt::RxFifo0NewMessage as u8));
+        assert_eq!(consumer.pop(), Some(Interrupt::RxFifo1NewMessage as u8));
+        assert_eq!(consumer.pop(), None);
+    }
+}
@@ -48,6 +48,139 @@ impl<'a, P, T: Copy> Filters<'a, P, T> {
         self.len += 1;
         Ok(index)
     }
+
+    /// Returns the number of filters in the list
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list has no filters
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Replaces the list with the `staged` filters. Filters beyond them are
+    /// disabled.
+    ///
+    /// Fails without changing anything if the staged filters do not fit. While
+    /// the peripheral is operating, frames are filtered by a partially
+    /// replaced list during the call; [`Aux::swap_filters`] avoids that.
+    ///
+    /// [`Aux::swap_filters`]: crate::bus::Aux::swap_filters
+    pub fn replace<const N: usize>(
+        &mut self,
+        staged: &StagedFilters<T, N>,
+    ) -> Result<(), TooManyFilters>
+    where
+        T: FilterElement,
+    {
+        self.check(staged)?;
+        let disabled = T::DISABLED;
+        for (index, cell) in self
+            .memory
+            .iter()
+            .enumerate()
+            .take(self.len.max(staged.len))
+        {
+            cell.set(staged.filters().get(index).copied().unwrap_or(disabled));
+        }
+        self.len = staged.len;
+        Ok(())
+    }
+
+    /// Fails if the `staged` filters do not fit into the list
+    pub(crate) fn check<const N: usize>(
+        &self,
+        staged: &StagedFilters<T, N>,
+    ) -> Result<(), TooManyFilters> {
+        if staged.len > self.memory.len() {
+            Err(TooManyFilters {
+                capacity: self.memory.len(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Staged filters do not fit into the filter list
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TooManyFilters {
+    /// Number of filters the list holds
+    pub capacity: usize,
+}
+
+/// Filter list built in a staging buffer, to be applied at once with
+/// [`Filters::replace`] or [`Aux::swap_filters`]
+///
+/// [`Aux::swap_filters`]: crate::bus::Aux::swap_filters
+pub struct StagedFilters<T, const N: usize> {
+    filters: [T; N],
+    len: usize,
+}
+
+/// Staged standard ID filters
+pub type StagedFiltersStandard<const N: usize> = StagedFilters<FilterStandardId, N>;
+/// Staged extended ID filters
+pub type StagedFiltersExtended<const N: usize> = StagedFilters<FilterExtendedId, N>;
+
+/// Filter element in the peripheral's representation
+pub trait FilterElement: Copy {
+    /// Disabled element, matching nothing
+    const DISABLED: Self;
+}
+
+impl FilterElement for FilterStandardId {
+    const DISABLED: Self = FilterStandardId(0);
+}
+
+impl FilterElement for FilterExtendedId {
+    const DISABLED: Self = FilterExtendedId([0; 2]);
+}
+
+impl<T: FilterElement, const N: usize> StagedFilters<T, N> {
+    /// Creates an empty staging buffer for up to `N` filters
+    pub const fn new() -> Self {
+        Self {
+            filters: [T::DISABLED; N],
+            len: 0,
+        }
+    }
+
+    /// Appends a `filter`. Returns the index it will be assigned, or the
+    /// `filter` back if the buffer is full.
+    pub fn push<F: Copy + Into<T>>(&mut self, filter: F) -> Result<usize, F> {
+        let index = self.len;
+        let slot = self.filters.get_mut(index).ok_or(filter)?;
+        *slot = filter.into();
+        self.len += 1;
+        Ok(index)
+    }
+
+    /// Removes all filters
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the number of staged filters
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no filters are staged
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn filters(&self) -> &[T] {
+        self.filters.get(..self.len).unwrap_or(&[])
+    }
+}
+
+impl<T: FilterElement, const N: usize> Default for StagedFilters<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// 11-bit filter in the peripheral's representation
@@ -111,7 +244,7 @@ pub enum SbMsgType {
     RxBuffer = 0,
     /// Debug message A
     DebugA,
-    /// Debug message D
+    /// Debug message B
     DebugB,
     /// Debug message C
     DebugC,
@@ -187,6 +320,84 @@ pub enum Action {
     PriorityFifo1,
 }
 
+/// IDs matched by a single filter element, see [`route`]
+#[derive(Copy, Clone)]
+pub enum IdMatch {
+    /// Standard IDs equal to `id` in the bits set in `mask`
+    Standard {
+        /// ID to match
+        id: StandardId,
+        /// Bits of the ID that are compared
+        mask: StandardId,
+    },
+    /// Standard IDs from `low` to `high`
+    StandardRange {
+        /// Lowest ID matched
+        low: StandardId,
+        /// Highest ID matched
+        high: StandardId,
+    },
+    /// Extended IDs equal to `id` in the bits set in `mask`
+    Extended {
+        /// ID to match
+        id: ExtendedId,
+        /// Bits of the ID that are compared
+        mask: ExtendedId,
+    },
+    /// Extended IDs from `low` to `high`
+    ExtendedRange {
+        /// Lowest ID matched
+        low: ExtendedId,
+        /// Highest ID matched
+        high: ExtendedId,
+    },
+}
+
+/// Appends a filter with `action` for each of `matches` to the filter lists.
+/// Fails with the first match not fitting into its list.
+///
+/// Combined with the handling of non-matching frames in
+/// [`CanConfig::global_filter`], this expresses policies splitting the
+/// traffic between the RX FIFOs; see [`CanConfigurable::route`].
+///
+/// [`CanConfig::global_filter`]: crate::config::CanConfig::global_filter
+/// [`CanConfigurable::route`]: crate::bus::CanConfigurable::route
+pub fn route<P>(
+    standard: &mut FiltersStandard<'_, P>,
+    extended: &mut FiltersExtended<'_, P>,
+    matches: &[IdMatch],
+    action: Action,
+) -> Result<(), IdMatch> {
+    for &m in matches {
+        let pushed = match m {
+            IdMatch::Standard { id, mask } => standard
+                .push(Filter::Classic {
+                    action,
+                    filter: id,
+                    mask,
+                })
+                .is_ok(),
+            IdMatch::StandardRange { low, high } => {
+                standard.push(Filter::Range { action, low, high }).is_ok()
+            }
+            IdMatch::Extended { id, mask } => extended
+                .push(ExtFilter::Classic {
+                    action,
+                    filter: id,
+                    mask,
+                })
+                .is_ok(),
+            IdMatch::ExtendedRange { low, high } => extended
+                .push(ExtFilter::Range { action, low, high })
+                .is_ok(),
+        };
+        if !pushed {
+            return Err(m);
+        }
+    }
+    Ok(())
+}
+
 impl From<Action> for u32 {
     fn from(val: Action) -> Self {
         match val {
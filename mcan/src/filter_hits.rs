@@ -0,0 +1,160 @@
+//! Counting of filter matches
+//!
+//! Filters with [`Action::Priority`] raise
+//! [`Interrupt::HighPriorityMessage`] for matching frames without storing
+//! them. This is a cheap way of accounting for traffic whose payload is
+//! irrelevant, e.g. to detect the presence of other nodes by their cyclic
+//! frames, without spending message RAM and CPU time on receiving them.
+//!
+//! The peripheral reports the filter element that matched last in the high
+//! priority message status, read through
+//! [`DynMonitor::high_priority_match`]. [`FilterHitCounter`] counts the
+//! matches per filter element. Matches of filters storing the message as
+//! well, [`Action::PriorityFifo0`] and [`Action::PriorityFifo1`], are counted
+//! likewise.
+//!
+//! Only the last match is reported, so matches following each other faster
+//! than the interrupt is handled are counted once.
+//!
+//! ```no_run
+//! use mcan::filter::{Action, Filter};
+//! use mcan::filter_hits::FilterHitCounter;
+//! use mcan::interrupt::{Interrupt, OwnedInterruptSet};
+//! use mcan::monitor::DynMonitor;
+//! use mcan::embedded_can::StandardId;
+//!
+//! // Count heartbeats of the nodes 0x700 to 0x77f in standard filter 0
+//! let heartbeats = Filter::Range {
+//!     action: Action::Priority,
+//!     low: StandardId::new(0x700).unwrap(),
+//!     high: StandardId::new(0x77f).unwrap(),
+//! };
+//!
+//! fn on_interrupt<Id: mcan_core::CanId>(
+//!     interrupts: &OwnedInterruptSet<Id>,
+//!     monitor: &impl DynMonitor,
+//!     counter: &mut FilterHitCounter<4, 0>,
+//! ) {
+//!     for interrupt in interrupts.iter_flagged() {
+//!         if let Interrupt::HighPriorityMessage = interrupt {
+//!             counter.record(monitor.high_priority_match());
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`Action::Priority`]: crate::filter::Action::Priority
+//! [`Action::PriorityFifo0`]: crate::filter::Action::PriorityFifo0
+//! [`Action::PriorityFifo1`]: crate::filter::Action::PriorityFifo1
+//! [`Interrupt::HighPriorityMessage`]: crate::interrupt::Interrupt::HighPriorityMessage
+//! [`DynMonitor::high_priority_match`]: crate::monitor::DynMonitor::high_priority_match
+
+/// Filter element that matched the last high priority message
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HighPriorityMatch {
+    /// The element is in the extended filter list
+    pub extended: bool,
+    /// Index of the element in its list
+    pub filter_index: u8,
+    /// The message was stored in an RX FIFO
+    pub stored: bool,
+}
+
+impl HighPriorityMatch {
+    /// Decodes the value of the HPMS register
+    pub(crate) fn from_bits(hpms: u32) -> Self {
+        let msi = (hpms >> 6) & 0x3;
+        Self {
+            extended: hpms & (1 << 15) != 0,
+            filter_index: ((hpms >> 8) & 0x7f) as u8,
+            stored: msi >= 2,
+        }
+    }
+}
+
+/// Counts matches of the first `S` standard and `E` extended filter elements
+#[derive(Clone, Debug)]
+pub struct FilterHitCounter<const S: usize, const E: usize> {
+    standard: [u32; S],
+    extended: [u32; E],
+}
+
+impl<const S: usize, const E: usize> FilterHitCounter<S, E> {
+    /// Creates a counter with all counts at zero
+    pub const fn new() -> Self {
+        Self {
+            standard: [0; S],
+            extended: [0; E],
+        }
+    }
+
+    /// Counts `hit`. Returns `false` if its filter element is beyond the
+    /// counted ones.
+    pub fn record(&mut self, hit: HighPriorityMatch) -> bool {
+        let counts = if hit.extended {
+            self.extended.as_mut_slice()
+        } else {
+            self.standard.as_mut_slice()
+        };
+        match counts.get_mut(usize::from(hit.filter_index)) {
+            Some(count) => {
+                *count = count.wrapping_add(1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the matches of standard filter element `index`
+    pub fn standard(&self, index: usize) -> u32 {
+        self.standard.get(index).copied().unwrap_or(0)
+    }
+
+    /// Returns the matches of extended filter element `index`
+    pub fn extended(&self, index: usize) -> u32 {
+        self.extended.get(index).copied().unwrap_or(0)
+    }
+
+    /// Resets all counts to zero
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<const S: usize, const E: usize> Default for FilterHitCounter<S, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_matches_per_filter_element() {
+        let mut counter = FilterHitCounter::<2, 1>::new();
+        // Standard element 1, no FIFO
+        let standard = HighPriorityMatch::from_bits(1 << 8);
+        assert_eq!(
+            standard,
+            HighPriorityMatch {
+                extended: false,
+                filter_index: 1,
+                stored: false,
+            }
+        );
+        // Extended element 0, stored in FIFO 1 at index 5
+        let extended = HighPriorityMatch::from_bits(1 << 15 | 3 << 6 | 5);
+        assert!(extended.extended && extended.stored);
+        assert!(counter.record(standard));
+        assert!(counter.record(standard));
+        assert!(counter.record(extended));
+        assert!(!counter.record(HighPriorityMatch::from_bits(2 << 8)));
+        assert_eq!(counter.standard(1), 2);
+        assert_eq!(counter.standard(0), 0);
+        assert_eq!(counter.extended(0), 1);
+        counter.reset();
+        assert_eq!(counter.standard(1), 0);
+    }
+}
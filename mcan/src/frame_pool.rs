@@ -0,0 +1,200 @@
+//! Pool of frame buffers shared between interrupt handlers and tasks
+//!
+//! CAN FD frames take up to 64 bytes of payload, and moving them through
+//! several queues on their way from the interrupt handler to the application
+//! copies them each time. A [`FramePool`] stores frames in place instead and
+//! hands out [`PoolFrame`] handles, which are cheap to move through queues
+//! such as [`Spillover`]. The frame is returned to the pool when its handle is
+//! dropped.
+//!
+//! Allocation and release are lock-free, using a bitmap of free buffers in an
+//! atomic word, so both can happen in interrupt handlers as well as in tasks.
+//! Like the [`stats`] module, the pool uses [`portable_atomic`] for
+//! read-modify-write operations on targets without native support for them.
+//!
+//! ```no_run
+//! use mcan::frame_pool::FramePool;
+//! use mcan::message::rx;
+//! use mcan::rx_fifo::DynRxFifo;
+//!
+//! static POOL: FramePool<rx::Message<64>, 8> = FramePool::new();
+//!
+//! fn on_rx_interrupt(fifo: &mut impl DynRxFifo<Message = rx::Message<64>>) {
+//!     while let Ok(message) = fifo.receive() {
+//!         match POOL.alloc(message) {
+//!             // Pass `frame` to the application, e.g. through a queue
+//!             Ok(frame) => drop(frame),
+//!             // The application holds all buffers, the frame is lost
+//!             Err(_message) => {}
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`Spillover`]: crate::spillover::Spillover
+//! [`stats`]: crate::stats
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Fixed-size pool of `N` buffers of frames `M`, with `N` at most 32
+pub struct FramePool<M, const N: usize> {
+    /// Bit `i` is set while buffer `i` is free
+    free: AtomicU32,
+    buffers: [UnsafeCell<MaybeUninit<M>>; N],
+}
+
+// Safety: A buffer is only accessed through the unique handle that allocated
+// it, and handles can only be created by claiming the buffer's bit in `free`.
+unsafe impl<M: Send, const N: usize> Sync for FramePool<M, N> {}
+
+impl<M, const N: usize> FramePool<M, N> {
+    const ASSERT_FITS_BITMAP: () = assert!(N <= 32, "a frame pool holds at most 32 buffers");
+
+    /// Creates a pool with all buffers free
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::ASSERT_FITS_BITMAP;
+        Self {
+            free: AtomicU32::new(Self::ALL_FREE),
+            buffers: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+
+    const ALL_FREE: u32 = match u32::MAX.checked_shr(32u32.saturating_sub(N as u32)) {
+        Some(mask) => mask,
+        None => 0,
+    };
+
+    /// Stores `frame` in a free buffer. Returns the `frame` back if all
+    /// buffers are taken.
+    pub fn alloc(&self, frame: M) -> Result<PoolFrame<'_, M>, M> {
+        let mut free = self.free.load(Ordering::Relaxed);
+        let bit = loop {
+            if free == 0 {
+                return Err(frame);
+            }
+            let index = free.trailing_zeros();
+            match self.free.compare_exchange_weak(
+                free,
+                free & !(1 << index),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break index,
+                Err(current) => free = current,
+            }
+        };
+        let Some(buffer) = self.buffers.get(bit as usize) else {
+            return Err(frame);
+        };
+        // Safety: The buffer was claimed above, so no other handle refers to
+        // it.
+        unsafe { (*buffer.get()).write(frame) };
+        Ok(PoolFrame {
+            free: &self.free,
+            buffer,
+            bit,
+        })
+    }
+
+    /// Returns the number of free buffers
+    pub fn available(&self) -> usize {
+        self.free.load(Ordering::Relaxed).count_ones() as usize
+    }
+
+    /// Returns the number of buffers
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<M, const N: usize> Default for FramePool<M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Frame stored in a [`FramePool`], returned to the pool when dropped
+pub struct PoolFrame<'p, M> {
+    free: &'p AtomicU32,
+    buffer: &'p UnsafeCell<MaybeUninit<M>>,
+    /// Bit of the buffer in the free bitmap
+    bit: u32,
+}
+
+// Safety: The handle owns the frame in its buffer, like a `Box`
+unsafe impl<M: Send> Send for PoolFrame<'_, M> {}
+unsafe impl<M: Sync> Sync for PoolFrame<'_, M> {}
+
+impl<M> PoolFrame<'_, M> {
+    /// Takes the frame out of the pool, freeing its buffer
+    pub fn into_inner(self) -> M {
+        // Safety: The buffer is initialized while the handle exists, and the
+        // handle is forgotten so that the frame is not dropped again.
+        let frame = unsafe { (*self.buffer.get()).assume_init_read() };
+        self.release();
+        core::mem::forget(self);
+        frame
+    }
+
+    fn release(&self) {
+        self.free.fetch_or(1 << self.bit, Ordering::Release);
+    }
+}
+
+impl<M> Deref for PoolFrame<'_, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        // Safety: The buffer is initialized and owned by the handle
+        unsafe { (*self.buffer.get()).assume_init_ref() }
+    }
+}
+
+impl<M> DerefMut for PoolFrame<'_, M> {
+    fn deref_mut(&mut self) -> &mut M {
+        // Safety: The buffer is initialized and owned by the handle
+        unsafe { (*self.buffer.get()).assume_init_mut() }
+    }
+}
+
+impl<M> Drop for PoolFrame<'_, M> {
+    fn drop(&mut self) {
+        // Safety: The buffer is initialized and owned by the handle, which
+        // gives it up below
+        unsafe { (*self.buffer.get()).assume_init_drop() };
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_after_release() {
+        let pool = FramePool::<[u8; 64], 2>::new();
+        let mut first = pool.alloc([1; 64]).unwrap();
+        let second = pool.alloc([2; 64]).unwrap();
+        assert_eq!(pool.available(), 0);
+        assert!(pool.alloc([3; 64]).is_err());
+        first[0] = 0;
+        assert_eq!(first[..2], [0, 1]);
+        drop(first);
+        assert_eq!(pool.available(), 1);
+        let third = pool.alloc([3; 64]).unwrap();
+        assert_eq!(third[0], 3);
+        assert_eq!(second.into_inner(), [2; 64]);
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[test]
+    fn full_pool_has_all_bits_set() {
+        let pool = FramePool::<u8, 32>::new();
+        assert_eq!(pool.available(), 32);
+        assert_eq!(FramePool::<u8, 0>::new().available(), 0);
+    }
+}
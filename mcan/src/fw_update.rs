@@ -0,0 +1,462 @@
+//! Firmware update receiver for bootloaders
+//!
+//! [`FirmwareReceiver`] implements the receiving end of a simple chunked
+//! transfer protocol, writing the image into a [`FirmwareStorage`] provided by
+//! the bootloader. The host sends commands on the request ID and the receiver
+//! acknowledges each of them with a [`Response`] on the response ID. The first
+//! byte of each frame is the command:
+//!
+//! | Command | Frame data                                      |
+//! |---------|-------------------------------------------------|
+//! | Start   | `0x01`, image size (`u32`, little endian)       |
+//! | Data    | `0x02`, sequence number, CRC-8 of block, block  |
+//! | Verify  | `0x03`, CRC-32 of image (`u32`, little endian)  |
+//! | Abort   | `0x04`                                          |
+//!
+//! Sequence numbers start at 0 and wrap around after 255. Blocks fill the
+//! rest of the frame, so frames with larger payloads carry larger blocks. A
+//! block is acknowledged again without being written if it is retransmitted
+//! after its acknowledgment was lost. Likewise, a verify command repeated
+//! after the image was committed is acknowledged with the final status. The
+//! CRC-8 uses the polynomial `0x07` and the CRC-32 is the one of IEEE 802.3.
+//!
+//! Responses consist of the command with the most significant bit set, a
+//! [`Status`] and the next expected sequence number.
+//!
+//! ```no_run
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::fw_update::{FirmwareReceiver, FirmwareStorage};
+//! use mcan::message::{rx, tx};
+//! use mcan::tx_buffers::DynTx;
+//!
+//! fn on_message<S: FirmwareStorage>(
+//!     receiver: &mut FirmwareReceiver<S>,
+//!     message: &rx::Message<8>,
+//!     tx: &mut impl DynTx<Message = tx::Message<8>>,
+//! ) {
+//!     if let Some(response) = receiver.process(message) {
+//!         let _ = receiver.respond(tx, response);
+//!     }
+//!     if receiver.is_complete() {
+//!         // Boot the new image
+//!     }
+//! }
+//! ```
+
+use crate::message::{tx, Raw};
+use crate::tx_buffers::{DynTx, Error};
+use embedded_can::Id;
+
+/// Storage the firmware image is written into, e.g. the update slot in flash
+pub trait FirmwareStorage {
+    /// Error reported by the storage
+    type Error;
+
+    /// Prepares the storage for an image of `size` bytes, e.g. by erasing it
+    fn begin(&mut self, size: u32) -> Result<(), Self::Error>;
+
+    /// Writes `data` at `offset` bytes from the start of the image
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Called once the complete image has been received and verified, e.g. to
+    /// mark it as bootable
+    fn commit(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Outcome of a command, sent in the [`Response`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Status {
+    /// The command was executed
+    Ok = 0,
+    /// The data block does not have the expected sequence number
+    SequenceError = 1,
+    /// The CRC-8 of the data block does not match
+    BlockCrcError = 2,
+    /// The image is larger than announced or incomplete when verified
+    SizeError = 3,
+    /// The storage failed, the transfer was aborted
+    StorageError = 4,
+    /// Data or verify command without an ongoing transfer
+    NotStarted = 5,
+    /// The CRC-32 of the image does not match, the transfer was aborted
+    VerifyFailed = 6,
+    /// The command is unknown or malformed
+    InvalidCommand = 7,
+}
+
+/// Acknowledgment of a command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    /// Command being acknowledged
+    pub command: u8,
+    /// Outcome of the command
+    pub status: Status,
+    /// Sequence number of the next expected data block
+    pub sequence: u8,
+}
+
+impl Response {
+    /// Frame data of the response
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [self.command | 0x80, self.status as u8, self.sequence]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    Receiving {
+        size: u32,
+        offset: u32,
+        sequence: u8,
+        crc: u32,
+    },
+    Complete {
+        /// CRC-32 of the committed image
+        crc: u32,
+    },
+}
+
+const START: u8 = 0x01;
+const DATA: u8 = 0x02;
+const VERIFY: u8 = 0x03;
+const ABORT: u8 = 0x04;
+
+/// Receiving end of the firmware transfer protocol, see the [module
+/// documentation](self)
+pub struct FirmwareReceiver<S> {
+    request_id: Id,
+    response_id: Id,
+    storage: S,
+    state: State,
+}
+
+impl<S: FirmwareStorage> FirmwareReceiver<S> {
+    /// Creates a receiver writing into `storage`, receiving commands on
+    /// `request_id` and responding on `response_id`
+    pub fn new(request_id: Id, response_id: Id, storage: S) -> Self {
+        Self {
+            request_id,
+            response_id,
+            storage,
+            state: State::Idle,
+        }
+    }
+
+    /// Returns `true` once an image has been received, verified and committed
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Complete { .. })
+    }
+
+    /// Number of image bytes received in the ongoing transfer
+    pub fn received(&self) -> u32 {
+        match self.state {
+            State::Receiving { offset, .. } => offset,
+            _ => 0,
+        }
+    }
+
+    /// Returns the storage
+    pub fn release(self) -> S {
+        self.storage
+    }
+
+    /// Processes a received message. Returns the response to send, or `None`
+    /// if the message is not addressed to the receiver.
+    pub fn process<M: Raw>(&mut self, message: &M) -> Option<Response> {
+        if message.id() != self.request_id || message.is_remote_frame() {
+            return None;
+        }
+        let (&command, payload) = message.data().split_first()?;
+        let status = match command {
+            START => self.start(payload),
+            DATA => self.data(payload),
+            VERIFY => self.verify(payload),
+            ABORT => {
+                self.state = State::Idle;
+                Status::Ok
+            }
+            _ => Status::InvalidCommand,
+        };
+        let sequence = match self.state {
+            State::Receiving { sequence, .. } => sequence,
+            _ => 0,
+        };
+        Some(Response {
+            command,
+            status,
+            sequence,
+        })
+    }
+
+    /// Builds the frame carrying `response`
+    pub fn response_frame<M: tx::AnyMessage>(&self, response: Response) -> Result<M, Error> {
+        M::new(tx::MessageBuilder {
+            id: self.response_id,
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&response.to_bytes())),
+            store_tx_event: None,
+        })
+        .map_err(Error::from)
+    }
+
+    /// Sends `response` through the transmit queue
+    pub fn respond<T>(&self, tx: &mut T, response: Response) -> nb::Result<(), Error>
+    where
+        T: DynTx,
+        T::Message: tx::AnyMessage,
+    {
+        tx.transmit_queued(self.response_frame(response)?)
+    }
+
+    fn start(&mut self, payload: &[u8]) -> Status {
+        let Some(size) = read_u32(payload) else {
+            return Status::InvalidCommand;
+        };
+        if self.storage.begin(size).is_err() {
+            self.state = State::Idle;
+            return Status::StorageError;
+        }
+        self.state = State::Receiving {
+            size,
+            offset: 0,
+            sequence: 0,
+            crc: CRC32_INIT,
+        };
+        Status::Ok
+    }
+
+    fn data(&mut self, payload: &[u8]) -> Status {
+        let State::Receiving {
+            size,
+            offset,
+            sequence,
+            crc,
+        } = self.state
+        else {
+            return Status::NotStarted;
+        };
+        let [block_sequence, block_crc, block @ ..] = payload else {
+            return Status::InvalidCommand;
+        };
+        if *block_sequence == sequence.wrapping_sub(1) && offset != 0 {
+            // Retransmission after a lost acknowledgment
+            return Status::Ok;
+        }
+        if *block_sequence != sequence {
+            return Status::SequenceError;
+        }
+        if crc8(block) != *block_crc {
+            return Status::BlockCrcError;
+        }
+        let end = u32::try_from(block.len())
+            .ok()
+            .and_then(|len| offset.checked_add(len))
+            .filter(|&end| end <= size);
+        let Some(end) = end else {
+            return Status::SizeError;
+        };
+        if self.storage.write(offset, block).is_err() {
+            self.state = State::Idle;
+            return Status::StorageError;
+        }
+        self.state = State::Receiving {
+            size,
+            offset: end,
+            sequence: sequence.wrapping_add(1),
+            crc: crc32_update(crc, block),
+        };
+        Status::Ok
+    }
+
+    fn verify(&mut self, payload: &[u8]) -> Status {
+        let (size, offset, crc) = match self.state {
+            State::Receiving {
+                size, offset, crc, ..
+            } => (size, offset, crc),
+            // Retransmission after the acknowledgment of the final status was
+            // lost
+            State::Complete { crc } => {
+                return match read_u32(payload) {
+                    Some(expected) if crc == expected => Status::Ok,
+                    Some(_) => Status::VerifyFailed,
+                    None => Status::InvalidCommand,
+                }
+            }
+            State::Idle => return Status::NotStarted,
+        };
+        let Some(expected) = read_u32(payload) else {
+            return Status::InvalidCommand;
+        };
+        if offset != size {
+            return Status::SizeError;
+        }
+        if !crc != expected {
+            self.state = State::Idle;
+            return Status::VerifyFailed;
+        }
+        if self.storage.commit().is_err() {
+            self.state = State::Idle;
+            return Status::StorageError;
+        }
+        self.state = State::Complete { crc: expected };
+        Status::Ok
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    let bytes = bytes.get(..4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// CRC-8 with polynomial `0x07`, as used for data blocks
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+const CRC32_INIT: u32 = u32::MAX;
+
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// CRC-32 of IEEE 802.3, as used to verify the image
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(CRC32_INIT, data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::StandardId;
+    extern crate std;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct Image {
+        data: Vec<u8>,
+        committed: bool,
+    }
+
+    impl FirmwareStorage for Image {
+        type Error = ();
+
+        fn begin(&mut self, size: u32) -> Result<(), ()> {
+            self.data = std::vec![0; size as usize];
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            self.data[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<(), ()> {
+            self.committed = true;
+            Ok(())
+        }
+    }
+
+    fn id(raw: u16) -> Id {
+        Id::Standard(StandardId::new(raw).unwrap())
+    }
+
+    fn frame(data: &[u8]) -> tx::Message<8> {
+        tx::MessageBuilder {
+            id: id(0x700),
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(data)),
+            store_tx_event: None,
+        }
+        .build()
+        .unwrap()
+    }
+
+    fn block(sequence: u8, data: &[u8]) -> tx::Message<8> {
+        let mut bytes = std::vec![DATA, sequence, crc8(data)];
+        bytes.extend_from_slice(data);
+        frame(&bytes)
+    }
+
+    fn status(receiver: &mut FirmwareReceiver<Image>, message: tx::Message<8>) -> Status {
+        receiver.process(&message).unwrap().status
+    }
+
+    #[test]
+    fn computes_checksums() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn receives_image() {
+        let image = b"firmware!";
+        let mut receiver = FirmwareReceiver::new(id(0x700), id(0x701), Image::default());
+        let mut start = std::vec![START];
+        start.extend_from_slice(&(image.len() as u32).to_le_bytes());
+        assert_eq!(status(&mut receiver, frame(&start)), Status::Ok);
+
+        assert_eq!(status(&mut receiver, block(1, b"x")), Status::SequenceError);
+        assert_eq!(
+            status(&mut receiver, frame(&[DATA, 0, 0, b'f'])),
+            Status::BlockCrcError
+        );
+        assert_eq!(status(&mut receiver, block(0, &image[..5])), Status::Ok);
+        // Retransmission is acknowledged, but not written twice
+        assert_eq!(status(&mut receiver, block(0, &image[..5])), Status::Ok);
+        assert_eq!(receiver.received(), 5);
+
+        let mut verify = std::vec![VERIFY];
+        verify.extend_from_slice(&crc32(image).to_le_bytes());
+        assert_eq!(status(&mut receiver, frame(&verify)), Status::SizeError);
+
+        let response = receiver.process(&block(1, &image[5..])).unwrap();
+        assert_eq!(response.to_bytes(), [DATA | 0x80, 0, 2]);
+        assert_eq!(status(&mut receiver, frame(&verify)), Status::Ok);
+        assert!(receiver.is_complete());
+        // A retransmitted verify repeats the final status
+        assert_eq!(status(&mut receiver, frame(&verify)), Status::Ok);
+        assert!(receiver.is_complete());
+
+        let image_storage = receiver.release();
+        assert!(image_storage.committed);
+        assert_eq!(image_storage.data, image);
+    }
+
+    #[test]
+    fn rejects_wrong_image() {
+        let mut receiver = FirmwareReceiver::new(id(0x700), id(0x701), Image::default());
+        assert_eq!(status(&mut receiver, block(0, b"x")), Status::NotStarted);
+        assert_eq!(
+            status(&mut receiver, frame(&[START, 1, 0, 0, 0])),
+            Status::Ok
+        );
+        assert_eq!(status(&mut receiver, block(0, b"xy")), Status::SizeError);
+        assert_eq!(status(&mut receiver, block(0, b"x")), Status::Ok);
+        assert_eq!(
+            status(&mut receiver, frame(&[VERIFY, 0, 0, 0, 0])),
+            Status::VerifyFailed
+        );
+        assert!(!receiver.is_complete());
+        assert_eq!(
+            status(&mut receiver, frame(&[0x42])),
+            Status::InvalidCommand
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Lookup of values by CAN identifier
+//!
+//! Receive handlers often boil down to a large `match` over message IDs.
+//! [`IdMap`] replaces it with a table that is sorted once on construction and
+//! searched in logarithmic time, mapping IDs to arbitrary values such as
+//! handler functions or indexes into application state.
+//!
+//! ```no_run
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::id_map::IdMap;
+//! use mcan::message::rx;
+//!
+//! fn on_speed(message: &rx::Message<8>) {}
+//! fn on_temperature(message: &rx::Message<8>) {}
+//!
+//! let handlers: IdMap<fn(&rx::Message<8>), 2> = IdMap::new([
+//!     (Id::Standard(StandardId::new(0x120).unwrap()), on_speed as fn(&rx::Message<8>)),
+//!     (Id::Standard(StandardId::new(0x100).unwrap()), on_temperature),
+//! ])
+//! .unwrap();
+//!
+//! # let message: rx::Message<8> = unimplemented!();
+//! if let Some(handler) = handlers.dispatch(&message) {
+//!     handler(&message);
+//! }
+//! ```
+
+use crate::message::Raw;
+use embedded_can::Id;
+
+/// Extended IDs are sorted after all standard IDs
+const EXTENDED_FLAG: u32 = 1 << 31;
+
+fn key(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw().into(),
+        Id::Extended(id) => id.as_raw() | EXTENDED_FLAG,
+    }
+}
+
+/// Map from CAN identifiers to values of type `V` with `N` entries
+pub struct IdMap<V, const N: usize> {
+    /// Entries sorted by key
+    entries: [(u32, V); N],
+}
+
+impl<V, const N: usize> IdMap<V, N> {
+    /// Creates a map from `entries`, which may be given in any order. Fails
+    /// with the offending ID if an ID occurs more than once.
+    pub fn new(entries: [(Id, V); N]) -> Result<Self, Id> {
+        let mut entries = entries.map(|(id, value)| (key(id), value));
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        let duplicate = entries.windows(2).find_map(|w| match w {
+            [(a, _), (b, _)] if a == b => Some(*a),
+            _ => None,
+        });
+        if let Some(key) = duplicate {
+            return Err(Self::id(key));
+        }
+        Ok(Self { entries })
+    }
+
+    fn id(key: u32) -> Id {
+        // Safety: Keys are only ever created from valid IDs by `key`.
+        unsafe {
+            if key & EXTENDED_FLAG != 0 {
+                Id::Extended(embedded_can::ExtendedId::new_unchecked(
+                    key & !EXTENDED_FLAG,
+                ))
+            } else {
+                Id::Standard(embedded_can::StandardId::new_unchecked(key as u16))
+            }
+        }
+    }
+
+    /// Returns the value associated with `id`
+    pub fn get(&self, id: Id) -> Option<&V> {
+        let key = key(id);
+        self.entries
+            .binary_search_by_key(&key, |(key, _)| *key)
+            .ok()
+            .and_then(|index| self.entries.get(index))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value associated with `id` mutably
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut V> {
+        let key = key(id);
+        self.entries
+            .binary_search_by_key(&key, |(key, _)| *key)
+            .ok()
+            .and_then(|index| self.entries.get_mut(index))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value associated with the ID of `message`
+    pub fn dispatch<M: Raw>(&self, message: &M) -> Option<&V> {
+        self.get(message.id())
+    }
+
+    /// Returns `true` if the map has a value for `id`
+    pub fn contains(&self, id: Id) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// An iterator over all entries, ordered by ID with standard IDs first
+    pub fn iter(&self) -> impl Iterator<Item = (Id, &V)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (Self::id(*key), value))
+    }
+
+    /// Returns the number of entries
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns `true` if the map has no entries
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::{ExtendedId, StandardId};
+
+    fn standard(id: u16) -> Id {
+        Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    fn extended(id: u32) -> Id {
+        Id::Extended(ExtendedId::new(id).unwrap())
+    }
+
+    #[test]
+    fn finds_values_regardless_of_order() {
+        let map = IdMap::new([
+            (standard(0x300), 3),
+            (extended(0x100), 4),
+            (standard(0x100), 1),
+            (standard(0x200), 2),
+        ])
+        .unwrap();
+        assert_eq!(map.get(standard(0x100)), Some(&1));
+        assert_eq!(map.get(standard(0x200)), Some(&2));
+        assert_eq!(map.get(standard(0x300)), Some(&3));
+        assert_eq!(map.get(extended(0x100)), Some(&4));
+        assert_eq!(map.get(standard(0x101)), None);
+        assert_eq!(map.get(extended(0x300)), None);
+    }
+
+    #[test]
+    fn iterates_standard_ids_first() {
+        let map = IdMap::new([(extended(0x1), 'a'), (standard(0x7ff), 'b')]).unwrap();
+        let mut iter = map.iter();
+        assert_eq!(iter.next(), Some((standard(0x7ff), &'b')));
+        assert_eq!(iter.next(), Some((extended(0x1), &'a')));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let result = IdMap::new([
+            (standard(0x10), 1),
+            (extended(0x10), 2),
+            (standard(0x10), 3),
+        ]);
+        assert_eq!(result.err(), Some(standard(0x10)));
+    }
+}
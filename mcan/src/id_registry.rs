@@ -0,0 +1,294 @@
+//! Registry of the CAN IDs used by application modules
+//!
+//! In larger applications, each module typically declares the messages it
+//! transmits and consumes in its own [`Declaration`] list. An [`IdRegistry`]
+//! checks the combined lists at startup, rejecting IDs transmitted by more
+//! than one module, and generates the RX filters accepting the consumed IDs.
+//!
+//! ```no_run
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::filter::{Action, FiltersExtended, FiltersStandard};
+//! use mcan::id_registry::{Declaration, IdRegistry, Usage};
+//!
+//! fn id(raw: u16) -> Id {
+//!     Id::Standard(StandardId::new(raw).unwrap())
+//! }
+//!
+//! fn setup<P>(standard: &mut FiltersStandard<'_, P>, extended: &mut FiltersExtended<'_, P>) {
+//!     let declarations = [
+//!         Declaration::new("engine", id(0x100), Usage::Transmit),
+//!         Declaration::new("engine", id(0x200), Usage::Receive(Action::StoreFifo0)),
+//!         Declaration::new("dashboard", id(0x100), Usage::Receive(Action::StoreFifo0)),
+//!     ];
+//!     let registry = IdRegistry::new(&declarations).unwrap();
+//!     registry.install_filters(standard, extended).unwrap();
+//! }
+//! ```
+
+use crate::filter::{Action, ExtFilter, Filter, FiltersExtended, FiltersStandard};
+use embedded_can::Id;
+
+/// How a module uses an ID
+#[derive(Copy, Clone)]
+pub enum Usage {
+    /// The module transmits messages with the ID. Only one module may do so.
+    Transmit,
+    /// The module consumes messages with the ID, which are accepted with the
+    /// given action
+    Receive(Action),
+}
+
+/// Use of an ID by a module
+#[derive(Copy, Clone)]
+pub struct Declaration {
+    /// Name of the declaring module, for diagnostics
+    pub owner: &'static str,
+    /// Declared ID
+    pub id: Id,
+    /// How the ID is used
+    pub usage: Usage,
+}
+
+impl Declaration {
+    /// Declares that `owner` uses `id`
+    pub const fn new(owner: &'static str, id: Id, usage: Usage) -> Self {
+        Self { owner, id, usage }
+    }
+
+    fn received(&self) -> Option<(Id, Action)> {
+        match self.usage {
+            Usage::Receive(action) => Some((self.id, action)),
+            Usage::Transmit => None,
+        }
+    }
+}
+
+/// ID transmitted by two modules
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// ID declared twice
+    pub id: Id,
+    /// Module declaring the ID first
+    pub first: &'static str,
+    /// Module declaring the ID again
+    pub second: &'static str,
+}
+
+/// Consumed IDs that conflict in their filter actions, or do not fit into
+/// the filter lists
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    /// The ID is consumed with different actions
+    ConflictingActions(Id),
+    /// The standard ID filter list is full
+    StandardFiltersFull,
+    /// The extended ID filter list is full
+    ExtendedFiltersFull,
+}
+
+/// Checked list of [`Declaration`]s
+pub struct IdRegistry<'d> {
+    declarations: &'d [Declaration],
+}
+
+impl<'d> IdRegistry<'d> {
+    /// Checks `declarations`. Fails with the first ID that is transmitted by
+    /// more than one module.
+    pub fn new(declarations: &'d [Declaration]) -> Result<Self, Conflict> {
+        let transmitted = || {
+            declarations
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| matches!(d.usage, Usage::Transmit))
+        };
+        for (i, first) in transmitted() {
+            if let Some((_, second)) = transmitted().find(|(j, d)| *j > i && d.id == first.id) {
+                return Err(Conflict {
+                    id: first.id,
+                    first: first.owner,
+                    second: second.owner,
+                });
+            }
+        }
+        Ok(Self { declarations })
+    }
+
+    /// Returns the module transmitting `id`
+    pub fn transmitter(&self, id: Id) -> Option<&'static str> {
+        self.declarations
+            .iter()
+            .find(|d| d.id == id && matches!(d.usage, Usage::Transmit))
+            .map(|d| d.owner)
+    }
+
+    /// An iterator over the modules consuming `id`
+    pub fn receivers(&self, id: Id) -> impl Iterator<Item = &'static str> + '_ {
+        self.declarations
+            .iter()
+            .filter(move |d| d.id == id && matches!(d.usage, Usage::Receive(_)))
+            .map(|d| d.owner)
+    }
+
+    /// An iterator over the consumed IDs and their actions, each ID once
+    fn consumed(&self) -> impl Iterator<Item = (Id, Action)> + '_ {
+        self.declarations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, d)| Some((i, d.received()?)))
+            .filter(|(i, (id, _))| {
+                !self
+                    .declarations
+                    .iter()
+                    .take(*i)
+                    .any(|d| d.received().is_some_and(|(other, _)| other == *id))
+            })
+            .map(|(_, consumed)| consumed)
+    }
+
+    /// Checks that each consumed ID has a single action
+    fn check_actions(&self) -> Result<(), FilterError> {
+        for (id, action) in self.consumed() {
+            let conflicting = self.declarations.iter().any(|d| {
+                d.received()
+                    .is_some_and(|(other, a)| other == id && u32::from(a) != u32::from(action))
+            });
+            if conflicting {
+                return Err(FilterError::ConflictingActions(id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pairs consecutive consumed IDs sharing an action into dual filters
+    fn pairs<T: Copy + 'static>(
+        &self,
+        select: fn(Id) -> Option<T>,
+    ) -> impl Iterator<Item = (Action, T, T)> + '_ {
+        let mut consumed = self
+            .consumed()
+            .filter_map(move |(id, action)| Some((select(id)?, action)))
+            .peekable();
+        core::iter::from_fn(move || {
+            let (id1, action) = consumed.next()?;
+            let id2 = consumed
+                .next_if(|(_, next)| u32::from(*next) == u32::from(action))
+                .map_or(id1, |(id2, _)| id2);
+            Some((action, id1, id2))
+        })
+    }
+
+    /// An iterator over the filters accepting the consumed standard IDs
+    pub fn standard_filters(&self) -> impl Iterator<Item = Filter> + '_ {
+        self.pairs(|id| match id {
+            Id::Standard(id) => Some(id),
+            Id::Extended(_) => None,
+        })
+        .map(|(action, id1, id2)| Filter::Dual { action, id1, id2 })
+    }
+
+    /// An iterator over the filters accepting the consumed extended IDs
+    pub fn extended_filters(&self) -> impl Iterator<Item = ExtFilter> + '_ {
+        self.pairs(|id| match id {
+            Id::Extended(id) => Some(id),
+            Id::Standard(_) => None,
+        })
+        .map(|(action, id1, id2)| ExtFilter::Dual { action, id1, id2 })
+    }
+
+    /// Appends the filters accepting all consumed IDs to the filter lists
+    pub fn install_filters<P>(
+        &self,
+        standard: &mut FiltersStandard<'_, P>,
+        extended: &mut FiltersExtended<'_, P>,
+    ) -> Result<(), FilterError> {
+        self.check_actions()?;
+        for filter in self.standard_filters() {
+            standard
+                .push(filter)
+                .map_err(|_| FilterError::StandardFiltersFull)?;
+        }
+        for filter in self.extended_filters() {
+            extended
+                .push(filter)
+                .map_err(|_| FilterError::ExtendedFiltersFull)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::{ExtendedId, StandardId};
+
+    extern crate std;
+    use std::vec::Vec;
+
+    fn standard(id: u16) -> Id {
+        Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    fn extended(id: u32) -> Id {
+        Id::Extended(ExtendedId::new(id).unwrap())
+    }
+
+    #[test]
+    fn rejects_ids_transmitted_twice() {
+        let declarations = [
+            Declaration::new("a", standard(0x10), Usage::Transmit),
+            Declaration::new("b", extended(0x10), Usage::Transmit),
+            Declaration::new("c", standard(0x10), Usage::Receive(Action::StoreFifo0)),
+            Declaration::new("d", standard(0x10), Usage::Transmit),
+        ];
+        let conflict = IdRegistry::new(&declarations).err().unwrap();
+        assert_eq!(
+            conflict,
+            Conflict {
+                id: standard(0x10),
+                first: "a",
+                second: "d"
+            }
+        );
+    }
+
+    #[test]
+    fn pairs_consumed_ids_into_filters() {
+        let declarations = [
+            Declaration::new("a", standard(0x1), Usage::Receive(Action::StoreFifo0)),
+            Declaration::new("b", standard(0x1), Usage::Receive(Action::StoreFifo0)),
+            Declaration::new("b", extended(0x5), Usage::Receive(Action::StoreFifo1)),
+            Declaration::new("a", standard(0x2), Usage::Receive(Action::StoreFifo0)),
+            Declaration::new("c", standard(0x3), Usage::Receive(Action::StoreFifo1)),
+            Declaration::new("c", standard(0x4), Usage::Transmit),
+        ];
+        let registry = IdRegistry::new(&declarations).unwrap();
+        assert_eq!(registry.transmitter(standard(0x4)), Some("c"));
+        assert_eq!(
+            registry.receivers(standard(0x1)).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        let filters: Vec<_> = registry
+            .standard_filters()
+            .map(|f| match f {
+                Filter::Dual { id1, id2, .. } => (id1.as_raw(), id2.as_raw()),
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(filters, [(0x1, 0x2), (0x3, 0x3)]);
+        assert_eq!(registry.extended_filters().count(), 1);
+        assert!(registry.check_actions().is_ok());
+    }
+
+    #[test]
+    fn detects_conflicting_actions() {
+        let declarations = [
+            Declaration::new("a", standard(0x1), Usage::Receive(Action::StoreFifo0)),
+            Declaration::new("b", standard(0x1), Usage::Receive(Action::Reject)),
+        ];
+        let registry = IdRegistry::new(&declarations).unwrap();
+        assert_eq!(
+            registry.check_actions(),
+            Err(FilterError::ConflictingActions(standard(0x1)))
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Scoped access to the protected configuration registers
+//!
+//! Most configuration registers of the peripheral are only writable while
+//! both CCCR.INIT and CCCR.CCE are set. [`InitModeGuard`] sets them on
+//! creation and returns the peripheral to the mode it was in when dropped.
+//! The crate uses it when reconfiguring the peripheral while operating, and
+//! it is available through [`Aux::enter_init_mode`] for vendor specific
+//! registers that require configuration change enable.
+//!
+//! Entering configuration mode drops pending transmissions and empties the
+//! receive FIFOs.
+//!
+//! ```no_run
+//! # use mcan::bus::Aux;
+//! # fn configure<'a, Id: mcan::core::CanId, D: mcan::core::Dependencies<Id>>(aux: &mut Aux<'a, Id, D>) {
+//! let guard = aux.enter_init_mode();
+//! // Safety: Only registers outside of the control of the abstraction are
+//! // written.
+//! let reg = unsafe { guard.registers() };
+//! # let _ = reg;
+//! // Returns to the previous mode
+//! drop(guard);
+//! # }
+//! ```
+//!
+//! [`Aux::enter_init_mode`]: crate::bus::Aux::enter_init_mode
+
+/// Keeps the peripheral in configuration mode, with CCCR.INIT and CCCR.CCE
+/// set, while it exists
+///
+/// When dropped, the peripheral returns to normal operation if it was
+/// operating before. Otherwise it stays in initialization mode with CCE
+/// cleared.
+#[must_use = "configuration mode is left when the guard is dropped"]
+pub struct InitModeGuard<'g, Id: mcan_core::CanId> {
+    reg: &'g crate::reg::Can<Id>,
+    /// Operating mode before entering configuration mode, restored on drop
+    was_operational: bool,
+}
+
+impl<'g, Id: mcan_core::CanId> InitModeGuard<'g, Id> {
+    /// Enters configuration mode
+    pub(crate) fn enter(reg: &'g crate::reg::Can<Id>) -> Self {
+        let was_operational = reg.is_operational();
+        reg.configuration_mode();
+        Self {
+            reg,
+            was_operational,
+        }
+    }
+
+    /// Raw access to the registers.
+    ///
+    /// # Safety
+    /// The abstraction assumes that it has exclusive ownership of the
+    /// registers. Direct access can break such assumptions.
+    pub unsafe fn registers(&self) -> &crate::reg::Can<Id> {
+        self.reg
+    }
+
+    /// Returns `true` if the peripheral returns to normal operation when the
+    /// guard is dropped
+    pub fn was_operational(&self) -> bool {
+        self.was_operational
+    }
+
+    /// Leaves configuration mode. Same as dropping the guard.
+    pub fn exit(self) {
+        // Configuration mode is left on drop
+    }
+}
+
+impl<Id: mcan_core::CanId> Drop for InitModeGuard<'_, Id> {
+    fn drop(&mut self) {
+        if self.was_operational {
+            // CCE is cleared automatically
+            self.reg.operational_mode();
+        } else {
+            self.reg.cccr.modify(|_, w| w.cce().clear_bit());
+        }
+    }
+}
@@ -38,7 +38,7 @@
 //! #     type DedicatedTxBuffers = U0;
 //! #     type TxEventFifo = U32;
 //! # }
-//! # let mut can: Can<'static, Can0, (), Caps> = unsafe { std::mem::transmute([0u8; 176]) };
+//! # let mut can: Can<'static, Can0, (), Caps> = unsafe { std::mem::transmute([0u8; 264]) };
 //! use mcan::interrupt::{Interrupt, InterruptLine};
 //! // During initialization
 //! let enabled_interrupts = can
@@ -88,7 +88,7 @@ pub enum InterruptLine {
 
 bitfield! {
     /// A set of CAN interrupts.
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, PartialEq, Eq)]
     pub struct InterruptSet(u32);
 
     /// Access to Reserved Address
@@ -175,6 +175,18 @@ impl From<Interrupt> for InterruptSet {
     }
 }
 
+impl<const N: usize> From<[Interrupt; N]> for InterruptSet {
+    fn from(value: [Interrupt; N]) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl From<&[Interrupt]> for InterruptSet {
+    fn from(value: &[Interrupt]) -> Self {
+        value.iter().copied().collect()
+    }
+}
+
 impl core::fmt::Debug for InterruptSet {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "InterruptSet {{ ")?;
@@ -273,7 +285,7 @@ impl core::fmt::Debug for InterruptSet {
 }
 
 /// A single interrupt.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Interrupt {
     /// RF0N
     RxFifo0NewMessage = 0,
@@ -401,6 +413,35 @@ impl InterruptSet {
     pub fn is_empty(&self) -> bool {
         self.0 == 0
     }
+
+    /// Returns `true` if `interrupt` is in the set
+    pub fn contains(&self, interrupt: Interrupt) -> bool {
+        self.0 & u32::from(interrupt) != 0
+    }
+
+    /// Returns the number of interrupts in the set
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns the interrupts in ascending order of their numbers, or `None`
+    /// unless the set contains exactly `N` of them
+    pub fn to_array<const N: usize>(&self) -> Option<[Interrupt; N]> {
+        if self.len() != N {
+            return None;
+        }
+        let mut interrupts = [Interrupt::RxFifo0NewMessage; N];
+        for (slot, interrupt) in interrupts.iter_mut().zip(self.iter()) {
+            *slot = interrupt;
+        }
+        Some(interrupts)
+    }
+
+    /// Returns the interrupts in ascending order of their numbers
+    #[cfg(feature = "heapless")]
+    pub fn to_vec(&self) -> heapless::Vec<Interrupt, 30> {
+        self.iter().collect()
+    }
 }
 
 /// An iterator over the items of an [`InterruptSet`].
@@ -448,6 +489,34 @@ impl<Id: mcan_core::CanId, State> Default for OwnedInterruptSet<Id, State> {
     }
 }
 
+/// Ownership, enable state and line of the interrupts of an
+/// [`OwnedInterruptSet`], recorded by [`InterruptConfiguration::snapshot`]
+///
+/// Snapshots can be kept across a reinitialization of the peripheral, after
+/// which [`InterruptConfiguration::restore`] recreates the set from the new
+/// pool of interrupts.
+#[derive(Copy, Clone)]
+pub struct InterruptSnapshot {
+    interrupts: InterruptSet,
+    line_0: InterruptSet,
+    line_1: InterruptSet,
+}
+
+impl InterruptSnapshot {
+    /// Interrupts of the recorded set
+    pub fn interrupts(&self) -> InterruptSet {
+        self.interrupts
+    }
+
+    /// Interrupts of the recorded set that were enabled on `line`
+    pub fn enabled(&self, line: InterruptLine) -> InterruptSet {
+        match line {
+            InterruptLine::Line0 => self.line_0,
+            InterruptLine::Line1 => self.line_1,
+        }
+    }
+}
+
 /// An input [`InterruptSet`] contained interrupts that were not available. The
 /// set wrapped in the error indicates which elements caused the problem.
 #[derive(Debug)]
@@ -502,6 +571,36 @@ impl<Id: mcan_core::CanId, State> OwnedInterruptSet<Id, State> {
         unsafe { Self::new(InterruptSet(split_out)) }
     }
 
+    /// Disables the interrupts of this set that are enabled until the returned
+    /// guard is dropped, which enables them again on their lines
+    ///
+    /// Meant for short critical sections that must not be interrupted by the
+    /// handlers of these interrupts, while other interrupts of the peripheral
+    /// and of the system stay enabled. Flags raised in the meantime remain
+    /// pending and trigger their interrupts after the guard is dropped.
+    ///
+    /// ```no_run
+    /// # use mcan::interrupt::{InterruptConfiguration, OwnedInterruptSet};
+    /// # fn f<Id: mcan_core::CanId>(
+    /// #     configuration: &mut InterruptConfiguration<Id>,
+    /// #     rx_interrupts: &OwnedInterruptSet<Id>,
+    /// # ) {
+    /// let _masked = rx_interrupts.masked(configuration);
+    /// // Shared state of the RX interrupt handler can be accessed here
+    /// # }
+    /// ```
+    pub fn masked<'a>(
+        &self,
+        configuration: &'a mut InterruptConfiguration<Id>,
+    ) -> MaskedInterrupts<'a, Id> {
+        let enabled = configuration.ie & self.0 .0;
+        configuration.set_enabled(enabled, false);
+        MaskedInterrupts {
+            configuration,
+            enabled,
+        }
+    }
+
     /// Internal function that allows conversions from any state to any state.
     ///
     /// # Safety
@@ -521,12 +620,47 @@ impl<Id: mcan_core::CanId, State: state::MaybeEnabled> OwnedInterruptSet<Id, Sta
 
     /// Clears the flagged interrupts owned by this `OwnedInterruptSet` and
     /// provides an iterator over the flags that were cleared.
+    ///
+    /// Only the flags that were read are cleared, so a flag raised in the
+    /// meantime is not lost but triggers the interrupt again. If the handler
+    /// is preempted by itself between the read and the clear, e.g. because
+    /// the line is pended again on an interrupt controller that allows it,
+    /// the nested handler may observe the same flags and both handle them.
+    /// Handlers that must see each flag exactly once, or that need to clear
+    /// the flags only after handling them, use [`Self::take_flags`] and
+    /// [`Self::clear`] instead.
     pub fn iter_flagged(&self) -> Iter {
         let interrupts = self.interrupt_flags();
         self.clear_interrupts(interrupts);
         interrupts.iter()
     }
 
+    /// Reads the flagged interrupts owned by this set without clearing them.
+    /// They remain raised until the returned flags are passed to
+    /// [`Self::clear`], which lets the handler choose the point at which the
+    /// interrupt may be raised again.
+    ///
+    /// ```no_run
+    /// # use mcan::interrupt::{Interrupt, OwnedInterruptSet};
+    /// # fn handle<Id: mcan_core::CanId>(interrupts: &OwnedInterruptSet<Id>) {
+    /// let flags = interrupts.take_flags();
+    /// for interrupt in flags.iter() {
+    ///     // Drain the FIFO before the flag is cleared
+    /// #   let _ = interrupt;
+    /// }
+    /// interrupts.clear(flags);
+    /// # }
+    /// ```
+    pub fn take_flags(&self) -> TakenFlags {
+        TakenFlags(self.interrupt_flags())
+    }
+
+    /// Clears the interrupts read by [`Self::take_flags`]. Flags raised since
+    /// are kept.
+    pub fn clear(&self, flags: TakenFlags) {
+        self.clear_interrupts(flags.0);
+    }
+
     /// Get the subset of interrupts in this set that are currently flagged.
     pub fn interrupt_flags(&self) -> InterruptSet {
         // Safety: The mask ensures that only flags under our control are returned.
@@ -555,8 +689,67 @@ impl<Id: mcan_core::CanId, State: state::MaybeEnabled> OwnedInterruptSet<Id, Sta
     }
 }
 
+/// Interrupt flags read by [`OwnedInterruptSet::take_flags`] and not yet
+/// cleared
+#[must_use = "the flags stay raised until passed to `OwnedInterruptSet::clear`"]
+pub struct TakenFlags(InterruptSet);
+
+impl TakenFlags {
+    /// Returns the flagged interrupts
+    pub fn interrupts(&self) -> InterruptSet {
+        self.0
+    }
+
+    /// Iterates over the flagged interrupts
+    pub fn iter(&self) -> Iter {
+        self.0.iter()
+    }
+
+    /// Returns `true` if no interrupt was flagged
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Guard keeping interrupts disabled, see [`OwnedInterruptSet::masked`]
+///
+/// Borrows the [`InterruptConfiguration`], so that no interrupts are
+/// reconfigured before they are restored.
+#[must_use = "the interrupts are enabled again when the guard is dropped"]
+pub struct MaskedInterrupts<'a, Id: mcan_core::CanId> {
+    configuration: &'a mut InterruptConfiguration<Id>,
+    enabled: u32,
+}
+
+impl<Id: mcan_core::CanId> MaskedInterrupts<'_, Id> {
+    /// Returns the interrupts that were disabled and are enabled again on drop
+    pub fn interrupts(&self) -> InterruptSet {
+        InterruptSet(self.enabled)
+    }
+}
+
+impl<Id: mcan_core::CanId> Drop for MaskedInterrupts<'_, Id> {
+    fn drop(&mut self) {
+        // The lines in ILS were left untouched
+        self.configuration.set_enabled(self.enabled, true);
+    }
+}
+
 /// Controls enabling and line selection of interrupts.
-pub struct InterruptConfiguration<P>(PhantomData<P>);
+///
+/// The contents of IE, ILS and ILE are mirrored in software. Since this type
+/// owns the registers, the mirrors are always up to date and changes are
+/// applied by writing absolute values instead of read-modify-write cycles. If
+/// the registers were changed behind its back, e.g. through
+/// [`Can::registers`], use [`Self::resync`].
+///
+/// [`Can::registers`]: crate::bus::Can::registers
+pub struct InterruptConfiguration<P> {
+    ie: u32,
+    ils: u32,
+    ile: u32,
+    _marker: PhantomData<P>,
+}
 
 impl<Id: mcan_core::CanId> InterruptConfiguration<Id> {
     /// Enable interrupts contained in an `interrupt` or switch them to the line
@@ -601,12 +794,59 @@ impl<Id: mcan_core::CanId> InterruptConfiguration<Id> {
     ) -> OwnedInterruptSet<Id, state::Disabled> {
         // Convert to `Dynamic` for HW calls
         // Safety: A `Dynamic` set can contain interrupts in any state
-        let interrupt = unsafe { interrupt.convert() };
-        self.set_enabled(&interrupt, false);
+        let interrupt: OwnedInterruptSet<Id> = unsafe { interrupt.convert() };
+        self.set_enabled(interrupt.0 .0, false);
         // Safety: Interrupt was disabled so type state is `Disabled`
         unsafe { interrupt.convert() }
     }
 
+    /// Moves the interrupts described by `subset` out of `from` and enables
+    /// them on the line 0. If `from` does not contain `subset`, an error is
+    /// returned and `from` is left unchanged.
+    pub fn enable_line_0_from<State>(
+        &mut self,
+        from: &mut OwnedInterruptSet<Id, State>,
+        subset: InterruptSet,
+    ) -> Result<OwnedInterruptSet<Id, state::EnabledLine0>, MaskError> {
+        Ok(self.enable_line_0(from.split(subset)?))
+    }
+
+    /// Moves the interrupts described by `subset` out of `from` and enables
+    /// them on the line 1. If `from` does not contain `subset`, an error is
+    /// returned and `from` is left unchanged.
+    pub fn enable_line_1_from<State>(
+        &mut self,
+        from: &mut OwnedInterruptSet<Id, State>,
+        subset: InterruptSet,
+    ) -> Result<OwnedInterruptSet<Id, state::EnabledLine1>, MaskError> {
+        Ok(self.enable_line_1(from.split(subset)?))
+    }
+
+    /// Moves the interrupts described by `subset` out of `from` and enables
+    /// them on the specified `line`. If `from` does not contain `subset`, an
+    /// error is returned and `from` is left unchanged.
+    ///
+    /// Returned set is in a dynamic state.
+    pub fn enable_from<State>(
+        &mut self,
+        from: &mut OwnedInterruptSet<Id, State>,
+        subset: InterruptSet,
+        line: InterruptLine,
+    ) -> Result<OwnedInterruptSet<Id>, MaskError> {
+        Ok(self.enable(from.split(subset)?, line))
+    }
+
+    /// Moves the interrupts described by `subset` out of `from` and disables
+    /// them. If `from` does not contain `subset`, an error is returned and
+    /// `from` is left unchanged.
+    pub fn disable_from<State>(
+        &mut self,
+        from: &mut OwnedInterruptSet<Id, State>,
+        subset: InterruptSet,
+    ) -> Result<OwnedInterruptSet<Id, state::Disabled>, MaskError> {
+        Ok(self.disable(from.split(subset)?))
+    }
+
     /// # Safety
     /// Caller must make sure that the type state matches the selected `line`.
     unsafe fn raw_enable<In, Out: state::MaybeEnabled>(
@@ -616,9 +856,9 @@ impl<Id: mcan_core::CanId> InterruptConfiguration<Id> {
     ) -> OwnedInterruptSet<Id, Out> {
         // Convert to `Dynamic` for HW calls
         // Safety: A `Dynamic` set can contain interrupts in any state
-        let interrupt = unsafe { interrupt.convert() };
-        self.set_line(&interrupt, line);
-        self.set_enabled(&interrupt, true);
+        let interrupt: OwnedInterruptSet<Id> = unsafe { interrupt.convert() };
+        self.set_line(interrupt.0 .0, line);
+        self.set_enabled(interrupt.0 .0, true);
         // Safety: Interrupt was enabled but type state is yet to be determined
         unsafe { interrupt.convert() }
     }
@@ -633,9 +873,16 @@ impl<Id: mcan_core::CanId> InterruptConfiguration<Id> {
     /// - IR
     pub(crate) unsafe fn new() -> (Self, OwnedInterruptSet<Id, state::Disabled>) {
         const RESERVED_BITS: u32 = 0x3fff_ffff;
-        let v = Self(PhantomData);
-        // Disable all interrupts on the peripheral by writing the reset value.
+        let v = Self {
+            ie: 0,
+            ils: 0,
+            ile: 0,
+            _marker: PhantomData,
+        };
+        // Disable all interrupts on the peripheral by writing the reset values.
+        v.ie().write(|w| w);
         v.ils().write(|w| w);
+        v.ile().write(|w| w);
         // Safety: The reserved bits are omitted and interrupts are disabled
         // and thus the state is correct.
         (v, unsafe {
@@ -658,37 +905,118 @@ impl<Id: mcan_core::CanId> InterruptConfiguration<Id> {
         &unsafe { &*Id::register_block() }.ie
     }
 
+    /// Reloads the software copies of IE, ILS and ILE from the peripheral.
+    ///
+    /// Only needed if the registers have been modified by other means than
+    /// this type.
+    pub fn resync(&mut self) {
+        self.ie = self.ie().read().bits();
+        self.ils = self.ils().read().bits();
+        self.ile = self.ile().read().bits();
+    }
+
+    /// Writes the software copies of IE, ILS and ILE to the peripheral.
+    ///
+    /// Needed if the registers have been reset behind the back of this type,
+    /// e.g. by a reset of the peripheral, to bring them in line with the
+    /// states of the existing [`OwnedInterruptSet`]s again.
+    pub fn reapply(&mut self) {
+        // Safety: The reserved bits are 0 by type invariant on `OwnedInterruptSet`,
+        // and only EINT0 and EINT1 are ever set in ILE.
+        unsafe {
+            self.ie().write(|w| w.bits(self.ie));
+            self.ils().write(|w| w.bits(self.ils));
+            self.ile().write(|w| w.bits(self.ile));
+        }
+    }
+
+    /// Disables both interrupt lines, e.g. while the node sleeps, keeping the
+    /// interrupts enabled on them. Undone by [`Self::unmask_lines`] or
+    /// [`Self::reapply`].
+    pub fn mask_lines(&mut self) {
+        self.ile().write(|w| w);
+    }
+
+    /// Enables the interrupt lines disabled by [`Self::mask_lines`] again
+    pub fn unmask_lines(&mut self) {
+        // Safety: Only EINT0 and EINT1 are ever set.
+        self.ile().write(|w| unsafe { w.bits(self.ile) });
+    }
+
+    /// Records which interrupts `set` owns and on which lines they are
+    /// enabled, to [`Self::restore`] the set later
+    pub fn snapshot<State>(&self, set: &OwnedInterruptSet<Id, State>) -> InterruptSnapshot {
+        let enabled = self.ie & set.0 .0;
+        InterruptSnapshot {
+            interrupts: set.0,
+            line_0: InterruptSet(enabled & !self.ils),
+            line_1: InterruptSet(enabled & self.ils),
+        }
+    }
+
+    /// Moves the interrupts recorded in `snapshot` out of `pool` and enables
+    /// them on the recorded lines. Fails if `pool` does not contain all of
+    /// them.
+    ///
+    /// Intended for restoring the interrupt sets of the application after the
+    /// peripheral has been reinitialized from scratch. The returned set is in
+    /// a dynamic state; enabling it on its line again converts it to a static
+    /// state without touching the registers.
+    pub fn restore(
+        &mut self,
+        pool: &mut OwnedInterruptSet<Id, state::Disabled>,
+        snapshot: &InterruptSnapshot,
+    ) -> Result<OwnedInterruptSet<Id>, MaskError> {
+        let interrupts = pool.split(snapshot.interrupts)?;
+        for line in [InterruptLine::Line0, InterruptLine::Line1] {
+            let mask = snapshot.enabled(line).0 & snapshot.interrupts.0;
+            if mask != 0 {
+                self.set_line(mask, line);
+                self.set_enabled(mask, true);
+            }
+        }
+        Ok(interrupts.into())
+    }
+
     /// Set the interrupt line that will trigger for a set of peripheral
     /// interrupts.
-    fn set_line(&mut self, interrupts: &OwnedInterruptSet<Id>, line: InterruptLine) {
+    fn set_line(&mut self, mask: u32, line: InterruptLine) {
         self.enable_line(line);
-        let mask = interrupts.0 .0;
-        // Safety: The reserved bits are 0 by type invariant on `OwnedInterruptSet`.
-        self.ils().modify(|r, w| unsafe {
-            w.bits(match line {
-                InterruptLine::Line0 => r.bits() & !mask,
-                InterruptLine::Line1 => r.bits() | mask,
-            })
-        });
+        let ils = match line {
+            InterruptLine::Line0 => self.ils & !mask,
+            InterruptLine::Line1 => self.ils | mask,
+        };
+        if ils != self.ils {
+            self.ils = ils;
+            // Safety: The reserved bits are 0 by type invariant on `OwnedInterruptSet`.
+            self.ils().write(|w| unsafe { w.bits(ils) });
+        }
     }
 
     fn enable_line(&mut self, line: InterruptLine) {
-        self.ile().modify(|_, w| match line {
-            InterruptLine::Line0 => w.eint0().set_bit(),
-            InterruptLine::Line1 => w.eint1().set_bit(),
-        });
-    }
-
-    fn set_enabled(&mut self, interrupts: &OwnedInterruptSet<Id>, enabled: bool) {
-        let mask = interrupts.0 .0;
-        // Safety: The reserved bits are 0 by type invariant on `OwnedInterruptSet`.
-        self.ie().modify(|r, w| unsafe {
-            w.bits(if enabled {
-                r.bits() | mask
-            } else {
-                r.bits() & !mask
-            })
-        });
+        let ile = self.ile
+            | match line {
+                InterruptLine::Line0 => 1 << 0,
+                InterruptLine::Line1 => 1 << 1,
+            };
+        if ile != self.ile {
+            self.ile = ile;
+            // Safety: Only EINT0 and EINT1 are ever set.
+            self.ile().write(|w| unsafe { w.bits(ile) });
+        }
+    }
+
+    fn set_enabled(&mut self, mask: u32, enabled: bool) {
+        let ie = if enabled {
+            self.ie | mask
+        } else {
+            self.ie & !mask
+        };
+        if ie != self.ie {
+            self.ie = ie;
+            // Safety: The reserved bits are 0 by type invariant on `OwnedInterruptSet`.
+            self.ie().write(|w| unsafe { w.bits(ie) });
+        }
     }
 }
 
@@ -722,4 +1050,23 @@ mod test {
     fn iter_collect_drops_reserved_bits() {
         assert_eq!(iter_collect(0xffff_ffff), 0x3fff_ffff);
     }
+
+    #[test]
+    fn array_round_trip() {
+        let interrupts = [Interrupt::BusOff, Interrupt::RxFifo0NewMessage];
+        let set = InterruptSet::from(interrupts);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(Interrupt::BusOff));
+        assert!(!set.contains(Interrupt::ErrorPassive));
+        assert_eq!(
+            set.to_array(),
+            Some([Interrupt::RxFifo0NewMessage, Interrupt::BusOff])
+        );
+        assert_eq!(set.to_array::<3>(), None);
+        assert_eq!(
+            InterruptSet::from(&interrupts[..1]),
+            Interrupt::BusOff.into()
+        );
+        assert_eq!(InterruptSet(0xffff_ffff).len(), 30);
+    }
 }
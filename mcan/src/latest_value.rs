@@ -0,0 +1,251 @@
+//! Most recent payload per CAN identifier
+//!
+//! Control loops are often only interested in the current value of a signal,
+//! not in the history of frames carrying it. A [`LatestValueCache`] is updated
+//! from the RX path and keeps the most recent payload and timestamp for each
+//! configured ID. Reads are lock-free: readers and the writer never wait for
+//! each other, and a reader retries if it raced with an update of the entry it
+//! reads. Each entry is double-buffered, so a reader preempting the writer
+//! reads the previous value instead of waiting for the write to complete.
+//!
+//! The cache is [`split`] into a single [`Writer`], typically owned by the
+//! interrupt handler, and any number of [`Reader`]s.
+//!
+//! ```no_run
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::latest_value::LatestValueCache;
+//! use mcan::message::rx;
+//!
+//! let speed = Id::Standard(StandardId::new(0x120).unwrap());
+//! let mut cache = LatestValueCache::<8, 1>::new([speed]).unwrap();
+//! let (mut writer, reader) = cache.split();
+//!
+//! # let message: rx::Message<8> = unimplemented!();
+//! // On reception
+//! writer.update(&message);
+//!
+//! // In the control loop
+//! if let Some(value) = reader.get(speed) {
+//!     let _payload = value.data();
+//! }
+//! ```
+//!
+//! [`split`]: LatestValueCache::split
+
+use crate::id_map::IdMap;
+use crate::message::rx;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+use embedded_can::Id;
+
+/// Most recent payload of an ID with up to `L` bytes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Value<const L: usize> {
+    data: [u8; L],
+    len: usize,
+    timestamp: u16,
+}
+
+impl<const L: usize> Value<L> {
+    /// Payload of the frame
+    pub fn data(&self) -> &[u8] {
+        self.data.get(..self.len).unwrap_or_default()
+    }
+
+    /// Timestamp of the frame, see [`rx::AnyMessage::timestamp`]
+    pub fn timestamp(&self) -> u16 {
+        self.timestamp
+    }
+}
+
+struct Slot<const L: usize> {
+    /// Number of completed writes, 0 if never written. The most recent value
+    /// is in `values[sequence % 2]`.
+    sequence: AtomicU32,
+    values: [UnsafeCell<Value<L>>; 2],
+}
+
+impl<const L: usize> Slot<L> {
+    /// Buffer holding the value written with `sequence`
+    fn buffer(&self, sequence: u32) -> *mut Value<L> {
+        let [even, odd] = &self.values;
+        if sequence & 1 == 0 { even } else { odd }.get()
+    }
+
+    /// Reads the value of the latest completed write, along with its sequence
+    /// number to be checked with [`Self::unchanged`]. `None` if the slot was
+    /// never written.
+    fn read(&self) -> Option<(u32, Value<L>)> {
+        let sequence = self.sequence.load(Ordering::Acquire);
+        if sequence == 0 {
+            return None;
+        }
+        // Safety: The buffer is only written after the sequence number has
+        // moved on, which is detected by `unchanged`.
+        let value = unsafe { core::ptr::read_volatile(self.buffer(sequence)) };
+        Some((sequence, value))
+    }
+
+    /// Returns `true` if no write started since the value with `sequence` was
+    /// read, so that it is not torn
+    fn unchanged(&self, sequence: u32) -> bool {
+        fence(Ordering::Acquire);
+        self.sequence.load(Ordering::Relaxed) == sequence
+    }
+}
+
+/// Cache of the most recent payloads with up to `L` bytes of `N` IDs
+pub struct LatestValueCache<const L: usize, const N: usize> {
+    slots: IdMap<Slot<L>, N>,
+}
+
+// Safety: Values are only written by the unique `Writer` and readers detect
+// concurrent writes by the sequence number of the slot.
+unsafe impl<const L: usize, const N: usize> Sync for LatestValueCache<L, N> {}
+
+impl<const L: usize, const N: usize> LatestValueCache<L, N> {
+    /// Creates a cache for `ids`. Fails with the offending ID if an ID occurs
+    /// more than once.
+    pub fn new(ids: [Id; N]) -> Result<Self, Id> {
+        let slots = IdMap::new(ids.map(|id| {
+            (
+                id,
+                Slot {
+                    sequence: AtomicU32::new(0),
+                    values: [const {
+                        UnsafeCell::new(Value {
+                            data: [0; L],
+                            len: 0,
+                            timestamp: 0,
+                        })
+                    }; 2],
+                },
+            )
+        }))?;
+        Ok(Self { slots })
+    }
+
+    /// Splits the cache into its writing and reading ends. The reader can be
+    /// copied freely.
+    pub fn split(&mut self) -> (Writer<'_, L, N>, Reader<'_, L, N>) {
+        let cache = &*self;
+        (Writer { cache }, Reader { cache })
+    }
+}
+
+/// Writing end of a [`LatestValueCache`]
+pub struct Writer<'c, const L: usize, const N: usize> {
+    cache: &'c LatestValueCache<L, N>,
+}
+
+impl<'c, const L: usize, const N: usize> Writer<'c, L, N> {
+    /// Stores the payload of `message` if its ID is cached. Payloads longer
+    /// than `L` bytes are truncated. Returns `false` if the ID is not cached.
+    pub fn update<M: rx::AnyMessage>(&mut self, message: &M) -> bool {
+        self.store(message.id(), message.data(), message.timestamp())
+    }
+
+    fn store(&mut self, id: Id, data: &[u8], timestamp: u16) -> bool {
+        let Some(slot) = self.cache.slots.get(id) else {
+            return false;
+        };
+        let mut value = Value {
+            data: [0; L],
+            len: data.len().min(L),
+            timestamp,
+        };
+        for (to, from) in value.data.iter_mut().zip(data) {
+            *to = *from;
+        }
+
+        // Skip 0 on wraparound, it marks slots that were never written. The
+        // parity still alternates since `u32::MAX` is odd.
+        let sequence = match slot.sequence.load(Ordering::Relaxed).wrapping_add(1) {
+            0 => 2,
+            next => next,
+        };
+        // The previous sequence number must be visible before the buffer is
+        // overwritten, so that a reader still holding the sequence number of
+        // the value written before it detects the write.
+        fence(Ordering::Release);
+        // Safety: The writer is unique. Readers of the buffer being written
+        // detect the write by the changed sequence number and retry.
+        unsafe {
+            core::ptr::write_volatile(slot.buffer(sequence), value);
+        }
+        slot.sequence.store(sequence, Ordering::Release);
+        true
+    }
+}
+
+/// Reading end of a [`LatestValueCache`]
+#[derive(Copy, Clone)]
+pub struct Reader<'c, const L: usize, const N: usize> {
+    cache: &'c LatestValueCache<L, N>,
+}
+
+impl<'c, const L: usize, const N: usize> Reader<'c, L, N> {
+    /// Returns the most recent value of `id`, or `None` if the ID is not
+    /// cached or no frame with the ID has been received yet
+    pub fn get(&self, id: Id) -> Option<Value<L>> {
+        let slot = self.cache.slots.get(id)?;
+        loop {
+            let (sequence, value) = slot.read()?;
+            if slot.unchanged(sequence) {
+                return Some(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::StandardId;
+
+    fn id(id: u16) -> Id {
+        Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    #[test]
+    fn keeps_latest_value_per_id() {
+        let mut cache = LatestValueCache::<4, 2>::new([id(0x10), id(0x20)]).unwrap();
+        let (mut writer, reader) = cache.split();
+        assert_eq!(reader.get(id(0x10)), None);
+        assert!(writer.store(id(0x10), &[1, 2], 100));
+        assert!(writer.store(id(0x10), &[3, 4, 5, 6, 7], 200));
+        assert!(!writer.store(id(0x30), &[1], 300));
+        let value = reader.get(id(0x10)).unwrap();
+        assert_eq!(value.data(), [3, 4, 5, 6]);
+        assert_eq!(value.timestamp(), 200);
+        assert_eq!(reader.get(id(0x20)), None);
+        assert_eq!(reader.get(id(0x30)), None);
+    }
+
+    #[test]
+    fn stalled_reader_detects_overwritten_buffer() {
+        let mut cache = LatestValueCache::<2, 1>::new([id(0x10)]).unwrap();
+        let (mut writer, reader) = cache.split();
+        writer.store(id(0x10), &[1, 1], 1);
+        let slot = cache_slot(&reader, id(0x10));
+        let (sequence, value) = slot.read().unwrap();
+        assert_eq!(value.data(), [1, 1]);
+        // The reader stalls after reading the sequence number. The first write
+        // goes to the other buffer, the second one to the buffer being read.
+        writer.store(id(0x10), &[2, 2], 2);
+        assert!(!slot.unchanged(sequence));
+        writer.store(id(0x10), &[3, 3], 3);
+        // Safety: Single threaded test
+        let torn = unsafe { core::ptr::read_volatile(slot.buffer(sequence)) };
+        assert_eq!(torn.data(), [3, 3]);
+        assert!(!slot.unchanged(sequence));
+        assert_eq!(reader.get(id(0x10)).unwrap().data(), [3, 3]);
+    }
+
+    fn cache_slot<'c, const L: usize, const N: usize>(
+        reader: &Reader<'c, L, N>,
+        id: Id,
+    ) -> &'c Slot<L> {
+        reader.cache.slots.get(id).unwrap()
+    }
+}
@@ -1,5 +1,17 @@
 #![no_std]
 #![warn(missing_docs)]
+#![cfg_attr(
+    all(feature = "no-panic", not(test)),
+    deny(
+        clippy::indexing_slicing,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
 //! # MCAN
 //!
 //! ## Overview
@@ -100,6 +112,13 @@
 //! static mut MESSAGE_RAM: SharedMemory<Capacities> = SharedMemory::new();
 //! ```
 //!
+//! A mis-sized linker section or faulty RAM can be detected during bring-up
+//! with [`SharedMemory::self_test`](crate::messageram::SharedMemory::self_test).
+//!
+//! The [`mcan_message_ram`] macro declares such a static and hands out a
+//! reference to it once, avoiding the unsafe access to the `static mut`:
+//! `let memory = mcan_message_ram!(Capacities in ".can").unwrap();`
+//!
 //! When it comes to the [`RTIC`] framework, suggested way of setting the shared
 //! memory up would be to use task-local resource in an `init` task. Reference
 //! to a task-local resource in an `init` has a static lifetime which is
@@ -250,9 +269,129 @@
 //! let tx = can.tx;
 //! let tx_event_fifo = can.tx_event_fifo;
 //! let aux = can.aux;
+//! let monitor = can.monitor;
+//! ```
+//!
+//! ## Panics
+//!
+//! The library does not panic in release builds. Failures are reported by
+//! returned errors, and conditions that cannot occur with a correctly
+//! configured peripheral, like out of range indices read from it, are handled
+//! without panicking. The `no-panic` feature turns this into a checked
+//! guarantee: it denies indexing, slicing, `unwrap`, `expect` and the
+//! panicking macros in the library, so that `cargo clippy --features
+//! no-panic` fails if a panicking path is introduced. Debug assertions and
+//! overflow checks of debug builds are not affected, nor are the unreachable
+//! arms of the generated register readers in [`reg`].
+//!
+//! ## Concurrency
+//!
+//! The parts of a split [`Can`] access disjoint registers and message RAM
+//! sections and share no mutable state, so each of them can be moved to a
+//! different task, interrupt handler or core. All parts are [`Send`]
+//! (provided the `Dependencies` are, in the case of [`Aux`]). Registers
+//! touched by several parts, like the interrupt flags, are only written with
+//! write-one-to-clear or write-one-to-set semantics and never read-modify-
+//! written.
+//!
+//! Apart from the [`Monitor`], whose statistics are atomic, the parts are not
+//! [`Sync`]: they keep statistics such as high water marks in [`Cell`]s.
+//! Sharing them between contexts requires a mutex.
+//!
+//! Message RAM accesses are ordered against the register accesses that hand
+//! elements over to or back from the peripheral by memory barriers, so the
+//! parts also work from several cores of a multi-core MCU. The Message RAM
+//! must be non-cacheable (or otherwise coherent) for all cores.
+//!
+//! Shared state like the [`stats`], the [`frame_pool`] and the
+//! [`rx_dedicated_buffers::RxBufferNotifier`] is updated with atomic
+//! read-modify-write operations from [`portable_atomic`]. Targets without
+//! native support for them, like `thumbv6m`, need the `critical-section`
+//! feature, which implements them with a critical section provided by the
+//! [`critical-section`](https://docs.rs/critical-section) crate, or have to
+//! be built with `--cfg portable_atomic_unsafe_assume_single_core`.
+//!
+//! ```
+//! # use mcan::generic_array::typenum::consts::*;
+//! # use mcan::message::{tx, rx};
+//! # struct Capacities;
+//! # impl mcan::messageram::Capacities for Capacities {
+//! #     type StandardFilters = U128;
+//! #     type ExtendedFilters = U64;
+//! #     type RxBufferMessage = rx::Message<64>;
+//! #     type DedicatedRxBuffers = U64;
+//! #     type RxFifo0Message = rx::Message<64>;
+//! #     type RxFifo0 = U64;
+//! #     type RxFifo1Message = rx::Message<64>;
+//! #     type RxFifo1 = U64;
+//! #     type TxMessage = tx::Message<64>;
+//! #     type TxBuffers = U32;
+//! #     type DedicatedTxBuffers = U0;
+//! #     type TxEventFifo = U32;
+//! # }
+//! # struct Can0;
+//! # unsafe impl mcan::core::CanId for Can0 {
+//! #     const ADDRESS: *const () = 0xDEAD0000 as *const _;
+//! # }
+//! use mcan::bus::Aux;
+//! use mcan::interrupt::{state, InterruptConfiguration, OwnedInterruptSet};
+//! use mcan::monitor::Monitor;
+//! use mcan::rx_dedicated_buffers::RxDedicatedBuffer;
+//! use mcan::rx_fifo::{Fifo0, Fifo1, RxFifo};
+//! use mcan::tx_buffers::Tx;
+//! use mcan::tx_event_fifo::TxEventFifo;
+//!
+//! fn assert_send<T: Send>() {}
+//!
+//! assert_send::<InterruptConfiguration<Can0>>();
+//! assert_send::<OwnedInterruptSet<Can0, state::EnabledLine0>>();
+//! assert_send::<RxFifo<'static, Fifo0, Can0, rx::Message<64>>>();
+//! assert_send::<RxFifo<'static, Fifo1, Can0, rx::Message<64>>>();
+//! assert_send::<RxDedicatedBuffer<'static, Can0, rx::Message<64>>>();
+//! assert_send::<Tx<'static, Can0, Capacities>>();
+//! assert_send::<TxEventFifo<'static, Can0>>();
+//! assert_send::<Aux<'static, Can0, ()>>();
+//! assert_send::<Monitor<Can0>>();
+//!
+//! fn assert_sync<T: Sync>() {}
+//!
+//! assert_sync::<Monitor<Can0>>();
+//! ```
+//!
+//! ```compile_fail
+//! # use mcan::generic_array::typenum::consts::*;
+//! # use mcan::message::{tx, rx};
+//! # struct Capacities;
+//! # impl mcan::messageram::Capacities for Capacities {
+//! #     type StandardFilters = U128;
+//! #     type ExtendedFilters = U64;
+//! #     type RxBufferMessage = rx::Message<64>;
+//! #     type DedicatedRxBuffers = U64;
+//! #     type RxFifo0Message = rx::Message<64>;
+//! #     type RxFifo0 = U64;
+//! #     type RxFifo1Message = rx::Message<64>;
+//! #     type RxFifo1 = U64;
+//! #     type TxMessage = tx::Message<64>;
+//! #     type TxBuffers = U32;
+//! #     type DedicatedTxBuffers = U0;
+//! #     type TxEventFifo = U32;
+//! # }
+//! # struct Can0;
+//! # unsafe impl mcan::core::CanId for Can0 {
+//! #     const ADDRESS: *const () = 0xDEAD0000 as *const _;
+//! # }
+//! use mcan::rx_fifo::{Fifo0, RxFifo};
+//!
+//! fn assert_sync<T: Sync>() {}
+//!
+//! assert_sync::<RxFifo<'static, Fifo0, Can0, rx::Message<64>>>();
 //! ```
 //!
 //! [`RTIC`]: https://rtic.rs
+//! [`Can`]: crate::bus::Can
+//! [`Aux`]: crate::bus::Aux
+//! [`Monitor`]: crate::monitor::Monitor
+//! [`Cell`]: ::core::cell::Cell
 //! [`CanConfigurable`]: crate::bus::CanConfigurable
 //! [`finalize`]: crate::bus::CanConfigurable::finalize
 //! [`released`]: crate::bus::Can::release
@@ -261,18 +400,72 @@
 //! [`Capacities`]: crate::messageram::Capacities
 //! [`SharedMemory`]: crate::messageram::SharedMemory
 
+pub mod adaptive_drain;
+pub mod arbitration;
+pub mod bitrate_check;
 pub mod bus;
+pub mod candump;
+pub mod capture;
+pub mod compress;
 pub mod config;
+pub mod controller_state;
+pub mod e2e;
+pub mod echo;
+pub mod event_queue;
 pub mod filter;
+pub mod filter_hits;
+pub mod frame_pool;
+#[cfg(feature = "fw-update")]
+pub mod fw_update;
+pub mod id_map;
+pub mod id_registry;
+pub mod init_mode;
 pub mod interrupt;
+pub mod latest_value;
 pub mod message;
 pub mod messageram;
+pub mod mirror;
+pub mod monitor;
+pub mod polled;
+pub mod port;
+pub mod power;
 pub mod prelude;
+pub mod provisioning;
+// The generated register readers match exhaustively on the bits of a field
+// and end in `unreachable!()` for the values that do not fit in it.
+#[cfg_attr(feature = "no-panic", allow(clippy::unreachable))]
 pub mod reg;
+pub mod replay;
+pub mod report;
+pub mod rx_chain;
+pub mod rx_coalescing;
 pub mod rx_dedicated_buffers;
 pub mod rx_fifo;
+pub mod rx_overflow;
+pub mod rx_timestamped;
+pub mod signals;
+pub mod silence;
+pub mod spillover;
+pub mod stats;
+pub mod status_event;
+pub mod test_mode;
+pub mod time_sync;
+pub mod timebase;
+pub mod timestamp;
+pub mod tolerance;
+pub mod tx_arbiter;
 pub mod tx_buffers;
 pub mod tx_event_fifo;
+pub mod tx_policy;
+pub mod wake;
+pub mod watch;
+pub mod watchdog;
+#[cfg(feature = "wcet")]
+pub mod wcet;
+pub mod xcp;
+
+#[cfg(feature = "std")]
+extern crate std;
 
 pub use embedded_can;
 pub use generic_array;
@@ -0,0 +1,124 @@
+//! Data length codes
+
+use super::TooMuchData;
+
+/// Data length code of a frame, from 0 to 15
+///
+/// Codes up to 8 give the length in bytes directly. Classic CAN frames treat
+/// the codes above as 8 bytes, while CAN FD frames map them to lengths of up
+/// to 64 bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dlc(u8);
+
+impl Dlc {
+    /// Code of empty frames
+    pub const ZERO: Self = Self(0);
+    /// Largest code, 8 bytes in classic CAN and 64 bytes in CAN FD
+    pub const MAX: Self = Self(15);
+
+    /// Returns the code `code`, or `None` if it exceeds 15
+    pub const fn new(code: u8) -> Option<Self> {
+        if code <= Self::MAX.0 {
+            Some(Self(code))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the smallest code that encodes at least `len` bytes in the
+    /// given frame format
+    pub const fn from_len(len: usize, fd_format: bool) -> Result<Self, TooMuchData> {
+        let code = match len {
+            0..=8 => len as u8,
+            _ if !fd_format => return Err(TooMuchData),
+            9..=12 => 9,
+            13..=16 => 10,
+            17..=20 => 11,
+            21..=24 => 12,
+            25..=32 => 13,
+            33..=48 => 14,
+            49..=64 => 15,
+            _ => return Err(TooMuchData),
+        };
+        Ok(Self(code))
+    }
+
+    /// Returns the number of bytes this code stands for in the given frame
+    /// format
+    pub const fn len(self, fd_format: bool) -> usize {
+        if !fd_format {
+            return if self.0 > 8 { 8 } else { self.0 as usize };
+        }
+        match self.0 {
+            0..=8 => self.0 as usize,
+            9 => 12,
+            10 => 16,
+            11 => 20,
+            12 => 24,
+            13 => 32,
+            14 => 48,
+            _ => 64,
+        }
+    }
+
+    /// Returns `true` if the code stands for no data
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the raw code
+    pub const fn code(self) -> u8 {
+        self.0
+    }
+
+    /// Takes the code from the low four bits of `bits`
+    pub(crate) const fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::MAX.0)
+    }
+}
+
+impl From<Dlc> for u8 {
+    fn from(dlc: Dlc) -> Self {
+        dlc.0
+    }
+}
+
+impl From<Dlc> for usize {
+    fn from(dlc: Dlc) -> Self {
+        dlc.0.into()
+    }
+}
+
+/// The data length code exceeds 15
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidDlc;
+
+impl TryFrom<u8> for Dlc {
+    type Error = InvalidDlc;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        Self::new(code).ok_or(InvalidDlc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lengths_round_trip() {
+        for code in 0..=15 {
+            let dlc = Dlc::new(code).unwrap();
+            for fd_format in [false, true] {
+                let len = dlc.len(fd_format);
+                let expected = if fd_format || code <= 8 { code } else { 8 };
+                assert_eq!(Dlc::from_len(len, fd_format).unwrap().code(), expected);
+            }
+        }
+        assert_eq!(Dlc::from_len(13, true).unwrap(), Dlc::new(10).unwrap());
+        assert!(Dlc::from_len(9, false).is_err());
+        assert!(Dlc::from_len(65, true).is_err());
+        assert!(Dlc::from_len(256 + 8, true).is_err());
+        assert_eq!(Dlc::try_from(16), Err(InvalidDlc));
+    }
+}
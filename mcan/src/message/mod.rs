@@ -1,9 +1,11 @@
 //! Handling of messages/frames
 
+mod dlc;
 pub mod rx;
 pub mod tx;
 mod tx_event;
 
+pub use dlc::{Dlc, InvalidDlc};
 pub use tx_event::{TxEvent, TxEventType};
 
 use core::cmp::min;
@@ -33,6 +35,20 @@ impl_any_message!(32, 5);
 impl_any_message!(48, 6);
 impl_any_message!(64, 7);
 
+/// Number of data bytes indicated by the data size field value `reg`
+pub(crate) const fn data_field_size(reg: u8) -> usize {
+    match reg & 7 {
+        0 => 8,
+        1 => 12,
+        2 => 16,
+        3 => 20,
+        4 => 24,
+        5 => 32,
+        6 => 48,
+        _ => 64,
+    }
+}
+
 /// Data does not fit in the backing buffer
 #[derive(Debug)]
 pub struct TooMuchData;
@@ -66,13 +82,11 @@ impl<const N: usize> Frame for Message<N> {
     }
 
     fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
-        if dlc > 15 {
-            return None;
-        }
+        let dlc = Dlc::new(u8::try_from(dlc).ok()?)?;
         tx::MessageBuilder {
             id: id.into(),
             frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Remote {
-                desired_len: dlc_to_len(dlc as u8, false),
+                desired_len: dlc.len(false),
             }),
             store_tx_event: None,
         }
@@ -118,7 +132,7 @@ pub trait Raw {
     /// Data length in bytes
     fn decoded_dlc(&self) -> usize;
     /// Data length code
-    fn dlc(&self) -> u8;
+    fn dlc(&self) -> Dlc;
     /// True if the header indicates that the frame uses the CAN FD format
     fn fd_format(&self) -> bool;
     /// Remote Transmission Request
@@ -150,11 +164,11 @@ impl<const N: usize> Raw for RawMessage<N> {
     }
 
     fn decoded_dlc(&self) -> usize {
-        dlc_to_len(self.dlc(), self.fd_format())
+        self.dlc().len(self.fd_format())
     }
 
-    fn dlc(&self) -> u8 {
-        ((self.header[1] >> 16) & 0xf) as u8 // DLC
+    fn dlc(&self) -> Dlc {
+        Dlc::from_bits((self.header[1] >> 16) as u8) // DLC
     }
 
     fn fd_format(&self) -> bool {
@@ -187,46 +201,3 @@ impl<const N: usize> Raw for RawMessage<N> {
         self.header[1] & (1 << 20) != 0 // BRS
     }
 }
-
-/// Finds the smallest data length code that encodes at least len bytes
-fn len_to_dlc(len: usize, fd_format: bool) -> Result<u8, TooMuchData> {
-    if fd_format {
-        match len as u8 {
-            0..=8 => Ok(len as u8),
-            9..=12 => Ok(9),
-            13..=16 => Ok(10),
-            17..=20 => Ok(11),
-            21..=24 => Ok(12),
-            25..=32 => Ok(13),
-            33..=48 => Ok(14),
-            49..=64 => Ok(15),
-            65.. => Err(TooMuchData),
-        }
-    } else {
-        match len as u8 {
-            0..=8 => Ok(len as u8),
-            9.. => Err(TooMuchData),
-        }
-    }
-}
-
-/// Converts data length code to a length in bytes
-fn dlc_to_len(dlc: u8, fd_format: bool) -> usize {
-    if fd_format {
-        match dlc {
-            0..=8 => dlc.into(),
-            9 => 12,
-            10 => 16,
-            11 => 20,
-            12 => 24,
-            13 => 32,
-            14 => 48,
-            15.. => 64,
-        }
-    } else {
-        match dlc {
-            0..=8 => dlc.into(),
-            9.. => 8,
-        }
-    }
-}
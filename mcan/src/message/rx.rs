@@ -18,6 +18,17 @@ pub trait AnyMessage: super::AnyMessage {
     /// `true` if no filter matched, but the message was accepted due to
     /// peripheral-wide settings. See also [`Self::filter_index`]
     fn accepted_non_matching_frame(&self) -> bool;
+
+    /// Converts the message into an owned frame of type `F`. Returns `None` if
+    /// `F` cannot represent the message, e.g. when the payload of an FD frame
+    /// is longer than 8 bytes and `F` only supports classic frames.
+    fn to_frame<F: Frame>(&self) -> Option<F> {
+        if self.is_remote_frame() {
+            F::new_remote(self.id(), self.dlc().into())
+        } else {
+            F::new(self.id(), self.data())
+        }
+    }
 }
 
 impl<const N: usize> super::AnyMessage for Message<N>
@@ -34,7 +45,7 @@ impl<const N: usize> super::Raw for Message<N> {
     fn decoded_dlc(&self) -> usize {
         self.0.decoded_dlc()
     }
-    fn dlc(&self) -> u8 {
+    fn dlc(&self) -> Dlc {
         self.0.dlc()
     }
     fn fd_format(&self) -> bool {
@@ -73,7 +84,7 @@ where
             } else {
                 tx::FrameType::Classic(if self.is_remote_frame() {
                     tx::ClassicFrameType::Remote {
-                        desired_len: dlc_to_len(self.dlc(), self.fd_format()),
+                        desired_len: self.dlc().len(self.fd_format()),
                     }
                 } else {
                     tx::ClassicFrameType::Data(self.data())
@@ -104,3 +115,32 @@ where
 #[repr(transparent)]
 #[derive(Copy, Clone)]
 pub struct Message<const N: usize>(pub(super) RawMessage<N>);
+
+impl<const N: usize> Message<N> {
+    /// Views the message as a message with a data field of `M` bytes without
+    /// copying it. Data beyond the first `M` bytes is not accessible through
+    /// the view. Fails to compile if `M` is larger than `N`.
+    pub fn truncate<const M: usize>(&self) -> &Message<M>
+    where
+        Message<M>: super::AnyMessage,
+    {
+        const { assert!(M <= N, "cannot truncate to a larger data size") };
+        // Safety: `Message` is a transparent wrapper around the `repr(C)`
+        // `RawMessage`, so `RawMessage<M>` is a prefix of `RawMessage<N>` with the
+        // same alignment.
+        unsafe { &*(self as *const Self as *const Message<M>) }
+    }
+
+    /// Views the message as a classic CAN message without copying it. Returns
+    /// `None` for CAN FD messages. Fails to compile if `N` is smaller than 8.
+    ///
+    /// Allows code written for classic messages to consume messages from
+    /// buffers configured for CAN FD.
+    pub fn as_classic(&self) -> Option<&Message<8>> {
+        if self.fd_format() {
+            None
+        } else {
+            Some(self.truncate())
+        }
+    }
+}
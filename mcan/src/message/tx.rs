@@ -23,7 +23,7 @@ impl<const N: usize> Raw for Message<N> {
     fn decoded_dlc(&self) -> usize {
         self.0.decoded_dlc()
     }
-    fn dlc(&self) -> u8 {
+    fn dlc(&self) -> Dlc {
         self.0.dlc()
     }
     fn fd_format(&self) -> bool {
@@ -60,6 +60,117 @@ where
 #[derive(Copy, Clone, Debug)]
 pub struct Message<const N: usize>(pub(super) RawMessage<N>);
 
+impl<const N: usize> Message<N> {
+    /// Returns the marker of the event stored in the TX event queue when the
+    /// message is transmitted, see [`MessageBuilder::store_tx_event`]
+    pub fn tx_event_marker(&self) -> Option<u8> {
+        let t1 = self.0.header[1];
+        (t1 & (1 << 23) != 0).then_some((t1 >> 24) as u8)
+    }
+
+    /// Changes whether an event is stored in the TX event queue when the
+    /// message is transmitted, see [`MessageBuilder::store_tx_event`]
+    pub fn set_tx_event_marker(&mut self, marker: Option<u8>) {
+        let t1 = self.0.header[1] & !(0xff << 24 | 1 << 23);
+        self.0.header[1] = match marker {
+            Some(mm) => t1 | 1 << 23 | (mm as u32) << 24,
+            None => t1,
+        };
+    }
+
+    /// Builds a classic data frame with a standard `id` in `const` context,
+    /// e.g. for tables of predefined frames in ROM. Returns `None` if `id` is
+    /// out of range or `data` does not fit.
+    ///
+    /// ```
+    /// use mcan::message::tx;
+    ///
+    /// const NMT_START_ALL: tx::Message<8> = tx::Message::standard(0x000, &[0x01, 0x00]).unwrap();
+    /// ```
+    pub const fn standard(id: u16, data: &[u8]) -> Option<Self> {
+        Parts::encode_opt(Parts::classic(data).standard(id))
+    }
+
+    /// Builds a classic data frame with an extended `id` in `const` context,
+    /// see [`Self::standard`]
+    pub const fn extended(id: u32, data: &[u8]) -> Option<Self> {
+        Parts::encode_opt(Parts::classic(data).extended(id))
+    }
+
+    /// Builds a CAN FD frame with a standard `id` in `const` context, see
+    /// [`Self::standard`]
+    pub const fn standard_fd(id: u16, data: &[u8], bit_rate_switching: bool) -> Option<Self> {
+        let parts = Parts {
+            brs: bit_rate_switching,
+            ..Parts::fd(data)
+        };
+        Parts::encode_opt(parts.standard(id))
+    }
+
+    /// Builds a CAN FD frame with an extended `id` in `const` context, see
+    /// [`Self::standard`]
+    pub const fn extended_fd(id: u32, data: &[u8], bit_rate_switching: bool) -> Option<Self> {
+        let parts = Parts {
+            brs: bit_rate_switching,
+            ..Parts::fd(data)
+        };
+        Parts::encode_opt(parts.extended(id))
+    }
+
+    /// Stores an event with `marker` in the TX event queue when the message is
+    /// transmitted, like [`Self::set_tx_event_marker`] in `const` context
+    pub const fn with_tx_event(mut self, marker: u8) -> Self {
+        self.0.header[1] = self.0.header[1] & !(0xff << 24) | 1 << 23 | (marker as u32) << 24;
+        self
+    }
+}
+
+/// Message that is a classic CAN frame, as transmitted on a bus restricted to
+/// [`ClassicOnly`] frames
+///
+/// [`ClassicOnly`]: crate::config::ClassicOnly
+#[derive(Copy, Clone, Debug)]
+pub struct ClassicMessage<M>(M);
+
+impl<M: AnyMessage> ClassicMessage<M> {
+    /// Builds a classic CAN frame, see [`MessageBuilder`]
+    pub fn new(
+        id: Id,
+        frame_type: ClassicFrameType,
+        store_tx_event: Option<u8>,
+    ) -> Result<Self, TooMuchData> {
+        M::new(MessageBuilder {
+            id,
+            frame_type: FrameType::Classic(frame_type),
+            store_tx_event,
+        })
+        .map(Self)
+    }
+
+    /// Wraps `message` unless it is a CAN FD frame
+    pub fn from_message(message: M) -> Result<Self, NotClassic> {
+        if message.fd_format() {
+            Err(NotClassic)
+        } else {
+            Ok(Self(message))
+        }
+    }
+
+    /// Returns the message
+    pub fn message(&self) -> &M {
+        &self.0
+    }
+
+    /// Unwraps the message
+    pub fn into_message(self) -> M {
+        self.0
+    }
+}
+
+/// Error converting a CAN FD frame into a [`ClassicMessage`]
+#[derive(Debug)]
+pub struct NotClassic;
+
 /// Selects the type of the Classic CAN frame.
 pub enum ClassicFrameType<'a> {
     /// 0-8 byte message payload
@@ -110,49 +221,117 @@ pub struct MessageBuilder<'a> {
 impl<'a> MessageBuilder<'a> {
     /// Create the message in the format required by the peripheral.
     pub fn build<const N: usize>(self) -> Result<Message<N>, TooMuchData> {
-        let mut data = [0; N];
-
-        let mut copy_payload = |d: &[u8]| {
-            if d.len() > N {
-                return Err(TooMuchData);
-            }
-            data[..d.len()].copy_from_slice(d);
-            Ok(())
+        let (id_field, xtd) = match self.id {
+            Id::Standard(id) => ((id.as_raw() as u32) << 18, false),
+            Id::Extended(id) => (id.as_raw(), true),
         };
-
-        let id_field = match self.id {
-            Id::Standard(id) => (id.as_raw() as u32) << 18,
-            Id::Extended(id) => id.as_raw(),
-        };
-        let xtd = matches!(self.id, Id::Extended(_));
-        let (fdf, brs, esi, rtr, len) = match self.frame_type {
-            FrameType::Classic(payload) => {
-                let (rtr, len) = match payload {
-                    ClassicFrameType::Data(payload) => {
-                        copy_payload(payload)?;
-                        (false, payload.len())
-                    }
-                    ClassicFrameType::Remote { desired_len } => (true, desired_len),
-                };
-                (false, false, false, rtr, len)
-            }
+        let parts = match self.frame_type {
+            FrameType::Classic(ClassicFrameType::Data(payload)) => Parts::classic(payload),
+            FrameType::Classic(ClassicFrameType::Remote { desired_len }) => Parts {
+                rtr: true,
+                len: desired_len,
+                ..Parts::classic(&[])
+            },
             FrameType::FlexibleDatarate {
                 payload,
-                bit_rate_switching: brs,
-                force_error_state_indicator: esi,
-            } => {
-                copy_payload(payload)?;
-                (true, brs, esi, false, payload.len())
-            }
+                bit_rate_switching,
+                force_error_state_indicator,
+            } => Parts {
+                brs: bit_rate_switching,
+                esi: force_error_state_indicator,
+                ..Parts::fd(payload)
+            },
         };
-        let dlc = len_to_dlc(len, fdf)?;
-        let efc = self.store_tx_event.is_some();
-        let mm = self.store_tx_event.unwrap_or(0);
-
-        let t0 = id_field | (rtr as u32) << 29 | (xtd as u32) << 30 | (esi as u32) << 31;
-        let t1 = (((dlc & 0xf) as u32) << 16)
-            | ((brs as u32) << 20)
-            | ((fdf as u32) << 21)
+        Parts {
+            id_field,
+            xtd,
+            store_tx_event: self.store_tx_event,
+            ..parts
+        }
+        .encode()
+    }
+}
+
+/// Fields of a message before encoding, shared by [`MessageBuilder::build`]
+/// and the `const` constructors of [`Message`]
+#[derive(Copy, Clone)]
+struct Parts<'a> {
+    id_field: u32,
+    xtd: bool,
+    rtr: bool,
+    esi: bool,
+    brs: bool,
+    fdf: bool,
+    len: usize,
+    payload: &'a [u8],
+    store_tx_event: Option<u8>,
+}
+
+impl<'a> Parts<'a> {
+    const fn classic(payload: &'a [u8]) -> Self {
+        Self {
+            id_field: 0,
+            xtd: false,
+            rtr: false,
+            esi: false,
+            brs: false,
+            fdf: false,
+            len: payload.len(),
+            payload,
+            store_tx_event: None,
+        }
+    }
+
+    const fn fd(payload: &'a [u8]) -> Self {
+        Self {
+            fdf: true,
+            ..Self::classic(payload)
+        }
+    }
+
+    const fn standard(self, id: u16) -> Option<Self> {
+        if id > 0x7ff {
+            return None;
+        }
+        Some(Self {
+            id_field: (id as u32) << 18,
+            ..self
+        })
+    }
+
+    const fn extended(self, id: u32) -> Option<Self> {
+        if id > 0x1fff_ffff {
+            return None;
+        }
+        Some(Self {
+            id_field: id,
+            xtd: true,
+            ..self
+        })
+    }
+
+    const fn encode<const N: usize>(self) -> Result<Message<N>, TooMuchData> {
+        let mut data = [0; N];
+        match data.split_at_mut_checked(self.payload.len()) {
+            Some((head, _)) => head.copy_from_slice(self.payload),
+            None => return Err(TooMuchData),
+        }
+        let dlc = match Dlc::from_len(self.len, self.fdf) {
+            Ok(dlc) => dlc,
+            Err(e) => return Err(e),
+        };
+        let (efc, mm) = match self.store_tx_event {
+            Some(mm) => (true, mm),
+            None => (false, 0),
+        };
+
+        let t0 = self.id_field
+            | (self.rtr as u32) << 29
+            | (self.xtd as u32) << 30
+            | (self.esi as u32) << 31;
+        let t1 = ((dlc.code() as u32) << 16)
+            | ((self.brs as u32) << 20)
+            | ((self.fdf as u32) << 21)
             | ((efc as u32) << 23)
             | ((mm as u32) << 24);
         Ok(Message(RawMessage {
@@ -160,4 +339,72 @@ impl<'a> MessageBuilder<'a> {
             data,
         }))
     }
+
+    const fn encode_opt<const N: usize>(parts: Option<Self>) -> Option<Message<N>> {
+        match parts {
+            Some(parts) => match parts.encode() {
+                Ok(message) => Some(message),
+                Err(_) => None,
+            },
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> Message<N> {
+    /// The message as received at `timestamp`, e.g. through loopback
+    pub(crate) fn received(&self, timestamp: u16) -> rx::Message<N> {
+        let [t0, t1] = self.0.header;
+        rx::Message(RawMessage {
+            header: [t0, (t1 & 0x003f_0000) | u32::from(timestamp)],
+            data: self.0.data,
+        })
+    }
+
+    /// The TX event stored for the message transmitted at `timestamp`
+    pub(crate) fn event(&self, timestamp: u16) -> TxEvent {
+        let [t0, t1] = self.0.header;
+        TxEvent(RawMessage {
+            // Event type "transmitted"
+            header: [t0, (t1 & 0xff3f_0000) | 0b01 << 22 | u32::from(timestamp)],
+            data: [],
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TABLE: [Option<Message<8>>; 4] = [
+        Message::standard(0x123, &[1, 2, 3]),
+        Message::extended(0x1234_5678, &[]),
+        Message::standard(0x800, &[]),
+        Message::standard(0x100, &[0; 9]),
+    ];
+
+    #[test]
+    fn const_messages_match_builder() {
+        let built: Message<8> = MessageBuilder {
+            id: Id::Standard(StandardId::new(0x123).unwrap()),
+            frame_type: FrameType::Classic(ClassicFrameType::Data(&[1, 2, 3])),
+            store_tx_event: Some(7),
+        }
+        .build()
+        .unwrap();
+        let constant = TABLE[0].unwrap().with_tx_event(7);
+        assert_eq!(constant.0.header, built.0.header);
+        assert_eq!(constant.data(), built.data());
+        assert_eq!(constant.tx_event_marker(), Some(7));
+        let extended = TABLE[1].unwrap();
+        assert_eq!(
+            extended.id(),
+            Id::Extended(ExtendedId::new(0x1234_5678).unwrap())
+        );
+        assert!(TABLE[2].is_none());
+        assert!(TABLE[3].is_none());
+        let fd = Message::<64>::standard_fd(0x10, &[0; 13], true).unwrap();
+        assert_eq!((fd.decoded_dlc(), fd.bit_rate_switching()), (16, true));
+    }
 }
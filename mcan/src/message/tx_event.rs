@@ -9,7 +9,7 @@ impl Raw for TxEvent {
     fn decoded_dlc(&self) -> usize {
         self.0.decoded_dlc()
     }
-    fn dlc(&self) -> u8 {
+    fn dlc(&self) -> Dlc {
         self.0.dlc()
     }
     fn fd_format(&self) -> bool {
@@ -45,6 +45,12 @@ impl TxEvent {
         (self.0.header[1] >> 24) as u8
     }
 
+    /// Returns the value of the timestamp counter when the start of frame
+    /// was transmitted
+    pub fn timestamp(&self) -> u16 {
+        self.0.header[1] as u16
+    }
+
     /// Parse the event type field. Indicates whether cancellation was requested
     /// at the time transmission succeeded.
     pub fn event_type(&self) -> TxEventType {
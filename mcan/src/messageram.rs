@@ -5,43 +5,103 @@
 //! [`crate`]: crate#message-ram-configuration
 use crate::filter::{FilterExtendedId, FilterStandardId};
 use crate::message::{rx, tx, TxEvent};
-use core::mem::MaybeUninit;
+use core::mem::{offset_of, size_of, MaybeUninit};
 use generic_array::{
-    typenum::{consts::*, IsLessOrEqual, LeEq, Same},
+    typenum::{consts::*, IsLessOrEqual, LeEq, Same, Unsigned},
     ArrayLength, GenericArray,
 };
+use portable_atomic::{AtomicBool, Ordering};
 use vcell::VolatileCell;
 
 /// Element capacities
+///
+/// Any capacity can be `U0`. Such a section takes no space in [`SharedMemory`]
+/// and is disabled in the peripheral, and the corresponding API is inert:
+/// receiving from an RX FIFO without capacity always yields
+/// [`nb::Error::WouldBlock`], an empty TX event FIFO never yields events and
+/// [`transmit_queued`] always fails with [`nb::Error::WouldBlock`] if all
+/// transmit buffers are dedicated, which is decided at compile time.
+///
+/// Applications using a single ID space can set the filter capacity of the
+/// other one to `U0` to get its message RAM back. The filter list of that ID
+/// space is then not accessible at all, and its frames are handled by
+/// [`CanConfig::global_filter`] alone: by default they are rejected.
+///
+/// The limits of the peripheral are checked when [`SharedMemory::new`] is
+/// instantiated, failing the build with a message naming the exceeded limit:
+///
+/// - up to 128 standard and 64 extended ID filters
+/// - up to 64 dedicated receive buffers and elements per receive FIFO
+/// - up to 32 transmit buffers, of which at most all are dedicated
+/// - up to 32 transmit event FIFO elements
+///
+/// ```compile_fail
+/// # use mcan::generic_array::typenum::consts::*;
+/// # use mcan::message::{rx, tx};
+/// # use mcan::messageram::SharedMemory;
+/// struct Capacities;
+/// impl mcan::messageram::Capacities for Capacities {
+/// #   type StandardFilters = U8;
+/// #   type ExtendedFilters = U0;
+/// #   type RxBufferMessage = rx::Message<8>;
+/// #   type DedicatedRxBuffers = U0;
+/// #   type RxFifo0Message = rx::Message<8>;
+/// #   type RxFifo0 = U8;
+/// #   type RxFifo1Message = rx::Message<8>;
+/// #   type RxFifo1 = U0;
+///     type TxMessage = tx::Message<8>;
+///     type TxBuffers = U4;
+///     // error: More dedicated transmit buffers than transmit buffers
+///     type DedicatedTxBuffers = U8;
+///     // ...
+/// #   type TxEventFifo = U0;
+/// }
+///
+/// let memory = SharedMemory::<Capacities>::new();
+/// ```
+///
+/// [`CanConfig::global_filter`]: crate::config::CanConfig::global_filter
+/// [`transmit_queued`]: crate::tx_buffers::DynTx::transmit_queued
 pub trait Capacities {
     /// Maximum number of Standard ID filters
-    type StandardFilters: LimitedArrayLength<VolatileCell<FilterStandardId>, U128>;
+    ///
+    /// With `U0`, [`CanConfigurable::filters_standard`] is not available.
+    ///
+    /// [`CanConfigurable::filters_standard`]: crate::bus::CanConfigurable::filters_standard
+    type StandardFilters: ArrayLength<VolatileCell<FilterStandardId>>;
     /// Maximum number of Extended ID filters
-    type ExtendedFilters: LimitedArrayLength<VolatileCell<FilterExtendedId>, U64>;
+    ///
+    /// With `U0`, [`CanConfigurable::filters_extended`] is not available.
+    ///
+    /// [`CanConfigurable::filters_extended`]: crate::bus::CanConfigurable::filters_extended
+    type ExtendedFilters: ArrayLength<VolatileCell<FilterExtendedId>>;
     /// [`rx::Message`] with size selected for use in dedicated receive buffers
     type RxBufferMessage: rx::AnyMessage;
     /// Maximum number of dedicated receive buffers
-    type DedicatedRxBuffers: LimitedArrayLength<VolatileCell<Self::RxBufferMessage>, U64>;
+    type DedicatedRxBuffers: ArrayLength<VolatileCell<Self::RxBufferMessage>>;
     /// [`rx::Message`] with size selected for use in receive FIFO 0
     type RxFifo0Message: rx::AnyMessage;
     /// Receive FIFO0 size
-    type RxFifo0: LimitedArrayLength<VolatileCell<Self::RxFifo0Message>, U64>;
+    type RxFifo0: ArrayLength<VolatileCell<Self::RxFifo0Message>>;
     /// [`rx::Message`] with size selected for use in receive FIFO 1
     type RxFifo1Message: rx::AnyMessage;
     /// Receive FIFO1 size
-    type RxFifo1: LimitedArrayLength<VolatileCell<Self::RxFifo1Message>, U64>;
+    type RxFifo1: ArrayLength<VolatileCell<Self::RxFifo1Message>>;
     /// [`tx::Message`] with size selected for use in transmit buffers
     type TxMessage: tx::AnyMessage;
     /// Number of transmit buffers (later split into dedicated and queue use)
-    type TxBuffers: LimitedArrayLength<VolatileCell<Self::TxMessage>, U32>;
+    type TxBuffers: ArrayLength<VolatileCell<Self::TxMessage>>;
     /// Number of transmit buffers to exempt from queue use to dedicate to
     /// specific messages. The rest are used as a queue.
-    type DedicatedTxBuffers: LimitedArrayLength<VolatileCell<Self::TxMessage>, Self::TxBuffers>;
+    type DedicatedTxBuffers: ArrayLength<VolatileCell<Self::TxMessage>>;
     /// Transmit event FIFO size
-    type TxEventFifo: LimitedArrayLength<VolatileCell<TxEvent>, U32>;
+    type TxEventFifo: ArrayLength<VolatileCell<TxEvent>>;
 }
 
 /// [`generic_array::ArrayLength`] with an upper bound.
+///
+/// No longer used by [`Capacities`], whose limits are checked when
+/// [`SharedMemory::new`] is instantiated.
 pub trait LimitedArrayLength<T, MaxLength>: ArrayLength<T> {}
 impl<T, N, MaxLength> LimitedArrayLength<T, MaxLength> for N
 where
@@ -62,12 +122,151 @@ pub(super) struct SharedMemoryInner<C: Capacities> {
     pub(super) tx_buffers: GenericArray<VolatileCell<C::TxMessage>, C::TxBuffers>,
 }
 
+/// Byte offsets of the sections of [`SharedMemory`], relative to its start
+///
+/// The peripheral expects the start addresses of the sections as offsets from
+/// the start of system RAM, which is the address of [`SharedMemory`] plus the
+/// offsets found here.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Standard ID filters
+    pub filters_standard: usize,
+    /// Extended ID filters
+    pub filters_extended: usize,
+    /// Receive FIFO 0
+    pub rx_fifo_0: usize,
+    /// Receive FIFO 1
+    pub rx_fifo_1: usize,
+    /// Dedicated receive buffers
+    pub rx_dedicated_buffers: usize,
+    /// Transmit event FIFO
+    pub tx_event_fifo: usize,
+    /// Transmit buffers, dedicated ones first followed by the queue
+    pub tx_buffers: usize,
+}
+
+/// Failure of [`SharedMemory::self_test`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RamTestError {
+    /// Byte offset of the failing word within the memory
+    pub offset: usize,
+    /// Value written to the word
+    pub expected: u32,
+    /// Value read back from the word
+    pub read: u32,
+}
+
 /// Memory shared between the peripheral and core. Provide a struct `C` that
 /// implements [`Capacities`] to select the sizes of the buffers, then construct
 /// this using `SharedMemory::<C>::new()`.
 pub struct SharedMemory<C: Capacities>(MaybeUninit<SharedMemoryInner<C>>);
 
 impl<C: Capacities> SharedMemory<C> {
+    /// Size of the memory in bytes
+    pub const SIZE: usize = size_of::<SharedMemoryInner<C>>();
+
+    /// Offsets of the sections within the memory
+    pub const LAYOUT: Layout = Layout {
+        filters_standard: offset_of!(SharedMemoryInner<C>, filters_standard),
+        filters_extended: offset_of!(SharedMemoryInner<C>, filters_extended),
+        rx_fifo_0: offset_of!(SharedMemoryInner<C>, rx_fifo_0),
+        rx_fifo_1: offset_of!(SharedMemoryInner<C>, rx_fifo_1),
+        rx_dedicated_buffers: offset_of!(SharedMemoryInner<C>, rx_dedicated_buffers),
+        tx_event_fifo: offset_of!(SharedMemoryInner<C>, tx_event_fifo),
+        tx_buffers: offset_of!(SharedMemoryInner<C>, tx_buffers),
+    };
+
+    /// The peripheral uses 16-bit addressing, so a memory larger than 64K can
+    /// never be addressed. Evaluated when [`Self::new`] is instantiated,
+    /// turning an oversized memory into a compile-time error.
+    const ASSERT_ADDRESSABLE_SIZE: () = assert!(
+        Self::SIZE <= 1 << 16,
+        "Message RAM exceeds the 64K addressable by the peripheral"
+    );
+
+    /// Limits of the peripheral on the capacities, see [`Capacities`].
+    /// Evaluated when [`Self::new`] is instantiated.
+    const ASSERT_CAPACITIES: () = {
+        assert!(
+            C::StandardFilters::USIZE <= 128,
+            "More than 128 standard ID filters"
+        );
+        assert!(
+            C::ExtendedFilters::USIZE <= 64,
+            "More than 64 extended ID filters"
+        );
+        assert!(
+            C::DedicatedRxBuffers::USIZE <= 64,
+            "More than 64 dedicated receive buffers"
+        );
+        assert!(
+            C::RxFifo0::USIZE <= 64,
+            "More than 64 elements in receive FIFO 0"
+        );
+        assert!(
+            C::RxFifo1::USIZE <= 64,
+            "More than 64 elements in receive FIFO 1"
+        );
+        assert!(C::TxBuffers::USIZE <= 32, "More than 32 transmit buffers");
+        assert!(
+            C::DedicatedTxBuffers::USIZE <= C::TxBuffers::USIZE,
+            "More dedicated transmit buffers than transmit buffers"
+        );
+        assert!(
+            C::TxEventFifo::USIZE <= 32,
+            "More than 32 transmit event FIFO elements"
+        );
+    };
+
+    /// Returns `true` if the memory fits in a region of `bytes` bytes.
+    ///
+    /// Meant for compile-time checks against the size of the RAM region the
+    /// memory is placed in, e.g. `const _: () =
+    /// assert!(SharedMemory::<Capacities>::fits_in(REGION_SIZE));`
+    pub const fn fits_in(bytes: usize) -> bool {
+        Self::SIZE <= bytes
+    }
+
+    /// Verifies that every bit of the memory can be written and read back,
+    /// then leaves it zeroed.
+    ///
+    /// Each word is filled with patterns of zeros, ones, alternating bits and
+    /// its own offset, catching stuck bits as well as a linker section that
+    /// is smaller than the memory and aliases or misses parts of it. Meant to
+    /// be run once before [`CanConfigurable::new`], which zeroes the memory
+    /// in any case.
+    ///
+    /// [`CanConfigurable::new`]: crate::bus::CanConfigurable::new
+    pub fn self_test(&mut self) -> Result<(), RamTestError> {
+        const PATTERNS: [u32; 4] = [0, u32::MAX, 0x5555_5555, 0xaaaa_aaaa];
+        let words = self.0.as_mut_ptr() as *mut u32;
+        let len = Self::SIZE / 4;
+        // Safety: The memory consists of 32-bit words and is exclusively borrowed.
+        let write = |index: usize, value: u32| unsafe { words.add(index).write_volatile(value) };
+        let check = |index: usize, expected: u32| {
+            // Safety: As above.
+            let read = unsafe { words.add(index).read_volatile() };
+            if read == expected {
+                Ok(())
+            } else {
+                Err(RamTestError {
+                    offset: index * 4,
+                    expected,
+                    read,
+                })
+            }
+        };
+        for pattern in PATTERNS {
+            (0..len).for_each(|index| write(index, pattern));
+            (0..len).try_for_each(|index| check(index, pattern))?;
+        }
+        // Distinct values reveal words aliasing each other
+        (0..len).for_each(|index| write(index, (index * 4) as u32));
+        let result = (0..len).try_for_each(|index| check(index, (index * 4) as u32));
+        (0..len).for_each(|index| write(index, 0));
+        result
+    }
+
     pub(super) fn init(&mut self) -> &mut SharedMemoryInner<C> {
         self.0 = MaybeUninit::zeroed();
         // Safety: All bits 0 is a valid value for all the contained arrays.
@@ -78,6 +277,10 @@ impl<C: Capacities> SharedMemory<C> {
     /// type can safely be assigned to a `link_section` that is not
     /// initialized by the system to control its position in memory.
     pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::ASSERT_CAPACITIES;
+        #[allow(clippy::let_unit_value)]
+        let () = Self::ASSERT_ADDRESSABLE_SIZE;
         Self(MaybeUninit::uninit())
     }
 
@@ -87,7 +290,132 @@ impl<C: Capacities> SharedMemory<C> {
     pub(crate) fn is_addressable(&self, eligible_message_ram_start: *const ()) -> bool {
         let eligible_message_ram_start = eligible_message_ram_start as usize;
         let start = self as *const _ as usize;
-        let end_exclusive = start + core::mem::size_of::<Self>();
+        let end_exclusive = start + Self::SIZE;
         eligible_message_ram_start <= start && end_exclusive - eligible_message_ram_start <= 1 << 16
     }
 }
+
+/// Declares a [`SharedMemory`] for the capacities `C` in the linker section
+/// `S` and returns a `&'static mut` reference to it, wrapped in [`Some`] the
+/// first time the expression is evaluated and [`None`] afterwards.
+///
+/// The memory is a `static` placed with `#[link_section]`, so its size and
+/// alignment follow from `C` and the section has to be mapped to RAM
+/// accessible by the peripheral as described in the [`crate`] documentation.
+///
+/// ```
+/// use mcan::generic_array::typenum::consts::*;
+/// use mcan::mcan_message_ram;
+/// use mcan::message::{rx, tx};
+/// use mcan::messageram::SharedMemory;
+/// struct Capacities;
+/// impl mcan::messageram::Capacities for Capacities {
+///     type StandardFilters = U8;
+///     type ExtendedFilters = U0;
+///     type RxBufferMessage = rx::Message<8>;
+///     type DedicatedRxBuffers = U0;
+///     type RxFifo0Message = rx::Message<8>;
+///     type RxFifo0 = U8;
+///     type RxFifo1Message = rx::Message<8>;
+///     type RxFifo1 = U0;
+///     type TxMessage = tx::Message<8>;
+///     type TxBuffers = U4;
+///     type DedicatedTxBuffers = U0;
+///     type TxEventFifo = U0;
+/// }
+///
+/// fn message_ram() -> Option<&'static mut SharedMemory<Capacities>> {
+///     mcan_message_ram!(Capacities in ".can")
+/// }
+///
+/// let memory = message_ram().unwrap();
+/// assert!(message_ram().is_none());
+/// # let _ = memory;
+/// ```
+#[macro_export]
+macro_rules! mcan_message_ram {
+    ($($caps:ident)::+ in $section:literal) => {{
+        #[link_section = $section]
+        static mut MESSAGE_RAM: $crate::messageram::SharedMemory<$($caps)::+> =
+            $crate::messageram::SharedMemory::new();
+        static CLAIM: $crate::messageram::Claim = $crate::messageram::Claim::new();
+        if CLAIM.claim() {
+            // Safety: The claim ensures that only one reference is created.
+            ::core::option::Option::Some(unsafe { &mut *::core::ptr::addr_of_mut!(MESSAGE_RAM) })
+        } else {
+            ::core::option::Option::None
+        }
+    }};
+}
+
+/// Flag ensuring that the memory declared by [`mcan_message_ram`] is only
+/// handed out once
+#[doc(hidden)]
+pub struct Claim(AtomicBool);
+
+impl Claim {
+    #[doc(hidden)]
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    /// Returns `true` on the first call only
+    #[doc(hidden)]
+    pub fn claim(&self) -> bool {
+        !self.0.swap(true, Ordering::AcqRel)
+    }
+}
+
+impl Default for Claim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Sparse;
+    impl Capacities for Sparse {
+        type StandardFilters = U0;
+        type ExtendedFilters = U0;
+        type RxBufferMessage = rx::Message<64>;
+        type DedicatedRxBuffers = U0;
+        type RxFifo0Message = rx::Message<8>;
+        type RxFifo0 = U2;
+        type RxFifo1Message = rx::Message<64>;
+        type RxFifo1 = U0;
+        type TxMessage = tx::Message<8>;
+        type TxBuffers = U1;
+        type DedicatedTxBuffers = U0;
+        type TxEventFifo = U0;
+    }
+
+    #[test]
+    fn self_test_passes_and_zeroes() {
+        let mut memory = SharedMemory::<Sparse>::new();
+        assert_eq!(memory.self_test(), Ok(()));
+        // Safety: The self test has initialized every byte.
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                memory.0.as_ptr() as *const u8,
+                SharedMemory::<Sparse>::SIZE,
+            )
+        };
+        assert!(bytes.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn zero_capacity_sections_take_no_space() {
+        let message = size_of::<rx::Message<8>>();
+        assert_eq!(
+            SharedMemory::<Sparse>::SIZE,
+            2 * message + size_of::<tx::Message<8>>()
+        );
+        let layout = SharedMemory::<Sparse>::LAYOUT;
+        assert_eq!(layout.rx_fifo_0, 0);
+        assert_eq!(layout.rx_fifo_1, 2 * message);
+        assert_eq!(layout.tx_buffers, 2 * message);
+    }
+}
@@ -0,0 +1,115 @@
+//! Mirroring of frames to a logger
+//!
+//! A [`Mirror`] copies received, and optionally transmitted, frames into a
+//! [`Spillover`] queue dedicated to a logger task. The logger drains the queue
+//! at its own pace: when it falls behind, mirrored frames are dropped and
+//! counted in [`Consumer::dropped`] of the mirror queue, while the application
+//! still gets every frame.
+//!
+//! ```no_run
+//! use mcan::message::{rx, tx};
+//! use mcan::mirror::{Mirror, Mirrored};
+//! use mcan::rx_fifo::DynRxFifo;
+//! use mcan::spillover::Spillover;
+//!
+//! type Frame = Mirrored<rx::Message<8>, tx::Message<8>>;
+//! static mut LOG: Spillover<Frame, 64> = Spillover::new();
+//!
+//! fn on_interrupt(
+//!     mirror: &mut Mirror<'static, rx::Message<8>, tx::Message<8>, 64>,
+//!     fifo: &mut impl DynRxFifo<Message = rx::Message<8>>,
+//! ) {
+//!     while let Ok(message) = mirror.receive(fifo) {
+//!         // Application quick path
+//!     }
+//! }
+//!
+//! // Safety: `LOG` is only split once
+//! let (producer, logger) = unsafe { (*core::ptr::addr_of_mut!(LOG)).split() };
+//! let mirror: Mirror<'static, rx::Message<8>, tx::Message<8>, 64> = Mirror::new(producer, true);
+//! ```
+//!
+//! [`Spillover`]: crate::spillover::Spillover
+//! [`Consumer::dropped`]: crate::spillover::Consumer::dropped
+
+use crate::rx_fifo::DynRxFifo;
+use crate::spillover::Producer;
+use core::convert::Infallible;
+
+/// Frame copied to the logger by a [`Mirror`]
+#[derive(Copy, Clone, Debug)]
+pub enum Mirrored<R, T> {
+    /// Received frame
+    Received(R),
+    /// Frame handed to the peripheral for transmission
+    Transmitted(T),
+}
+
+/// Copies frames into the producing end of a [`Spillover`] queue
+///
+/// [`Spillover`]: crate::spillover::Spillover
+pub struct Mirror<'q, R, T, const N: usize> {
+    producer: Producer<'q, Mirrored<R, T>, N>,
+    transmitted: bool,
+}
+
+impl<'q, R: Clone, T: Clone, const N: usize> Mirror<'q, R, T, N> {
+    /// Mirrors into `producer`. Transmitted frames are only mirrored if
+    /// `transmitted` is set.
+    pub fn new(producer: Producer<'q, Mirrored<R, T>, N>, transmitted: bool) -> Self {
+        Self {
+            producer,
+            transmitted,
+        }
+    }
+
+    /// Copies a received frame to the logger
+    pub fn on_received(&mut self, message: &R) {
+        let _ = self.producer.push(Mirrored::Received(message.clone()));
+    }
+
+    /// Copies a frame handed to the peripheral for transmission to the
+    /// logger, if transmitted frames are mirrored
+    pub fn on_transmitted(&mut self, message: &T) {
+        if self.transmitted {
+            let _ = self.producer.push(Mirrored::Transmitted(message.clone()));
+        }
+    }
+
+    /// Receives a message from `fifo`, copying it to the logger
+    pub fn receive<F: DynRxFifo<Message = R>>(
+        &mut self,
+        fifo: &mut F,
+    ) -> nb::Result<R, Infallible> {
+        let message = fifo.receive()?;
+        self.on_received(&message);
+        Ok(message)
+    }
+
+    /// Returns the producing end of the queue
+    pub fn release(self) -> Producer<'q, Mirrored<R, T>, N> {
+        self.producer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spillover::Spillover;
+
+    #[test]
+    fn mirrors_without_affecting_the_caller() {
+        let mut queue = Spillover::<Mirrored<u8, u16>, 2>::new();
+        let (producer, mut logger) = queue.split();
+        let mut mirror = Mirror::new(producer, false);
+        mirror.on_received(&1);
+        mirror.on_transmitted(&2);
+        let mut mirror = Mirror::new(mirror.release(), true);
+        mirror.on_transmitted(&3);
+        mirror.on_received(&4);
+        assert!(matches!(logger.pop(), Some(Mirrored::Received(1))));
+        assert!(matches!(logger.pop(), Some(Mirrored::Transmitted(3))));
+        assert!(logger.pop().is_none());
+        assert_eq!(logger.dropped(), 1);
+    }
+}
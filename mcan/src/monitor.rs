@@ -0,0 +1,166 @@
+//! Status of the peripheral for monitoring tasks
+//!
+//! [`Monitor`] gives access to the status registers of the peripheral: the
+//! protocol status, the error counters, the fill levels of the FIFOs and the
+//! timestamp counter. It does not interfere with receiving and transmitting,
+//! so it can be moved into a low-priority monitoring task while the RX and TX
+//! handles stay with the tasks serving them.
+//!
+//! [`Monitor`] is [`Send`] and [`Sync`]. Its statistics are updated
+//! atomically, so the same monitor can account for error logging overflows in
+//! an interrupt handler and be read from a task. Note that reading the status
+//! registers clears some of their fields, so concurrent readers each observe
+//! only part of them.
+//!
+//! ```no_run
+//! use mcan::monitor::DynMonitor;
+//!
+//! fn monitoring_task(monitor: &impl DynMonitor) {
+//!     let errors = monitor.error_counters();
+//!     let status = monitor.protocol_status();
+//!     let pending = monitor.rx_fifo_0_fill_level();
+//!     // ...
+//! #   let _ = (errors, status, pending);
+//! }
+//! ```
+
+use crate::bus::{ErrorCounters, ProtocolStatus};
+use crate::filter_hits::HighPriorityMatch;
+use crate::stats::{AtomicStats, Stats};
+
+/// Status registers of a CAN peripheral
+pub struct Monitor<Id> {
+    reg: crate::reg::Can<Id>,
+    /// Statistics accumulated from registers
+    stats: AtomicStats,
+}
+
+// Safety: Registers are only accessed by single volatile reads and the
+// statistics are atomic.
+unsafe impl<Id> Sync for Monitor<Id> {}
+
+/// Trait which erases generic parametrization for [`Monitor`] type
+pub trait DynMonitor {
+    /// CAN identity type
+    type Id;
+
+    /// Access the error counters register value
+    ///
+    /// Reading the register clears the CAN error logging counter (CEL), whose
+    /// value is accumulated into [`Stats::protocol_errors`].
+    fn error_counters(&self) -> ErrorCounters;
+
+    /// Returns the statistics accumulated so far
+    fn stats(&self) -> Stats;
+
+    /// Resets the accumulated statistics
+    fn reset_stats(&self);
+
+    /// Accounts for an overflow of the CAN error logging counter. Should be
+    /// called when [`Interrupt::ErrorLoggingOverflow`] is raised.
+    ///
+    /// [`Interrupt::ErrorLoggingOverflow`]: crate::interrupt::Interrupt::ErrorLoggingOverflow
+    fn on_error_logging_overflow(&self);
+
+    /// Counts a frame that lost arbitration into
+    /// [`Stats::arbitration_losses`]
+    fn on_arbitration_loss(&self);
+
+    /// Access the protocol status register value
+    ///
+    /// Reading the register clears fields: PXE, RFDF, RBRS, RESI, DLEC, LEC.
+    fn protocol_status(&self) -> ProtocolStatus;
+
+    /// Current value of the timestamp counter
+    ///
+    /// If timestamping is disabled, its value is zero.
+    fn timestamp(&self) -> u16;
+
+    /// Filter element that matched the last message flagged with
+    /// [`Interrupt::HighPriorityMessage`], see [`crate::filter_hits`]
+    ///
+    /// [`Interrupt::HighPriorityMessage`]: crate::interrupt::Interrupt::HighPriorityMessage
+    fn high_priority_match(&self) -> HighPriorityMatch;
+
+    /// Number of messages in RX FIFO 0
+    fn rx_fifo_0_fill_level(&self) -> usize;
+
+    /// Number of messages in RX FIFO 1
+    fn rx_fifo_1_fill_level(&self) -> usize;
+
+    /// Number of free elements in the TX FIFO/queue
+    fn tx_queue_free_level(&self) -> usize;
+
+    /// Number of events in the TX event FIFO
+    fn tx_event_fifo_fill_level(&self) -> usize;
+}
+
+impl<Id: mcan_core::CanId> Monitor<Id> {
+    /// # Safety
+    /// The status registers must not be read by anything else, as reading
+    /// clears some of their fields.
+    pub(crate) unsafe fn new() -> Self {
+        Self {
+            reg: crate::reg::Can::new(),
+            stats: AtomicStats::new(),
+        }
+    }
+}
+
+impl<Id: mcan_core::CanId> DynMonitor for Monitor<Id> {
+    type Id = Id;
+
+    fn error_counters(&self) -> ErrorCounters {
+        let ecr = self.reg.ecr.read();
+        self.stats.add_error_log(ecr.cel().bits());
+        ecr.into()
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats.load()
+    }
+
+    fn reset_stats(&self) {
+        // Clear the hardware counter as well
+        self.reg.ecr.read();
+        self.stats.reset();
+    }
+
+    fn on_error_logging_overflow(&self) {
+        self.error_counters();
+        // The error that overflowed the saturated counter
+        self.stats.add_error_log(1);
+    }
+
+    fn on_arbitration_loss(&self) {
+        self.stats.add_arbitration_loss();
+    }
+
+    fn protocol_status(&self) -> ProtocolStatus {
+        self.reg.psr.read().into()
+    }
+
+    fn timestamp(&self) -> u16 {
+        self.reg.tscv.read().tsc().bits()
+    }
+
+    fn high_priority_match(&self) -> HighPriorityMatch {
+        HighPriorityMatch::from_bits(self.reg.hpms.read().bits())
+    }
+
+    fn rx_fifo_0_fill_level(&self) -> usize {
+        self.reg.rxf0.s.read().ffl().bits().into()
+    }
+
+    fn rx_fifo_1_fill_level(&self) -> usize {
+        self.reg.rxf1.s.read().ffl().bits().into()
+    }
+
+    fn tx_queue_free_level(&self) -> usize {
+        self.reg.txfqs.read().tffl().bits().into()
+    }
+
+    fn tx_event_fifo_fill_level(&self) -> usize {
+        self.reg.txefs.read().effl().bits().into()
+    }
+}
@@ -0,0 +1,217 @@
+//! Operation without interrupts
+//!
+//! Simple applications and superloop architectures often do without
+//! interrupts altogether. The peripheral still sets its interrupt flags when
+//! no interrupt is enabled, so a [`Poller`] services everything from a single
+//! [`Poller::poll`] call: received messages of both RX FIFOs, TX events and
+//! changes of the error state, which are handed to a [`PollHandler`].
+//!
+//! Each call handles at most a configured number of messages and events per
+//! queue, so that its duration is bounded within the loop. Interrupt lines
+//! should stay disabled, which is the default.
+//!
+//! ```no_run
+//! use mcan::message::{rx, TxEvent};
+//! use mcan::polled::{PollHandler, Poller};
+//! use mcan::status_event::StatusEvent;
+//!
+//! struct App;
+//!
+//! impl PollHandler<rx::Message<8>, rx::Message<8>> for App {
+//!     fn on_rx_fifo_0(&mut self, message: rx::Message<8>) {
+//!         // ...
+//!     }
+//!
+//!     fn on_status(&mut self, event: StatusEvent) {
+//!         // ...
+//!     }
+//! }
+//!
+//! # fn main_loop<Id: mcan::core::CanId, D: mcan::core::Dependencies<Id>, C>(
+//! #     mut can: mcan::bus::Can<'static, Id, D, C>,
+//! # ) -> !
+//! # where
+//! #     C: mcan::messageram::Capacities<
+//! #         RxFifo0Message = rx::Message<8>,
+//! #         RxFifo1Message = rx::Message<8>,
+//! #     >,
+//! # {
+//! let mut poller = Poller::new(&mut can, 8).recover_from_bus_off(true);
+//! let mut app = App;
+//! loop {
+//!     poller.poll(&mut can, &mut app);
+//!     // Other work of the superloop
+//! }
+//! # }
+//! ```
+
+use crate::bus::{Can, DynAux};
+use crate::config::FrameFormat;
+use crate::interrupt::{state, OwnedInterruptSet};
+use crate::message::TxEvent;
+use crate::messageram::Capacities;
+use crate::monitor::DynMonitor;
+use crate::power::{NoTransceiver, Transceiver};
+use crate::rx_fifo::DynRxFifo;
+use crate::status_event::StatusEvent;
+use crate::tx_event_fifo::DynTxEventFifo;
+
+/// Receives what a [`Poller`] finds. All methods do nothing by default.
+pub trait PollHandler<M0, M1> {
+    /// Called with each message received in RX FIFO 0
+    fn on_rx_fifo_0(&mut self, _message: M0) {}
+
+    /// Called with each message received in RX FIFO 1
+    fn on_rx_fifo_1(&mut self, _message: M1) {}
+
+    /// Called with each TX event
+    fn on_tx_event(&mut self, _event: TxEvent) {}
+
+    /// Called with each change of the error state, protocol error and
+    /// overrun
+    fn on_status(&mut self, _event: StatusEvent) {}
+}
+
+/// Number of messages and events handled by a call to [`Poller::poll`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PollSummary {
+    /// Messages received from RX FIFO 0
+    pub rx_fifo_0: usize,
+    /// Messages received from RX FIFO 1
+    pub rx_fifo_1: usize,
+    /// TX events taken from the TX event FIFO
+    pub tx_events: usize,
+    /// Status events
+    pub status: usize,
+}
+
+/// Services a peripheral without interrupts from a single entry point
+pub struct Poller<Id> {
+    status: OwnedInterruptSet<Id>,
+    max_per_poll: usize,
+    recover: bool,
+    /// Bus off was reported and the peripheral was not restarted yet
+    bus_off: bool,
+}
+
+impl<Id: mcan_core::CanId> Poller<Id> {
+    /// Takes the status interrupts, see [`StatusEvent::interrupts`], out of
+    /// the interrupts of `can`. Each poll handles at most `max_per_poll`
+    /// elements of each queue.
+    ///
+    /// Only the flags of the interrupts are used, the interrupts stay
+    /// disabled. Interrupts already split off from `can` are not serviced.
+    pub fn new<D, C: Capacities, F>(can: &mut Can<'_, Id, D, C, F>, max_per_poll: usize) -> Self {
+        let mut status = OwnedInterruptSet::<Id, state::Disabled>::default();
+        for interrupt in StatusEvent::interrupts().iter() {
+            if let Ok(owned) = can.interrupts.split(interrupt.into()) {
+                status.join(owned);
+            }
+        }
+        Self {
+            status: status.into(),
+            max_per_poll,
+            recover: false,
+            bus_off: false,
+        }
+    }
+
+    /// Leaves bus off automatically: the peripheral resumes operation once
+    /// it has observed the bus idle for the time required by the protocol.
+    /// Off by default, leaving recovery to the application.
+    ///
+    /// With [`Self::poll_with_transceiver`], recovery is deferred while the
+    /// transceiver reports a fault and retried on each poll until it clears.
+    pub fn recover_from_bus_off(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
+    }
+
+    /// Services the peripheral: hands received messages, TX events and status
+    /// events to `handler`. Status events are handled first, so that
+    /// messages lost by an overrun are reported before those that follow.
+    pub fn poll<D, C, F, H>(
+        &mut self,
+        can: &mut Can<'_, Id, D, C, F>,
+        handler: &mut H,
+    ) -> PollSummary
+    where
+        D: mcan_core::Dependencies<Id>,
+        C: Capacities,
+        F: FrameFormat,
+        H: PollHandler<C::RxFifo0Message, C::RxFifo1Message>,
+    {
+        match self.poll_with_transceiver(can, handler, &mut NoTransceiver) {
+            Ok(summary) => summary,
+            Err(e) => match e {},
+        }
+    }
+
+    /// [`Self::poll`] with bus off recovery coordinated with `transceiver`:
+    /// the peripheral is only restarted if the transceiver reports no fault,
+    /// after switching it back to normal mode. Errors of the transceiver are
+    /// returned after all queues have been serviced.
+    pub fn poll_with_transceiver<D, C, F, H, T>(
+        &mut self,
+        can: &mut Can<'_, Id, D, C, F>,
+        handler: &mut H,
+        transceiver: &mut T,
+    ) -> Result<PollSummary, T::Error>
+    where
+        D: mcan_core::Dependencies<Id>,
+        C: Capacities,
+        F: FrameFormat,
+        H: PollHandler<C::RxFifo0Message, C::RxFifo1Message>,
+        T: Transceiver,
+    {
+        let mut summary = PollSummary::default();
+        let mut recovery = Ok(());
+        let flags = self.status.interrupt_flags();
+        if !flags.is_empty() {
+            self.status.clear_interrupts(flags);
+            let mut bus_off = false;
+            StatusEvent::decode(flags, &can.monitor.protocol_status(), |event| {
+                bus_off |= event == StatusEvent::BusOff;
+                summary.status += 1;
+                handler.on_status(event);
+            });
+            self.bus_off |= bus_off && self.recover;
+        }
+        if self.bus_off {
+            match Self::recover(&can.aux, transceiver) {
+                Ok(restarted) => self.bus_off = !restarted,
+                Err(e) => recovery = Err(e),
+            }
+        }
+        summary.rx_fifo_0 = can
+            .rx_fifo_0
+            .receive_bounded(self.max_per_poll, |message| handler.on_rx_fifo_0(message));
+        summary.rx_fifo_1 = can
+            .rx_fifo_1
+            .receive_bounded(self.max_per_poll, |message| handler.on_rx_fifo_1(message));
+        summary.tx_events = can
+            .tx_event_fifo
+            .pop_bounded(self.max_per_poll, |event| handler.on_tx_event(event));
+        recovery.map(|()| summary)
+    }
+
+    /// Restarts the peripheral after bus off unless the transceiver reports a
+    /// fault. Returns `true` once the peripheral is operational again, which
+    /// may also be the case if the application restarted it.
+    fn recover<T: Transceiver>(aux: &impl DynAux, transceiver: &mut T) -> Result<bool, T::Error> {
+        if aux.is_operational() {
+            return Ok(true);
+        }
+        if transceiver.fault()? {
+            return Ok(false);
+        }
+        transceiver.normal()?;
+        aux.operational_mode();
+        Ok(true)
+    }
+
+    /// Returns the status interrupts
+    pub fn release(self) -> OwnedInterruptSet<Id, state::Dynamic> {
+        self.status
+    }
+}
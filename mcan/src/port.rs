@@ -0,0 +1,141 @@
+//! Generic access to CAN peripherals
+//!
+//! The handles of a [`Can`] are parametrized by the peripheral and its
+//! [`Capacities`], so code written for one instance does not accept another.
+//! [`CanPort`] erases these parameters behind the `Dyn*` traits of the
+//! handles. Gateways, schedulers or statistics written against [`CanPort`]
+//! serve any number of peripherals, each with its own capacities.
+//!
+//! ```no_run
+//! use mcan::message::tx;
+//! use mcan::port::CanPort;
+//! use mcan::rx_fifo::DynRxFifo;
+//! use mcan::tx_buffers::DynTx;
+//!
+//! /// Forwards frames received by `from` to `to`
+//! fn forward<A: CanPort, B: CanPort>(from: &mut A, to: &mut B)
+//! where
+//!     <B::Tx as DynTx>::Message: tx::AnyMessage,
+//! {
+//!     while let Ok(message) = from.rx_fifo_0().receive() {
+//!         if let Ok(frame) = B::convert(&message) {
+//!             let _ = to.tx().transmit_queued(frame);
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::bus::{Aux, Can, DynAux};
+use crate::config::FrameFormat;
+use crate::message::{rx, tx, TooMuchData};
+use crate::messageram::Capacities;
+use crate::monitor::{DynMonitor, Monitor};
+use crate::rx_dedicated_buffers::{DynRxDedicatedBuffer, RxDedicatedBuffer};
+use crate::rx_fifo::{DynRxFifo, Fifo0, Fifo1, RxFifo};
+use crate::tx_buffers::{DynTx, Tx};
+use crate::tx_event_fifo::{DynTxEventFifo, TxEventFifo};
+
+/// Mutable access to all handles of a [`CanPort`] at once
+pub struct PortParts<'p, P: CanPort + ?Sized> {
+    /// Receive FIFO 0
+    pub rx_fifo_0: &'p mut P::RxFifo0,
+    /// Receive FIFO 1
+    pub rx_fifo_1: &'p mut P::RxFifo1,
+    /// Dedicated receive buffers
+    pub rx_dedicated_buffers: &'p mut P::RxDedicatedBuffers,
+    /// Transmit buffers
+    pub tx: &'p mut P::Tx,
+    /// Transmit event FIFO
+    pub tx_event_fifo: &'p mut P::TxEventFifo,
+    /// Auxiliary functionality
+    pub aux: &'p mut P::Aux,
+    /// Status registers
+    pub monitor: &'p P::Monitor,
+}
+
+/// CAN peripheral with its capacities erased
+pub trait CanPort {
+    /// Receive FIFO 0
+    type RxFifo0: DynRxFifo<Message: rx::AnyMessage>;
+    /// Receive FIFO 1
+    type RxFifo1: DynRxFifo<Message: rx::AnyMessage>;
+    /// Dedicated receive buffers
+    type RxDedicatedBuffers: DynRxDedicatedBuffer<Message: rx::AnyMessage>;
+    /// Transmit buffers
+    type Tx: DynTx;
+    /// Transmit event FIFO
+    type TxEventFifo: DynTxEventFifo;
+    /// Auxiliary functionality
+    type Aux: DynAux;
+    /// Status registers
+    type Monitor: DynMonitor;
+
+    /// Returns receive FIFO 0
+    fn rx_fifo_0(&mut self) -> &mut Self::RxFifo0;
+
+    /// Returns receive FIFO 1
+    fn rx_fifo_1(&mut self) -> &mut Self::RxFifo1;
+
+    /// Returns the transmit buffers
+    fn tx(&mut self) -> &mut Self::Tx;
+
+    /// Returns the status registers
+    fn monitor(&self) -> &Self::Monitor;
+
+    /// Returns all handles at once
+    fn parts(&mut self) -> PortParts<'_, Self>;
+
+    /// Converts a received message into a message transmittable through this
+    /// port, e.g. to forward it from another port. Fails if the payload does
+    /// not fit.
+    fn convert<M: rx::AnyMessage>(message: &M) -> Result<<Self::Tx as DynTx>::Message, TooMuchData>
+    where
+        <Self::Tx as DynTx>::Message: tx::AnyMessage,
+    {
+        <<Self::Tx as DynTx>::Message as tx::AnyMessage>::new(message.as_tx_builder())
+    }
+}
+
+impl<'a, Id, D, C, F> CanPort for Can<'a, Id, D, C, F>
+where
+    Id: mcan_core::CanId,
+    D: mcan_core::Dependencies<Id>,
+    C: Capacities,
+    F: FrameFormat,
+{
+    type RxFifo0 = RxFifo<'a, Fifo0, Id, C::RxFifo0Message>;
+    type RxFifo1 = RxFifo<'a, Fifo1, Id, C::RxFifo1Message>;
+    type RxDedicatedBuffers = RxDedicatedBuffer<'a, Id, C::RxBufferMessage>;
+    type Tx = Tx<'a, Id, C, F>;
+    type TxEventFifo = TxEventFifo<'a, Id>;
+    type Aux = Aux<'a, Id, D>;
+    type Monitor = Monitor<Id>;
+
+    fn rx_fifo_0(&mut self) -> &mut Self::RxFifo0 {
+        &mut self.rx_fifo_0
+    }
+
+    fn rx_fifo_1(&mut self) -> &mut Self::RxFifo1 {
+        &mut self.rx_fifo_1
+    }
+
+    fn tx(&mut self) -> &mut Self::Tx {
+        &mut self.tx
+    }
+
+    fn monitor(&self) -> &Self::Monitor {
+        &self.monitor
+    }
+
+    fn parts(&mut self) -> PortParts<'_, Self> {
+        PortParts {
+            rx_fifo_0: &mut self.rx_fifo_0,
+            rx_fifo_1: &mut self.rx_fifo_1,
+            rx_dedicated_buffers: &mut self.rx_dedicated_buffers,
+            tx: &mut self.tx,
+            tx_event_fifo: &mut self.tx_event_fifo,
+            aux: &mut self.aux,
+            monitor: &self.monitor,
+        }
+    }
+}
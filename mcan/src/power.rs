@@ -0,0 +1,254 @@
+//! Sequencing of sleep entry and exit
+//!
+//! Putting a CAN node to sleep involves the controller and the transceiver
+//! in a particular order: the controller finishes its ongoing transfers and
+//! stops its clock, the interrupt lines are masked so that the controller
+//! does not keep the CPU awake, and only then the transceiver enters standby,
+//! from which it signals wake-ups on bus activity. Waking up runs the same
+//! steps in reverse.
+//!
+//! Bus disturbances can wake the transceiver without any node actually
+//! addressing this one. [`PowerManager`] therefore confirms each wake-up with
+//! bus activity, typically a received frame, within a number of polls, and
+//! goes back to sleep otherwise.
+//!
+//! The transceiver is controlled through the [`Transceiver`] trait, which is
+//! implemented by the application or the board support, e.g. for a standby
+//! pin or an SPI driver. Besides sleep, it is used by the bus off recovery of
+//! [`Poller`] and by the [`LivenessWatchdog`]. [`NoTransceiver`] stands in for
+//! transceivers without any control.
+//!
+//! ```no_run
+//! use mcan::bus::DynAux;
+//! use mcan::interrupt::InterruptConfiguration;
+//! use mcan::power::{PowerManager, Transceiver};
+//!
+//! fn go_to_sleep<Id: mcan::core::CanId, T: Transceiver>(
+//!     power: &mut PowerManager<T>,
+//!     aux: &impl DynAux,
+//!     interrupts: &mut InterruptConfiguration<Id>,
+//! ) -> Result<(), T::Error> {
+//!     nb::block!(power.sleep(aux, interrupts))
+//! }
+//!
+//! // Called from the wake-up interrupt of the transceiver, then periodically
+//! fn on_wake<Id: mcan::core::CanId, T: Transceiver>(
+//!     power: &mut PowerManager<T>,
+//!     aux: &impl DynAux,
+//!     interrupts: &mut InterruptConfiguration<Id>,
+//!     frame_received: bool,
+//! ) -> Result<(), T::Error> {
+//!     power.wake(aux)?;
+//!     match power.confirm_wake(frame_received, aux, interrupts) {
+//!         Ok(true) => { /* Awake */ }
+//!         Ok(false) => { /* Spurious wake-up, asleep again */ }
+//!         Err(nb::Error::WouldBlock) => { /* Poll again later */ }
+//!         Err(nb::Error::Other(e)) => return Err(e),
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Poller`]: crate::polled::Poller
+//! [`LivenessWatchdog`]: crate::watchdog::LivenessWatchdog
+
+use crate::bus::DynAux;
+use crate::interrupt::InterruptConfiguration;
+
+/// Mode control and status of a CAN transceiver
+///
+/// Only the mode switches are mandatory. Transceivers without a sleep mode,
+/// error flag or wake-up detection keep the provided implementations.
+pub trait Transceiver {
+    /// Error controlling the transceiver
+    type Error;
+
+    /// Switches to normal mode, transmitting and receiving
+    fn normal(&mut self) -> Result<(), Self::Error>;
+
+    /// Switches to standby, in which the transceiver only watches the bus for
+    /// wake-up events
+    fn standby(&mut self) -> Result<(), Self::Error>;
+
+    /// Switches to sleep, the lowest power mode from which the transceiver
+    /// still detects wake-ups. Falls back to standby by default.
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.standby()
+    }
+
+    /// Returns `true` if the transceiver reports a fault, e.g. a shorted bus
+    /// line, overtemperature or a dominant timeout of the transmit pin.
+    /// Reports no faults by default.
+    fn fault(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Returns `true` if the transceiver detected a wake-up on the bus since
+    /// the last call, for transceivers signalling wake-ups through a status
+    /// register rather than an interrupt. Detects none by default.
+    fn wake_detected(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
+
+/// Transceiver without any control, always in normal mode
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoTransceiver;
+
+impl Transceiver for NoTransceiver {
+    type Error = core::convert::Infallible;
+
+    fn normal(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn standby(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Power state of the node, see [`PowerManager`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PowerState {
+    /// Controller and transceiver are operating
+    Active,
+    /// The controller was asked to finish its transfers and stop its clock
+    EnteringSleep,
+    /// The controller is stopped and the transceiver in standby
+    Asleep,
+    /// Woken up, waiting for bus activity to confirm the wake-up with at most
+    /// the given number of polls left
+    Waking(u32),
+}
+
+/// Sequences the transceiver, controller clock stop and interrupt lines on
+/// sleep entry and exit
+pub struct PowerManager<T> {
+    transceiver: T,
+    state: PowerState,
+    deep_sleep: bool,
+    confirm_polls: u32,
+}
+
+impl<T: Transceiver> PowerManager<T> {
+    /// Manages `transceiver` of an active node. Wake-ups without bus activity
+    /// within `confirm_polls` calls to [`Self::confirm_wake`] are considered
+    /// spurious.
+    pub fn new(transceiver: T, confirm_polls: u32) -> Self {
+        Self {
+            transceiver,
+            state: PowerState::Active,
+            confirm_polls,
+            deep_sleep: false,
+        }
+    }
+
+    /// Puts the transceiver into [`Transceiver::sleep`] instead of
+    /// [`Transceiver::standby`] when the node goes to sleep
+    pub fn deep_sleep(mut self, deep_sleep: bool) -> Self {
+        self.deep_sleep = deep_sleep;
+        self
+    }
+
+    /// Returns the current power state
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+
+    /// Gives access to the transceiver
+    pub fn transceiver(&mut self) -> &mut T {
+        &mut self.transceiver
+    }
+
+    /// Puts the node to sleep. Fails with [`nb::Error::WouldBlock`] while the
+    /// controller is finishing its transfers. Once it has stopped, the
+    /// interrupt lines are masked and the transceiver enters standby, or sleep
+    /// if configured by [`Self::deep_sleep`].
+    ///
+    /// A congested bus can keep the controller from stopping; see
+    /// [`DynAux::is_ready_for_power_off`].
+    pub fn sleep<Id: mcan_core::CanId>(
+        &mut self,
+        aux: &impl DynAux,
+        interrupts: &mut InterruptConfiguration<Id>,
+    ) -> nb::Result<(), T::Error> {
+        match self.state {
+            PowerState::Asleep => return Ok(()),
+            PowerState::Active | PowerState::Waking(_) => {
+                aux.power_down_mode();
+                self.state = PowerState::EnteringSleep;
+            }
+            PowerState::EnteringSleep => {}
+        }
+        if !aux.is_ready_for_power_off() {
+            return Err(nb::Error::WouldBlock);
+        }
+        interrupts.mask_lines();
+        if self.deep_sleep {
+            self.transceiver.sleep()?;
+        } else {
+            self.transceiver.standby()?;
+        }
+        self.state = PowerState::Asleep;
+        Ok(())
+    }
+
+    /// Handles a wake-up: the transceiver returns to normal mode and the
+    /// controller to operation, with its interrupt lines still masked until
+    /// the wake-up is confirmed. Does nothing unless asleep.
+    pub fn wake(&mut self, aux: &impl DynAux) -> Result<(), T::Error> {
+        if self.state != PowerState::Asleep {
+            return Ok(());
+        }
+        self.transceiver.normal()?;
+        aux.operational_mode();
+        self.state = PowerState::Waking(self.confirm_polls);
+        Ok(())
+    }
+
+    /// Calls [`Self::wake`] if the transceiver detected a wake-up, see
+    /// [`Transceiver::wake_detected`]. Returns `true` if it did.
+    pub fn poll_wake(&mut self, aux: &impl DynAux) -> Result<bool, T::Error> {
+        if self.state != PowerState::Asleep || !self.transceiver.wake_detected()? {
+            return Ok(false);
+        }
+        self.wake(aux)?;
+        Ok(true)
+    }
+
+    /// Confirms a wake-up by bus `activity`, unmasking the interrupt lines,
+    /// and returns `true`. Without activity, fails with
+    /// [`nb::Error::WouldBlock`] until the configured number of polls has
+    /// passed, then goes back to sleep and returns `false`.
+    ///
+    /// Returns `true` right away when active.
+    pub fn confirm_wake<Id: mcan_core::CanId>(
+        &mut self,
+        activity: bool,
+        aux: &impl DynAux,
+        interrupts: &mut InterruptConfiguration<Id>,
+    ) -> nb::Result<bool, T::Error> {
+        match self.state {
+            PowerState::Active => Ok(true),
+            PowerState::Waking(_) if activity => {
+                interrupts.unmask_lines();
+                self.state = PowerState::Active;
+                Ok(true)
+            }
+            PowerState::Waking(polls) if polls > 1 => {
+                self.state = PowerState::Waking(polls - 1);
+                Err(nb::Error::WouldBlock)
+            }
+            PowerState::Waking(_) | PowerState::EnteringSleep => {
+                self.sleep(aux, interrupts)?;
+                Ok(false)
+            }
+            PowerState::Asleep => Ok(false),
+        }
+    }
+
+    /// Returns the transceiver
+    pub fn release(self) -> T {
+        self.transceiver
+    }
+}
@@ -14,6 +14,7 @@ pub use rx::AnyMessage as _;
 pub use tx::AnyMessage as _;
 
 pub use crate::bus::DynAux as _;
+pub use crate::monitor::DynMonitor as _;
 pub use crate::rx_dedicated_buffers::DynRxDedicatedBuffer as _;
 pub use crate::rx_fifo::DynRxFifo as _;
 pub use crate::tx_buffers::DynTx as _;
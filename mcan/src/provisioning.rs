@@ -0,0 +1,463 @@
+//! Persisted configuration for field provisioning
+//!
+//! Devices configured in the field store their CAN configuration in flash or
+//! EEPROM and apply it at boot. [`Provisioned`] holds a complete
+//! configuration, the [`CanConfig`] and both filter lists, and encodes it into
+//! a compact binary representation with [`Provisioned::encode`].
+//! [`CanConfigurable::provision`] applies a decoded configuration.
+//!
+//! # Format
+//!
+//! The encoding is the [postcard] wire format of the configuration, so that
+//! provisioning tools can produce it with `serde` and `postcard` from a
+//! mirroring struct:
+//!
+//! - integers wider than a byte are unsigned LEB128, bytes are stored as is
+//! - `bool`s are a byte of 0 or 1
+//! - enums are the LEB128 index of the variant followed by its fields
+//! - `Option`s are a byte of 0, or a byte of 1 followed by the value
+//! - sequences are the LEB128 number of elements followed by the elements
+//!
+//! The first byte is the format version, [`FORMAT_VERSION`]. It is followed
+//! by the fields of [`CanConfig`] in declaration order, bitrates in Hz and
+//! enums from the register definitions by their register values, and the
+//! sequences of standard and extended filter elements, each element as its
+//! words in the peripheral's representation.
+//!
+//! The encoding has no integrity check. Storage that may be corrupted needs
+//! one of its own, e.g. a CRC over the encoded bytes.
+//!
+//! ```no_run
+//! use mcan::config::CanConfig;
+//! use mcan::filter::{Action, Filter};
+//! use mcan::provisioning::Provisioned;
+//! use mcan::embedded_can::StandardId;
+//! # use fugit::RateExtU32;
+//!
+//! let mut provisioned = Provisioned::<4, 0>::new(CanConfig::new(500.kHz()));
+//! provisioned.standard.push(Filter::Classic {
+//!     action: Action::StoreFifo0,
+//!     filter: StandardId::new(0x100).unwrap(),
+//!     mask: StandardId::MAX,
+//! });
+//! let mut storage = [0; 64];
+//! let len = provisioned.encode(&mut storage).unwrap();
+//! // At boot
+//! let provisioned = Provisioned::<4, 0>::decode(&storage[..len]).unwrap();
+//! ```
+//!
+//! [postcard]: https://docs.rs/postcard
+//! [`CanConfigurable::provision`]: crate::bus::CanConfigurable::provision
+
+pub use crate::compress::BufferFull;
+use crate::config::{
+    BitTiming, CanConfig, Mode, NonMatchingFrames, RxFifoConfig, RxFifoMode, TimeStampSelect,
+    TimeoutSelect, TxQueueMode,
+};
+use crate::filter::{
+    FilterExtendedId, FilterStandardId, StagedFiltersExtended, StagedFiltersStandard,
+};
+use fugit::HertzU32;
+
+/// Version of the encoding written by [`Provisioned::encode`]
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Encoded configuration cannot be decoded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes end within the configuration
+    Truncated,
+    /// The configuration was encoded in an unknown format version
+    UnsupportedVersion(u8),
+    /// A value is out of range of its field
+    InvalidValue,
+    /// A filter list holds more filters than the staging buffer
+    TooManyFilters,
+    /// An RX FIFO is configured in overwrite mode, which needs
+    /// [`Provisioned::decode_allowing_overwrite`]
+    OverwriteMode,
+}
+
+/// Complete configuration of the peripheral as persisted for provisioning,
+/// with up to `S` standard and `E` extended filters
+pub struct Provisioned<const S: usize, const E: usize> {
+    /// Configuration of the bus
+    pub config: CanConfig,
+    /// Filters for standard IDs
+    pub standard: StagedFiltersStandard<S>,
+    /// Filters for extended IDs
+    pub extended: StagedFiltersExtended<E>,
+}
+
+impl<const S: usize, const E: usize> Provisioned<S, E> {
+    /// Creates a configuration without filters
+    pub fn new(config: CanConfig) -> Self {
+        Self {
+            config,
+            standard: StagedFiltersStandard::new(),
+            extended: StagedFiltersExtended::new(),
+        }
+    }
+
+    /// Encodes the configuration into `buffer`. Returns the number of bytes
+    /// used.
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, BufferFull> {
+        let mut w = Writer { buffer, len: 0 };
+        let c = &self.config;
+        w.byte(FORMAT_VERSION)?;
+        match c.mode {
+            Mode::Classic => w.varint(0)?,
+            Mode::Fd {
+                allow_bit_rate_switching,
+                data_phase_timing,
+            } => {
+                w.varint(1)?;
+                w.bool(allow_bit_rate_switching)?;
+                w.bit_timing(&data_phase_timing)?;
+            }
+        }
+        w.bool(c.loopback)?;
+        w.bit_timing(&c.nominal_timing)?;
+        w.varint(u8::from(c.timestamp.select).into())?;
+        w.byte(c.timestamp.prescaler)?;
+        w.bool(c.timeout.enabled)?;
+        w.varint(u8::from(c.timeout.select).into())?;
+        w.varint(c.timeout.period.into())?;
+        for fifo in [&c.rx_fifo_0, &c.rx_fifo_1] {
+            w.varint(bool::from(fifo.mode).into())?;
+            w.byte(fifo.watermark)?;
+        }
+        w.byte(c.tx.tx_event_fifo_watermark)?;
+        w.varint(bool::from(c.tx.tx_queue_submode).into())?;
+        for handling in [c.global_filter.standard, c.global_filter.extended] {
+            w.varint(match handling {
+                NonMatchingFrames::Reject => 0,
+                NonMatchingFrames::Fifo0 => 1,
+                NonMatchingFrames::Fifo1 => 2,
+            })?;
+        }
+        match c.clock_tolerance_ppm {
+            None => w.byte(0)?,
+            Some(ppm) => {
+                w.byte(1)?;
+                w.varint(ppm)?;
+            }
+        }
+        let standard = self.standard.filters();
+        w.varint(u32::try_from(standard.len()).unwrap_or(u32::MAX))?;
+        for filter in standard {
+            w.varint(filter.0)?;
+        }
+        let extended = self.extended.filters();
+        w.varint(u32::try_from(extended.len()).unwrap_or(u32::MAX))?;
+        for filter in extended {
+            filter.0.iter().try_for_each(|&word| w.varint(word))?;
+        }
+        Ok(w.len)
+    }
+
+    /// Decodes a configuration encoded by [`Self::encode`]. Bytes following
+    /// it, e.g. erased flash, are ignored.
+    ///
+    /// Fails with [`DecodeError::OverwriteMode`] if an RX FIFO is configured
+    /// in overwrite mode, as that mode puts requirements on reading the FIFO,
+    /// see [`RxFifoMode::overwrite`].
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode_with(bytes, false)
+    }
+
+    /// Decodes a configuration like [`Self::decode`], accepting RX FIFOs in
+    /// overwrite mode.
+    ///
+    /// # Safety
+    /// The application must read RX FIFOs in overwrite mode as described in
+    /// [`RxFifoMode::overwrite`].
+    pub unsafe fn decode_allowing_overwrite(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode_with(bytes, true)
+    }
+
+    fn decode_with(bytes: &[u8], allow_overwrite: bool) -> Result<Self, DecodeError> {
+        let mut r = Reader { bytes };
+        let version = r.byte()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let mode = match r.varint()? {
+            0 => Mode::Classic,
+            1 => Mode::Fd {
+                allow_bit_rate_switching: r.bool()?,
+                data_phase_timing: r.bit_timing()?,
+            },
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        let loopback = r.bool()?;
+        let mut config = CanConfig::new(HertzU32::from_raw(0));
+        config.mode = mode;
+        config.loopback = loopback;
+        config.nominal_timing = r.bit_timing()?;
+        config.timestamp.select = match r.varint()? {
+            0 => TimeStampSelect::ZERO,
+            1 => TimeStampSelect::INC,
+            2 => TimeStampSelect::EXT,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        config.timestamp.prescaler = r.byte()?;
+        config.timeout.enabled = r.bool()?;
+        config.timeout.select = match r.varint()? {
+            0 => TimeoutSelect::CONT,
+            1 => TimeoutSelect::TXEF,
+            2 => TimeoutSelect::RXF0,
+            3 => TimeoutSelect::RXF1,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        config.timeout.period = r.varint()?.try_into().or(Err(DecodeError::InvalidValue))?;
+        for fifo in [&mut config.rx_fifo_0, &mut config.rx_fifo_1] {
+            let mode = match r.varint()? {
+                0 => RxFifoMode::blocking(),
+                // Safety: The caller of `decode_allowing_overwrite` takes
+                // responsibility for reading the FIFO accordingly
+                1 if allow_overwrite => unsafe { RxFifoMode::overwrite() },
+                1 => return Err(DecodeError::OverwriteMode),
+                _ => return Err(DecodeError::InvalidValue),
+            };
+            *fifo = RxFifoConfig {
+                mode,
+                watermark: r.byte()?,
+            };
+        }
+        config.tx.tx_event_fifo_watermark = r.byte()?;
+        config.tx.tx_queue_submode = match r.varint()? {
+            0 => TxQueueMode::Fifo,
+            1 => TxQueueMode::Priority,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        for handling in [
+            &mut config.global_filter.standard,
+            &mut config.global_filter.extended,
+        ] {
+            *handling = match r.varint()? {
+                0 => NonMatchingFrames::Reject,
+                1 => NonMatchingFrames::Fifo0,
+                2 => NonMatchingFrames::Fifo1,
+                _ => return Err(DecodeError::InvalidValue),
+            };
+        }
+        config.clock_tolerance_ppm = match r.byte()? {
+            0 => None,
+            1 => Some(r.varint()?),
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        let mut provisioned = Self::new(config);
+        for _ in 0..r.varint()? {
+            let filter = FilterStandardId(r.varint()?);
+            provisioned
+                .standard
+                .push(filter)
+                .or(Err(DecodeError::TooManyFilters))?;
+        }
+        for _ in 0..r.varint()? {
+            let filter = FilterExtendedId([r.varint()?, r.varint()?]);
+            provisioned
+                .extended
+                .push(filter)
+                .or(Err(DecodeError::TooManyFilters))?;
+        }
+        Ok(provisioned)
+    }
+}
+
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn byte(&mut self, byte: u8) -> Result<(), BufferFull> {
+        let slot = self.buffer.get_mut(self.len).ok_or(BufferFull)?;
+        *slot = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn bool(&mut self, value: bool) -> Result<(), BufferFull> {
+        self.byte(value.into())
+    }
+
+    fn varint(&mut self, mut value: u32) -> Result<(), BufferFull> {
+        loop {
+            let low = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return self.byte(low);
+            }
+            self.byte(low | 0x80)?;
+        }
+    }
+
+    fn bit_timing(&mut self, timing: &BitTiming) -> Result<(), BufferFull> {
+        self.byte(timing.sjw)?;
+        self.byte(timing.phase_seg_1)?;
+        self.byte(timing.phase_seg_2)?;
+        self.varint(timing.bitrate.raw())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(DecodeError::Truncated)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u32, DecodeError> {
+        let mut value: u32 = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u32::from(byte & 0x7f);
+            // The fifth byte holds only the top four bits
+            if bits.checked_shl(shift).map(|v| v >> shift) != Some(bits) {
+                return Err(DecodeError::InvalidValue);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::InvalidValue)
+    }
+
+    fn bit_timing(&mut self) -> Result<BitTiming, DecodeError> {
+        Ok(BitTiming {
+            sjw: self.byte()?,
+            phase_seg_1: self.byte()?,
+            phase_seg_2: self.byte()?,
+            bitrate: HertzU32::from_raw(self.varint()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::{Action, ExtFilter, Filter};
+    use embedded_can::{ExtendedId, StandardId};
+    use fugit::RateExtU32;
+
+    fn provisioned() -> Provisioned<2, 1> {
+        let mut config = CanConfig::new(500.kHz());
+        config.mode = Mode::Fd {
+            allow_bit_rate_switching: true,
+            data_phase_timing: BitTiming::new(2.MHz()),
+        };
+        config.timeout.select = TimeoutSelect::RXF1;
+        config.timeout.period = 1000;
+        config.tx.tx_queue_submode = TxQueueMode::Priority;
+        config.global_filter.extended = NonMatchingFrames::Fifo1;
+        config.clock_tolerance_ppm = Some(100);
+        let mut provisioned = Provisioned::new(config);
+        provisioned
+            .standard
+            .push(Filter::Range {
+                action: Action::StoreFifo0,
+                low: StandardId::new(0x100).unwrap(),
+                high: StandardId::new(0x1ff).unwrap(),
+            })
+            .ok()
+            .unwrap();
+        provisioned
+            .extended
+            .push(ExtFilter::Classic {
+                action: Action::StoreFifo1,
+                filter: ExtendedId::MAX,
+                mask: ExtendedId::MAX,
+            })
+            .ok()
+            .unwrap();
+        provisioned
+    }
+
+    #[test]
+    fn configuration_survives_a_round_trip() {
+        let mut buffer = [0xff; 64];
+        let len = provisioned().encode(&mut buffer).unwrap();
+        let decoded = Provisioned::<2, 1>::decode(&buffer).unwrap();
+        let c = decoded.config;
+        assert!(matches!(
+            c.mode,
+            Mode::Fd {
+                allow_bit_rate_switching: true,
+                data_phase_timing: BitTiming { bitrate, .. },
+            } if bitrate == 2.MHz::<1, 1>()
+        ));
+        assert_eq!(c.nominal_timing.bitrate, 500.kHz::<1, 1>());
+        assert_eq!(c.nominal_timing.phase_seg_1, 0xB);
+        assert_eq!(c.timeout.select, TimeoutSelect::RXF1);
+        assert_eq!(c.timeout.period, 1000);
+        assert!(bool::from(c.tx.tx_queue_submode));
+        assert_eq!(c.global_filter.extended, NonMatchingFrames::Fifo1);
+        assert_eq!(c.clock_tolerance_ppm, Some(100));
+        let original = provisioned();
+        assert_eq!(decoded.standard.len(), 1);
+        assert_eq!(
+            decoded.standard.filters()[0].0,
+            original.standard.filters()[0].0
+        );
+        assert_eq!(
+            decoded.extended.filters()[0].0,
+            original.extended.filters()[0].0
+        );
+
+        let mut reencoded = [0; 64];
+        assert_eq!(decoded.encode(&mut reencoded), Ok(len));
+        assert_eq!(reencoded[..len], buffer[..len]);
+    }
+
+    #[test]
+    fn invalid_encodings_are_rejected() {
+        let mut buffer = [0; 64];
+        let len = provisioned().encode(&mut buffer).unwrap();
+        assert_eq!(
+            provisioned().encode(&mut buffer[..len - 1]),
+            Err(BufferFull)
+        );
+        assert!(matches!(
+            Provisioned::<2, 1>::decode(&buffer[..len - 1]),
+            Err(DecodeError::Truncated)
+        ));
+        assert!(matches!(
+            Provisioned::<2, 0>::decode(&buffer),
+            Err(DecodeError::TooManyFilters)
+        ));
+        buffer[0] = 2;
+        assert!(matches!(
+            Provisioned::<2, 1>::decode(&buffer),
+            Err(DecodeError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn overwrite_mode_needs_opting_in() {
+        let mut provisioned = provisioned();
+        provisioned.config.rx_fifo_1.mode = unsafe { RxFifoMode::overwrite() };
+        let mut buffer = [0; 64];
+        provisioned.encode(&mut buffer).unwrap();
+        assert!(matches!(
+            Provisioned::<2, 1>::decode(&buffer),
+            Err(DecodeError::OverwriteMode)
+        ));
+        let decoded = unsafe { Provisioned::<2, 1>::decode_allowing_overwrite(&buffer) }.unwrap();
+        assert!(bool::from(decoded.config.rx_fifo_1.mode));
+    }
+}
@@ -296,6 +296,10 @@ pub mod rxfc;
 pub type RXFS = crate::Reg<rxfs::RXFS_SPEC>;
 #[doc = "Rx FIFO Status"]
 pub mod rxfs;
+#[doc = "RXF1S register accessor: an alias for `Reg<RXF1S_SPEC>`"]
+pub type RXF1S = crate::Reg<rxf1s::RXF1S_SPEC>;
+#[doc = "Rx FIFO 1 Status"]
+pub mod rxf1s;
 #[doc = "RXFA register accessor: an alias for `Reg<RXFA_SPEC>`"]
 pub type RXFA = crate::Reg<rxfa::RXFA_SPEC>;
 #[doc = "Rx FIFO Acknowledge"]
@@ -0,0 +1,188 @@
+//! Replay of recorded traffic
+//!
+//! Regression tests of ECUs often feed them with traffic captured on a real
+//! bus. [`Replay`] transmits a sequence of records, each a frame with its
+//! delay after the previous one, keeping the relative timing of the capture.
+//!
+//! Time is taken from a [`MonotonicClock`], so any [`TimestampSource`] with a
+//! resolution fine enough for the capture can drive the replay. The schedule
+//! is anchored at the start of the replay: frames delayed by a full transmit
+//! queue are sent as soon as possible, and the following frames keep their
+//! original spacing to the start rather than to the delayed frame.
+//!
+//! ```no_run
+//! use mcan::message::tx;
+//! use mcan::replay::Replay;
+//! use mcan::rx_timestamped::MonotonicClock;
+//! use mcan::timestamp::TimestampSource;
+//! use mcan::tx_buffers::DynTx;
+//! use fugit::MicrosDurationU32;
+//!
+//! fn replay(
+//!     tx: &mut impl DynTx<Message = tx::Message<8>>,
+//!     source: impl TimestampSource,
+//!     records: &[(MicrosDurationU32, tx::Message<8>)],
+//! ) {
+//!     let mut replay = Replay::new(records.iter().copied(), MonotonicClock::new(source));
+//!     while let Err(nb::Error::WouldBlock) = replay.poll(tx) {}
+//! }
+//! ```
+//!
+//! [`TimestampSource`]: crate::timestamp::TimestampSource
+
+use crate::rx_timestamped::MonotonicClock;
+use crate::timestamp::TimestampSource;
+use crate::tx_buffers::{DynTx, Error};
+use fugit::MicrosDurationU32;
+
+/// Transmits records of `(delay, frame)` with their relative timing
+pub struct Replay<M, I, S> {
+    records: I,
+    clock: MonotonicClock<S>,
+    /// Frame waiting for its time or for space in the queue
+    pending: Option<M>,
+    /// Time the pending frame is due, in nanoseconds of the clock
+    due: u64,
+    sent: usize,
+}
+
+impl<M, I, S> Replay<M, I, S>
+where
+    M: Clone,
+    I: Iterator<Item = (MicrosDurationU32, M)>,
+    S: TimestampSource,
+{
+    /// Starts replaying `records` now, timed by `clock`. The delay of the
+    /// first record is counted from now.
+    pub fn new(records: I, mut clock: MonotonicClock<S>) -> Self {
+        let now = clock.now();
+        let due = clock.to_nanos(now);
+        Self {
+            records,
+            clock,
+            pending: None,
+            due,
+            sent: 0,
+        }
+    }
+
+    /// Transmits all frames that are due through `tx`. Returns `Ok(())` once
+    /// all records are sent and [`nb::Error::WouldBlock`] while frames are
+    /// waiting for their time or for space in the transmit queue.
+    ///
+    /// A frame rejected by `tx` with an error is skipped, and the error is
+    /// returned. Polling again continues with the next record.
+    pub fn poll<T: DynTx<Message = M>>(&mut self, tx: &mut T) -> nb::Result<(), Error> {
+        let now = self.clock.now();
+        let now = self.clock.to_nanos(now);
+        self.poll_at(now, |frame| tx.transmit_queued(frame))
+    }
+
+    /// Returns the number of frames sent so far
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    /// Returns the nanoseconds until the next frame is due, zero if it is
+    /// overdue, or `None` if all records are sent. Useful to sleep between
+    /// polls.
+    pub fn until_next(&mut self) -> Option<u64> {
+        let now = self.clock.now();
+        let now = self.clock.to_nanos(now);
+        self.next()?;
+        Some(self.due.saturating_sub(now))
+    }
+
+    /// Returns the clock and the records not sent yet
+    pub fn release(self) -> (MonotonicClock<S>, I) {
+        (self.clock, self.records)
+    }
+
+    /// Returns the pending frame, taking the next record if there is none
+    fn next(&mut self) -> Option<&M> {
+        if self.pending.is_none() {
+            let (delay, frame) = self.records.next()?;
+            self.due = self
+                .due
+                .saturating_add(u64::from(delay.ticks()).saturating_mul(1000));
+            self.pending = Some(frame);
+        }
+        self.pending.as_ref()
+    }
+
+    fn poll_at(
+        &mut self,
+        now: u64,
+        mut send: impl FnMut(M) -> nb::Result<(), Error>,
+    ) -> nb::Result<(), Error> {
+        while self.next().is_some() {
+            if self.due > now {
+                return Err(nb::Error::WouldBlock);
+            }
+            let Some(frame) = self.pending.clone() else {
+                break;
+            };
+            let result = send(frame);
+            if let Err(nb::Error::WouldBlock) = result {
+                return result;
+            }
+            self.pending = None;
+            result?;
+            self.sent += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::timestamp::Timer;
+    use core::cell::Cell;
+    use fugit::ExtU32;
+    use std::vec::Vec;
+
+    #[test]
+    fn keeps_relative_timing() {
+        let counter = Cell::new(0);
+        let clock = MonotonicClock::new(Timer::new(|| counter.get(), 32, 1000.nanos()));
+        let records = [
+            (0.micros(), 1),
+            (10.micros(), 2),
+            (0.micros(), 3),
+            (5.micros(), 4),
+        ];
+        let mut replay = Replay::new(records.into_iter(), clock);
+        let mut sent = Vec::new();
+        let full = Cell::new(false);
+        let mut send = |frame| {
+            if full.get() {
+                Err(nb::Error::WouldBlock)
+            } else {
+                sent.push(frame);
+                Ok(())
+            }
+        };
+        assert!(matches!(
+            replay.poll_at(9_999, &mut send),
+            Err(nb::Error::WouldBlock)
+        ));
+        full.set(true);
+        assert!(matches!(
+            replay.poll_at(10_000, &mut send),
+            Err(nb::Error::WouldBlock)
+        ));
+        full.set(false);
+        // Delayed by a full queue, the last frame keeps its time
+        assert!(matches!(
+            replay.poll_at(14_000, &mut send),
+            Err(nb::Error::WouldBlock)
+        ));
+        assert_eq!(replay.sent(), 3);
+        assert!(replay.poll_at(15_000, &mut send).is_ok());
+        assert_eq!(sent, [1, 2, 3, 4]);
+        assert_eq!(replay.until_next(), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! Summary of the applied configuration
+//!
+//! Requirements on a CAN node are usually stated in terms of the bus, like
+//! bitrates and sample points, while the configuration is given in terms of
+//! the peripheral. [`InitReport`] states the configuration applied by
+//! [`CanConfigurable::finalize`] in both terms, for logging and for checking
+//! it against the requirements at startup.
+//!
+//! ```no_run
+//! # fn report<Id: mcan::core::CanId, D: mcan::core::Dependencies<Id>, C: mcan::messageram::Capacities>(
+//! #     can: &mcan::bus::Can<'_, Id, D, C>,
+//! # ) {
+//! let report = can.init_report();
+//! assert!((800..=875).contains(&report.nominal.sample_point_permille));
+//! # }
+//! ```
+//!
+//! [`CanConfigurable::finalize`]: crate::bus::CanConfigurable::finalize
+
+use crate::config::{BitTiming, BitTimingRanges, CanConfig, Mode, TimeStampSelect};
+use crate::messageram::Layout;
+use core::fmt;
+use fugit::HertzU32;
+
+/// Bit timing as applied to the peripheral
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AppliedTiming {
+    /// Bitrate
+    pub bitrate: HertzU32,
+    /// Division of the peripheral clock into time quanta
+    pub prescaler: u16,
+    /// Number of time quanta per bit
+    pub time_quanta_per_bit: u32,
+    /// Position of the sample point in per mille of the bit time
+    pub sample_point_permille: u16,
+    /// Synchronization jump width in time quanta
+    pub sjw: u8,
+}
+
+impl AppliedTiming {
+    pub(crate) fn new(timing: &BitTiming, can_clock: HertzU32, valid: &BitTimingRanges) -> Self {
+        Self {
+            bitrate: timing.bitrate,
+            // The timing was validated when it was applied
+            prescaler: timing.prescaler(can_clock, valid).unwrap_or(0),
+            time_quanta_per_bit: timing.time_quanta_per_bit(),
+            sample_point_permille: timing.sample_point_permille(),
+            sjw: timing.sjw,
+        }
+    }
+}
+
+impl AppliedTiming {
+    /// Computes the timing from the fields of NBTP or DBTP, which hold each
+    /// value minus one, and the clock of the peripheral
+    pub(crate) fn from_registers(
+        can_clock: HertzU32,
+        prescaler: u16,
+        tseg1: u8,
+        tseg2: u8,
+        sjw: u8,
+    ) -> Self {
+        let prescaler = prescaler.saturating_add(1);
+        let time_quanta_per_bit = 3 + u32::from(tseg1) + u32::from(tseg2);
+        let bitrate = can_clock
+            .to_Hz()
+            .checked_div(u32::from(prescaler) * time_quanta_per_bit)
+            .unwrap_or(0);
+        Self {
+            bitrate: HertzU32::from_raw(bitrate),
+            prescaler,
+            time_quanta_per_bit,
+            sample_point_permille: ((2 + u32::from(tseg1)) * 1000 / time_quanta_per_bit) as u16,
+            sjw: sjw.saturating_add(1),
+        }
+    }
+}
+
+impl fmt::Display for AppliedTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bit/s, prescaler {}, {} tq/bit, sample point {}.{}%, sjw {}",
+            self.bitrate.to_Hz(),
+            self.prescaler,
+            self.time_quanta_per_bit,
+            self.sample_point_permille / 10,
+            self.sample_point_permille % 10,
+            self.sjw
+        )
+    }
+}
+
+/// Summary of the configuration applied to the peripheral, see
+/// [`Can::init_report`]
+///
+/// [`Can::init_report`]: crate::bus::Can::init_report
+#[derive(Copy, Clone, Debug)]
+pub struct InitReport {
+    /// Clock of the peripheral
+    pub can_clock: HertzU32,
+    /// Bit timing of arbitration and of classic frames
+    pub nominal: AppliedTiming,
+    /// Bit timing of the data phase of CAN FD frames, if enabled
+    pub data: Option<AppliedTiming>,
+    /// Bit rate switching is allowed for transmissions
+    pub bit_rate_switching: bool,
+    /// Internal loopback is enabled
+    pub loopback: bool,
+    /// The timestamp counter is running
+    pub timestamps: bool,
+    /// The timeout counter is enabled
+    pub timeout: bool,
+    /// Sections of the message RAM
+    pub layout: Layout,
+    /// Size of the message RAM in bytes
+    pub message_ram_size: usize,
+    /// Number of standard ID filters installed
+    pub standard_filters: usize,
+    /// Number of extended ID filters installed
+    pub extended_filters: usize,
+}
+
+impl InitReport {
+    pub(crate) fn new(
+        config: &CanConfig,
+        can_clock: HertzU32,
+        layout: Layout,
+        message_ram_size: usize,
+        filters: (usize, usize),
+    ) -> Self {
+        let (data, bit_rate_switching) = match config.mode {
+            Mode::Classic => (None, false),
+            Mode::Fd {
+                allow_bit_rate_switching,
+                data_phase_timing,
+            } => (
+                Some(AppliedTiming::new(
+                    &data_phase_timing,
+                    can_clock,
+                    &crate::config::DATA_BIT_TIMING_RANGES,
+                )),
+                allow_bit_rate_switching,
+            ),
+        };
+        Self {
+            can_clock,
+            nominal: AppliedTiming::new(
+                &config.nominal_timing,
+                can_clock,
+                &crate::config::NOMINAL_BIT_TIMING_RANGES,
+            ),
+            data,
+            bit_rate_switching,
+            loopback: config.loopback,
+            timestamps: config.timestamp.select != TimeStampSelect::ZERO,
+            timeout: config.timeout.enabled,
+            layout,
+            message_ram_size,
+            standard_filters: filters.0,
+            extended_filters: filters.1,
+        }
+    }
+}
+
+impl fmt::Display for InitReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "clock {} Hz", self.can_clock.to_Hz())?;
+        writeln!(f, "nominal: {}", self.nominal)?;
+        if let Some(data) = &self.data {
+            writeln!(
+                f,
+                "data: {}, bit rate switching {}",
+                data,
+                on_off(self.bit_rate_switching)
+            )?;
+        }
+        writeln!(
+            f,
+            "message RAM: {} bytes, filters: {} standard, {} extended",
+            self.message_ram_size, self.standard_filters, self.extended_filters
+        )?;
+        write!(
+            f,
+            "loopback {}, timestamps {}, timeout {}",
+            on_off(self.loopback),
+            on_off(self.timestamps),
+            on_off(self.timeout)
+        )
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fugit::RateExtU32;
+
+    #[test]
+    fn timing_from_registers() {
+        // 48 MHz, prescaler 6, 1 + 11 + 4 tq
+        let timing = AppliedTiming::from_registers(48.MHz(), 5, 10, 3, 3);
+        assert_eq!(timing.bitrate, 500.kHz::<1, 1>());
+        assert_eq!(timing.prescaler, 6);
+        assert_eq!(timing.time_quanta_per_bit, 16);
+        assert_eq!(timing.sample_point_permille, 750);
+        assert_eq!(timing.sjw, 4);
+        let applied = BitTiming {
+            sjw: 4,
+            phase_seg_1: 11,
+            phase_seg_2: 4,
+            bitrate: 500.kHz(),
+        };
+        assert_eq!(
+            AppliedTiming::new(
+                &applied,
+                48.MHz(),
+                &crate::config::NOMINAL_BIT_TIMING_RANGES
+            ),
+            timing
+        );
+    }
+}
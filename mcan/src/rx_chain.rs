@@ -0,0 +1,337 @@
+//! Software processing of received frames
+//!
+//! An [`RxFilterChain`] sits between an RX FIFO and the application and passes
+//! each received frame through a sequence of [`Stage`]s. Every stage may drop
+//! the frame, transform it into a different type or attach information to it.
+//! Stages are composed with [`RxFilterChain::then`] and can be reconfigured at
+//! runtime through [`RxFilterChain::stages_mut`], e.g. by wrapping them in a
+//! [`Switchable`].
+//!
+//! ```
+//! use mcan::message::{rx, Raw};
+//! use mcan::rx_chain::{self, AsClassic, RxFilterChain, Switchable, Tag, Tagged};
+//!
+//! let mut chain = RxFilterChain::new()
+//!     // Drop frames without payload, unless disabled at runtime
+//!     .then(Switchable::new(rx_chain::filter(|frame: &rx::Message<64>| {
+//!         !frame.data().is_empty()
+//!     })))
+//!     // Only deliver classic frames, e.g. to a classic CAN protocol stack
+//!     .then(AsClassic)
+//!     // Record the port the frames were received on
+//!     .then(Tag(1_u8));
+//!
+//! fn deliver(frame: Tagged<u8, rx::Message<8>>) {
+//!     // ...
+//! }
+//!
+//! // `chain.drain(&mut fifo, deliver)` in the interrupt handler
+//!
+//! // Deliver frames without payload as well
+//! chain.stages_mut().0 .0 .1.set_enabled(false);
+//! ```
+
+use crate::message::{rx, Raw};
+use crate::rx_fifo::DynRxFifo;
+
+/// Step of an [`RxFilterChain`] processing frames of type `In`
+pub trait Stage<In> {
+    /// Type of the processed frames
+    type Out;
+
+    /// Processes `frame`. Returns `None` to drop it.
+    fn process(&mut self, frame: In) -> Option<Self::Out>;
+}
+
+/// Stage passing frames through unchanged
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Identity;
+
+impl<In> Stage<In> for Identity {
+    type Out = In;
+
+    fn process(&mut self, frame: In) -> Option<In> {
+        Some(frame)
+    }
+}
+
+/// Two stages applied one after the other
+#[derive(Copy, Clone, Debug)]
+pub struct Then<A, B>(pub A, pub B);
+
+impl<In, A: Stage<In>, B: Stage<A::Out>> Stage<In> for Then<A, B> {
+    type Out = B::Out;
+
+    fn process(&mut self, frame: In) -> Option<B::Out> {
+        self.0
+            .process(frame)
+            .and_then(|frame| self.1.process(frame))
+    }
+}
+
+/// Stage created by [`filter`]
+#[derive(Copy, Clone, Debug)]
+pub struct Filter<F>(F);
+
+/// Creates a stage dropping frames for which `predicate` returns `false`
+pub fn filter<In, F: FnMut(&In) -> bool>(predicate: F) -> Filter<F> {
+    Filter(predicate)
+}
+
+impl<In, F: FnMut(&In) -> bool> Stage<In> for Filter<F> {
+    type Out = In;
+
+    fn process(&mut self, frame: In) -> Option<In> {
+        (self.0)(&frame).then_some(frame)
+    }
+}
+
+/// Stage created by [`filter_map`]
+#[derive(Copy, Clone, Debug)]
+pub struct FilterMap<F>(F);
+
+/// Creates a stage transforming frames with `f`, which returns `None` to drop
+/// a frame
+pub fn filter_map<In, Out, F: FnMut(In) -> Option<Out>>(f: F) -> FilterMap<F> {
+    FilterMap(f)
+}
+
+impl<In, Out, F: FnMut(In) -> Option<Out>> Stage<In> for FilterMap<F> {
+    type Out = Out;
+
+    fn process(&mut self, frame: In) -> Option<Out> {
+        (self.0)(frame)
+    }
+}
+
+/// Stage that can be disabled at runtime, passing frames through unchanged
+/// while disabled
+#[derive(Copy, Clone, Debug)]
+pub struct Switchable<S> {
+    stage: S,
+    enabled: bool,
+}
+
+impl<S> Switchable<S> {
+    /// Wraps `stage`, initially enabled
+    pub fn new(stage: S) -> Self {
+        Self {
+            stage,
+            enabled: true,
+        }
+    }
+
+    /// Enables or disables the stage
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Returns `true` if the stage is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the wrapped stage
+    pub fn stage_mut(&mut self) -> &mut S {
+        &mut self.stage
+    }
+}
+
+impl<In, S: Stage<In, Out = In>> Stage<In> for Switchable<S> {
+    type Out = In;
+
+    fn process(&mut self, frame: In) -> Option<In> {
+        if self.enabled {
+            self.stage.process(frame)
+        } else {
+            Some(frame)
+        }
+    }
+}
+
+/// Frame with additional information attached by [`Tag`]
+#[derive(Copy, Clone, Debug)]
+pub struct Tagged<T, M> {
+    /// Attached information, e.g. the port the frame was received on
+    pub tag: T,
+    /// The frame
+    pub frame: M,
+}
+
+/// Stage attaching a copy of its value to each frame
+#[derive(Copy, Clone, Debug)]
+pub struct Tag<T>(pub T);
+
+impl<T: Copy, M> Stage<M> for Tag<T> {
+    type Out = Tagged<T, M>;
+
+    fn process(&mut self, frame: M) -> Option<Self::Out> {
+        Some(Tagged { tag: self.0, frame })
+    }
+}
+
+/// Stage converting frames into classic CAN frames with 8 data bytes,
+/// dropping CAN FD frames, see [`rx::Message::as_classic`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AsClassic;
+
+impl<const N: usize> Stage<rx::Message<N>> for AsClassic
+where
+    rx::Message<N>: rx::AnyMessage,
+{
+    type Out = rx::Message<8>;
+
+    fn process(&mut self, frame: rx::Message<N>) -> Option<rx::Message<8>> {
+        frame.as_classic().copied()
+    }
+}
+
+/// Frame whose payload is shortened by [`StripPadding`]
+#[derive(Copy, Clone, Debug)]
+pub struct Stripped<M> {
+    /// The frame
+    pub frame: M,
+    len: usize,
+}
+
+impl<M: Raw> Stripped<M> {
+    /// Payload of the frame without padding
+    pub fn data(&self) -> &[u8] {
+        let data = self.frame.data();
+        data.get(..self.len).unwrap_or(data)
+    }
+}
+
+/// Stage removing trailing padding bytes from the payload, as inserted to
+/// reach a valid CAN FD data length
+#[derive(Copy, Clone, Debug)]
+pub struct StripPadding {
+    /// Value of the padding bytes
+    pub padding: u8,
+}
+
+impl<M: Raw> Stage<M> for StripPadding {
+    type Out = Stripped<M>;
+
+    fn process(&mut self, frame: M) -> Option<Self::Out> {
+        let len = frame
+            .data()
+            .iter()
+            .rposition(|&byte| byte != self.padding)
+            .map_or(0, |last| last + 1);
+        Some(Stripped { frame, len })
+    }
+}
+
+/// Chain of [`Stage`]s between an RX FIFO and the application
+pub struct RxFilterChain<S> {
+    stages: S,
+    dropped: u32,
+}
+
+impl RxFilterChain<Identity> {
+    /// Creates a chain passing frames through unchanged
+    pub fn new() -> Self {
+        Self {
+            stages: Identity,
+            dropped: 0,
+        }
+    }
+}
+
+impl Default for RxFilterChain<Identity> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> RxFilterChain<S> {
+    /// Appends `stage` to the chain
+    pub fn then<T>(self, stage: T) -> RxFilterChain<Then<S, T>> {
+        RxFilterChain {
+            stages: Then(self.stages, stage),
+            dropped: self.dropped,
+        }
+    }
+
+    /// Returns the stages for reconfiguration. Stages appended to a new chain
+    /// with [`Self::then`] nest as `Then(Then(Then(Identity, first), second),
+    /// third)`.
+    pub fn stages_mut(&mut self) -> &mut S {
+        &mut self.stages
+    }
+
+    /// Number of frames dropped by the stages, wrapping around on overflow
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Passes `frame` through the stages. Returns `None` if it was dropped.
+    pub fn process<In>(&mut self, frame: In) -> Option<S::Out>
+    where
+        S: Stage<In>,
+    {
+        let out = self.stages.process(frame);
+        if out.is_none() {
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+        out
+    }
+
+    /// Receives all messages from `fifo`, passes them through the stages and
+    /// hands the frames that were not dropped to `f`. Returns the number of
+    /// frames handed to `f`.
+    pub fn drain<F: DynRxFifo>(&mut self, fifo: &mut F, mut f: impl FnMut(S::Out)) -> usize
+    where
+        S: Stage<F::Message>,
+    {
+        let mut delivered = 0;
+        while let Ok(message) = fifo.receive() {
+            if let Some(frame) = self.process(message) {
+                f(frame);
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::tx;
+    use embedded_can::{Id, StandardId};
+
+    fn message(data: &[u8]) -> tx::Message<8> {
+        tx::MessageBuilder {
+            id: Id::Standard(StandardId::new(0x10).unwrap()),
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(data)),
+            store_tx_event: None,
+        }
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn composes_stages() {
+        let mut chain = RxFilterChain::new()
+            .then(filter(|frame: &tx::Message<8>| {
+                frame.data().first() != Some(&0)
+            }))
+            .then(Switchable::new(filter_map(|frame: tx::Message<8>| {
+                (frame.data().len() > 1).then_some(frame)
+            })))
+            .then(StripPadding { padding: 0xcc })
+            .then(Tag('a'));
+
+        assert!(chain.process(message(&[0, 1])).is_none());
+        assert!(chain.process(message(&[1])).is_none());
+        let frame = chain.process(message(&[1, 2, 0xcc, 0xcc])).unwrap();
+        assert_eq!(frame.tag, 'a');
+        assert_eq!(frame.frame.data(), [1, 2]);
+        assert_eq!(chain.dropped(), 2);
+
+        chain.stages_mut().0 .0 .1.set_enabled(false);
+        assert!(chain.process(message(&[1])).is_some());
+    }
+}
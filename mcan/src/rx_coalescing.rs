@@ -0,0 +1,148 @@
+//! Coalescing of RX FIFO new message interrupts
+//!
+//! Under bursty traffic, an interrupt per received message can starve the
+//! rest of the system. [`RxCoalescing`] suppresses the new message interrupt
+//! of an RX FIFO for a window after each one, bounding the interrupt rate to
+//! one per window. The window is timed by the timeout counter, which is
+//! restarted at the start of each window.
+//!
+//! The timeout counter has to be configured in [`TimeoutSelect::CONT`] mode,
+//! with [`TimeoutCounter::period`] set to the length of the window, and must
+//! not be used for anything else.
+//!
+//! ```no_run
+//! use mcan::bus::Aux;
+//! use mcan::interrupt::{Interrupt, InterruptConfiguration, InterruptLine, OwnedInterruptSet};
+//! use mcan::rx_coalescing::RxCoalescing;
+//! use mcan::rx_fifo::DynRxFifo;
+//!
+//! fn setup<Id: mcan::core::CanId>(
+//!     interrupts: &mut OwnedInterruptSet<Id, mcan::interrupt::state::Disabled>,
+//!     config: &mut InterruptConfiguration<Id>,
+//! ) -> RxCoalescing<Id> {
+//!     let new_message = interrupts.split(Interrupt::RxFifo0NewMessage.into()).unwrap();
+//!     let timeout = interrupts.split(Interrupt::TimeoutOccured.into()).unwrap();
+//!     RxCoalescing::new(config, new_message, timeout, InterruptLine::Line0)
+//! }
+//!
+//! fn on_interrupt<Id: mcan::core::CanId, D: mcan::core::Dependencies<Id>>(
+//!     coalescing: &mut RxCoalescing<Id>,
+//!     config: &mut InterruptConfiguration<Id>,
+//!     aux: &Aux<'_, Id, D>,
+//!     fifo: &mut impl DynRxFifo,
+//! ) {
+//!     coalescing.handle(config, aux, fifo, |message| {
+//!         // ...
+//!     });
+//! }
+//! ```
+//!
+//! [`TimeoutSelect::CONT`]: crate::config::TimeoutSelect::CONT
+//! [`TimeoutCounter::period`]: crate::config::TimeoutCounter::period
+
+use crate::bus::Aux;
+use crate::interrupt::{state, InterruptConfiguration, InterruptLine, OwnedInterruptSet};
+use crate::rx_fifo::DynRxFifo;
+
+/// Suppresses the new message interrupt of an RX FIFO for a window after each
+/// one
+pub struct RxCoalescing<Id> {
+    new_message: OwnedInterruptSet<Id>,
+    timeout: OwnedInterruptSet<Id>,
+    line: InterruptLine,
+    suppressed: bool,
+}
+
+impl<Id: mcan_core::CanId> RxCoalescing<Id> {
+    /// Enables `new_message` on interrupt `line`. `timeout` is enabled on the
+    /// same line during windows.
+    pub fn new(
+        config: &mut InterruptConfiguration<Id>,
+        new_message: OwnedInterruptSet<Id, state::Disabled>,
+        timeout: OwnedInterruptSet<Id, state::Disabled>,
+        line: InterruptLine,
+    ) -> Self {
+        Self {
+            new_message: config.enable(new_message, line),
+            timeout: timeout.into(),
+            line,
+            suppressed: false,
+        }
+    }
+
+    /// Returns `true` during a window, while the new message interrupt is
+    /// suppressed
+    pub fn is_suppressed(&self) -> bool {
+        self.suppressed
+    }
+
+    /// Handles an interrupt: receives all messages from `fifo` into `f`.
+    ///
+    /// On a new message interrupt, a window is started. At the end of the
+    /// window, the new message interrupt is enabled again and raised right
+    /// away if messages arrived after the last ones were received. Returns the
+    /// number of messages received.
+    pub fn handle<D: mcan_core::Dependencies<Id>, F: DynRxFifo>(
+        &mut self,
+        config: &mut InterruptConfiguration<Id>,
+        aux: &Aux<'_, Id, D>,
+        fifo: &mut F,
+        f: impl FnMut(F::Message),
+    ) -> usize {
+        if self.suppressed {
+            if self.timeout.interrupt_flags().is_empty() {
+                return 0;
+            }
+            self.timeout
+                .clear_interrupts(self.timeout.interrupt_flags());
+            self.switch(config, false);
+        } else {
+            if self.new_message.interrupt_flags().is_empty() {
+                return 0;
+            }
+            self.switch(config, true);
+            aux.restart_timeout_counter();
+            // The counter might have expired before it was restarted
+            self.timeout
+                .clear_interrupts(self.timeout.interrupt_flags());
+        }
+        // Messages arriving from here on raise the flag again
+        self.new_message
+            .clear_interrupts(self.new_message.interrupt_flags());
+        Self::receive_all(fifo, f)
+    }
+
+    /// Disables both interrupts and returns them
+    pub fn release(
+        self,
+        config: &mut InterruptConfiguration<Id>,
+    ) -> (
+        OwnedInterruptSet<Id, state::Disabled>,
+        OwnedInterruptSet<Id, state::Disabled>,
+    ) {
+        (
+            config.disable(self.new_message),
+            config.disable(self.timeout),
+        )
+    }
+
+    fn receive_all<F: DynRxFifo>(fifo: &mut F, mut f: impl FnMut(F::Message)) -> usize {
+        let mut received = 0;
+        while let Ok(message) = fifo.receive() {
+            f(message);
+            received += 1;
+        }
+        received
+    }
+
+    fn switch(&mut self, config: &mut InterruptConfiguration<Id>, suppressed: bool) {
+        let (enable, disable) = if suppressed {
+            (&mut self.timeout, &mut self.new_message)
+        } else {
+            (&mut self.new_message, &mut self.timeout)
+        };
+        *enable = config.enable(core::mem::take(enable), self.line);
+        *disable = config.disable(core::mem::take(disable)).into();
+        self.suppressed = suppressed;
+    }
+}
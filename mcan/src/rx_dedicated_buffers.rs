@@ -10,6 +10,9 @@ use crate::message::rx;
 use crate::reg;
 use core::convert::Infallible;
 use core::marker::PhantomData;
+use core::ops::Range;
+use core::sync::atomic::fence;
+use portable_atomic::{AtomicU32, Ordering};
 use reg::AccessRegisterBlock as _;
 use vcell::VolatileCell;
 
@@ -18,8 +21,16 @@ use vcell::VolatileCell;
 pub struct OutOfBounds;
 
 /// Dedicated receive buffers on peripheral `P`
+///
+/// Initially covers all buffers. [`Self::split_at`] partitions them into
+/// contiguous ranges for different owners. Buffers are always addressed by
+/// their index in the peripheral, as used by [`Filter::StoreBuffer`].
+///
+/// [`Filter::StoreBuffer`]: crate::filter::Filter::StoreBuffer
 pub struct RxDedicatedBuffer<'a, P, M: rx::AnyMessage> {
     memory: &'a mut [VolatileCell<M>],
+    /// Index of the first buffer in `memory`
+    offset: usize,
     _markers: PhantomData<P>,
 }
 
@@ -35,7 +46,115 @@ pub trait DynRxDedicatedBuffer {
     fn receive(&mut self, index: usize) -> nb::Result<Self::Message, OutOfBounds>;
 
     /// Returns a received frame from any dedicated buffer if available
-    fn receive_any(&mut self) -> nb::Result<Self::Message, Infallible>;
+    fn receive_any(&mut self) -> nb::Result<Self::Message, Infallible> {
+        self.receive_any_indexed().map(|(_, message)| message)
+    }
+
+    /// Returns a received frame from any dedicated buffer if available, along
+    /// with the index of the buffer it was stored in
+    ///
+    /// The index identifies the filter that stored the frame, see
+    /// [`Filter::StoreBuffer`].
+    ///
+    /// [`Filter::StoreBuffer`]: crate::filter::Filter::StoreBuffer
+    fn receive_any_indexed(&mut self) -> nb::Result<(usize, Self::Message), Infallible>;
+
+    /// Receives messages from any dedicated buffer into `frames`, converted to
+    /// the owned frame type `F`, until no buffer holds new data or `frames` is
+    /// full. Returns the number of messages taken from the buffers.
+    ///
+    /// Messages that `F` cannot represent are dropped; see
+    /// [`rx::AnyMessage::to_frame`].
+    #[cfg(feature = "heapless")]
+    fn receive_owned_into<F: embedded_can::Frame, const N: usize>(
+        &mut self,
+        frames: &mut heapless::Vec<F, N>,
+    ) -> usize
+    where
+        Self::Message: rx::AnyMessage,
+    {
+        let mut received = 0;
+        while !frames.is_full() {
+            let Ok(message) = self.receive_any() else {
+                break;
+            };
+            received += 1;
+            if let Some(frame) = rx::AnyMessage::to_frame(&message) {
+                // Cannot fail, the vector is not full
+                let _ = frames.push(frame);
+            }
+        }
+        received
+    }
+
+    /// Marks the selected buffer as read without reading out its content
+    fn discard(&mut self, index: usize) -> Result<(), OutOfBounds>;
+
+    /// Marks all dedicated buffers as read without reading out their content
+    fn discard_all(&mut self);
+
+    /// Buffers holding messages that were not read yet, bit `n` standing for
+    /// buffer `n`
+    fn pending(&self) -> u64;
+}
+
+/// Bits `offset..offset + len` set
+fn range_mask(offset: usize, len: usize) -> u64 {
+    let len = u32::try_from(len).unwrap_or(u32::MAX);
+    let offset = u32::try_from(offset).unwrap_or(u32::MAX);
+    u64::MAX
+        .checked_shr(64_u32.saturating_sub(len))
+        .unwrap_or(0)
+        .checked_shl(offset)
+        .unwrap_or(0)
+}
+
+fn registers<P: mcan_core::CanId>() -> &'static reg::RegisterBlock {
+    // Safety: The NDAT registers are owned by `RxDedicatedBuffer` and the handles
+    // split from it, which only write the flags of their own buffers.
+    unsafe { &*P::register_block() }
+}
+
+/// New data flags of peripheral `P`, NDAT1 in the lower and NDAT2 in the upper
+/// half. Reading does not affect the flags.
+fn new_data<P: mcan_core::CanId>() -> u64 {
+    let regs = registers::<P>();
+    let flags = u64::from(regs.ndat1.read().bits()) | u64::from(regs.ndat2.read().bits()) << 32;
+    // Messages must not be read before the flag announcing them.
+    fence(Ordering::Acquire);
+    flags
+}
+
+fn has_new_data<P: mcan_core::CanId>(index: usize) -> bool {
+    let regs = registers::<P>();
+    let new_data = if index < 32 {
+        regs.ndat1.read().bits() & (1 << index) != 0
+    } else if index < 64 {
+        regs.ndat2.read().bits() & (1 << (index - 32)) != 0
+    } else {
+        false
+    };
+    // Messages must not be read before the flag announcing them.
+    fence(Ordering::Acquire);
+    new_data
+}
+
+/// Clears the new data flag of buffer `index` only, as writing 0 leaves the
+/// other flags unchanged.
+fn mark_buffer_read<P: mcan_core::CanId>(index: usize) {
+    let regs = registers::<P>();
+    // The message must have been copied out before the buffer is released.
+    fence(Ordering::Release);
+    // Safety: Writing a 1 clears the new data flag of the buffer.
+    if index < 32 {
+        unsafe {
+            regs.ndat1.write(|w| w.bits(1 << index));
+        }
+    } else if index < 64 {
+        unsafe {
+            regs.ndat2.write(|w| w.bits(1 << (index - 32)));
+        }
+    }
 }
 
 impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> RxDedicatedBuffer<'a, P, M> {
@@ -49,60 +168,101 @@ impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> RxDedicatedBuffer<'a, P, M> {
     pub(crate) unsafe fn new(memory: &'a mut [VolatileCell<M>]) -> Self {
         Self {
             memory,
+            offset: 0,
             _markers: PhantomData,
         }
     }
 
-    /// Raw access to the registers.
-    unsafe fn regs(&self) -> &reg::RegisterBlock {
-        &(*P::register_block())
+    /// Range of buffer indices covered
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.memory.len()
     }
 
-    fn ndat1(&self) -> &reg::NDAT1 {
-        // Safety: `Self` owns the register.
-        unsafe { &self.regs().ndat1 }
+    /// Partitions the buffers into those before buffer `index` and those from
+    /// buffer `index` onwards. Each part only receives from and clears the
+    /// flags of its own buffers. Fails if `index` is outside of
+    /// [`Self::range`] and not its end.
+    ///
+    /// ```no_run
+    /// # use mcan::rx_dedicated_buffers::{OutOfBounds, RxDedicatedBuffer};
+    /// # fn f<'a, P: mcan::core::CanId>(
+    /// #     buffers: RxDedicatedBuffer<'a, P, mcan::message::rx::Message<8>>,
+    /// # ) -> Result<(), OutOfBounds> {
+    /// // Buffers 0-15 for task A, 16-31 for task B
+    /// let (task_a, rest) = buffers.split_at(16)?;
+    /// let (task_b, _) = rest.split_at(32)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split_at(self, index: usize) -> Result<(Self, Self), OutOfBounds> {
+        let mid = index
+            .checked_sub(self.offset)
+            .filter(|&mid| mid <= self.memory.len())
+            .ok_or(OutOfBounds)?;
+        let (first, second) = self.memory.split_at_mut(mid);
+        Ok((
+            Self {
+                memory: first,
+                offset: self.offset,
+                _markers: PhantomData,
+            },
+            Self {
+                memory: second,
+                offset: index,
+                _markers: PhantomData,
+            },
+        ))
     }
 
-    fn ndat2(&self) -> &reg::NDAT2 {
-        // Safety: `Self` owns the register.
-        unsafe { &self.regs().ndat2 }
+    /// Position of buffer `index` in `memory`
+    fn local(&self, index: usize) -> Result<usize, OutOfBounds> {
+        index
+            .checked_sub(self.offset)
+            .filter(|&local| local < self.memory.len())
+            .ok_or(OutOfBounds)
     }
 
-    fn has_new_data(&self, index: usize) -> bool {
-        if index < 32 {
-            self.ndat1().read().bits() & (1 << index) != 0
-        } else if index < 64 {
-            self.ndat2().read().bits() & (1 << (index - 32)) != 0
-        } else {
-            false
-        }
+    fn mask(&self) -> u64 {
+        range_mask(self.offset, self.memory.len())
     }
 
-    fn has_new_data_checked(&self, index: usize) -> Result<bool, OutOfBounds> {
-        if index < 64 {
-            Ok(self.has_new_data(index))
-        } else {
-            Err(OutOfBounds)
-        }
+    /// Splits the buffers into a handle per buffer, which can be moved to the
+    /// task processing its messages, and [`NewData`] for dispatching
+    /// notifications from the interrupt handler.
+    ///
+    /// Each handle only clears the new data flag of its own buffer.
+    pub fn split(self) -> (NewData<P>, impl Iterator<Item = RxBuffer<'a, P, M>>) {
+        let new_data = NewData {
+            mask: self.mask(),
+            _markers: PhantomData,
+        };
+        let offset = self.offset;
+        let buffers = self
+            .memory
+            .iter_mut()
+            .enumerate()
+            .map(move |(local, cell)| RxBuffer {
+                index: offset + local,
+                cell,
+                _markers: PhantomData,
+            });
+        (new_data, buffers)
+    }
+
+    fn has_new_data(&self, index: usize) -> bool {
+        has_new_data::<P>(index)
     }
 
     fn mark_buffer_read(&self, index: usize) {
-        if index < 32 {
-            unsafe {
-                self.ndat1().write(|w| w.bits(1 << index));
-            }
-        } else if index < 64 {
-            unsafe {
-                self.ndat2().write(|w| w.bits(1 << index));
-            }
-        }
+        mark_buffer_read::<P>(index);
     }
 
     fn peek(&self, index: usize) -> nb::Result<M, OutOfBounds> {
-        if self.has_new_data_checked(index)? {
+        let local = self.local(index)?;
+        if self.has_new_data(index) {
             Ok(self
                 .memory
-                .get(index)
+                .get(local)
                 .ok_or(nb::Error::Other(OutOfBounds))?
                 .get())
         } else {
@@ -123,19 +283,42 @@ impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> DynRxDedicatedBuffer
         Ok(message)
     }
 
-    fn receive_any(&mut self) -> nb::Result<Self::Message, Infallible> {
+    fn receive_any_indexed(&mut self) -> nb::Result<(usize, Self::Message), Infallible> {
+        let pending = self.pending();
         self.memory
             .iter()
             .enumerate()
-            .filter(|&(i, _)| self.has_new_data(i))
+            .map(|(local, m)| (self.offset + local, m))
+            .filter(|&(i, _)| pending & 1_u64.checked_shl(i as u32).unwrap_or(0) != 0)
             .map(|(i, m)| (i, m.get()))
             .min_by_key(|(_, m)| m.id())
             .map(|(i, m)| {
                 self.mark_buffer_read(i);
-                m
+                (i, m)
             })
             .ok_or(nb::Error::WouldBlock)
     }
+
+    fn discard(&mut self, index: usize) -> Result<(), OutOfBounds> {
+        self.local(index)?;
+        self.mark_buffer_read(index);
+        Ok(())
+    }
+
+    fn discard_all(&mut self) {
+        let regs = registers::<P>();
+        let mask = self.mask();
+        // Safety: Writing a 1 clears the new data flag. `Self` owns the flags in
+        // its range.
+        unsafe {
+            regs.ndat1.write(|w| w.bits(mask as u32));
+            regs.ndat2.write(|w| w.bits((mask >> 32) as u32));
+        }
+    }
+
+    fn pending(&self) -> u64 {
+        new_data::<P>() & self.mask()
+    }
 }
 
 impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> Iterator for RxDedicatedBuffer<'a, P, M> {
@@ -145,3 +328,159 @@ impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> Iterator for RxDedicatedBuffer<
         self.receive_any().ok()
     }
 }
+
+/// Single dedicated receive buffer, split from [`RxDedicatedBuffer`]
+pub struct RxBuffer<'a, P, M: rx::AnyMessage> {
+    index: usize,
+    cell: &'a mut VolatileCell<M>,
+    _markers: PhantomData<P>,
+}
+
+impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> RxBuffer<'a, P, M> {
+    /// Index of the buffer, as used by [`Filter::StoreBuffer`]
+    ///
+    /// [`Filter::StoreBuffer`]: crate::filter::Filter::StoreBuffer
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns `true` if the buffer holds a message that was not read yet
+    pub fn has_new_data(&self) -> bool {
+        has_new_data::<P>(self.index)
+    }
+
+    /// Returns the received message if available
+    pub fn receive(&mut self) -> nb::Result<M, Infallible> {
+        if !self.has_new_data() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let message = self.cell.get();
+        mark_buffer_read::<P>(self.index);
+        Ok(message)
+    }
+
+    /// Marks the buffer as read without reading out its content
+    pub fn discard(&mut self) {
+        mark_buffer_read::<P>(self.index);
+    }
+}
+
+/// Read-only access to the new data flags of buffers split from
+/// [`RxDedicatedBuffer`]
+///
+/// Meant for the handler of [`Interrupt::MessageStoredToDedicatedRxBuffer`],
+/// which passes [`Self::pending`] to [`RxBufferNotifier::notify`]. The flags
+/// are cleared by the handles as they read their buffers.
+///
+/// [`Interrupt::MessageStoredToDedicatedRxBuffer`]: crate::interrupt::Interrupt::MessageStoredToDedicatedRxBuffer
+pub struct NewData<P> {
+    mask: u64,
+    _markers: PhantomData<P>,
+}
+
+impl<P: mcan_core::CanId> NewData<P> {
+    /// Buffers holding messages that were not read yet, bit `n` standing for
+    /// buffer `n`
+    pub fn pending(&self) -> u64 {
+        new_data::<P>() & self.mask
+    }
+}
+
+/// Flags announcing new data to the handles of dedicated buffers
+///
+/// The interrupt handler sets the flags of buffers that received a message,
+/// waking up the tasks responsible for them, and each task takes the flag of
+/// its own buffer. Unlike [`DynRxDedicatedBuffer::receive_any`], no task has
+/// to scan all buffers.
+///
+/// ```no_run
+/// # use mcan::rx_dedicated_buffers::{NewData, RxBufferNotifier};
+/// # struct Can0;
+/// # unsafe impl mcan::core::CanId for Can0 {
+/// #     const ADDRESS: *const () = 0xDEAD0000 as *const _;
+/// # }
+/// static NOTIFIER: RxBufferNotifier = RxBufferNotifier::new();
+///
+/// fn on_dedicated_buffer_interrupt(new_data: &NewData<Can0>) {
+///     NOTIFIER.notify(new_data.pending(), |index| {
+///         // Wake the task responsible for buffer `index`
+///     });
+/// }
+///
+/// fn task(index: usize) {
+///     if NOTIFIER.take(index) {
+///         // `buffer.receive()`
+///     }
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct RxBufferNotifier {
+    flags: [AtomicU32; 2],
+}
+
+impl RxBufferNotifier {
+    /// Creates a notifier with no flags set
+    pub const fn new() -> Self {
+        Self {
+            flags: [AtomicU32::new(0), AtomicU32::new(0)],
+        }
+    }
+
+    /// Sets the flags of the buffers in `pending`, bit `n` standing for buffer
+    /// `n`, and calls `wake` with the index of each buffer whose flag was not
+    /// set yet.
+    pub fn notify(&self, pending: u64, mut wake: impl FnMut(usize)) {
+        for (word, flags) in self.flags.iter().enumerate() {
+            let bits = (pending >> (32 * word)) as u32;
+            let mut new = bits & !flags.fetch_or(bits, Ordering::AcqRel);
+            while new != 0 {
+                let bit = new.trailing_zeros();
+                new &= new - 1;
+                wake(32 * word + bit as usize);
+            }
+        }
+    }
+
+    /// Returns `true` if buffer `index` was notified since the last call,
+    /// clearing its flag.
+    pub fn take(&self, index: usize) -> bool {
+        let Some(flags) = self.flags.get(index / 32) else {
+            return false;
+        };
+        let bit = 1 << (index % 32);
+        flags.fetch_and(!bit, Ordering::AcqRel) & bit != 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    extern crate std;
+    use std::vec::Vec;
+
+    #[test]
+    fn masks_ranges() {
+        assert_eq!(range_mask(0, 0), 0);
+        assert_eq!(range_mask(0, 3), 0b111);
+        assert_eq!(range_mask(16, 16), 0xffff_0000);
+        assert_eq!(range_mask(0, 64), u64::MAX);
+        assert_eq!(range_mask(32, 32), 0xffff_ffff_0000_0000);
+        assert_eq!(range_mask(64, 0), 0);
+    }
+
+    #[test]
+    fn notifies_new_buffers_once() {
+        let notifier = RxBufferNotifier::new();
+        let mut woken = Vec::new();
+        notifier.notify(1 << 3 | 1 << 40, |index| woken.push(index));
+        notifier.notify(1 << 3 | 1 << 5, |index| woken.push(index));
+        assert_eq!(woken, [3, 40, 5]);
+
+        assert!(notifier.take(40));
+        assert!(!notifier.take(40));
+        assert!(notifier.take(3));
+        assert!(!notifier.take(64));
+        notifier.notify(1 << 3, |index| woken.push(index));
+        assert_eq!(woken, [3, 40, 5, 3]);
+    }
+}
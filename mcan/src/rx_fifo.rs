@@ -4,23 +4,32 @@
 //!
 //! [`Action`]: crate::filter::Action
 
+use crate::config::{CanConfig, RxFifoConfig};
+use crate::interrupt::Interrupt;
 use crate::message::rx;
 use crate::reg;
+use core::cell::Cell;
 use core::convert::Infallible;
 use core::marker::PhantomData;
+use core::sync::atomic::{fence, Ordering};
+use mcan_core::MessageRamDma;
 use reg::AccessRegisterBlock as _;
 use vcell::VolatileCell;
 
 /// Receive FIFO `F` on peripheral `P`.
 pub struct RxFifo<'a, F, P, M: rx::AnyMessage> {
     memory: &'a mut [VolatileCell<M>],
+    high_water_mark: Cell<usize>,
+    /// Index of a message delivered ahead of order by
+    /// [`DynRxFifo::receive_high_priority`], skipped once it is the oldest
+    delivered: Option<u8>,
     _markers: PhantomData<(F, P)>,
 }
 
 /// Trait which erases generic parametrization for [`RxFifo`] type
 pub trait DynRxFifo {
     /// RX FIFO identity type
-    type RxFifoId;
+    type RxFifoId: FifoId;
 
     /// CAN identity type
     type CanId;
@@ -37,9 +46,144 @@ pub trait DynRxFifo {
     /// Returns the number of elements the queue can hold
     fn capacity(&self) -> usize;
 
+    /// Returns the highest fill level observed since construction or the last
+    /// call to [`Self::reset_high_water_mark`].
+    ///
+    /// The fill level is sampled whenever the status of the queue is read,
+    /// i.e. on calls to [`Self::len`], [`Self::receive`] and [`Self::flush`].
+    fn high_water_mark(&self) -> usize;
+
+    /// Resets the high water mark to the current fill level
+    fn reset_high_water_mark(&mut self);
+
     /// Returns a received frame if available. Note that the FIFO also
     /// implements [`Iterator`] to receive messages until the queue is empty.
     fn receive(&mut self) -> nb::Result<Self::Message, Infallible>;
+
+    /// Returns a copy of the message [`Self::receive`] would return next,
+    /// leaving it in the queue, e.g. to decide how to handle it before
+    /// committing to consume it.
+    ///
+    /// The message stays in the queue until it is received, so it keeps
+    /// occupying an element and may be overwritten in overwrite mode.
+    fn peek(&self) -> nb::Result<Self::Message, Infallible>;
+
+    /// Returns the message flagged by the last high priority message
+    /// interrupt, if it was stored in this FIFO, ahead of the older messages
+    /// in the queue. It is skipped when it is reached by [`Self::receive`].
+    ///
+    /// Should be called once [`Interrupt::HighPriorityMessage`] was raised
+    /// and cleared. Only the latest high priority message is known to the
+    /// peripheral, earlier ones are received in order.
+    fn receive_high_priority(&mut self) -> nb::Result<Self::Message, Infallible>;
+
+    /// Receives at most `max` messages into `f`, bounding the work of an
+    /// interrupt handler draining the queue. Returns the number of messages
+    /// received; messages beyond `max` are left in the queue.
+    fn receive_bounded(&mut self, max: usize, mut f: impl FnMut(Self::Message)) -> usize {
+        let mut received = 0;
+        while received < max {
+            let Ok(message) = self.receive() else {
+                break;
+            };
+            f(message);
+            received += 1;
+        }
+        received
+    }
+
+    /// Receives messages into `frames`, converted to the owned frame type `F`,
+    /// until the queue is empty or `frames` is full. Returns the number of
+    /// messages taken from the queue.
+    ///
+    /// Messages that `F` cannot represent are dropped; see
+    /// [`rx::AnyMessage::to_frame`].
+    #[cfg(feature = "heapless")]
+    fn receive_owned_into<F: embedded_can::Frame, const N: usize>(
+        &mut self,
+        frames: &mut heapless::Vec<F, N>,
+    ) -> usize
+    where
+        Self::Message: rx::AnyMessage,
+    {
+        let mut received = 0;
+        while !frames.is_full() {
+            let Ok(message) = self.receive() else {
+                break;
+            };
+            received += 1;
+            if let Some(frame) = rx::AnyMessage::to_frame(&message) {
+                // Cannot fail, the vector is not full
+                let _ = frames.push(frame);
+            }
+        }
+        received
+    }
+
+    /// Acknowledges all messages currently in the queue without reading them
+    /// out. Returns the number of messages that were discarded.
+    ///
+    /// Messages that arrive while the queue is being flushed are kept.
+    fn flush(&mut self) -> usize;
+
+    /// Returns an iterator receiving messages until the queue is empty,
+    /// decoded into protocol messages `T`, e.g. the types generated by
+    /// `mcan-dbc`.
+    ///
+    /// Messages that `T` fails to decode are yielded as `Err` with the
+    /// received message, so they can be skipped with
+    /// `.filter_map(Result::ok)` or handled separately.
+    ///
+    /// ```no_run
+    /// use mcan::message::{rx, Raw};
+    /// use mcan::rx_fifo::DynRxFifo;
+    ///
+    /// struct Heartbeat(u8);
+    ///
+    /// impl TryFrom<&rx::Message<8>> for Heartbeat {
+    ///     type Error = ();
+    ///
+    ///     fn try_from(message: &rx::Message<8>) -> Result<Self, ()> {
+    ///         message.data().first().map(|&state| Heartbeat(state)).ok_or(())
+    ///     }
+    /// }
+    ///
+    /// fn receive(fifo: &mut impl DynRxFifo<Message = rx::Message<8>>) {
+    ///     for heartbeat in fifo.decode::<Heartbeat>().filter_map(Result::ok) {
+    ///         let _state = heartbeat.0;
+    ///     }
+    /// }
+    /// ```
+    fn decode<T>(&mut self) -> Decode<'_, Self, T>
+    where
+        Self: Sized,
+        T: for<'m> TryFrom<&'m Self::Message>,
+    {
+        Decode {
+            fifo: self,
+            _decoded: PhantomData,
+        }
+    }
+}
+
+/// Iterator over decoded messages of an RX FIFO, see [`DynRxFifo::decode`]
+pub struct Decode<'f, R, T> {
+    fifo: &'f mut R,
+    _decoded: PhantomData<fn() -> T>,
+}
+
+impl<R, T> Iterator for Decode<'_, R, T>
+where
+    R: DynRxFifo,
+    T: for<'m> TryFrom<&'m R::Message>,
+{
+    type Item = Result<T, R::Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.fifo.receive().ok()?;
+        let decoded = T::try_from(&message).ok();
+        Some(decoded.ok_or(message))
+    }
 }
 
 /// Value of the type-level FIFO selection enum representing FIFO 0.
@@ -47,6 +191,107 @@ pub struct Fifo0;
 /// Value of the type-level FIFO selection enum representing FIFO 1.
 pub struct Fifo1;
 
+mod private {
+    /// Super trait used to mark traits with an exhaustive set of
+    /// implementations
+    pub trait Sealed {}
+}
+use private::Sealed;
+
+impl Sealed for Fifo0 {}
+impl Sealed for Fifo1 {}
+
+/// Type-level FIFO selection, implemented by [`Fifo0`] and [`Fifo1`]
+///
+/// Allows code that is generic over an RX FIFO to select the configuration
+/// and interrupts belonging to it.
+pub trait FifoId: Sealed {
+    /// Interrupt raised when a new message was stored in the FIFO
+    const NEW_MESSAGE: Interrupt;
+    /// Interrupt raised when the fill level reaches the watermark
+    const WATERMARK_REACHED: Interrupt;
+    /// Interrupt raised when the FIFO is full
+    const FULL: Interrupt;
+    /// Interrupt raised when a message was lost because the FIFO was full
+    const MESSAGE_LOST: Interrupt;
+
+    /// Returns the configuration of the FIFO within `config`
+    fn config(config: &mut CanConfig) -> &mut RxFifoConfig;
+
+    #[doc(hidden)]
+    const STORAGE_INDICATOR: reg::hpms::MSISELECT_A;
+
+    #[doc(hidden)]
+    fn registers(block: &reg::RegisterBlock) -> &reg::RxFifoRegs;
+}
+
+impl FifoId for Fifo0 {
+    const NEW_MESSAGE: Interrupt = Interrupt::RxFifo0NewMessage;
+    const WATERMARK_REACHED: Interrupt = Interrupt::RxFifo0WatermarkReached;
+    const FULL: Interrupt = Interrupt::RxFifo0Full;
+    const MESSAGE_LOST: Interrupt = Interrupt::RxFifo0MessageLost;
+    const STORAGE_INDICATOR: reg::hpms::MSISELECT_A = reg::hpms::MSISELECT_A::FIFO0;
+
+    fn config(config: &mut CanConfig) -> &mut RxFifoConfig {
+        &mut config.rx_fifo_0
+    }
+
+    fn registers(block: &reg::RegisterBlock) -> &reg::RxFifoRegs {
+        &block.rxf0
+    }
+}
+
+impl FifoId for Fifo1 {
+    const NEW_MESSAGE: Interrupt = Interrupt::RxFifo1NewMessage;
+    const WATERMARK_REACHED: Interrupt = Interrupt::RxFifo1WatermarkReached;
+    const FULL: Interrupt = Interrupt::RxFifo1Full;
+    const MESSAGE_LOST: Interrupt = Interrupt::RxFifo1MessageLost;
+    const STORAGE_INDICATOR: reg::hpms::MSISELECT_A = reg::hpms::MSISELECT_A::FIFO1;
+
+    fn config(config: &mut CanConfig) -> &mut RxFifoConfig {
+        &mut config.rx_fifo_1
+    }
+
+    fn registers(block: &reg::RegisterBlock) -> &reg::RxFifoRegs {
+        &block.rxf1
+    }
+}
+
+/// State of the debug message handling.
+///
+/// Debug messages are routed into dedicated RX buffers by filters using
+/// [`SbMsgType::DebugA`], [`SbMsgType::DebugB`] and [`SbMsgType::DebugC`].
+/// Once the whole A/B/C sequence has been received, the peripheral raises its
+/// DMA request line. The state is reported in the status register of RX FIFO
+/// 1, see [`RxFifo::debug_message_status`].
+///
+/// [`SbMsgType::DebugA`]: crate::filter::SbMsgType::DebugA
+/// [`SbMsgType::DebugB`]: crate::filter::SbMsgType::DebugB
+/// [`SbMsgType::DebugC`]: crate::filter::SbMsgType::DebugC
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugMessageStatus {
+    /// Waiting for debug message A
+    Idle,
+    /// Debug message A received
+    A,
+    /// Debug messages A and B received
+    AB,
+    /// Debug messages A, B and C received, DMA request is set
+    ABC,
+}
+
+impl From<reg::rxf1s::DMSSELECT_A> for DebugMessageStatus {
+    fn from(value: reg::rxf1s::DMSSELECT_A) -> Self {
+        use reg::rxf1s::DMSSELECT_A;
+        match value {
+            DMSSELECT_A::IDLE => Self::Idle,
+            DMSSELECT_A::DBGA => Self::A,
+            DMSSELECT_A::DBGB => Self::AB,
+            DMSSELECT_A::DBGC => Self::ABC,
+        }
+    }
+}
+
 /// Provides raw access to the registers controlling the RX FIFO.
 pub trait GetRxFifoRegs {
     /// # Safety
@@ -54,21 +299,31 @@ pub trait GetRxFifoRegs {
     unsafe fn registers(&self) -> &reg::RxFifoRegs;
 }
 
-impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> GetRxFifoRegs for RxFifo<'a, Fifo0, P, M> {
+impl<'a, F: FifoId, P: mcan_core::CanId, M: rx::AnyMessage> GetRxFifoRegs for RxFifo<'a, F, P, M> {
     unsafe fn registers(&self) -> &reg::RxFifoRegs {
-        &(*P::register_block()).rxf0
+        F::registers(&*P::register_block())
     }
 }
-impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> GetRxFifoRegs for RxFifo<'a, Fifo1, P, M> {
-    unsafe fn registers(&self) -> &reg::RxFifoRegs {
-        &(*P::register_block()).rxf1
+
+/// Returns the index of the oldest message in a FIFO of `capacity` elements
+/// that was not delivered yet, and whether the message at `get_index` has to
+/// be skipped since it was already delivered by
+/// [`DynRxFifo::receive_high_priority`]. `None` if there is no such message.
+fn undelivered(
+    get_index: u8,
+    len: usize,
+    delivered: Option<u8>,
+    capacity: usize,
+) -> Option<(u8, bool)> {
+    let skip = delivered == Some(get_index);
+    if len <= usize::from(skip) {
+        return None;
     }
+    let index = (usize::from(get_index) + usize::from(skip)).checked_rem(capacity)?;
+    Some((index as u8, skip))
 }
 
-impl<'a, F, P: mcan_core::CanId, M: rx::AnyMessage> RxFifo<'a, F, P, M>
-where
-    Self: GetRxFifoRegs,
-{
+impl<'a, F: FifoId, P: mcan_core::CanId, M: rx::AnyMessage> RxFifo<'a, F, P, M> {
     /// # Safety
     /// The caller must be the owner or the peripheral referenced by `P`. The
     /// constructed type assumes ownership of some of the registers from the
@@ -80,6 +335,8 @@ where
     pub(crate) unsafe fn new(memory: &'a mut [VolatileCell<M>]) -> Self {
         Self {
             memory,
+            high_water_mark: Cell::new(0),
+            delivered: None,
             _markers: PhantomData,
         }
     }
@@ -88,18 +345,141 @@ where
         // Safety: The RxFifo owns the registers.
         unsafe { self.registers() }
     }
+
+    /// Reads the status register and keeps track of the fill level.
+    fn status(&self) -> reg::rxfs::R {
+        let status = self.regs().s.read();
+        // Messages must not be read before the status announcing them.
+        fence(Ordering::Acquire);
+        let len = status.ffl().bits() as usize;
+        if len > self.high_water_mark.get() {
+            self.high_water_mark.set(len);
+        }
+        status
+    }
+
+    /// Starts moving the oldest message in the queue into `destination` using
+    /// `dma`. The message is acknowledged once the transfer has completed,
+    /// which is awaited by [`RxDmaTransfer::wait`] or when the transfer is
+    /// dropped. Fails with [`nb::Error::WouldBlock`] if the queue is empty.
+    ///
+    /// # Safety
+    /// The returned transfer must not be leaked, e.g. by [`core::mem::forget`].
+    /// Otherwise `destination` could be accessed while the DMA engine is still
+    /// writing to it.
+    pub unsafe fn receive_dma<'t, D: MessageRamDma>(
+        &'t mut self,
+        dma: &'t mut D,
+        destination: &'t mut M,
+    ) -> nb::Result<RxDmaTransfer<'a, 't, F, P, M, D>, D::Error> {
+        let status = self.status();
+        let Some((get_index, skip)) = undelivered(
+            status.fgi().bits(),
+            status.ffl().bits() as usize,
+            self.delivered,
+            self.memory.len(),
+        ) else {
+            return Err(nb::Error::WouldBlock);
+        };
+        if skip {
+            // Drop the message already delivered by `receive_high_priority`.
+            // Safety: The written index must be valid since it was retrieved
+            // from the peripheral, and the configuration was not changed.
+            unsafe {
+                self.regs().a.write(|w| w.fai().bits(status.fgi().bits()));
+            }
+            self.delivered = None;
+        }
+        let source = self
+            .memory
+            .get(get_index as usize)
+            .ok_or(nb::Error::WouldBlock)?
+            .as_ptr();
+        dma.start_transfer(
+            source as *const u32,
+            destination as *mut M as *mut u32,
+            core::mem::size_of::<M>() / 4,
+        )
+        .map_err(nb::Error::Other)?;
+        Ok(RxDmaTransfer {
+            fifo: self,
+            dma,
+            get_index,
+            _destination: PhantomData,
+        })
+    }
 }
 
-impl<'a, F, P: mcan_core::CanId, M: rx::AnyMessage> DynRxFifo for RxFifo<'a, F, P, M>
-where
-    Self: GetRxFifoRegs,
+/// Ongoing DMA transfer of a message out of an RX FIFO
+///
+/// Created by [`RxFifo::receive_dma`]. Dropping the transfer blocks until the
+/// DMA engine has completed it, using [`MessageRamDma::wait`], which
+/// busy-waits unless the DMA implementation overrides it. Check
+/// [`Self::is_complete`] before dropping to avoid blocking.
+pub struct RxDmaTransfer<
+    'a,
+    't,
+    F: FifoId,
+    P: mcan_core::CanId,
+    M: rx::AnyMessage,
+    D: MessageRamDma,
+> {
+    fifo: &'t mut RxFifo<'a, F, P, M>,
+    dma: &'t mut D,
+    get_index: u8,
+    _destination: PhantomData<&'t mut M>,
+}
+
+impl<'a, 't, F: FifoId, P: mcan_core::CanId, M: rx::AnyMessage, D: MessageRamDma>
+    RxDmaTransfer<'a, 't, F, P, M, D>
+{
+    /// Returns `true` once the message has been copied to its destination
+    pub fn is_complete(&mut self) -> bool {
+        self.dma.is_complete()
+    }
+
+    /// Blocks until the transfer has completed and acknowledges the message.
+    pub fn wait(self) {
+        // Completion is awaited and the message acknowledged on drop
+    }
+}
+
+impl<'a, 't, F: FifoId, P: mcan_core::CanId, M: rx::AnyMessage, D: MessageRamDma> Drop
+    for RxDmaTransfer<'a, 't, F, P, M, D>
 {
+    fn drop(&mut self) {
+        self.dma.wait();
+        // Safety: The written index must be valid since it was retrieved from the
+        // peripheral, and the configuration was not changed.
+        unsafe {
+            self.fifo.regs().a.write(|w| w.fai().bits(self.get_index));
+        }
+        if self.fifo.delivered == Some(self.get_index) {
+            self.fifo.delivered = None;
+        }
+    }
+}
+
+impl<'a, P: mcan_core::CanId, M: rx::AnyMessage> RxFifo<'a, Fifo1, P, M> {
+    /// Returns the state of the debug message handling.
+    ///
+    /// The state returns to [`DebugMessageStatus::Idle`] once the DMA
+    /// controller has read out debug message C from its RX buffer.
+    pub fn debug_message_status(&self) -> DebugMessageStatus {
+        // Safety: RXF1S shares the layout of RXFS with the debug message status
+        // in the upper bits, and the register is owned by this FIFO.
+        let status = unsafe { &*(&self.regs().s as *const reg::RXFS as *const reg::RXF1S) };
+        status.read().dms().variant().into()
+    }
+}
+
+impl<'a, F: FifoId, P: mcan_core::CanId, M: rx::AnyMessage> DynRxFifo for RxFifo<'a, F, P, M> {
     type RxFifoId = F;
     type CanId = P;
     type Message = M;
 
     fn len(&self) -> usize {
-        self.regs().s.read().ffl().bits() as usize
+        self.status().ffl().bits() as usize
     }
 
     fn is_empty(&self) -> bool {
@@ -110,31 +490,134 @@ where
         self.memory.len()
     }
 
+    fn high_water_mark(&self) -> usize {
+        self.high_water_mark.get()
+    }
+
+    fn reset_high_water_mark(&mut self) {
+        self.high_water_mark.set(0);
+        self.status();
+    }
+
     fn receive(&mut self) -> nb::Result<Self::Message, Infallible> {
-        let status = self.regs().s.read();
-        let len = status.ffl().bits();
-        if len == 0 {
+        // A FIFO without capacity is disabled and never receives anything.
+        if self.memory.is_empty() {
             return Err(nb::Error::WouldBlock);
         }
-        let get_index = status.fgi().bits() as usize;
-        let message = self.memory[get_index].get();
-        // Mark the message as read.
-        // Safety: The written index must be valid since it was retrieved from the
-        // peripheral, and the configuration was not changed.
-        unsafe {
-            self.regs().a.write(|w| w.fai().bits(get_index as u8));
+        loop {
+            let status = self.status();
+            let len = status.ffl().bits();
+            if len == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+            let get_index = status.fgi().bits();
+            // The peripheral only reports indices within the configured FIFO.
+            let Some(message) = self.memory.get(get_index as usize).map(VolatileCell::get) else {
+                return Err(nb::Error::WouldBlock);
+            };
+            // Mark the message as read, once it has been copied out.
+            fence(Ordering::Release);
+            // Safety: The written index must be valid since it was retrieved from the
+            // peripheral, and the configuration was not changed.
+            unsafe {
+                self.regs().a.write(|w| w.fai().bits(get_index));
+            }
+            if self.delivered == Some(get_index) {
+                self.delivered = None;
+                continue;
+            }
+            return Ok(message);
+        }
+    }
+
+    fn peek(&self) -> nb::Result<Self::Message, Infallible> {
+        let capacity = self.memory.len();
+        if capacity == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let status = self.status();
+        let (index, _) = undelivered(
+            status.fgi().bits(),
+            status.ffl().bits() as usize,
+            self.delivered,
+            capacity,
+        )
+        .ok_or(nb::Error::WouldBlock)?;
+        self.memory
+            .get(index as usize)
+            .map(VolatileCell::get)
+            .ok_or(nb::Error::WouldBlock)
+    }
+
+    fn receive_high_priority(&mut self) -> nb::Result<Self::Message, Infallible> {
+        // Safety: HPMS is a read-only status register without side effects on
+        // reading.
+        let hpms = unsafe { (*P::register_block()).hpms.read() };
+        if hpms.msi().variant() != F::STORAGE_INDICATOR {
+            return Err(nb::Error::WouldBlock);
+        }
+        let index = hpms.bidx().bits();
+        let status = self.status();
+        let capacity = self.memory.len();
+        let position = (usize::from(index) + capacity)
+            .checked_sub(status.fgi().bits() as usize)
+            .and_then(|offset| offset.checked_rem(capacity));
+        // The message must still be in the queue and not yet delivered.
+        match position {
+            Some(position) if position < status.ffl().bits() as usize => {}
+            _ => return Err(nb::Error::WouldBlock),
         }
+        if self.delivered == Some(index) {
+            return Err(nb::Error::WouldBlock);
+        }
+        let Some(message) = self.memory.get(index as usize).map(VolatileCell::get) else {
+            return Err(nb::Error::WouldBlock);
+        };
+        // A previously delivered message that was not reached yet is
+        // received again, in order.
+        self.delivered = Some(index);
         Ok(message)
     }
+
+    fn flush(&mut self) -> usize {
+        self.delivered = None;
+        let status = self.status();
+        let len = status.ffl().bits() as usize;
+        if len == 0 || self.memory.is_empty() {
+            return 0;
+        }
+        // Acknowledging an element frees it along with all elements before it.
+        let last_index = (status.fgi().bits() as usize + len - 1) % self.memory.len();
+        // Safety: The written index must be valid since it was derived from the
+        // peripheral state and the configured FIFO size.
+        unsafe {
+            self.regs().a.write(|w| w.fai().bits(last_index as u8));
+        }
+        len
+    }
 }
 
-impl<'a, F, P: mcan_core::CanId, M: rx::AnyMessage> Iterator for RxFifo<'a, F, P, M>
-where
-    Self: GetRxFifoRegs,
-{
+impl<'a, F: FifoId, P: mcan_core::CanId, M: rx::AnyMessage> Iterator for RxFifo<'a, F, P, M> {
     type Item = M;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.receive().ok()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delivered_message_is_skipped() {
+        assert_eq!(undelivered(0, 0, None, 4), None);
+        assert_eq!(undelivered(3, 2, None, 4), Some((3, false)));
+        assert_eq!(undelivered(3, 2, Some(3), 4), Some((0, true)));
+        // Only the delivered message is left
+        assert_eq!(undelivered(3, 1, Some(3), 4), None);
+        // A message delivered further back is received in order
+        assert_eq!(undelivered(1, 3, Some(2), 4), Some((1, false)));
+        assert_eq!(undelivered(0, 1, None, 0), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Observable handling of full RX FIFOs
+//!
+//! In both of its modes, the peripheral drops frames silently once an RX
+//! FIFO is full: blocking mode discards new frames, overwrite mode the oldest
+//! ones. [`OverflowHandler`] adds a policy in software that reports each
+//! dropped frame to a callback, so that the loss can be accounted for even
+//! when the payloads cannot be kept.
+//!
+//! - [`FullPolicy::DropNew`] leaves dropping to the peripheral in blocking
+//!   mode. Only the loss of a frame is known, not which frame it was.
+//! - [`FullPolicy::DropOldest`] keeps free space in a FIFO in blocking mode
+//!   by discarding its oldest frames, reporting their ID and timestamp.
+//!
+//! The handler should be called on the watermark, full and message lost
+//! interrupts of the FIFO.
+//!
+//! ```no_run
+//! use mcan::interrupt::{state, OwnedInterruptSet};
+//! use mcan::message::rx;
+//! use mcan::rx_fifo::DynRxFifo;
+//! use mcan::rx_overflow::{DroppedFrame, OverflowHandler};
+//!
+//! fn on_interrupt<Id: mcan::core::CanId>(
+//!     handler: &mut OverflowHandler<impl FnMut(DroppedFrame)>,
+//!     interrupts: &OwnedInterruptSet<Id, state::EnabledLine0>,
+//!     fifo: &mut impl DynRxFifo<Message = rx::Message<8>>,
+//! ) {
+//!     let flags = interrupts.interrupt_flags();
+//!     interrupts.clear_interrupts(flags);
+//!     handler.handle(fifo, flags);
+//! }
+//! ```
+
+use crate::interrupt::InterruptSet;
+use crate::message::rx;
+use crate::rx_fifo::{DynRxFifo, FifoId};
+use embedded_can::Id;
+
+/// What to drop when an RX FIFO is full
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// New frames are dropped by the peripheral
+    DropNew,
+    /// The oldest frames are dropped to keep at least `reserve` elements of
+    /// the FIFO free
+    DropOldest {
+        /// Number of elements kept free
+        reserve: usize,
+    },
+}
+
+/// Frame dropped by an [`OverflowHandler`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DroppedFrame {
+    /// A frame was discarded from the FIFO
+    Discarded {
+        /// ID of the frame
+        id: Id,
+        /// Reception timestamp of the frame
+        timestamp: u16,
+    },
+    /// A frame was lost by the peripheral, which does not tell which one
+    Lost,
+}
+
+/// Applies a [`FullPolicy`] to an RX FIFO, reporting dropped frames
+pub struct OverflowHandler<F> {
+    policy: FullPolicy,
+    on_drop: F,
+}
+
+impl<F: FnMut(DroppedFrame)> OverflowHandler<F> {
+    /// Applies `policy`, calling `on_drop` for every frame dropped
+    pub fn new(policy: FullPolicy, on_drop: F) -> Self {
+        Self { policy, on_drop }
+    }
+
+    /// Returns the policy applied
+    pub fn policy(&self) -> FullPolicy {
+        self.policy
+    }
+
+    /// Handles the interrupt `flags` of `fifo`: reports a lost frame if a
+    /// message was lost, and discards the oldest frames as required by the
+    /// policy. Returns the number of frames dropped.
+    pub fn handle<R: DynRxFifo>(&mut self, fifo: &mut R, flags: InterruptSet) -> usize
+    where
+        R::Message: rx::AnyMessage,
+    {
+        let lost = flags.contains(R::RxFifoId::MESSAGE_LOST);
+        self.handle_with(lost, |reserve| {
+            if fifo.capacity().saturating_sub(fifo.len()) >= reserve {
+                return None;
+            }
+            let message = fifo.receive().ok()?;
+            Some(DroppedFrame::Discarded {
+                id: crate::message::Raw::id(&message),
+                timestamp: rx::AnyMessage::timestamp(&message),
+            })
+        })
+    }
+
+    /// Reports a lost frame if `lost` is set and applies the policy with
+    /// `discard`, which discards the oldest frame while fewer than the given
+    /// number of elements are free.
+    fn handle_with(
+        &mut self,
+        lost: bool,
+        mut discard: impl FnMut(usize) -> Option<DroppedFrame>,
+    ) -> usize {
+        let mut dropped = 0;
+        if lost {
+            (self.on_drop)(DroppedFrame::Lost);
+            dropped += 1;
+        }
+        if let FullPolicy::DropOldest { reserve } = self.policy {
+            while let Some(frame) = discard(reserve) {
+                (self.on_drop)(frame);
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use embedded_can::StandardId;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    const CAPACITY: usize = 4;
+
+    fn discard_from(fifo: &mut VecDeque<u16>) -> impl FnMut(usize) -> Option<DroppedFrame> + '_ {
+        |reserve| {
+            if CAPACITY - fifo.len() >= reserve {
+                return None;
+            }
+            let timestamp = fifo.pop_front()?;
+            Some(DroppedFrame::Discarded {
+                id: Id::Standard(StandardId::new(timestamp).unwrap()),
+                timestamp,
+            })
+        }
+    }
+
+    #[test]
+    fn lost_frame_is_reported_and_space_recovered() {
+        let mut dropped = Vec::new();
+        let mut handler = OverflowHandler::new(FullPolicy::DropOldest { reserve: 2 }, |frame| {
+            dropped.push(frame)
+        });
+        // A frame arrived while the FIFO was full
+        let mut fifo = VecDeque::from([1, 2, 3, 4]);
+        assert_eq!(handler.handle_with(true, discard_from(&mut fifo)), 3);
+        assert_eq!(fifo, [3, 4]);
+        // Once space is kept free, frames are no longer dropped
+        fifo.push_back(5);
+        assert_eq!(handler.handle_with(false, discard_from(&mut fifo)), 1);
+        assert_eq!(handler.handle_with(false, discard_from(&mut fifo)), 0);
+        assert_eq!(fifo, [4, 5]);
+        let id = |id| Id::Standard(StandardId::new(id).unwrap());
+        assert_eq!(
+            dropped,
+            [
+                DroppedFrame::Lost,
+                DroppedFrame::Discarded {
+                    id: id(1),
+                    timestamp: 1
+                },
+                DroppedFrame::Discarded {
+                    id: id(2),
+                    timestamp: 2
+                },
+                DroppedFrame::Discarded {
+                    id: id(3),
+                    timestamp: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_new_only_reports_lost_frames() {
+        let mut lost = 0;
+        let mut handler = OverflowHandler::new(FullPolicy::DropNew, |_| lost += 1);
+        let mut fifo = VecDeque::from([1, 2, 3, 4]);
+        assert_eq!(handler.handle_with(true, discard_from(&mut fifo)), 1);
+        assert_eq!(handler.handle_with(false, discard_from(&mut fifo)), 0);
+        assert_eq!(fifo.len(), CAPACITY);
+        assert_eq!(lost, 1);
+    }
+}
@@ -0,0 +1,184 @@
+//! Received messages with absolute timestamps
+//!
+//! Messages carry the 16-bit value of the timestamp counter at their start of
+//! frame. The counter wraps around within milliseconds to seconds, and its
+//! tick depends on the configuration, so the raw values are hard to use far
+//! from the interrupt handler that received the messages.
+//!
+//! A [`MonotonicClock`] extends a [`TimestampSource`] to a 64-bit counter that
+//! does not wrap around in practice. A [`TimestampingProducer`] uses it to
+//! convert the timestamps of messages while moving them from an RX FIFO into a
+//! [`Spillover`] queue, where they are queued as [`Timestamped`] messages.
+//! Consumers get monotonic ticks and nanoseconds without access to the
+//! peripheral or its configuration.
+//!
+//! The conversion relies on messages being taken out of the FIFO less than
+//! one period of the counter after their reception, and on the clock being
+//! read at least once per period, e.g. by draining the FIFO periodically.
+//!
+//! ```no_run
+//! use mcan::message::rx;
+//! use mcan::monitor::DynMonitor;
+//! use mcan::rx_fifo::DynRxFifo;
+//! use mcan::rx_timestamped::{MonotonicClock, Timestamped, TimestampingProducer};
+//! use mcan::spillover::Spillover;
+//! use mcan::timestamp::PeripheralCounter;
+//! # use fugit::ExtU32;
+//!
+//! static mut QUEUE: Spillover<Timestamped<rx::Message<8>>, 32> = Spillover::new();
+//!
+//! fn setup<M: DynMonitor>(monitor: &M, fifo: &mut impl DynRxFifo<Message = rx::Message<8>>) {
+//!     // External timestamp unit running at 1 MHz
+//!     let clock = MonotonicClock::new(PeripheralCounter::external(monitor, 1000.nanos()));
+//!     // Safety: `QUEUE` is only split once
+//!     let (producer, mut consumer) = unsafe { (*core::ptr::addr_of_mut!(QUEUE)).split() };
+//!     let mut producer = TimestampingProducer::new(producer, clock);
+//!
+//!     // In the interrupt handler
+//!     producer.drain(fifo);
+//!
+//!     // In the application
+//!     while let Some(received) = consumer.pop() {
+//!         let _ = received.nanos;
+//!     }
+//! }
+//! ```
+//!
+//! [`Spillover`]: crate::spillover::Spillover
+
+use crate::message::rx;
+use crate::rx_fifo::DynRxFifo;
+use crate::spillover::Producer;
+use crate::timestamp::TimestampSource;
+
+/// Extends a wrapping [`TimestampSource`] to a 64-bit monotonic counter
+pub struct MonotonicClock<S> {
+    source: S,
+    last: u32,
+    ticks: u64,
+}
+
+impl<S: TimestampSource> MonotonicClock<S> {
+    /// Starts counting at zero from the current value of `source`
+    pub fn new(source: S) -> Self {
+        let last = source.now();
+        Self {
+            source,
+            last,
+            ticks: 0,
+        }
+    }
+
+    /// Returns the ticks since the clock was created. Has to be called at
+    /// least once per period of the source to account for all its
+    /// wraparounds.
+    pub fn now(&mut self) -> u64 {
+        let now = self.source.now();
+        let elapsed = self.source.ticks_between(self.last, now);
+        self.last = now;
+        self.ticks = self.ticks.wrapping_add(elapsed.into());
+        self.ticks
+    }
+
+    /// Converts `timestamp` of the source, taken less than one period ago, to
+    /// ticks of the clock
+    pub fn extend(&mut self, timestamp: u32) -> u64 {
+        let now = self.now();
+        now.wrapping_sub(self.source.ticks_between(timestamp, self.last).into())
+    }
+
+    /// Converts `ticks` of the clock to nanoseconds
+    pub fn to_nanos(&self, ticks: u64) -> u64 {
+        ticks.saturating_mul(self.source.tick_duration().to_nanos().into())
+    }
+
+    /// Returns the timestamp source
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+}
+
+/// Message with its timestamp converted by a [`MonotonicClock`]
+#[derive(Copy, Clone, Debug)]
+pub struct Timestamped<M> {
+    /// Received message
+    pub message: M,
+    /// Start of frame in ticks of the clock
+    pub ticks: u64,
+    /// Start of frame in nanoseconds since the clock was created
+    pub nanos: u64,
+}
+
+/// Moves messages from RX FIFOs into a [`Spillover`] queue, converting their
+/// timestamps on the way
+///
+/// [`Spillover`]: crate::spillover::Spillover
+pub struct TimestampingProducer<'q, M, S, const N: usize> {
+    producer: Producer<'q, Timestamped<M>, N>,
+    clock: MonotonicClock<S>,
+}
+
+impl<'q, M: rx::AnyMessage, S: TimestampSource, const N: usize> TimestampingProducer<'q, M, S, N> {
+    /// Feeds `producer` with timestamps of `clock`, which has to read the
+    /// counter the messages are timestamped with
+    pub fn new(producer: Producer<'q, Timestamped<M>, N>, clock: MonotonicClock<S>) -> Self {
+        Self { producer, clock }
+    }
+
+    /// Converts the timestamp of `message` and appends it to the queue. If
+    /// the queue is full, the message is dropped and handed back.
+    pub fn push(&mut self, message: M) -> Result<(), Timestamped<M>> {
+        let ticks = self.clock.extend(message.timestamp().into());
+        self.producer.push(Timestamped {
+            message,
+            ticks,
+            nanos: self.clock.to_nanos(ticks),
+        })
+    }
+
+    /// Moves all messages from `fifo` into the queue, see
+    /// [`Producer::drain`]. Returns the number of messages taken from `fifo`.
+    pub fn drain<F: DynRxFifo<Message = M>>(&mut self, fifo: &mut F) -> usize {
+        self.drain_bounded(fifo, usize::MAX)
+    }
+
+    /// Moves at most `max` messages from `fifo` into the queue. Returns the
+    /// number of messages taken from `fifo`.
+    pub fn drain_bounded<F: DynRxFifo<Message = M>>(&mut self, fifo: &mut F, max: usize) -> usize {
+        fifo.receive_bounded(max, |message| {
+            let _ = self.push(message);
+        })
+    }
+
+    /// Returns the clock
+    pub fn clock(&mut self) -> &mut MonotonicClock<S> {
+        &mut self.clock
+    }
+
+    /// Returns the producer and the clock
+    pub fn release(self) -> (Producer<'q, Timestamped<M>, N>, MonotonicClock<S>) {
+        (self.producer, self.clock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::timestamp::Timer;
+    use core::cell::Cell;
+    use fugit::ExtU32;
+
+    #[test]
+    fn clock_extends_wrapping_timestamps() {
+        let counter = Cell::new(0xff00);
+        let mut clock = MonotonicClock::new(Timer::new(|| counter.get(), 16, 2.nanos()));
+        for _ in 0..3 {
+            counter.set((counter.get() + 0x8000) & 0xffff);
+            clock.now();
+        }
+        assert_eq!(clock.now(), 0x18000);
+        counter.set(0x7f10);
+        assert_eq!(clock.extend(0x7ef0), 0x17ff0);
+        assert_eq!(clock.to_nanos(0x17ff0), 2 * 0x17ff0);
+    }
+}
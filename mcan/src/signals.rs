@@ -0,0 +1,342 @@
+//! Encoding and decoding of signals in frame payloads
+//!
+//! Signals are bit fields within the payload of a frame, described the way
+//! DBC files and J1939 define them: a start bit, a length, a byte order and a
+//! linear conversion from the raw value to the physical value.
+//!
+//! Bits are numbered from the least significant bit of the first byte, i.e.
+//! bit 0 is the least significant bit of byte 0 and bit 8 the least
+//! significant bit of byte 1. For [`ByteOrder::LittleEndian`] (Intel) signals,
+//! the start bit is the least significant bit of the signal. For
+//! [`ByteOrder::BigEndian`] (Motorola) signals, it is the most significant bit,
+//! as in DBC files.
+//!
+//! Layouts of whole messages are declared with [`message_layout!`].
+//!
+//! ```
+//! use mcan::signals::{ByteOrder, Signal};
+//!
+//! const ENGINE_SPEED: Signal = Signal::new(24, 16, ByteOrder::LittleEndian).scaled(0.125, 0.0);
+//!
+//! let data = [0xff, 0xff, 0xff, 0x68, 0x13, 0xff, 0xff, 0xff];
+//! assert_eq!(ENGINE_SPEED.decode(&data), Some(621.0));
+//! ```
+//!
+//! [`message_layout!`]: crate::message_layout
+
+/// Order of the bytes of a signal spanning multiple bytes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Least significant byte first, also known as Intel byte order
+    LittleEndian,
+    /// Most significant byte first, also known as Motorola byte order
+    BigEndian,
+}
+
+/// Errors of encoding signals
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignalError {
+    /// The signal extends beyond the end of the payload
+    OutOfBounds,
+    /// The value cannot be represented by the signal
+    ValueOutOfRange,
+}
+
+/// Description of a signal within a payload
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Signal {
+    /// Bit the signal starts at, see the [module documentation](self)
+    pub start_bit: u16,
+    /// Length of the signal in bits, 1..=64
+    pub length: u8,
+    /// Byte order of the signal
+    pub byte_order: ByteOrder,
+    /// `true` if the raw value is a two's complement signed integer
+    pub signed: bool,
+    /// Factor of the conversion from raw to physical value
+    pub factor: f64,
+    /// Offset of the conversion from raw to physical value
+    pub offset: f64,
+}
+
+impl Signal {
+    /// Describes an unsigned signal whose physical value equals the raw value
+    pub const fn new(start_bit: u16, length: u8, byte_order: ByteOrder) -> Self {
+        Self {
+            start_bit,
+            length,
+            byte_order,
+            signed: false,
+            factor: 1.0,
+            offset: 0.0,
+        }
+    }
+
+    /// Interprets the raw value as two's complement signed integer
+    pub const fn signed(mut self) -> Self {
+        self.signed = true;
+        self
+    }
+
+    /// Converts raw values to physical values as `raw * factor + offset`
+    pub const fn scaled(mut self, factor: f64, offset: f64) -> Self {
+        self.factor = factor;
+        self.offset = offset;
+        self
+    }
+
+    /// Calls `f` with the positions of the bits of the signal, most
+    /// significant bit first. Returns `false` if a bit is beyond `len` bytes.
+    fn for_each_bit(&self, len: usize, mut f: impl FnMut(usize)) -> bool {
+        let bits = len * 8;
+        let length = usize::from(self.length);
+        if length == 0 || length > 64 {
+            return false;
+        }
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                let start = usize::from(self.start_bit);
+                if start + length > bits {
+                    return false;
+                }
+                (start..start + length).rev().for_each(f);
+            }
+            ByteOrder::BigEndian => {
+                let mut position = usize::from(self.start_bit);
+                for i in 0..length {
+                    if position >= bits {
+                        return false;
+                    }
+                    f(position);
+                    if i + 1 < length {
+                        position = if position % 8 == 0 {
+                            position + 15
+                        } else {
+                            position - 1
+                        };
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Extracts the raw value of the signal from `data` without sign
+    /// extension. Returns `None` if the signal does not fit into `data`.
+    pub fn extract_raw(&self, data: &[u8]) -> Option<u64> {
+        let mut raw = 0_u64;
+        self.for_each_bit(data.len(), |bit| {
+            let byte = data.get(bit / 8).copied().unwrap_or_default();
+            raw = raw << 1 | u64::from(byte >> (bit % 8) & 1);
+        })
+        .then_some(raw)
+    }
+
+    /// Extracts the raw value of the signal from `data`, sign extended if the
+    /// signal is signed
+    pub fn extract(&self, data: &[u8]) -> Option<i64> {
+        let raw = self.extract_raw(data)?;
+        let unused = 64 - u32::from(self.length);
+        Some(if self.signed {
+            ((raw << unused) as i64) >> unused
+        } else {
+            raw as i64
+        })
+    }
+
+    /// Decodes the physical value of the signal from `data`
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        let raw = if self.signed {
+            self.extract(data)? as f64
+        } else {
+            self.extract_raw(data)? as f64
+        };
+        Some(raw * self.factor + self.offset)
+    }
+
+    /// Inserts the raw value `raw` into `data`, leaving other bits untouched.
+    /// Bits of `raw` beyond the length of the signal are ignored.
+    pub fn insert_raw(&self, data: &mut [u8], raw: u64) -> Result<(), SignalError> {
+        // Check first, so that `data` is left untouched on failure
+        if !self.for_each_bit(data.len(), |_| ()) {
+            return Err(SignalError::OutOfBounds);
+        }
+        // Bits are visited most significant first
+        let mut remaining = u32::from(self.length);
+        self.for_each_bit(data.len(), |bit| {
+            remaining -= 1;
+            if let Some(byte) = data.get_mut(bit / 8) {
+                if raw >> remaining & 1 != 0 {
+                    *byte |= 1 << (bit % 8);
+                } else {
+                    *byte &= !(1 << (bit % 8));
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Encodes the physical value `value` into `data`, rounding to the
+    /// nearest raw value
+    pub fn encode(&self, data: &mut [u8], value: f64) -> Result<(), SignalError> {
+        let length = u32::from(self.length);
+        if !(1..=64).contains(&length) {
+            return Err(SignalError::OutOfBounds);
+        }
+        let raw = (value - self.offset) / self.factor;
+        if raw.is_nan() {
+            return Err(SignalError::ValueOutOfRange);
+        }
+        let raw = if raw < 0.0 { raw - 0.5 } else { raw + 0.5 };
+        let (min, max) = if self.signed {
+            (-(1_i128 << (length - 1)), (1_i128 << (length - 1)) - 1)
+        } else {
+            (0, (1_i128 << length) - 1)
+        };
+        // `as` saturates
+        let raw = raw as i128;
+        if !(min..=max).contains(&raw) {
+            return Err(SignalError::ValueOutOfRange);
+        }
+        self.insert_raw(data, raw as u64)
+    }
+}
+
+/// Declares the signal layout of a message
+///
+/// Generates a struct with a public `f64` field holding the physical value of
+/// each signal, together with
+/// - `decode(data: &[u8]) -> Option<Self>`, failing if a signal does not fit
+///   into `data`, and
+/// - `encode(&self, data: &mut [u8]) -> Result<(), SignalError>`.
+///
+/// ```
+/// use mcan::signals::{ByteOrder, Signal};
+///
+/// mcan::message_layout! {
+///     /// Electronic engine controller 1
+///     #[derive(Debug, PartialEq)]
+///     pub struct Eec1 {
+///         /// Engine speed in rpm
+///         engine_speed: Signal::new(24, 16, ByteOrder::LittleEndian).scaled(0.125, 0.0),
+///         /// Actual engine torque in percent
+///         actual_torque: Signal::new(16, 8, ByteOrder::LittleEndian).scaled(1.0, -125.0),
+///     }
+/// }
+///
+/// let eec1 = Eec1::decode(&[0xff, 0xff, 0x7d, 0x68, 0x13, 0xff, 0xff, 0xff]).unwrap();
+/// assert_eq!(eec1, Eec1 { engine_speed: 621.0, actual_torque: 0.0 });
+///
+/// let mut data = [0xff; 8];
+/// eec1.encode(&mut data).unwrap();
+/// assert_eq!(data, [0xff, 0xff, 0x7d, 0x68, 0x13, 0xff, 0xff, 0xff]);
+/// ```
+#[macro_export]
+macro_rules! message_layout {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                $field:ident : $signal:expr
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                pub $field: f64,
+            )*
+        }
+
+        impl $name {
+            /// Decodes the physical values of all signals from `data`
+            pub fn decode(data: &[u8]) -> ::core::option::Option<Self> {
+                ::core::option::Option::Some(Self {
+                    $(
+                        $field: $crate::signals::Signal::decode(&$signal, data)?,
+                    )*
+                })
+            }
+
+            /// Encodes the physical values of all signals into `data`
+            pub fn encode(
+                &self,
+                data: &mut [u8],
+            ) -> ::core::result::Result<(), $crate::signals::SignalError> {
+                $(
+                    $crate::signals::Signal::encode(&$signal, data, self.$field)?;
+                )*
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extracts_little_endian() {
+        let data = [0x34, 0x12, 0xf0];
+        let signal = Signal::new(0, 16, ByteOrder::LittleEndian);
+        assert_eq!(signal.extract_raw(&data), Some(0x1234));
+        let signal = Signal::new(4, 8, ByteOrder::LittleEndian);
+        assert_eq!(signal.extract_raw(&data), Some(0x23));
+        let signal = Signal::new(20, 4, ByteOrder::LittleEndian).signed();
+        assert_eq!(signal.extract(&data), Some(-1));
+        let signal = Signal::new(16, 16, ByteOrder::LittleEndian);
+        assert_eq!(signal.extract_raw(&data), None);
+    }
+
+    #[test]
+    fn extracts_big_endian() {
+        let data = [0x12, 0x34, 0x56];
+        // MSB is bit 7 of byte 0
+        let signal = Signal::new(7, 16, ByteOrder::BigEndian);
+        assert_eq!(signal.extract_raw(&data), Some(0x1234));
+        // MSB is bit 3 of byte 0
+        let signal = Signal::new(3, 8, ByteOrder::BigEndian);
+        assert_eq!(signal.extract_raw(&data), Some(0x23));
+        let signal = Signal::new(15, 24, ByteOrder::BigEndian);
+        assert_eq!(signal.extract_raw(&data), None);
+    }
+
+    #[test]
+    fn round_trips_physical_values() {
+        let signals = [
+            Signal::new(3, 12, ByteOrder::BigEndian)
+                .signed()
+                .scaled(0.5, 10.0),
+            Signal::new(13, 12, ByteOrder::LittleEndian)
+                .signed()
+                .scaled(0.5, 10.0),
+        ];
+        for signal in signals {
+            let mut data = [0xa5; 4];
+            signal.encode(&mut data, -20.5).unwrap();
+            assert_eq!(signal.decode(&data), Some(-20.5));
+            assert_eq!(signal.extract(&data), Some(-61));
+            assert_eq!(
+                signal.encode(&mut data, 1100.0),
+                Err(SignalError::ValueOutOfRange)
+            );
+        }
+    }
+
+    #[test]
+    fn preserves_surrounding_bits() {
+        let mut data = [0xff; 2];
+        Signal::new(4, 8, ByteOrder::LittleEndian)
+            .insert_raw(&mut data, 0)
+            .unwrap();
+        assert_eq!(data, [0x0f, 0xf0]);
+        let mut data = [0x00; 2];
+        Signal::new(3, 8, ByteOrder::BigEndian)
+            .insert_raw(&mut data, 0xff)
+            .unwrap();
+        assert_eq!(data, [0x0f, 0xf0]);
+    }
+}
@@ -0,0 +1,103 @@
+//! Detection of a silent bus
+//!
+//! Reports when no frame has been received for a configurable period. There
+//! are two ways to detect silence:
+//!
+//! - Interrupt driven, with the timeout counter of the peripheral. Configure
+//!   [`CanConfig::timeout`] with [`TimeoutSelect::CONT`], enable
+//!   [`Interrupt::TimeoutOccured`] and call [`Aux::restart_timeout_counter`]
+//!   for every received frame. The interrupt fires once the bus has been
+//!   silent for the configured period.
+//! - Polled, with [`SilenceMonitor`], which compares the timestamps of received
+//!   frames to the current value of the timestamp counter.
+//!
+//! [`CanConfig::timeout`]: crate::config::CanConfig::timeout
+//! [`TimeoutSelect::CONT`]: crate::config::TimeoutSelect::CONT
+//! [`Interrupt::TimeoutOccured`]: crate::interrupt::Interrupt::TimeoutOccured
+//! [`Aux::restart_timeout_counter`]: crate::bus::Aux::restart_timeout_counter
+
+use crate::config::{BitTiming, Timestamp};
+use crate::message::rx;
+
+/// Polled detector of bus silence based on timestamps
+///
+/// Timestamps are in ticks of the timestamp counter, see
+/// [`Timestamp::tick_duration`]. Since the counter is only 16 bits wide and
+/// wraps around, [`Self::poll`] must be called at least once per period for
+/// silence to be detected reliably.
+#[derive(Clone, Debug)]
+pub struct SilenceMonitor {
+    period: u16,
+    last_activity: u16,
+    silent: bool,
+}
+
+impl SilenceMonitor {
+    /// Creates a monitor reporting silence after `period` timestamp ticks
+    /// without received frames, starting at timestamp `now`
+    pub fn new(period: u16, now: u16) -> Self {
+        Self {
+            period,
+            last_activity: now,
+            silent: false,
+        }
+    }
+
+    /// Creates a monitor reporting silence after `period`, rounded down to
+    /// whole timestamp ticks, starting at timestamp `now`. The period is
+    /// saturated to the longest period that can be measured.
+    pub fn with_period<const NOM: u32, const DENOM: u32>(
+        period: fugit::Duration<u32, NOM, DENOM>,
+        timestamp: &Timestamp,
+        bit_timing: &BitTiming,
+        now: u16,
+    ) -> Self {
+        let ticks = bit_timing.bit_times_in(period) / u32::from(timestamp.prescaler.max(1));
+        Self::new(ticks.try_into().unwrap_or(u16::MAX), now)
+    }
+
+    /// Records activity at timestamp `timestamp`
+    pub fn on_activity(&mut self, timestamp: u16) {
+        self.last_activity = timestamp;
+        self.silent = false;
+    }
+
+    /// Records the reception of `message`
+    pub fn on_message<M: rx::AnyMessage>(&mut self, message: &M) {
+        self.on_activity(message.timestamp());
+    }
+
+    /// Returns `true` if the bus has been silent for at least the configured
+    /// period at timestamp `now`. Once silence is detected, it is reported
+    /// until the next activity is recorded.
+    pub fn poll(&mut self, now: u16) -> bool {
+        if now.wrapping_sub(self.last_activity) >= self.period {
+            self.silent = true;
+        }
+        self.silent
+    }
+
+    /// Returns `true` if silence was detected by the last call to
+    /// [`Self::poll`]
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_silence_across_wraparound() {
+        let mut monitor = SilenceMonitor::new(100, 0xfff0);
+        assert!(!monitor.poll(0xffff));
+        assert!(!monitor.poll(0x0050));
+        assert!(monitor.poll(0x0054));
+        assert!(monitor.poll(0x0055));
+        monitor.on_activity(0x0060);
+        assert!(!monitor.is_silent());
+        assert!(!monitor.poll(0x00c3));
+        assert!(monitor.poll(0x00c4));
+    }
+}
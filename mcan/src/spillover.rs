@@ -0,0 +1,285 @@
+//! Bounded software buffer for received messages
+//!
+//! When the application consumes messages slower than they arrive, the RX
+//! FIFOs of the peripheral eventually overflow and messages are lost without a
+//! trace. A [`Spillover`] buffer can be filled from the interrupt handler to
+//! bridge bursts the hardware queues cannot absorb. If the buffer is full as
+//! well, messages are dropped and counted, so that the loss is visible to the
+//! application.
+//!
+//! The buffer is a single-producer single-consumer queue. It is [`split`] into
+//! a [`Producer`], typically owned by the interrupt handler, and a
+//! [`Consumer`], owned by the application.
+//!
+//! ```no_run
+//! use mcan::message::rx;
+//! use mcan::rx_fifo::DynRxFifo;
+//! use mcan::spillover::{Consumer, Producer, Spillover};
+//!
+//! static mut SPILLOVER: Spillover<rx::Message<8>, 32> = Spillover::new();
+//!
+//! fn on_interrupt(
+//!     producer: &mut Producer<'static, rx::Message<8>, 32>,
+//!     fifo: &mut impl DynRxFifo<Message = rx::Message<8>>,
+//! ) {
+//!     producer.drain(fifo);
+//! }
+//!
+//! fn in_application(consumer: &mut Consumer<'static, rx::Message<8>, 32>) {
+//!     while let Some(message) = consumer.pop() {
+//!         // ...
+//!     }
+//!     if consumer.dropped() != 0 {
+//!         // ...
+//!     }
+//! }
+//!
+//! // Safety: `SPILLOVER` is only split once
+//! let (producer, consumer) = unsafe { (*core::ptr::addr_of_mut!(SPILLOVER)).split() };
+//! ```
+//!
+//! [`split`]: Spillover::split
+
+use crate::rx_fifo::DynRxFifo;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bounded single-producer single-consumer queue holding up to `N` elements
+pub struct Spillover<T, const N: usize> {
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Position of the next element pushed, modulo `2 * N`
+    head: AtomicUsize,
+    /// Position of the next element popped, modulo `2 * N`
+    tail: AtomicUsize,
+    /// Number of elements dropped because the queue was full, wrapping
+    dropped: AtomicUsize,
+}
+
+// Safety: Elements are moved from the producer to the consumer, which may live
+// in different execution contexts. Access to the slots is synchronized by
+// `head` and `tail`.
+unsafe impl<T: Send, const N: usize> Sync for Spillover<T, N> {}
+
+impl<T, const N: usize> Spillover<T, N> {
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        Self {
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Splits the queue into its producer and consumer ends.
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        let queue = &*self;
+        (Producer { queue }, Consumer { queue })
+    }
+
+    /// Returns the number of elements the queue can hold
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Slot holding the element at `position`
+    fn slot(&self, position: usize) -> Option<&UnsafeCell<MaybeUninit<T>>> {
+        self.buffer.get(position.checked_rem(N)?)
+    }
+
+    /// Returns the position following `position`. Positions are kept modulo
+    /// `2 * N`, so that a full queue can be told apart from an empty one
+    /// without relying on the wrap-around of `usize` being a multiple of `N`.
+    fn advance(position: usize) -> usize {
+        let next = position.wrapping_add(1);
+        if next == N.wrapping_mul(2) {
+            0
+        } else {
+            next
+        }
+    }
+
+    /// Number of elements between `tail` and `head`
+    fn distance(head: usize, tail: usize) -> usize {
+        if head >= tail {
+            head - tail
+        } else {
+            head.wrapping_add(N.wrapping_mul(2)).wrapping_sub(tail)
+        }
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        Self::distance(head, tail)
+    }
+}
+
+impl<T, const N: usize> Default for Spillover<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Spillover<T, N> {
+    fn drop(&mut self) {
+        let (_, mut consumer) = self.split();
+        while consumer.pop().is_some() {}
+    }
+}
+
+/// Producing end of a [`Spillover`] queue
+pub struct Producer<'q, T, const N: usize> {
+    queue: &'q Spillover<T, N>,
+}
+
+impl<'q, T, const N: usize> Producer<'q, T, N> {
+    /// Appends `element` to the queue. If the queue is full, the element is
+    /// dropped, accounted for in [`Consumer::dropped`] and handed back.
+    pub fn push(&mut self, element: T) -> Result<(), T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        let tail = self.queue.tail.load(Ordering::Acquire);
+        if Spillover::<T, N>::distance(head, tail) >= N {
+            let dropped = self.queue.dropped.load(Ordering::Relaxed);
+            self.queue
+                .dropped
+                .store(dropped.wrapping_add(1), Ordering::Release);
+            return Err(element);
+        }
+        let Some(slot) = self.queue.slot(head) else {
+            return Err(element);
+        };
+        // Safety: The slot is outside of the range owned by the consumer, and the
+        // producer is unique.
+        unsafe {
+            (*slot.get()).write(element);
+        }
+        self.queue
+            .head
+            .store(Spillover::<T, N>::advance(head), Ordering::Release);
+        Ok(())
+    }
+
+    /// Moves all messages from `fifo` into the queue. Messages that do not
+    /// fit are dropped, keeping the hardware FIFO from overflowing. Returns the
+    /// number of messages taken from `fifo`.
+    pub fn drain<F: DynRxFifo<Message = T>>(&mut self, fifo: &mut F) -> usize {
+        let mut received = 0;
+        while let Ok(message) = fifo.receive() {
+            received += 1;
+            let _ = self.push(message);
+        }
+        received
+    }
+
+    /// Moves at most `max` messages from `fifo` into the queue, like
+    /// [`Self::drain`] with bounded work. Returns the number of messages
+    /// taken from `fifo`.
+    pub fn drain_bounded<F: DynRxFifo<Message = T>>(&mut self, fifo: &mut F, max: usize) -> usize {
+        fifo.receive_bounded(max, |message| {
+            let _ = self.push(message);
+        })
+    }
+
+    /// Returns `true` if the queue is full
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= N
+    }
+}
+
+/// Consuming end of a [`Spillover`] queue
+pub struct Consumer<'q, T, const N: usize> {
+    queue: &'q Spillover<T, N>,
+}
+
+impl<'q, T, const N: usize> Consumer<'q, T, N> {
+    /// Takes the oldest element from the queue
+    pub fn pop(&mut self) -> Option<T> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let head = self.queue.head.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let slot = self.queue.slot(tail)?;
+        // Safety: The slot was initialized by the producer, which does not touch it
+        // again until `tail` has been advanced past it.
+        let element = unsafe { (*slot.get()).assume_init_read() };
+        self.queue
+            .tail
+            .store(Spillover::<T, N>::advance(tail), Ordering::Release);
+        Some(element)
+    }
+
+    /// Returns the number of elements in the queue
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements dropped because the queue was full. The
+    /// counter wraps around on overflow.
+    pub fn dropped(&self) -> usize {
+        self.queue.dropped.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn preserves_order() {
+        let mut queue = Spillover::<u32, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for round in 0..3 {
+            for i in 0..3 {
+                producer.push(round * 10 + i).unwrap();
+            }
+            for i in 0..3 {
+                assert_eq!(consumer.pop(), Some(round * 10 + i));
+            }
+            assert_eq!(consumer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn counts_dropped_elements() {
+        let mut queue = Spillover::<u32, 2>::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert!(producer.is_full());
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(producer.push(4), Err(4));
+        assert_eq!(consumer.dropped(), 2);
+        assert_eq!(consumer.len(), 2);
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(5), Ok(()));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(5));
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn positions_wrap_for_any_capacity() {
+        let mut queue = Spillover::<u32, 3>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for i in 0..20 {
+            producer.push(i).unwrap();
+            producer.push(i + 100).unwrap();
+            assert_eq!(consumer.len(), 2);
+            assert_eq!(consumer.pop(), Some(i));
+            assert_eq!(consumer.pop(), Some(i + 100));
+        }
+        for i in 0..3 {
+            producer.push(i).unwrap();
+        }
+        assert!(producer.is_full());
+        assert_eq!(producer.push(3), Err(3));
+    }
+}
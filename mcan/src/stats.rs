@@ -0,0 +1,90 @@
+//! Statistics accumulated in software
+//!
+//! Some counters of the peripheral are narrow and saturate quickly. The
+//! statistics collected here are accumulated into wider software counters
+//! whenever the corresponding registers are read.
+//!
+//! The counters are kept in [`AtomicStats`], so that they can be updated from
+//! an interrupt handler and read from a task without critical sections. Read-
+//! modify-write operations come from [`portable_atomic`], which also supports
+//! targets without native support for them, like `thumbv6m`. On such targets,
+//! enable the `critical-section` feature, see the [crate level
+//! documentation](crate#concurrency).
+
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Statistics of a CAN peripheral, see [`DynMonitor::stats`]
+///
+/// [`DynMonitor::stats`]: crate::monitor::DynMonitor::stats
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of protocol errors that incremented the transmit or receive
+    /// error counter, accumulated from the 8-bit CAN error logging counter
+    /// (ECR.CEL)
+    ///
+    /// Errors that occur while the hardware counter is saturated are lost.
+    /// [`DynMonitor::on_error_logging_overflow`] should be called on
+    /// [`Interrupt::ErrorLoggingOverflow`] to keep the count accurate on noisy
+    /// buses. Saturates at `u32::MAX`.
+    ///
+    /// [`DynMonitor::on_error_logging_overflow`]: crate::monitor::DynMonitor::on_error_logging_overflow
+    /// [`Interrupt::ErrorLoggingOverflow`]: crate::interrupt::Interrupt::ErrorLoggingOverflow
+    pub protocol_errors: u32,
+    /// Number of frames that started on the bus more than one frame time
+    /// after they were requested, reported through
+    /// [`DynMonitor::on_arbitration_loss`], e.g. by an
+    /// [`ArbitrationTracker`]. Saturates at `u32::MAX`.
+    ///
+    /// [`DynMonitor::on_arbitration_loss`]: crate::monitor::DynMonitor::on_arbitration_loss
+    /// [`ArbitrationTracker`]: crate::arbitration::ArbitrationTracker
+    pub arbitration_losses: u32,
+}
+
+/// [`Stats`] that are updated atomically
+#[derive(Debug, Default)]
+pub struct AtomicStats {
+    protocol_errors: AtomicU32,
+    arbitration_losses: AtomicU32,
+}
+
+impl AtomicStats {
+    /// Creates zeroed statistics
+    pub const fn new() -> Self {
+        Self {
+            protocol_errors: AtomicU32::new(0),
+            arbitration_losses: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns the current values of the statistics
+    pub fn load(&self) -> Stats {
+        Stats {
+            protocol_errors: self.protocol_errors.load(Ordering::Relaxed),
+            arbitration_losses: self.arbitration_losses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the statistics to zero
+    pub fn reset(&self) {
+        self.protocol_errors.store(0, Ordering::Relaxed);
+        self.arbitration_losses.store(0, Ordering::Relaxed);
+    }
+
+    /// Adds the value of the CAN error logging counter, read out of ECR.
+    pub(crate) fn add_error_log(&self, cel: u8) {
+        let _ = self
+            .protocol_errors
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_add(cel.into()))
+            });
+    }
+
+    /// Counts a frame delayed by lost arbitration
+    pub(crate) fn add_arbitration_loss(&self) {
+        let _ =
+            self.arbitration_losses
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                    Some(count.saturating_add(1))
+                });
+    }
+}
@@ -0,0 +1,317 @@
+//! High-level events derived from status interrupts
+//!
+//! Supervisory code is usually interested in transitions of the error state
+//! and in lost messages rather than in individual interrupt flags. The
+//! interrupt handler turns the flags of the error interrupts into
+//! [`StatusEvent`]s with [`StatusEvent::decode`] and pushes them into a small
+//! [`StatusEventQueue`], from which the application consumes them.
+//!
+//! ```no_run
+//! use mcan::monitor::DynMonitor;
+//! use mcan::interrupt::{state, OwnedInterruptSet};
+//! use mcan::spillover::{Consumer, Producer};
+//! use mcan::status_event::{StatusEvent, StatusEventQueue};
+//!
+//! static mut EVENTS: StatusEventQueue<8> = StatusEventQueue::new();
+//!
+//! fn on_interrupt<Id: mcan::core::CanId>(
+//!     interrupts: &OwnedInterruptSet<Id, state::EnabledLine0>,
+//!     monitor: &impl DynMonitor,
+//!     producer: &mut Producer<'static, StatusEvent, 8>,
+//! ) {
+//!     let flags = interrupts.interrupt_flags();
+//!     interrupts.clear_interrupts(flags);
+//!     StatusEvent::decode(flags, &monitor.protocol_status(), |event| {
+//!         let _ = producer.push(event);
+//!     });
+//! }
+//!
+//! fn in_application(consumer: &mut Consumer<'static, StatusEvent, 8>) {
+//!     while let Some(event) = consumer.pop() {
+//!         match event {
+//!             StatusEvent::BusOff => { /* ... */ }
+//!             _ => (),
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::bus::ProtocolStatus;
+use crate::interrupt::{Interrupt, InterruptSet};
+use crate::reg::psr::{DLECSELECT_A, LECSELECT_A};
+use crate::spillover::Spillover;
+
+/// Bounded queue of [`StatusEvent`]s, see [`Spillover`]
+pub type StatusEventQueue<const N: usize> = Spillover<StatusEvent, N>;
+
+/// Last error code of the CAN protocol
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lec {
+    /// No error occurred since the code was last read
+    None,
+    /// More than 5 equal bits in a sequence
+    Stuff,
+    /// A fixed format part of a frame had the wrong format
+    Form,
+    /// A transmitted message was not acknowledged
+    Ack,
+    /// A recessive bit was sent but a dominant level was monitored
+    Bit1,
+    /// A dominant bit was sent but a recessive level was monitored
+    Bit0,
+    /// The CRC of a received message was wrong
+    Crc,
+    /// No CAN bus event was detected since the code was last read
+    NoChange,
+}
+
+impl From<LECSELECT_A> for Lec {
+    fn from(value: LECSELECT_A) -> Self {
+        match value {
+            LECSELECT_A::NONE => Self::None,
+            LECSELECT_A::STUFF => Self::Stuff,
+            LECSELECT_A::FORM => Self::Form,
+            LECSELECT_A::ACK => Self::Ack,
+            LECSELECT_A::BIT1 => Self::Bit1,
+            LECSELECT_A::BIT0 => Self::Bit0,
+            LECSELECT_A::CRC => Self::Crc,
+            LECSELECT_A::NC => Self::NoChange,
+        }
+    }
+}
+
+impl From<DLECSELECT_A> for Lec {
+    fn from(value: DLECSELECT_A) -> Self {
+        match value {
+            DLECSELECT_A::NONE => Self::None,
+            DLECSELECT_A::STUFF => Self::Stuff,
+            DLECSELECT_A::FORM => Self::Form,
+            DLECSELECT_A::ACK => Self::Ack,
+            DLECSELECT_A::BIT1 => Self::Bit1,
+            DLECSELECT_A::BIT0 => Self::Bit0,
+            DLECSELECT_A::CRC => Self::Crc,
+            DLECSELECT_A::NC => Self::NoChange,
+        }
+    }
+}
+
+/// Queue that lost a message because it was full
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverrunSource {
+    /// RX FIFO 0
+    RxFifo0,
+    /// RX FIFO 1
+    RxFifo1,
+    /// TX event FIFO
+    TxEventFifo,
+}
+
+/// Status transition or error reported by the peripheral
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StatusEvent {
+    /// An error counter reached the warning limit of 96
+    ErrorWarning,
+    /// The node became error passive
+    ErrorPassive,
+    /// The node went bus off
+    BusOff,
+    /// The node left the error passive state while an error counter is still
+    /// above the warning limit
+    LeftErrorPassive,
+    /// The node returned to the error active state without warning
+    Recovered,
+    /// A protocol error was detected. Errors in the data phase of CAN FD
+    /// frames report the data phase error code.
+    ProtocolError(Lec),
+    /// A message was lost because a queue was full
+    Overrun(OverrunSource),
+}
+
+/// Error state bits of the protocol status register
+#[derive(Copy, Clone)]
+struct ErrorState {
+    warning: bool,
+    passive: bool,
+    bus_off: bool,
+}
+
+impl StatusEvent {
+    /// The interrupts events are derived from. These should be enabled and
+    /// passed to [`Self::decode`].
+    pub fn interrupts() -> InterruptSet {
+        [
+            Interrupt::WarningStatusChanged,
+            Interrupt::ErrorPassive,
+            Interrupt::BusOff,
+            Interrupt::ProtocolErrorArbitration,
+            Interrupt::ProtocolErrorData,
+            Interrupt::RxFifo0MessageLost,
+            Interrupt::RxFifo1MessageLost,
+            Interrupt::TxEventFifoElementLost,
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// Calls `emit` for every event signalled by the interrupt `flags`, given
+    /// the protocol status read after the flags.
+    ///
+    /// Flags other than [`Self::interrupts`] are ignored. Events are emitted
+    /// in order of severity of the error state, followed by protocol errors
+    /// and overruns.
+    pub fn decode(flags: InterruptSet, status: &ProtocolStatus, emit: impl FnMut(StatusEvent)) {
+        let state = ErrorState {
+            warning: status.ew().bit(),
+            passive: status.ep().bit(),
+            bus_off: status.bo().bit(),
+        };
+        Self::decode_state(
+            flags,
+            state,
+            status.lec().variant().into(),
+            status.dlec().variant().into(),
+            emit,
+        );
+    }
+
+    fn decode_state(
+        flags: InterruptSet,
+        state: ErrorState,
+        lec: Lec,
+        dlec: Lec,
+        mut emit: impl FnMut(StatusEvent),
+    ) {
+        if flags.bo() && state.bus_off {
+            emit(Self::BusOff);
+        }
+        if flags.ep() && state.passive {
+            emit(Self::ErrorPassive);
+        }
+        if flags.ew() && state.warning {
+            emit(Self::ErrorWarning);
+        }
+        if flags.ep() && state.warning && !(state.passive || state.bus_off) {
+            emit(Self::LeftErrorPassive);
+        }
+        if (flags.bo() || flags.ep() || flags.ew())
+            && !(state.bus_off || state.passive || state.warning)
+        {
+            emit(Self::Recovered);
+        }
+        if flags.pea() {
+            emit(Self::ProtocolError(lec));
+        }
+        if flags.ped() {
+            emit(Self::ProtocolError(dlec));
+        }
+        if flags.rf0l() {
+            emit(Self::Overrun(OverrunSource::RxFifo0));
+        }
+        if flags.rf1l() {
+            emit(Self::Overrun(OverrunSource::RxFifo1));
+        }
+        if flags.tefl() {
+            emit(Self::Overrun(OverrunSource::TxEventFifo));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn decode(flags: &[Interrupt], state: ErrorState) -> Vec<StatusEvent> {
+        let mut events = Vec::new();
+        StatusEvent::decode_state(
+            flags.iter().copied().collect(),
+            state,
+            Lec::Stuff,
+            Lec::Crc,
+            |event| events.push(event),
+        );
+        events
+    }
+
+    #[test]
+    fn reports_transitions_and_recovery() {
+        let error_active = ErrorState {
+            warning: false,
+            passive: false,
+            bus_off: false,
+        };
+        let bus_off = ErrorState {
+            warning: true,
+            passive: true,
+            bus_off: true,
+        };
+        assert_eq!(
+            decode(&[Interrupt::BusOff, Interrupt::ErrorPassive], bus_off),
+            [StatusEvent::BusOff, StatusEvent::ErrorPassive]
+        );
+        assert_eq!(
+            decode(
+                &[Interrupt::BusOff, Interrupt::WarningStatusChanged],
+                error_active
+            ),
+            [StatusEvent::Recovered]
+        );
+        assert_eq!(decode(&[Interrupt::TimestampWraparound], bus_off), []);
+    }
+
+    #[test]
+    fn reports_leaving_error_passive_for_warning() {
+        let warning = ErrorState {
+            warning: true,
+            passive: false,
+            bus_off: false,
+        };
+        let error_passive = ErrorState {
+            warning: true,
+            passive: true,
+            bus_off: false,
+        };
+        assert_eq!(
+            decode(&[Interrupt::ErrorPassive], error_passive),
+            [StatusEvent::ErrorPassive]
+        );
+        assert_eq!(
+            decode(&[Interrupt::ErrorPassive], warning),
+            [StatusEvent::LeftErrorPassive]
+        );
+        // Passing through error passive between two reads
+        assert_eq!(
+            decode(
+                &[Interrupt::WarningStatusChanged, Interrupt::ErrorPassive],
+                warning
+            ),
+            [StatusEvent::ErrorWarning, StatusEvent::LeftErrorPassive]
+        );
+    }
+
+    #[test]
+    fn reports_errors_and_overruns() {
+        let state = ErrorState {
+            warning: false,
+            passive: false,
+            bus_off: false,
+        };
+        assert_eq!(
+            decode(
+                &[
+                    Interrupt::ProtocolErrorData,
+                    Interrupt::ProtocolErrorArbitration,
+                    Interrupt::TxEventFifoElementLost,
+                ],
+                state
+            ),
+            [
+                StatusEvent::ProtocolError(Lec::Stuff),
+                StatusEvent::ProtocolError(Lec::Crc),
+                StatusEvent::Overrun(OverrunSource::TxEventFifo),
+            ]
+        );
+    }
+}
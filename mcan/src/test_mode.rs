@@ -0,0 +1,122 @@
+//! Test mode for bring-up and hardware-in-the-loop checks
+//!
+//! In test mode, the transmit pin can be driven directly and the level of the
+//! receive pin can be observed, which allows checking the wiring to the
+//! transceiver from firmware. The peripheral can also be looped back on itself.
+//!
+//! Forcing the transmit pin also serves error injection in conformance and
+//! robustness tests: holding it dominant emulates a stuck transmitter, which
+//! blocks all traffic on the bus. Since this disturbs every other node, the
+//! pin control is `unsafe` to use; see [`TestMode::set_tx_pin`].
+//!
+//! Test mode is entered with [`Aux::enter_test_mode`] or, scoped to a closure,
+//! with [`Aux::with_test_mode`].
+//!
+//! ```no_run
+//! # use mcan::bus::Aux;
+//! # use mcan::test_mode::TxPinControl;
+//! # fn check<'a, Id: mcan::core::CanId, D: mcan::core::Dependencies<Id>>(aux: &mut Aux<'a, Id, D>) {
+//! // Safety: The bus is disconnected from other nodes during bring-up.
+//! let wiring_ok = aux.with_test_mode(|test| unsafe {
+//!     let dominant_seen = test.with_tx_pin(TxPinControl::DOMINANT, |test| !test.rx_pin());
+//!     let recessive_seen = test.with_tx_pin(TxPinControl::RECESSIVE, |test| test.rx_pin());
+//!     dominant_seen && recessive_seen
+//! });
+//! # }
+//! ```
+//!
+//! [`Aux::enter_test_mode`]: crate::bus::Aux::enter_test_mode
+//! [`Aux::with_test_mode`]: crate::bus::Aux::with_test_mode
+
+use crate::init_mode::InitModeGuard;
+
+pub use crate::reg::test::TXSELECT_A as TxPinControl;
+
+/// Handle to the peripheral while in test mode
+///
+/// Test mode is left when the handle is dropped, restoring the loopback
+/// setting from the configuration and the previous operating mode.
+pub struct TestMode<'t, Id: mcan_core::CanId> {
+    reg: &'t crate::reg::Can<Id>,
+    /// Loopback setting of the configuration, restored on exit
+    loopback: bool,
+}
+
+impl<'t, Id: mcan_core::CanId> TestMode<'t, Id> {
+    /// Enters test mode. Transitions through configuration mode, which resets
+    /// pending transmissions and the state of the receive FIFOs.
+    pub(crate) fn enter(reg: &'t crate::reg::Can<Id>, loopback: bool) -> Self {
+        let _init = InitModeGuard::enter(reg);
+        reg.cccr.modify(|_, w| w.test().set_bit());
+        Self { reg, loopback }
+    }
+
+    /// Leaves test mode. Same as dropping the handle.
+    pub fn exit(self) {
+        // Test mode is left on drop
+    }
+
+    /// Enables or disables internal loopback. In loopback mode, transmitted
+    /// messages are received by the peripheral itself and the bus is ignored.
+    pub fn set_loopback(&mut self, enabled: bool) {
+        self.reg.test.modify(|_, w| w.lbck().bit(enabled));
+    }
+
+    /// Returns `true` if internal loopback is enabled
+    pub fn loopback(&self) -> bool {
+        self.reg.test.read().lbck().bit()
+    }
+
+    /// Selects what drives the transmit pin. Leaving test mode returns it to
+    /// [`TxPinControl::CORE`].
+    ///
+    /// # Safety
+    /// Driving the pin bypasses the CAN protocol. A dominant level blocks the
+    /// bus and destroys frames of all other nodes, and toggling it produces
+    /// arbitrary bit patterns. The caller is responsible for the rest of the
+    /// system tolerating this, e.g. by disconnecting it from the bus or by
+    /// running a fault injection test it is prepared for.
+    pub unsafe fn set_tx_pin(&mut self, control: TxPinControl) {
+        self.reg.test.modify(|_, w| w.tx().variant(control));
+    }
+
+    /// Runs `f` with the transmit pin driven by `control`, restoring what
+    /// drove it before afterwards.
+    ///
+    /// # Safety
+    /// See [`Self::set_tx_pin`].
+    pub unsafe fn with_tx_pin<R>(
+        &mut self,
+        control: TxPinControl,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let previous = self.tx_pin();
+        self.set_tx_pin(control);
+        let result = f(self);
+        self.set_tx_pin(previous);
+        result
+    }
+
+    /// Returns what currently drives the transmit pin
+    pub fn tx_pin(&self) -> TxPinControl {
+        self.reg.test.read().tx().variant()
+    }
+
+    /// Returns the level of the receive pin; `true` is recessive, `false` is
+    /// dominant
+    pub fn rx_pin(&self) -> bool {
+        self.reg.test.read().rx().bit()
+    }
+}
+
+impl<'t, Id: mcan_core::CanId> Drop for TestMode<'t, Id> {
+    fn drop(&mut self) {
+        // Test mode does not change the operating mode, so the guard returns
+        // to the one test mode was entered from
+        let _init = InitModeGuard::enter(self.reg);
+        self.reg
+            .test
+            .modify(|_, w| w.tx().variant(TxPinControl::CORE).lbck().bit(self.loopback));
+        self.reg.cccr.modify(|_, w| w.test().bit(self.loopback));
+    }
+}
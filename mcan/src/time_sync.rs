@@ -0,0 +1,257 @@
+//! Time synchronization over CAN
+//!
+//! Time synchronization in the style of AUTOSAR CanTSyn: a time master
+//! distributes its global time with pairs of messages. The SYNC message
+//! carries the seconds part of the time at which it is transmitted. Since that
+//! time is only known precisely once the SYNC message has left the peripheral,
+//! it is taken from the TX event of the SYNC message and sent in a subsequent
+//! follow-up (FUP) message together with the nanoseconds part.
+//!
+//! Time slaves record the local time at which the SYNC message was received,
+//! e.g. derived from its RX timestamp, and compute the offset of the global
+//! time as soon as the follow-up arrives. Consecutive synchronizations
+//! additionally yield the rate deviation of the local clock.
+//!
+//! Times are in nanoseconds. The local time base is up to the application and
+//! only needs to be monotonic.
+//!
+//! Both messages have 8 bytes of data:
+//!
+//! | Byte | SYNC                    | FUP                        |
+//! |------|-------------------------|----------------------------|
+//! | 0    | `0x10`                  | `0x18`                     |
+//! | 1    | reserved                | reserved                   |
+//! | 2    | domain (4 bits), sequence counter (4 bits) | domain, sequence counter |
+//! | 3    | reserved                | overflow of seconds (2 bits) |
+//! | 4..8 | seconds, low 32 bits, big endian | nanoseconds, big endian |
+
+use crate::message::{tx, Raw, TooMuchData, TxEvent};
+use embedded_can::Id;
+
+const SYNC: u8 = 0x10;
+const FOLLOW_UP: u8 = 0x18;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+fn header(kind: u8, domain: u8, sequence: u8) -> [u8; 4] {
+    [kind, 0, domain << 4 | (sequence & 0xf), 0]
+}
+
+/// Transmitting side of time synchronization
+#[derive(Clone, Debug)]
+pub struct TimeSyncMaster {
+    id: Id,
+    domain: u8,
+    marker: u8,
+    sequence: u8,
+    /// Seconds sent in the pending SYNC message
+    pending: Option<u64>,
+}
+
+impl TimeSyncMaster {
+    /// Creates a master sending messages with `id` for time `domain` (0..=15).
+    /// SYNC messages store TX events with `marker`.
+    pub fn new(id: Id, domain: u8, marker: u8) -> Self {
+        Self {
+            id,
+            domain: domain & 0xf,
+            marker,
+            sequence: 0,
+            pending: None,
+        }
+    }
+
+    /// Builds the SYNC message for global time `now`. The message must be
+    /// transmitted promptly, so that its transmission falls within the second
+    /// sent in the message.
+    pub fn sync<M: tx::AnyMessage>(&mut self, now: u64) -> Result<M, TooMuchData> {
+        self.sequence = self.sequence.wrapping_add(1) & 0xf;
+        let seconds = now / NANOS_PER_SECOND;
+        self.pending = Some(seconds);
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&header(SYNC, self.domain, self.sequence));
+        data[4..].copy_from_slice(&(seconds as u32).to_be_bytes());
+        M::new(tx::MessageBuilder {
+            id: self.id,
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&data)),
+            store_tx_event: Some(self.marker),
+        })
+    }
+
+    /// Returns `true` if `event` confirms the transmission of the pending SYNC
+    /// message
+    pub fn is_sync_event(&self, event: &TxEvent) -> bool {
+        self.pending.is_some() && event.message_marker() == self.marker && event.id() == self.id
+    }
+
+    /// Builds the follow-up message of the pending SYNC message, given the
+    /// global time at which the SYNC message was transmitted, i.e. the time
+    /// corresponding to [`TxEvent::timestamp`] of its TX event. Returns `None`
+    /// if no SYNC message is pending.
+    pub fn follow_up<M: tx::AnyMessage>(
+        &mut self,
+        transmitted_at: u64,
+    ) -> Option<Result<M, TooMuchData>> {
+        let sent_seconds = self.pending.take()?;
+        let seconds = transmitted_at / NANOS_PER_SECOND;
+        let nanos = transmitted_at % NANOS_PER_SECOND;
+        // The seconds sent in SYNC are authoritative, carry over into the
+        // nanoseconds if transmission was delayed past a second boundary.
+        let nanos = nanos + seconds.saturating_sub(sent_seconds) * NANOS_PER_SECOND;
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&header(FOLLOW_UP, self.domain, self.sequence));
+        data[3] = ((sent_seconds >> 32) & 0x3) as u8;
+        data[4..].copy_from_slice(&(nanos.min(u32::MAX.into()) as u32).to_be_bytes());
+        Some(M::new(tx::MessageBuilder {
+            id: self.id,
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&data)),
+            store_tx_event: None,
+        }))
+    }
+}
+
+/// Correspondence of a global time to a local time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct SyncPoint {
+    local: u64,
+    global: u64,
+}
+
+/// Receiving side of time synchronization, exposing a bus-synchronized clock
+#[derive(Clone, Debug)]
+pub struct TimeSyncSlave {
+    id: Id,
+    domain: u8,
+    /// Sequence counter, seconds and local reception time of the last SYNC
+    pending: Option<(u8, u64, u64)>,
+    reference: Option<SyncPoint>,
+    /// Deviation of the global clock rate from the local one in parts per
+    /// billion
+    rate_ppb: i64,
+}
+
+impl TimeSyncSlave {
+    /// Creates a slave synchronizing to messages with `id` for time `domain`
+    pub fn new(id: Id, domain: u8) -> Self {
+        Self {
+            id,
+            domain: domain & 0xf,
+            pending: None,
+            reference: None,
+            rate_ppb: 0,
+        }
+    }
+
+    /// Processes a received message. `received_at` is the local time at which
+    /// the message was received. Returns `true` if the message completed a
+    /// synchronization.
+    ///
+    /// Messages with other IDs or domains are ignored.
+    pub fn on_message<M: Raw>(&mut self, message: &M, received_at: u64) -> bool {
+        let &[kind, _, header, high, v0, v1, v2, v3, ..] = message.data() else {
+            return false;
+        };
+        if message.id() != self.id || header >> 4 != self.domain {
+            return false;
+        }
+        let sequence = header & 0xf;
+        let value = u32::from_be_bytes([v0, v1, v2, v3]);
+        match kind {
+            SYNC => {
+                self.pending = Some((sequence, value.into(), received_at));
+                false
+            }
+            FOLLOW_UP => match self.pending.take() {
+                Some((sync_sequence, seconds, local)) if sync_sequence == sequence => {
+                    let seconds = seconds | u64::from(high & 0x3) << 32;
+                    let global = seconds * NANOS_PER_SECOND + u64::from(value);
+                    self.synchronize(SyncPoint { local, global });
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn synchronize(&mut self, point: SyncPoint) {
+        if let Some(reference) = self.reference {
+            let local = point.local.wrapping_sub(reference.local) as i64;
+            let global = point.global.wrapping_sub(reference.global) as i64;
+            if local > 0 {
+                self.rate_ppb =
+                    ((global - local) as i128 * NANOS_PER_SECOND as i128 / local as i128) as i64;
+            }
+        }
+        self.reference = Some(point);
+    }
+
+    /// Returns `true` once at least one synchronization has completed
+    pub fn is_synchronized(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    /// Returns the global time at local time `local`, or `None` if not
+    /// synchronized yet
+    pub fn global_time(&self, local: u64) -> Option<u64> {
+        let reference = self.reference?;
+        let elapsed = local.wrapping_sub(reference.local) as i64;
+        let correction =
+            (elapsed as i128 * self.rate_ppb as i128 / NANOS_PER_SECOND as i128) as i64;
+        Some(reference.global.wrapping_add((elapsed + correction) as u64))
+    }
+
+    /// Returns the offset of the global time to the local time at the last
+    /// synchronization, or `None` if not synchronized yet
+    pub fn offset(&self) -> Option<i64> {
+        let reference = self.reference?;
+        Some(reference.global.wrapping_sub(reference.local) as i64)
+    }
+
+    /// Returns the deviation of the global clock rate from the local clock
+    /// rate in parts per billion. Positive values mean the local clock is
+    /// slow.
+    pub fn rate_deviation_ppb(&self) -> i64 {
+        self.rate_ppb
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_can::StandardId;
+
+    const SECOND: u64 = NANOS_PER_SECOND;
+
+    #[test]
+    fn synchronizes_offset_and_rate() {
+        let id = Id::Standard(StandardId::new(0x80).unwrap());
+        let mut master = TimeSyncMaster::new(id, 1, 0xaa);
+        let mut slave = TimeSyncSlave::new(id, 1);
+
+        // The global clock runs 100 ppm faster than the local one and is
+        // ahead by 1000 s.
+        let global = |local: u64| 1000 * SECOND + local + local / 10_000;
+        for local in [5 * SECOND, 15 * SECOND] {
+            let sync: tx::Message<8> = master.sync(global(local) - 100).unwrap();
+            assert!(!slave.on_message(&sync, local));
+            let follow_up: tx::Message<8> = master.follow_up(global(local)).unwrap().unwrap();
+            assert!(slave.on_message(&follow_up, local + 50_000));
+        }
+
+        assert_eq!(slave.rate_deviation_ppb(), 100_000);
+        let local = 25 * SECOND;
+        assert_eq!(slave.global_time(local), Some(global(local)));
+    }
+
+    #[test]
+    fn ignores_follow_up_without_sync() {
+        let id = Id::Standard(StandardId::new(0x80).unwrap());
+        let mut master = TimeSyncMaster::new(id, 0, 0);
+        let mut slave = TimeSyncSlave::new(id, 0);
+        let _: tx::Message<8> = master.sync(SECOND).unwrap();
+        let follow_up: tx::Message<8> = master.follow_up(SECOND).unwrap().unwrap();
+        assert!(!slave.on_message(&follow_up, 0));
+        assert!(!slave.is_synchronized());
+        assert_eq!(slave.global_time(0), None);
+    }
+}
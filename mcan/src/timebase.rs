@@ -0,0 +1,248 @@
+//! Common timeline for two peripherals
+//!
+//! Gateways and loggers attached to two buses timestamp the frames of each bus
+//! with the counter of its peripheral. The counters start at different times
+//! and may run at slightly different rates, e.g. when clocked by different
+//! sources, so their timestamps cannot be compared directly.
+//! [`TimebaseCorrelation`] samples both counters close together and maintains
+//! a model of the offset and rate between them, mapping timestamps of the
+//! second peripheral, B, onto the timeline of the first, A.
+//!
+//! Each sample reads A, then B, then A again, and takes the middle of the two
+//! readings of A as the time B was read. Samples where the readings are
+//! further apart than a configured window, e.g. because an interrupt preempted
+//! the sampling, are rejected. The rate is measured between the first accepted
+//! sample and the latest one, so it becomes more precise the longer the
+//! correlation runs.
+//!
+//! ```no_run
+//! use mcan::message::rx::{self, AnyMessage};
+//! use mcan::rx_timestamped::MonotonicClock;
+//! use mcan::timebase::TimebaseCorrelation;
+//! use mcan::timestamp::TimestampSource;
+//! use fugit::ExtU32;
+//!
+//! fn merge(
+//!     a: impl TimestampSource,
+//!     b: impl TimestampSource,
+//!     from_a: &rx::Message<8>,
+//!     from_b: &rx::Message<8>,
+//! ) -> bool {
+//!     let mut correlation =
+//!         TimebaseCorrelation::new(MonotonicClock::new(a), MonotonicClock::new(b), 5.micros());
+//!     // Periodically, at least once per period of both counters
+//!     correlation.sample();
+//!     let a_time = correlation.time_a(from_a.timestamp().into());
+//!     let b_time = correlation.time_b(from_b.timestamp().into());
+//!     b_time.is_some_and(|b_time| b_time < a_time)
+//! }
+//! ```
+
+use crate::rx_timestamped::MonotonicClock;
+use crate::timestamp::TimestampSource;
+use fugit::NanosDurationU32;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// Times of A and B, in nanoseconds of their clocks, taken at the same moment
+#[derive(Copy, Clone, Debug)]
+struct Sample {
+    a: u64,
+    b: u64,
+}
+
+/// Offset and rate model between two clocks
+#[derive(Clone, Debug, Default)]
+pub struct Correlation {
+    first: Option<Sample>,
+    latest: Option<Sample>,
+    /// Deviation of the rate of A from the rate of B in parts per billion
+    rate_ppb: i64,
+}
+
+impl Correlation {
+    /// Creates a model without samples
+    pub const fn new() -> Self {
+        Self {
+            first: None,
+            latest: None,
+            rate_ppb: 0,
+        }
+    }
+
+    /// Adds a sample of both clocks taken at the same moment, in nanoseconds
+    pub fn add_sample(&mut self, a: u64, b: u64) {
+        let sample = Sample { a, b };
+        let first = *self.first.get_or_insert(sample);
+        let b_elapsed = b.wrapping_sub(first.b) as i64;
+        if b_elapsed > 0 {
+            let a_elapsed = a.wrapping_sub(first.a) as i64;
+            self.rate_ppb = (i128::from(a_elapsed - b_elapsed) * NANOS_PER_SECOND
+                / i128::from(b_elapsed)) as i64;
+        }
+        self.latest = Some(sample);
+    }
+
+    /// Returns `true` once at least one sample was added
+    pub fn is_correlated(&self) -> bool {
+        self.latest.is_some()
+    }
+
+    /// Returns the time of A at time `b` of B, or `None` without samples
+    pub fn to_a(&self, b: u64) -> Option<u64> {
+        let latest = self.latest?;
+        let elapsed = b.wrapping_sub(latest.b) as i64;
+        let correction =
+            (i128::from(elapsed) * i128::from(self.rate_ppb) / NANOS_PER_SECOND) as i64;
+        Some(
+            latest
+                .a
+                .wrapping_add(elapsed.wrapping_add(correction) as u64),
+        )
+    }
+
+    /// Returns the offset of A to B at the latest sample, or `None` without
+    /// samples
+    pub fn offset(&self) -> Option<i64> {
+        let latest = self.latest?;
+        Some(latest.a.wrapping_sub(latest.b) as i64)
+    }
+
+    /// Returns the deviation of the rate of A from the rate of B in parts per
+    /// billion. Positive values mean that A runs faster.
+    pub fn rate_deviation_ppb(&self) -> i64 {
+        self.rate_ppb
+    }
+
+    /// Discards all samples, e.g. after one of the clocks was adjusted
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Correlates the timestamp counters of two peripherals, see the [module
+/// level documentation](self)
+pub struct TimebaseCorrelation<A, B> {
+    a: MonotonicClock<A>,
+    b: MonotonicClock<B>,
+    /// Longest accepted time between the readings of A around B, in
+    /// nanoseconds
+    window: u64,
+    correlation: Correlation,
+}
+
+impl<A: TimestampSource, B: TimestampSource> TimebaseCorrelation<A, B> {
+    /// Correlates clock `b` to clock `a`, accepting samples whose readings of
+    /// A are at most `window` apart
+    pub fn new(a: MonotonicClock<A>, b: MonotonicClock<B>, window: NanosDurationU32) -> Self {
+        Self {
+            a,
+            b,
+            window: window.ticks().into(),
+            correlation: Correlation::new(),
+        }
+    }
+
+    /// Samples both clocks. Returns `false` if the sample was rejected since
+    /// the readings were too far apart.
+    ///
+    /// Has to be called at least once per period of both counters, see
+    /// [`MonotonicClock::now`].
+    pub fn sample(&mut self) -> bool {
+        let before = self.a.now();
+        let b = self.b.now();
+        let after = self.a.now();
+        let before = self.a.to_nanos(before);
+        let after = self.a.to_nanos(after);
+        if after.saturating_sub(before) > self.window {
+            return false;
+        }
+        let a = before + after.saturating_sub(before) / 2;
+        self.correlation.add_sample(a, self.b.to_nanos(b));
+        true
+    }
+
+    /// Converts `timestamp` of A, taken less than one period ago, to
+    /// nanoseconds on the common timeline
+    pub fn time_a(&mut self, timestamp: u32) -> u64 {
+        let ticks = self.a.extend(timestamp);
+        self.a.to_nanos(ticks)
+    }
+
+    /// Converts `timestamp` of B, taken less than one period ago, to
+    /// nanoseconds on the common timeline. Returns `None` until a sample was
+    /// accepted.
+    pub fn time_b(&mut self, timestamp: u32) -> Option<u64> {
+        let ticks = self.b.extend(timestamp);
+        self.correlation.to_a(self.b.to_nanos(ticks))
+    }
+
+    /// Returns the model of offset and rate
+    pub fn correlation(&self) -> &Correlation {
+        &self.correlation
+    }
+
+    /// Returns the clocks
+    pub fn release(self) -> (MonotonicClock<A>, MonotonicClock<B>) {
+        (self.a, self.b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::timestamp::Timer;
+    use core::cell::Cell;
+    use fugit::ExtU32;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn maps_b_onto_a_with_offset_and_rate() {
+        // A runs 50 ppm faster than B and is ahead by 2 s
+        let a = |b: u64| 2 * SECOND + b + b / 20_000;
+        let mut correlation = Correlation::new();
+        assert_eq!(correlation.to_a(0), None);
+        correlation.add_sample(a(SECOND), SECOND);
+        correlation.add_sample(a(11 * SECOND), 11 * SECOND);
+        assert_eq!(correlation.rate_deviation_ppb(), 50_000);
+        assert_eq!(correlation.to_a(21 * SECOND), Some(a(21 * SECOND)));
+        assert_eq!(correlation.offset(), Some((2 * SECOND + 550_000) as i64));
+        correlation.reset();
+        assert!(!correlation.is_correlated());
+    }
+
+    #[test]
+    fn slow_samples_are_rejected() {
+        let a_counter = Cell::new(0u32);
+        let b_counter = Cell::new(0u32);
+        // Reading B takes `b_delay` ticks of A
+        let b_delay = Cell::new(1);
+        let a = Timer::new(
+            || {
+                let now = a_counter.get();
+                a_counter.set(now + 1);
+                now
+            },
+            32,
+            1000.nanos(),
+        );
+        let b = Timer::new(
+            || {
+                a_counter.set(a_counter.get() + b_delay.get());
+                b_counter.get()
+            },
+            16,
+            1000.nanos(),
+        );
+        let mut correlation =
+            TimebaseCorrelation::new(MonotonicClock::new(a), MonotonicClock::new(b), 5.micros());
+        b_delay.set(10);
+        assert!(!correlation.sample());
+        assert_eq!(correlation.time_b(0), None);
+        b_delay.set(1);
+        assert!(correlation.sample());
+        // A was read at 24 and 26 µs around B at 0
+        assert_eq!(correlation.time_b(0), Some(25_000));
+    }
+}
@@ -0,0 +1,178 @@
+//! Pluggable timestamp sources
+//!
+//! Timing features such as latency statistics, time synchronization or
+//! debouncing need a free-running counter with a known tick duration. The
+//! timestamp counter of the peripheral is the natural choice, but its internal
+//! mode counts bit times, which are not constant with CAN FD bitrate
+//! switching. Depending on the chip, an external timestamp unit (TSU) or a
+//! timer of the HAL has to be used instead.
+//!
+//! [`TimestampSource`] abstracts over these, so that timing code is written
+//! once:
+//!
+//! - [`PeripheralCounter`] reads the timestamp counter of the peripheral, in
+//!   internal or external mode
+//! - [`Timer`] reads any counter through a closure, e.g. a HAL timer
+//!
+//! Counters of any width up to 32 bits wrap around; differences of timestamps
+//! are computed modulo the width by [`TimestampSource::ticks_between`].
+//!
+//! ```no_run
+//! use mcan::monitor::DynMonitor;
+//! use mcan::timestamp::{PeripheralCounter, TimestampSource};
+//!
+//! fn measure<S: TimestampSource>(source: &S, f: impl FnOnce()) -> u64 {
+//!     let start = source.now();
+//!     f();
+//!     source.to_nanos(source.elapsed(start))
+//! }
+//!
+//! fn with_external_tsu<M: DynMonitor>(monitor: &M) -> u64 {
+//!     // External counter running at 1 MHz
+//!     let source = PeripheralCounter::external(monitor, 1000.nanos());
+//!     measure(&source, || ())
+//! }
+//! # use fugit::ExtU32;
+//! ```
+
+use crate::config::{BitTiming, Timestamp};
+use crate::monitor::DynMonitor;
+use fugit::NanosDurationU32;
+
+/// Free-running counter providing timestamps
+pub trait TimestampSource {
+    /// Current value of the counter
+    fn now(&self) -> u32;
+
+    /// Width of the counter in bits (1..=32), after which it wraps around
+    fn bits(&self) -> u32;
+
+    /// Duration of one tick of the counter
+    fn tick_duration(&self) -> NanosDurationU32;
+
+    /// Returns the number of ticks from timestamp `earlier` to `later`,
+    /// assuming that the counter wrapped around at most once in between
+    fn ticks_between(&self, earlier: u32, later: u32) -> u32 {
+        let mask = u32::MAX
+            .checked_shr(32 - self.bits().clamp(1, 32))
+            .unwrap_or(0);
+        later.wrapping_sub(earlier) & mask
+    }
+
+    /// Returns the number of ticks passed since timestamp `since`
+    fn elapsed(&self, since: u32) -> u32 {
+        self.ticks_between(since, self.now())
+    }
+
+    /// Converts a number of ticks to nanoseconds
+    fn to_nanos(&self, ticks: u32) -> u64 {
+        u64::from(ticks) * u64::from(self.tick_duration().ticks())
+    }
+}
+
+impl<T: TimestampSource + ?Sized> TimestampSource for &T {
+    fn now(&self) -> u32 {
+        (**self).now()
+    }
+
+    fn bits(&self) -> u32 {
+        (**self).bits()
+    }
+
+    fn tick_duration(&self) -> NanosDurationU32 {
+        (**self).tick_duration()
+    }
+}
+
+/// 16 bit timestamp counter of the peripheral, read through a monitor
+///
+/// Messages are timestamped by the same counter, so RX and TX event
+/// timestamps compare directly with [`TimestampSource::now`].
+pub struct PeripheralCounter<'m, M> {
+    monitor: &'m M,
+    tick: NanosDurationU32,
+}
+
+impl<'m, M: DynMonitor> PeripheralCounter<'m, M> {
+    /// Reads the internal counter, configured by `timestamp` and counting bit
+    /// times of `bit_timing`
+    ///
+    /// With CAN FD bitrate switching, the duration of bit times varies and
+    /// the internal counter is not usable as a time base.
+    pub fn internal(monitor: &'m M, timestamp: &Timestamp, bit_timing: &BitTiming) -> Self {
+        Self {
+            monitor,
+            tick: timestamp.tick_duration(bit_timing),
+        }
+    }
+
+    /// Reads an external counter, e.g. a TSU, with ticks of `tick`
+    pub fn external(monitor: &'m M, tick: NanosDurationU32) -> Self {
+        Self { monitor, tick }
+    }
+}
+
+impl<M: DynMonitor> TimestampSource for PeripheralCounter<'_, M> {
+    fn now(&self) -> u32 {
+        self.monitor.timestamp().into()
+    }
+
+    fn bits(&self) -> u32 {
+        16
+    }
+
+    fn tick_duration(&self) -> NanosDurationU32 {
+        self.tick
+    }
+}
+
+/// Counter read through a closure, e.g. a timer of the HAL
+pub struct Timer<F> {
+    now: F,
+    bits: u32,
+    tick: NanosDurationU32,
+}
+
+impl<F: Fn() -> u32> Timer<F> {
+    /// Reads a counter of `bits` width with ticks of `tick` through `now`
+    pub fn new(now: F, bits: u32, tick: NanosDurationU32) -> Self {
+        Self {
+            now,
+            bits: bits.clamp(1, 32),
+            tick,
+        }
+    }
+}
+
+impl<F: Fn() -> u32> TimestampSource for Timer<F> {
+    fn now(&self) -> u32 {
+        (self.now)()
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn tick_duration(&self) -> NanosDurationU32 {
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+    use fugit::ExtU32;
+
+    #[test]
+    fn differences_wrap_at_counter_width() {
+        let counter = Cell::new(0xfff0);
+        let timer = Timer::new(|| counter.get(), 16, 250.nanos());
+        let start = timer.now();
+        counter.set(0x0010);
+        assert_eq!(timer.elapsed(start), 0x20);
+        assert_eq!(timer.to_nanos(0x20), 8000);
+        let wide = Timer::new(|| 5, 32, 1.nanos());
+        assert_eq!(wide.ticks_between(u32::MAX, 4), 5);
+    }
+}
@@ -0,0 +1,221 @@
+//! Oscillator tolerance of the bit timing
+//!
+//! All nodes on a bus derive their bit timing from their own oscillators. The
+//! bit timing determines how far these may deviate from their nominal
+//! frequency while the nodes still stay synchronized, under the conditions
+//! given by ISO 11898-1 and the CiA recommendations for CAN FD:
+//!
+//! 1. Resynchronization after a stuff error must still sample the correct bit
+//!    within 13 bit times
+//! 2. The synchronization jump width must compensate the phase error
+//!    accumulated over 10 bit times
+//! 3. Likewise for the data phase of CAN FD frames
+//! 4. and 5. The phase error accumulated across the switch between the
+//!    bitrates must be compensated
+//!
+//! [`clock_tolerance`] computes the tolerance allowed by a configuration.
+//! Setting [`CanConfig::clock_tolerance_ppm`] to the tolerance of the
+//! oscillator makes [`CanConfigurable::validate`] reject configurations that
+//! do not allow it.
+//!
+//! ```no_run
+//! use mcan::config::CanConfig;
+//! use mcan::tolerance::clock_tolerance;
+//! # use fugit::RateExtU32;
+//!
+//! let config = CanConfig::new(500.kHz());
+//! let report = clock_tolerance(&config, 48.MHz()).unwrap();
+//! // Crystal with 100 ppm including temperature drift and aging
+//! assert!(report.margin_ppm(100) > 0);
+//! ```
+//!
+//! [`CanConfigurable::validate`]: crate::bus::CanConfigurable::validate
+
+use crate::config::{
+    BitTiming, BitTimingError, CanConfig, Mode, DATA_BIT_TIMING_RANGES, NOMINAL_BIT_TIMING_RANGES,
+};
+use fugit::HertzU32;
+
+/// Condition of the bit timing limiting the oscillator tolerance
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToleranceCondition {
+    /// Resynchronization after a stuff error, limited by the nominal phase
+    /// segments
+    NominalPhaseSegments,
+    /// Phase error within the nominal bit timing, limited by the nominal
+    /// synchronization jump width
+    NominalSjw,
+    /// Phase error within the data bit timing, limited by the data
+    /// synchronization jump width
+    DataSjw,
+    /// Phase error accumulated in the data phase, limited by the nominal phase
+    /// segments after switching back
+    SwitchToNominal,
+    /// Phase error accumulated in the arbitration phase, limited by the data
+    /// synchronization jump width after switching
+    SwitchToData,
+}
+
+/// Oscillator tolerance allowed by a configuration, see [`clock_tolerance`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ToleranceReport {
+    /// Largest deviation of each oscillator from its nominal frequency, in
+    /// parts per million
+    pub allowed_ppm: u32,
+    /// Condition that limits the tolerance
+    pub limiting: ToleranceCondition,
+}
+
+impl ToleranceReport {
+    /// Returns the tolerance left when the oscillator deviates by up to
+    /// `oscillator_ppm`. Negative if the configuration is out of
+    /// specification.
+    pub fn margin_ppm(&self, oscillator_ppm: u32) -> i64 {
+        i64::from(self.allowed_ppm) - i64::from(oscillator_ppm)
+    }
+}
+
+/// Computes the oscillator tolerance allowed by the bit timing of `config`
+/// with the peripheral clocked at `can_clock`. Fails if the bit timing cannot
+/// be applied.
+pub fn clock_tolerance(
+    config: &CanConfig,
+    can_clock: HertzU32,
+) -> Result<ToleranceReport, BitTimingError> {
+    let nominal = Timing::new(&config.nominal_timing);
+    let nominal_prescaler = config
+        .nominal_timing
+        .prescaler(can_clock, &NOMINAL_BIT_TIMING_RANGES)?;
+    let data = match config.mode {
+        Mode::Classic => None,
+        Mode::Fd {
+            data_phase_timing, ..
+        } => Some((
+            Timing::new(&data_phase_timing),
+            data_phase_timing.prescaler(can_clock, &DATA_BIT_TIMING_RANGES)?,
+        )),
+    };
+    Ok(tolerance(nominal, nominal_prescaler.into(), data))
+}
+
+/// Bit timing in time quanta
+#[derive(Copy, Clone)]
+struct Timing {
+    phase_seg_1: u64,
+    phase_seg_2: u64,
+    sjw: u64,
+    bit_time: u64,
+}
+
+impl Timing {
+    fn new(timing: &BitTiming) -> Self {
+        Self {
+            phase_seg_1: timing.phase_seg_1.into(),
+            phase_seg_2: timing.phase_seg_2.into(),
+            sjw: timing.sjw.into(),
+            bit_time: timing.time_quanta_per_bit().into(),
+        }
+    }
+}
+
+/// `numerator / denominator` in parts per million, saturating
+fn ppm(numerator: u64, denominator: u64) -> u32 {
+    numerator
+        .saturating_mul(1_000_000)
+        .checked_div(denominator)
+        .map_or(u32::MAX, |ppm| u32::try_from(ppm).unwrap_or(u32::MAX))
+}
+
+fn tolerance(
+    nominal: Timing,
+    nominal_prescaler: u64,
+    data: Option<(Timing, u16)>,
+) -> ToleranceReport {
+    let n = nominal;
+    let phase_segments = n.phase_seg_1.min(n.phase_seg_2);
+    let mut conditions = [
+        (
+            ppm(
+                phase_segments,
+                2 * (13 * n.bit_time).saturating_sub(n.phase_seg_2),
+            ),
+            ToleranceCondition::NominalPhaseSegments,
+        ),
+        (ppm(n.sjw, 20 * n.bit_time), ToleranceCondition::NominalSjw),
+        (u32::MAX, ToleranceCondition::DataSjw),
+        (u32::MAX, ToleranceCondition::SwitchToNominal),
+        (u32::MAX, ToleranceCondition::SwitchToData),
+    ];
+    if let Some((d, data_prescaler)) = data {
+        let data_prescaler = u64::from(data_prescaler);
+        let [_, _, data_sjw, to_nominal, to_data] = &mut conditions;
+        data_sjw.0 = ppm(d.sjw, 20 * d.bit_time);
+        // Durations in the data phase are scaled to nominal time quanta by
+        // multiplying both sides with the nominal prescaler
+        to_nominal.0 = ppm(
+            phase_segments * nominal_prescaler,
+            2 * ((6 * d.bit_time).saturating_sub(d.phase_seg_2) * data_prescaler
+                + 7 * n.bit_time * nominal_prescaler),
+        );
+        // and vice versa
+        to_data.0 = ppm(
+            (d.sjw * data_prescaler)
+                .saturating_sub(nominal_prescaler.saturating_sub(data_prescaler)),
+            2 * ((2 * n.bit_time).saturating_sub(n.phase_seg_2) * nominal_prescaler
+                + (d.phase_seg_2 + 4 * d.bit_time) * data_prescaler),
+        );
+    }
+    let (allowed_ppm, limiting) = conditions
+        .into_iter()
+        .min_by_key(|(ppm, _)| *ppm)
+        .unwrap_or((0, ToleranceCondition::NominalPhaseSegments));
+    ToleranceReport {
+        allowed_ppm,
+        limiting,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn timing(phase_seg_1: u64, phase_seg_2: u64, sjw: u64) -> Timing {
+        Timing {
+            phase_seg_1,
+            phase_seg_2,
+            sjw,
+            bit_time: 1 + phase_seg_1 + phase_seg_2,
+        }
+    }
+
+    #[test]
+    fn classic_tolerance_is_limited_by_phase_segments() {
+        // 2 / (2 * (13 * 16 - 2))
+        let report = tolerance(timing(13, 2, 2), 1, None);
+        assert_eq!(report.allowed_ppm, 4854);
+        assert_eq!(report.limiting, ToleranceCondition::NominalPhaseSegments);
+        assert_eq!(report.margin_ppm(5000), -146);
+        // 1 / (20 * 16)
+        let report = tolerance(timing(11, 4, 1), 1, None);
+        assert_eq!(report.allowed_ppm, 3125);
+        assert_eq!(report.limiting, ToleranceCondition::NominalSjw);
+    }
+
+    #[test]
+    fn fd_tolerance_accounts_for_bitrate_switch() {
+        // 500 kbit/s and 2 Mbit/s from 40 MHz, both with 80 % sample point
+        let report = tolerance(timing(63, 16, 16), 1, Some((timing(15, 4, 4), 1)));
+        // 16 / (2 * (13 * 80 - 16))
+        assert_eq!(report.allowed_ppm, 7812);
+        assert_eq!(report.limiting, ToleranceCondition::NominalPhaseSegments);
+        let report = tolerance(timing(63, 16, 16), 1, Some((timing(15, 4, 3), 1)));
+        // 3 / (2 * ((2 * 80 - 16) + 4 + 4 * 20))
+        assert_eq!(report.allowed_ppm, 6578);
+        assert_eq!(report.limiting, ToleranceCondition::SwitchToData);
+        // Data prescaler 2 at 1 Mbit/s, data SJW shortened by the ratio
+        let report = tolerance(timing(63, 16, 16), 4, Some((timing(15, 4, 3), 2)));
+        // (3 * 2 - 2) / (2 * ((2 * 80 - 16) * 4 + (4 + 4 * 20) * 2))
+        assert_eq!(report.allowed_ppm, 2688);
+        assert_eq!(report.limiting, ToleranceCondition::SwitchToData);
+    }
+}
@@ -0,0 +1,263 @@
+//! Software transmit queue with pluggable arbitration
+//!
+//! The peripheral sends frames from its transmit queue by ID priority or in
+//! FIFO order, and has few buffers. When more frames are pending than fit
+//! into the hardware, a [`TxScheduler`] holds them in software and decides
+//! with a [`TxArbiter`] which of them goes into the next free buffer:
+//!
+//! - [`IdPriority`] picks the frame that would win arbitration on the bus
+//! - [`EarliestDeadline`] picks the frame whose deadline is closest
+//! - [`RoundRobin`] cycles through the IDs of the pending frames, so that no
+//!   ID is starved by frames with a higher priority
+//!
+//! Applications can implement [`TxArbiter`] for their own policies.
+//!
+//! ```no_run
+//! use mcan::message::tx;
+//! use mcan::tx_arbiter::{EarliestDeadline, TxScheduler};
+//! use mcan::tx_buffers::DynTx;
+//!
+//! fn on_tick(
+//!     scheduler: &mut TxScheduler<tx::Message<8>, EarliestDeadline, 16>,
+//!     tx: &mut impl DynTx<Message = tx::Message<8>>,
+//!     now_ms: u32,
+//! ) {
+//!     // Called periodically and on transmission completed interrupts
+//!     let _ = scheduler.feed(tx, now_ms);
+//! }
+//! ```
+
+use crate::message::Raw;
+use crate::tx_buffers::{DynTx, Error};
+use embedded_can::Id;
+
+/// Frame waiting in a [`TxScheduler`]
+#[derive(Copy, Clone, Debug)]
+pub struct Pending<M> {
+    /// Frame to transmit
+    pub message: M,
+    /// Time by which the frame should be transmitted, in the unit of time of
+    /// the application. May wrap around.
+    pub deadline: u32,
+}
+
+/// Decides which pending frame is transmitted next
+pub trait TxArbiter<M> {
+    /// Returns `true` if `candidate` should be transmitted before `current`
+    /// at time `now`
+    fn precedes(&self, candidate: &Pending<M>, current: &Pending<M>, now: u32) -> bool;
+
+    /// Called with each frame put into a hardware buffer
+    fn on_selected(&mut self, _frame: &Pending<M>) {}
+}
+
+/// Transmits frames in the order of their priority on the bus
+#[derive(Copy, Clone, Debug, Default)]
+pub struct IdPriority;
+
+impl<M: Raw> TxArbiter<M> for IdPriority {
+    fn precedes(&self, candidate: &Pending<M>, current: &Pending<M>, _now: u32) -> bool {
+        candidate.message.id() < current.message.id()
+    }
+}
+
+/// Transmits the frame with the earliest deadline first. Deadlines are
+/// compared relative to the current time, so they may wrap around as long as
+/// all pending ones are within half the range of `u32`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EarliestDeadline;
+
+impl<M> TxArbiter<M> for EarliestDeadline {
+    fn precedes(&self, candidate: &Pending<M>, current: &Pending<M>, now: u32) -> bool {
+        let remaining = |frame: &Pending<M>| frame.deadline.wrapping_sub(now) as i32;
+        remaining(candidate) < remaining(current)
+    }
+}
+
+/// Transmits the frame with the next ID in priority order after the one last
+/// transmitted, wrapping around to the highest priority
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RoundRobin {
+    last: Option<Id>,
+}
+
+impl RoundRobin {
+    /// Starts with the frame of highest priority
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Orders frames after the last transmitted ID before the others
+    fn key(&self, id: Id) -> (bool, Id) {
+        (self.last.is_some_and(|last| id <= last), id)
+    }
+}
+
+impl<M: Raw> TxArbiter<M> for RoundRobin {
+    fn precedes(&self, candidate: &Pending<M>, current: &Pending<M>, _now: u32) -> bool {
+        self.key(candidate.message.id()) < self.key(current.message.id())
+    }
+
+    fn on_selected(&mut self, frame: &Pending<M>) {
+        self.last = Some(frame.message.id());
+    }
+}
+
+/// Holds up to `N` frames in software, feeding them to the transmit queue in
+/// the order chosen by a [`TxArbiter`]
+///
+/// Frames the arbiter considers equal are transmitted in the order they were
+/// pushed.
+pub struct TxScheduler<M, A, const N: usize> {
+    pending: [Option<Pending<M>>; N],
+    len: usize,
+    arbiter: A,
+}
+
+impl<M: Clone, A: TxArbiter<M>, const N: usize> TxScheduler<M, A, N> {
+    /// Creates an empty scheduler using `arbiter`
+    pub fn new(arbiter: A) -> Self {
+        Self {
+            pending: core::array::from_fn(|_| None),
+            len: 0,
+            arbiter,
+        }
+    }
+
+    /// Adds `message` to transmit by `deadline`. Hands the message back if
+    /// the scheduler is full.
+    pub fn push(&mut self, message: M, deadline: u32) -> Result<(), M> {
+        match self.pending.get_mut(self.len) {
+            Some(slot) => {
+                *slot = Some(Pending { message, deadline });
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(message),
+        }
+    }
+
+    /// Returns the number of pending frames
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no frames are pending
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the frame the arbiter selects at time `now`
+    pub fn peek(&self, now: u32) -> Option<&Pending<M>> {
+        self.select(now).and_then(|index| self.get(index))
+    }
+
+    /// Removes the frame the arbiter selects at time `now`
+    pub fn pop(&mut self, now: u32) -> Option<Pending<M>> {
+        let index = self.select(now)?;
+        let frame = self.remove(index)?;
+        self.arbiter.on_selected(&frame);
+        Some(frame)
+    }
+
+    /// Puts pending frames into the transmit queue of `tx` until it is full or
+    /// no frames are left. Returns the number of frames queued.
+    pub fn feed<T: DynTx<Message = M>>(&mut self, tx: &mut T, now: u32) -> Result<usize, Error> {
+        let mut queued = 0;
+        while let Some(index) = self.select(now) {
+            let Some(frame) = self.get(index) else {
+                break;
+            };
+            match tx.transmit_queued(frame.message.clone()) {
+                Ok(()) => {
+                    if let Some(frame) = self.remove(index) {
+                        self.arbiter.on_selected(&frame);
+                    }
+                    queued += 1;
+                }
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Returns the arbiter
+    pub fn arbiter(&mut self) -> &mut A {
+        &mut self.arbiter
+    }
+
+    fn get(&self, index: usize) -> Option<&Pending<M>> {
+        self.pending.get(index)?.as_ref()
+    }
+
+    fn select(&self, now: u32) -> Option<usize> {
+        let mut best: Option<(usize, &Pending<M>)> = None;
+        for (index, frame) in self.pending.iter().take(self.len).enumerate() {
+            let Some(frame) = frame else {
+                continue;
+            };
+            if best.is_none_or(|(_, current)| self.arbiter.precedes(frame, current, now)) {
+                best = Some((index, frame));
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+
+    /// Removes the frame at `index`, keeping the others in order
+    fn remove(&mut self, index: usize) -> Option<Pending<M>> {
+        let pending = self.pending.get_mut(index..self.len)?;
+        let frame = pending.first_mut()?.take();
+        pending.rotate_left(1);
+        self.len -= 1;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::tx;
+    use embedded_can::StandardId;
+
+    fn message(id: u16) -> tx::Message<8> {
+        tx::MessageBuilder {
+            id: Id::Standard(StandardId::new(id).unwrap()),
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(&[])),
+            store_tx_event: None,
+        }
+        .build()
+        .unwrap()
+    }
+
+    fn order<A: TxArbiter<tx::Message<8>>>(arbiter: A, frames: &[(u16, u32)]) -> [u16; 4] {
+        let mut scheduler = TxScheduler::<_, _, 4>::new(arbiter);
+        for &(id, deadline) in frames {
+            scheduler.push(message(id), deadline).unwrap();
+        }
+        assert!(scheduler.push(message(0), 0).is_err());
+        let mut ids = [0; 4];
+        for id in &mut ids {
+            *id = match scheduler.pop(u32::MAX - 5).unwrap().message.id() {
+                Id::Standard(id) => id.as_raw(),
+                Id::Extended(_) => unreachable!(),
+            };
+        }
+        assert!(scheduler.is_empty());
+        ids
+    }
+
+    #[test]
+    fn arbiters_order_pending_frames() {
+        let frames = [(0x300, 2), (0x100, 40), (0x200, u32::MAX), (0x100, 1)];
+        assert_eq!(order(IdPriority, &frames), [0x100, 0x100, 0x200, 0x300]);
+        assert_eq!(
+            order(EarliestDeadline, &frames),
+            [0x200, 0x100, 0x300, 0x100]
+        );
+        assert_eq!(
+            order(RoundRobin::new(), &frames),
+            [0x100, 0x200, 0x300, 0x100]
+        );
+    }
+}
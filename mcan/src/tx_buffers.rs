@@ -6,12 +6,16 @@
 //! automatically selects where new messages are placed. The ordering of the
 //! queue is configurable; see [`crate::config::TxQueueMode`].
 
-use crate::config::Mode;
+use crate::config::{AnyFormat, FrameFormat, Mode};
+use crate::message::{Dlc, TooMuchData};
 use crate::messageram::Capacities;
 use crate::reg;
+use core::cell::Cell;
 use core::convert::Infallible;
 use core::marker::PhantomData;
+use core::sync::atomic::{fence, Ordering};
 use generic_array::{typenum::Unsigned, GenericArray};
+use mcan_core::MessageRamDma;
 use reg::AccessRegisterBlock as _;
 use vcell::VolatileCell;
 
@@ -30,13 +34,47 @@ pub enum Error {
     /// In order to be able to send CAN FD messages change its mode of operation
     /// to [`Mode::Fd { bit_rate_switching: true }`].
     BitRateSwitchingDisabled,
+    /// The payload length differs from the data length of the stored message
+    LengthMismatch,
+    /// The payload does not fit into the message
+    TooMuchData,
+    /// The dedicated transmit buffer was never written, so it holds no
+    /// message to transmit
+    NotWritten,
+}
+
+impl From<TooMuchData> for Error {
+    fn from(_: TooMuchData) -> Self {
+        Self::TooMuchData
+    }
+}
+
+/// Errors of transmissions where the message is moved by DMA
+#[derive(Debug)]
+pub enum DmaError<E> {
+    /// The message cannot be transmitted
+    Tx(Error),
+    /// The DMA transfer could not be started
+    Dma(E),
+}
+
+impl<E> From<Error> for DmaError<E> {
+    fn from(value: Error) -> Self {
+        Self::Tx(value)
+    }
 }
 
 /// Transmit queue and dedicated buffers
-pub struct Tx<'a, P, C: Capacities> {
+///
+/// The [`FrameFormat`] `F` selects the messages accepted, see
+/// [`DynTx::Message`].
+pub struct Tx<'a, P, C: Capacities, F = AnyFormat> {
     memory: &'a mut GenericArray<VolatileCell<C::TxMessage>, C::TxBuffers>,
     pub(crate) mode: Mode,
-    _markers: PhantomData<P>,
+    queue_high_water_mark: Cell<usize>,
+    /// Bit `n` is set once buffer `n` was written
+    written: u32,
+    _markers: PhantomData<(P, F)>,
 }
 
 /// Trait which erases generic parametrization for [`Tx`] type
@@ -57,6 +95,16 @@ pub trait DynTx {
     /// Fails with [`nb::Error::WouldBlock`] if the transmit buffer is full.
     fn transmit_queued(&mut self, message: Self::Message) -> nb::Result<(), Error>;
 
+    /// Returns the highest number of pending messages in the transmit queue
+    /// observed since construction or the last call to
+    /// [`Self::reset_queue_high_water_mark`].
+    ///
+    /// The fill level is sampled on calls to [`Self::transmit_queued`].
+    fn queue_high_water_mark(&self) -> usize;
+
+    /// Resets the queue high water mark to the current fill level
+    fn reset_queue_high_water_mark(&mut self);
+
     /// Allow [`Interrupt::TransmissionCancellationFinished`] to be triggered by
     /// `to_be_enabled`. Interrupts for other buffers remain unchanged.
     ///
@@ -120,7 +168,7 @@ pub trait DynTx {
     fn cancel(&mut self, index: usize) -> nb::Result<(), Infallible>;
 }
 
-impl<'a, P: mcan_core::CanId, C: Capacities> Tx<'a, P, C> {
+impl<'a, P: mcan_core::CanId, C: Capacities, F: FrameFormat> Tx<'a, P, C, F> {
     /// # Safety
     /// The caller must be the owner or the peripheral referenced by `P`. The
     /// constructed type assumes ownership of some of the registers from the
@@ -141,6 +189,19 @@ impl<'a, P: mcan_core::CanId, C: Capacities> Tx<'a, P, C> {
         Self {
             memory,
             mode,
+            queue_high_water_mark: Cell::new(0),
+            written: 0,
+            _markers: PhantomData,
+        }
+    }
+
+    /// Changes the frame format of the accepted messages
+    pub(crate) fn into_format<G: FrameFormat>(self) -> Tx<'a, P, C, G> {
+        Tx {
+            memory: self.memory,
+            mode: self.mode,
+            queue_high_water_mark: self.queue_high_water_mark,
+            written: self.written,
             _markers: PhantomData,
         }
     }
@@ -195,6 +256,8 @@ impl<'a, P: mcan_core::CanId, C: Capacities> Tx<'a, P, C> {
         // "TXBAR bits are set only for those Tx Buffers configured via TXBC". Our
         // interpretation is that add requests for buffers not configured in TXBC are
         // ignored.
+        // The message must be in message RAM before the request is observed.
+        fence(Ordering::Release);
         unsafe { self.txbar().write(|w| w.bits(1 << index)) }
     }
 
@@ -217,12 +280,232 @@ impl<'a, P: mcan_core::CanId, C: Capacities> Tx<'a, P, C> {
             .get_mut(index)
             .ok_or(Error::OutOfBounds)?
             .set(message);
+        self.written |= 1 << index;
+        self.add_request(index);
+        Ok(())
+    }
+
+    /// Dedicated transmit buffer `index`
+    fn dedicated(&self, index: usize) -> Result<&VolatileCell<C::TxMessage>, Error> {
+        if index >= C::DedicatedTxBuffers::USIZE {
+            return Err(Error::OutOfBounds);
+        }
+        self.memory.get(index).ok_or(Error::OutOfBounds)
+    }
+
+    /// Returns the message currently stored in dedicated transmit buffer
+    /// `index`, whether or not its transmission is pending.
+    ///
+    /// Buffers that were never written hold a zeroed message.
+    pub fn read_back(&self, index: usize) -> Result<C::TxMessage, Error> {
+        self.dedicated(index).map(VolatileCell::get)
+    }
+
+    /// Requests transmission of the message stored in dedicated transmit
+    /// buffer `index` again, e.g. after it was canceled or inspected with
+    /// [`Self::read_back`]. Fails with [`nb::Error::WouldBlock`] if its
+    /// transmission is still pending, and with [`Error::NotWritten`] if no
+    /// message was ever stored in the buffer.
+    pub fn retransmit(&mut self, index: usize) -> nb::Result<(), Error> {
+        self.dedicated(index)?;
+        if self.written & (1 << index) == 0 {
+            return Err(Error::NotWritten.into());
+        }
+        if self.is_buffer_in_use(index) {
+            return Err(nb::Error::WouldBlock);
+        }
         self.add_request(index);
         Ok(())
     }
 
+    /// Overwrites the payload of the message stored in dedicated transmit
+    /// buffer `index`, leaving its header untouched. Meant for cyclic
+    /// messages, whose payload is updated before each [`Self::retransmit`].
+    ///
+    /// `data` must have the data length of the stored message. Fails with
+    /// [`nb::Error::WouldBlock`] if its transmission is pending.
+    pub fn update_payload(&mut self, index: usize, data: &[u8]) -> nb::Result<(), Error> {
+        let element = self.dedicated(index)?.as_ptr() as *mut u32;
+        if self.is_buffer_in_use(index) {
+            return Err(nb::Error::WouldBlock);
+        }
+        // Safety: The element holds at least the two header words, and the buffer is
+        // not pending, so the peripheral does not access it.
+        let [t0, t1] = unsafe { [element.read_volatile(), element.add(1).read_volatile()] };
+        let capacity = core::mem::size_of::<C::TxMessage>().saturating_sub(8);
+        let len = if t0 & (1 << 29) != 0 {
+            // Remote frames have no payload
+            0
+        } else {
+            Dlc::from_bits((t1 >> 16) as u8)
+                .len(t1 & (1 << 21) != 0)
+                .min(capacity)
+        };
+        if data.len() != len {
+            return Err(Error::LengthMismatch.into());
+        }
+        for (i, chunk) in data.chunks(4).enumerate() {
+            // Safety: The word is within the data field of the element, as the
+            // payload length is bounded by its capacity. Message RAM is accessed
+            // with whole words.
+            unsafe {
+                let word = element.add(2 + i);
+                let mut bytes = word.read_volatile().to_le_bytes();
+                for (byte, value) in bytes.iter_mut().zip(chunk) {
+                    *byte = *value;
+                }
+                word.write_volatile(u32::from_le_bytes(bytes));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts `messages` into free dedicated transmit buffers and requests their
+    /// transmission with a single write, so that they enter arbitration
+    /// together: none of them is sent before all are in place. Among each
+    /// other, they are sent in the order of their ID priority, and of their
+    /// position in `messages` for equal IDs. Returns the buffers used.
+    ///
+    /// Fails with [`nb::Error::WouldBlock`] if fewer dedicated buffers are
+    /// free than `messages` holds, and with [`Error::OutOfBounds`] if there
+    /// are fewer dedicated buffers altogether. Nothing is written on failure.
+    pub fn transmit_group(
+        &mut self,
+        messages: &[F::Message<C::TxMessage>],
+    ) -> nb::Result<TxBufferSet, Error>
+    where
+        F::Message<C::TxMessage>: Copy,
+    {
+        if messages.len() > C::DedicatedTxBuffers::USIZE {
+            return Err(Error::OutOfBounds.into());
+        }
+        let in_use = self.txbar().read().bits() | self.txbrp().read().bits();
+        let free = (0..C::DedicatedTxBuffers::USIZE).filter(|&index| in_use & (1 << index) == 0);
+        if free.clone().count() < messages.len() {
+            return Err(nb::Error::WouldBlock);
+        }
+        for message in messages {
+            self.validate_message(&F::into_message(*message))?;
+        }
+        let mut requests = 0;
+        for (index, message) in free.zip(messages) {
+            self.memory
+                .get_mut(index)
+                .ok_or(Error::OutOfBounds)?
+                .set(F::into_message(*message));
+            requests |= 1 << index;
+        }
+        if requests != 0 {
+            // The messages must be in message RAM before the requests are
+            // observed.
+            fence(Ordering::Release);
+            // Safety: There are no reserved bit patterns, and only dedicated
+            // buffers are requested.
+            unsafe { self.txbar().write(|w| w.bits(requests)) }
+        }
+        self.written |= requests;
+        Ok(TxBufferSet(requests))
+    }
+
+    /// Number of buffers making up the transmit queue
+    fn queue_size() -> usize {
+        C::TxBuffers::USIZE - C::DedicatedTxBuffers::USIZE
+    }
+
+    /// Records the fill level of the queue.
+    ///
+    /// TXFQS.TFFL is only maintained in FIFO mode, so the level is taken from
+    /// the pending requests of the queue buffers, which works in both modes.
+    fn record_queue_level(&self) {
+        let queue = u32::MAX
+            .checked_shl(C::DedicatedTxBuffers::U32)
+            .unwrap_or(0);
+        let len = (self.txbrp().read().bits() & queue).count_ones() as usize;
+        if len > self.queue_high_water_mark.get() {
+            self.queue_high_water_mark.set(len);
+        }
+    }
+
+    /// Starts moving `message` into the specified transmit buffer using `dma`.
+    fn transmit_dma<'t, D: MessageRamDma>(
+        &'t mut self,
+        index: usize,
+        dma: &'t mut D,
+        message: &'t C::TxMessage,
+    ) -> nb::Result<TxDmaTransfer<'a, 't, P, C, F, D>, DmaError<D::Error>> {
+        if self.is_buffer_in_use(index) {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.validate_message(message).map_err(DmaError::from)?;
+        let destination = self
+            .memory
+            .get(index)
+            .ok_or(DmaError::Tx(Error::OutOfBounds))?
+            .as_ptr();
+        // Safety: Both the message and the buffer are borrowed for the lifetime of
+        // the transfer, which blocks until completion when dropped.
+        unsafe {
+            dma.start_transfer(
+                message as *const C::TxMessage as *const u32,
+                destination as *mut u32,
+                core::mem::size_of::<C::TxMessage>() / 4,
+            )
+        }
+        .map_err(DmaError::Dma)?;
+        Ok(TxDmaTransfer {
+            tx: self,
+            dma,
+            index,
+            _message: PhantomData,
+        })
+    }
+
+    /// Puts a frame in the specified dedicated transmit buffer using `dma`.
+    /// Transmission is requested once the transfer has completed, which is
+    /// awaited by [`TxDmaTransfer::wait`] or when the transfer is dropped.
+    /// Fails with [`nb::Error::WouldBlock`] if the transmit buffer is full.
+    ///
+    /// # Safety
+    /// The returned transfer must not be leaked, e.g. by [`core::mem::forget`].
+    /// Otherwise `message` could be modified while the DMA engine is still
+    /// reading it.
+    pub unsafe fn transmit_dedicated_dma<'t, D: MessageRamDma>(
+        &'t mut self,
+        index: usize,
+        dma: &'t mut D,
+        message: &'t C::TxMessage,
+    ) -> nb::Result<TxDmaTransfer<'a, 't, P, C, F, D>, DmaError<D::Error>> {
+        if index >= C::DedicatedTxBuffers::USIZE {
+            Err(DmaError::Tx(Error::OutOfBounds))?;
+        }
+        self.transmit_dma(index, dma, message)
+    }
+
+    /// Puts a frame in the queue using `dma`. Transmission is requested once
+    /// the transfer has completed, which is awaited by [`TxDmaTransfer::wait`]
+    /// or when the transfer is dropped. Fails with [`nb::Error::WouldBlock`]
+    /// if the queue is full.
+    ///
+    /// # Safety
+    /// The returned transfer must not be leaked, e.g. by [`core::mem::forget`].
+    /// Otherwise `message` could be modified while the DMA engine is still
+    /// reading it.
+    pub unsafe fn transmit_queued_dma<'t, D: MessageRamDma>(
+        &'t mut self,
+        dma: &'t mut D,
+        message: &'t C::TxMessage,
+    ) -> nb::Result<TxDmaTransfer<'a, 't, P, C, F, D>, DmaError<D::Error>> {
+        let index = self.find_put_index().ok_or(nb::Error::WouldBlock)?;
+        self.transmit_dma(index, dma, message)
+    }
+
     /// Returns the put index if available. `None` if the queue is full.
     fn find_put_index(&self) -> Option<usize> {
+        // Without a queue, the put index would refer to a dedicated buffer.
+        if Self::queue_size() == 0 {
+            return None;
+        }
+        self.record_queue_level();
         let status = self.txfqs().read();
         if status.tfqf().bit() {
             None
@@ -260,9 +543,9 @@ impl<'a, P: mcan_core::CanId, C: Capacities> Tx<'a, P, C> {
     }
 }
 
-impl<'a, P: mcan_core::CanId, C: Capacities> DynTx for Tx<'a, P, C> {
+impl<'a, P: mcan_core::CanId, C: Capacities, F: FrameFormat> DynTx for Tx<'a, P, C, F> {
     type Id = P;
-    type Message = C::TxMessage;
+    type Message = F::Message<C::TxMessage>;
 
     fn transmit_dedicated(
         &mut self,
@@ -272,12 +555,23 @@ impl<'a, P: mcan_core::CanId, C: Capacities> DynTx for Tx<'a, P, C> {
         if index > C::DedicatedTxBuffers::USIZE {
             Err(Error::OutOfBounds)?;
         }
-        self.transmit(index, message)
+        self.transmit(index, F::into_message(message))
     }
 
     fn transmit_queued(&mut self, message: Self::Message) -> nb::Result<(), Error> {
         let index = self.find_put_index().ok_or(nb::Error::WouldBlock)?;
-        self.transmit(index, message)
+        self.transmit(index, F::into_message(message))?;
+        self.record_queue_level();
+        Ok(())
+    }
+
+    fn queue_high_water_mark(&self) -> usize {
+        self.queue_high_water_mark.get()
+    }
+
+    fn reset_queue_high_water_mark(&mut self) {
+        self.queue_high_water_mark.set(0);
+        self.record_queue_level();
     }
 
     fn enable_cancellation_interrupt(&mut self, to_be_enabled: TxBufferSet) {
@@ -343,6 +637,57 @@ impl<'a, P: mcan_core::CanId, C: Capacities> DynTx for Tx<'a, P, C> {
     }
 }
 
+/// Ongoing DMA transfer of a message into a transmit buffer
+///
+/// Created by [`Tx::transmit_dedicated_dma`] and [`Tx::transmit_queued_dma`].
+/// Dropping the transfer blocks until the DMA engine has completed it, using
+/// [`MessageRamDma::wait`], which busy-waits unless the DMA implementation
+/// overrides it. Check [`Self::is_complete`] before dropping to avoid
+/// blocking.
+pub struct TxDmaTransfer<
+    'a,
+    't,
+    P: mcan_core::CanId,
+    C: Capacities,
+    F: FrameFormat,
+    D: MessageRamDma,
+> {
+    tx: &'t mut Tx<'a, P, C, F>,
+    dma: &'t mut D,
+    index: usize,
+    _message: PhantomData<&'t C::TxMessage>,
+}
+
+impl<'a, 't, P: mcan_core::CanId, C: Capacities, F: FrameFormat, D: MessageRamDma>
+    TxDmaTransfer<'a, 't, P, C, F, D>
+{
+    /// Index of the transmit buffer the message is moved to
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns `true` once the message has been copied to the transmit buffer
+    pub fn is_complete(&mut self) -> bool {
+        self.dma.is_complete()
+    }
+
+    /// Blocks until the transfer has completed and requests transmission.
+    pub fn wait(self) {
+        // Completion is awaited and transmission requested on drop
+    }
+}
+
+impl<'a, 't, P: mcan_core::CanId, C: Capacities, F: FrameFormat, D: MessageRamDma> Drop
+    for TxDmaTransfer<'a, 't, P, C, F, D>
+{
+    fn drop(&mut self) {
+        self.dma.wait();
+        self.tx.written |= 1 << self.index;
+        self.tx.add_request(self.index);
+        self.tx.record_queue_level();
+    }
+}
+
 /// A set of transmit buffers, which may be dedicated buffers or part of the
 /// queue.
 #[derive(Copy, Clone)]
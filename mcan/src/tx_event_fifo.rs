@@ -5,13 +5,16 @@
 //! [`store_tx_event`]: crate::message::tx::MessageBuilder::store_tx_event
 use crate::message::TxEvent;
 use crate::reg;
+use core::cell::Cell;
 use core::marker::PhantomData;
+use core::sync::atomic::{fence, Ordering};
 use reg::AccessRegisterBlock as _;
 use vcell::VolatileCell;
 
 /// Transmit event queue on peripheral `P`
 pub struct TxEventFifo<'a, P> {
     memory: &'a mut [VolatileCell<TxEvent>],
+    high_water_mark: Cell<usize>,
     _markers: PhantomData<P>,
 }
 
@@ -26,8 +29,30 @@ pub trait DynTxEventFifo {
     fn is_empty(&self) -> bool;
     /// Returns the number of elements the queue can hold
     fn capacity(&self) -> usize;
+    /// Returns the highest fill level observed since construction or the last
+    /// call to [`Self::reset_high_water_mark`].
+    ///
+    /// The fill level is sampled whenever the status of the queue is read,
+    /// i.e. on calls to [`Self::len`] and [`Self::pop`].
+    fn high_water_mark(&self) -> usize;
+    /// Resets the high water mark to the current fill level
+    fn reset_high_water_mark(&mut self);
     /// Takes the first event from the queue
     fn pop(&mut self) -> Option<TxEvent>;
+    /// Takes at most `max` events from the queue into `f`, bounding the work
+    /// of an interrupt handler draining it. Returns the number of events
+    /// taken.
+    fn pop_bounded(&mut self, max: usize, mut f: impl FnMut(TxEvent)) -> usize {
+        let mut popped = 0;
+        while popped < max {
+            let Some(event) = self.pop() else {
+                break;
+            };
+            f(event);
+            popped += 1;
+        }
+        popped
+    }
 }
 
 impl<'a, P: mcan_core::CanId> TxEventFifo<'a, P> {
@@ -41,6 +66,7 @@ impl<'a, P: mcan_core::CanId> TxEventFifo<'a, P> {
     pub(crate) unsafe fn new(memory: &'a mut [VolatileCell<TxEvent>]) -> Self {
         Self {
             memory,
+            high_water_mark: Cell::new(0),
             _markers: PhantomData,
         }
     }
@@ -59,13 +85,25 @@ impl<'a, P: mcan_core::CanId> TxEventFifo<'a, P> {
         // Safety: `Self` owns the register.
         unsafe { &self.regs().txefa }
     }
+
+    /// Reads the status register and keeps track of the fill level.
+    fn status(&self) -> reg::txefs::R {
+        let status = self.txefs().read();
+        // Events must not be read before the status announcing them.
+        fence(Ordering::Acquire);
+        let len = status.effl().bits() as usize;
+        if len > self.high_water_mark.get() {
+            self.high_water_mark.set(len);
+        }
+        status
+    }
 }
 
 impl<'a, P: mcan_core::CanId> DynTxEventFifo for TxEventFifo<'a, P> {
     type Id = P;
 
     fn len(&self) -> usize {
-        self.txefs().read().effl().bits() as usize
+        self.status().effl().bits() as usize
     }
 
     fn is_empty(&self) -> bool {
@@ -76,13 +114,28 @@ impl<'a, P: mcan_core::CanId> DynTxEventFifo for TxEventFifo<'a, P> {
         self.memory.len()
     }
 
+    fn high_water_mark(&self) -> usize {
+        self.high_water_mark.get()
+    }
+
+    fn reset_high_water_mark(&mut self) {
+        self.high_water_mark.set(0);
+        self.status();
+    }
+
     fn pop(&mut self) -> Option<TxEvent> {
-        let status = self.txefs().read();
+        // A FIFO without capacity is disabled and never stores events.
+        if self.memory.is_empty() {
+            return None;
+        }
+        let status = self.status();
         if status.effl().bits() == 0 {
             None
         } else {
             let get_index = status.efgi().bits();
             let event = self.memory.get(get_index as usize)?.get();
+            // Acknowledge the event once it has been copied out.
+            fence(Ordering::Release);
             // Safety: The get index must be valid since it was retrieved from the
             // peripheral and the configuration has not changed.
             unsafe {
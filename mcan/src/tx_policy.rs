@@ -0,0 +1,328 @@
+//! Transmit policies depending on the error state
+//!
+//! A node that is error passive has been involved in many errors, and every
+//! further transmission risks making bus conditions worse. A [`TxPolicy`]
+//! decides which transmissions are admitted, based on the [`StatusEvent`]s of
+//! the node and the [`TxClass`] of each transmission. [`PolicedTx`] applies a
+//! policy to the transmit queue.
+//!
+//! [`ErrorPassiveThrottle`] throttles or suspends non-critical transmissions
+//! while the node is error passive or bus off and resumes them once it has
+//! left the error passive state.
+//!
+//! [`EsiPolicy`] decides per class whether CAN FD frames may be sent with the
+//! error state indicator (ESI) set, which the peripheral does for all frames
+//! while the node is error passive. Receivers may discard such frames, so
+//! applications can suppress them or count them for diagnosis.
+//!
+//! ```no_run
+//! use mcan::message::tx;
+//! use mcan::status_event::StatusEvent;
+//! use mcan::tx_buffers::DynTx;
+//! use mcan::tx_policy::{ErrorPassiveThrottle, PassiveBehavior, PolicedTx, TxClass};
+//!
+//! fn run(tx: impl DynTx<Message = tx::Message<8>>, events: impl Iterator<Item = StatusEvent>) {
+//!     // Send at most one non-critical message per 100 ms while error passive
+//!     let policy = ErrorPassiveThrottle::new(PassiveBehavior::Throttle { interval: 100 });
+//!     let mut tx = PolicedTx::new(tx, policy);
+//!     for event in events {
+//!         tx.on_status_event(event);
+//!     }
+//!     # let (message, now_ms): (tx::Message<8>, u32) = unimplemented!();
+//!     let _ = tx.transmit_queued(TxClass::NonCritical, message, now_ms);
+//! }
+//! ```
+
+use crate::status_event::StatusEvent;
+use crate::tx_buffers::{DynTx, Error};
+
+/// Importance of a transmission
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TxClass {
+    /// Always transmitted, e.g. safety-relevant messages
+    Critical,
+    /// Subject to the transmit policy
+    NonCritical,
+}
+
+/// Decides which transmissions are admitted
+pub trait TxPolicy {
+    /// Updates the policy with a status event of the node
+    fn on_status_event(&mut self, event: StatusEvent);
+
+    /// Returns `true` if a transmission of `class` is admitted at time `now`.
+    /// The unit of time is up to the application and may wrap around.
+    fn admit(&mut self, class: TxClass, now: u32) -> bool;
+}
+
+/// Treatment of non-critical transmissions while error passive or bus off
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PassiveBehavior {
+    /// Transmit as usual
+    Transmit,
+    /// Transmit at most once per `interval`
+    Throttle {
+        /// Minimum time between non-critical transmissions
+        interval: u32,
+    },
+    /// Do not transmit
+    Suspend,
+}
+
+/// Policy restricting non-critical transmissions while the node is error
+/// passive or bus off
+#[derive(Clone, Debug)]
+pub struct ErrorPassiveThrottle {
+    behavior: PassiveBehavior,
+    restricted: bool,
+    last_admitted: Option<u32>,
+}
+
+impl ErrorPassiveThrottle {
+    /// Creates a policy applying `behavior` to non-critical transmissions
+    /// while error passive or bus off
+    pub fn new(behavior: PassiveBehavior) -> Self {
+        Self {
+            behavior,
+            restricted: false,
+            last_admitted: None,
+        }
+    }
+
+    /// Returns `true` if non-critical transmissions are currently restricted
+    pub fn is_restricted(&self) -> bool {
+        self.restricted
+    }
+}
+
+impl TxPolicy for ErrorPassiveThrottle {
+    fn on_status_event(&mut self, event: StatusEvent) {
+        match event {
+            StatusEvent::ErrorPassive | StatusEvent::BusOff => self.restricted = true,
+            StatusEvent::LeftErrorPassive | StatusEvent::Recovered => {
+                self.restricted = false;
+                self.last_admitted = None;
+            }
+            _ => (),
+        }
+    }
+
+    fn admit(&mut self, class: TxClass, now: u32) -> bool {
+        if class == TxClass::Critical || !self.restricted {
+            return true;
+        }
+        match self.behavior {
+            PassiveBehavior::Transmit => true,
+            PassiveBehavior::Suspend => false,
+            PassiveBehavior::Throttle { interval } => {
+                let admitted = self
+                    .last_admitted
+                    .is_none_or(|last| now.wrapping_sub(last) >= interval);
+                if admitted {
+                    self.last_admitted = Some(now);
+                }
+                admitted
+            }
+        }
+    }
+}
+
+/// Treatment of transmissions that would carry the error state indicator
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EsiBehavior {
+    /// Transmit as usual
+    Transmit,
+    /// Transmit and count the transmission in [`EsiPolicy::flagged`]
+    Flag,
+    /// Do not transmit
+    Suppress,
+}
+
+/// Policy for transmissions while the node is error passive, where CAN FD
+/// frames are sent with the error state indicator (ESI) set
+///
+/// The state is followed through status events. It can be corrected through
+/// [`Self::set_error_passive`], e.g. from the `ep` bit of [`ProtocolStatus`].
+///
+/// [`ProtocolStatus`]: crate::bus::ProtocolStatus
+#[derive(Clone, Debug)]
+pub struct EsiPolicy {
+    critical: EsiBehavior,
+    non_critical: EsiBehavior,
+    error_passive: bool,
+    flagged: u32,
+}
+
+impl EsiPolicy {
+    /// Creates a policy applying `critical` and `non_critical` to the
+    /// respective classes of transmissions while error passive
+    pub fn new(critical: EsiBehavior, non_critical: EsiBehavior) -> Self {
+        Self {
+            critical,
+            non_critical,
+            error_passive: false,
+            flagged: 0,
+        }
+    }
+
+    /// Sets whether the node is error passive
+    pub fn set_error_passive(&mut self, error_passive: bool) {
+        self.error_passive = error_passive;
+    }
+
+    /// Returns `true` if transmissions are expected to carry the error state
+    /// indicator
+    pub fn is_error_passive(&self) -> bool {
+        self.error_passive
+    }
+
+    /// Returns the number of transmissions admitted with
+    /// [`EsiBehavior::Flag`] while error passive
+    pub fn flagged(&self) -> u32 {
+        self.flagged
+    }
+
+    /// Returns [`Self::flagged`] and resets it to zero
+    pub fn take_flagged(&mut self) -> u32 {
+        core::mem::take(&mut self.flagged)
+    }
+}
+
+impl TxPolicy for EsiPolicy {
+    fn on_status_event(&mut self, event: StatusEvent) {
+        match event {
+            StatusEvent::ErrorPassive | StatusEvent::BusOff => self.error_passive = true,
+            StatusEvent::LeftErrorPassive | StatusEvent::Recovered => self.error_passive = false,
+            _ => (),
+        }
+    }
+
+    fn admit(&mut self, class: TxClass, _now: u32) -> bool {
+        if !self.error_passive {
+            return true;
+        }
+        let behavior = match class {
+            TxClass::Critical => self.critical,
+            TxClass::NonCritical => self.non_critical,
+        };
+        match behavior {
+            EsiBehavior::Transmit => true,
+            EsiBehavior::Flag => {
+                self.flagged = self.flagged.saturating_add(1);
+                true
+            }
+            EsiBehavior::Suppress => false,
+        }
+    }
+}
+
+/// Transmit queue with a [`TxPolicy`] applied
+pub struct PolicedTx<T, P> {
+    tx: T,
+    policy: P,
+}
+
+impl<T: DynTx, P: TxPolicy> PolicedTx<T, P> {
+    /// Applies `policy` to transmissions through `tx`
+    pub fn new(tx: T, policy: P) -> Self {
+        Self { tx, policy }
+    }
+
+    /// Forwards a status event of the node to the policy
+    pub fn on_status_event(&mut self, event: StatusEvent) {
+        self.policy.on_status_event(event);
+    }
+
+    /// Puts `message` of `class` into the transmit queue if the policy admits
+    /// it at time `now`. Returns [`nb::Error::WouldBlock`] if the policy holds
+    /// the message back or the queue is full.
+    pub fn transmit_queued(
+        &mut self,
+        class: TxClass,
+        message: T::Message,
+        now: u32,
+    ) -> nb::Result<(), Error> {
+        if !self.policy.admit(class, now) {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.tx.transmit_queued(message)
+    }
+
+    /// Returns the policy
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Returns the transmit queue, bypassing the policy
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.tx
+    }
+
+    /// Returns the transmit queue and the policy
+    pub fn into_inner(self) -> (T, P) {
+        (self.tx, self.policy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn throttles_while_error_passive() {
+        let mut policy = ErrorPassiveThrottle::new(PassiveBehavior::Throttle { interval: 10 });
+        assert!(policy.admit(TxClass::NonCritical, 0));
+        assert!(policy.admit(TxClass::NonCritical, 1));
+        policy.on_status_event(StatusEvent::ErrorPassive);
+        assert!(policy.admit(TxClass::NonCritical, 2));
+        assert!(!policy.admit(TxClass::NonCritical, 11));
+        assert!(policy.admit(TxClass::Critical, 11));
+        assert!(policy.admit(TxClass::NonCritical, 12));
+        policy.on_status_event(StatusEvent::Recovered);
+        assert!(policy.admit(TxClass::NonCritical, 13));
+        assert!(policy.admit(TxClass::NonCritical, 14));
+    }
+
+    #[test]
+    fn lifts_when_leaving_error_passive() {
+        let mut policy = ErrorPassiveThrottle::new(PassiveBehavior::Suspend);
+        policy.on_status_event(StatusEvent::ErrorPassive);
+        assert!(policy.is_restricted());
+        policy.on_status_event(StatusEvent::LeftErrorPassive);
+        assert!(!policy.is_restricted());
+        assert!(policy.admit(TxClass::NonCritical, 0));
+    }
+
+    #[test]
+    fn suspends_until_recovered() {
+        let mut policy = ErrorPassiveThrottle::new(PassiveBehavior::Suspend);
+        policy.on_status_event(StatusEvent::BusOff);
+        assert!(!policy.admit(TxClass::NonCritical, 0));
+        policy.on_status_event(StatusEvent::ErrorWarning);
+        assert!(!policy.admit(TxClass::NonCritical, 1));
+        assert!(policy.admit(TxClass::Critical, 1));
+        policy.on_status_event(StatusEvent::Recovered);
+        assert!(policy.admit(TxClass::NonCritical, 2));
+    }
+
+    #[test]
+    fn applies_esi_behavior_per_class() {
+        let mut policy = EsiPolicy::new(EsiBehavior::Flag, EsiBehavior::Suppress);
+        assert!(policy.admit(TxClass::NonCritical, 0));
+        policy.on_status_event(StatusEvent::ErrorPassive);
+        policy.on_status_event(StatusEvent::ErrorWarning);
+        assert!(!policy.admit(TxClass::NonCritical, 1));
+        assert!(policy.admit(TxClass::Critical, 1));
+        assert!(policy.admit(TxClass::Critical, 2));
+        assert_eq!(policy.take_flagged(), 2);
+        assert_eq!(policy.flagged(), 0);
+        policy.set_error_passive(false);
+        assert!(policy.admit(TxClass::NonCritical, 3));
+        assert!(policy.admit(TxClass::Critical, 3));
+        assert_eq!(policy.flagged(), 0);
+        policy.on_status_event(StatusEvent::BusOff);
+        assert!(policy.is_error_passive());
+        policy.on_status_event(StatusEvent::LeftErrorPassive);
+        assert!(!policy.is_error_passive());
+    }
+}
@@ -0,0 +1,171 @@
+//! Selective wake-up over CAN
+//!
+//! Partial networking transceivers (ISO 11898-2:2016) stay in sleep while
+//! the bus is active and only wake the node on a dedicated wake-up frame.
+//! [`WakeFrame`] describes that frame in the terms of the standard: an ID
+//! with an ID mask, the DLC and optionally a data mask. The transceiver is
+//! programmed through the [`SelectiveWake`] trait, implemented by its driver.
+//!
+//! Sleep is entered with [`WakeFrame::enter_sleep`], which requests power
+//! down of the peripheral and arms the transceiver once the peripheral is
+//! ready to be switched off. After waking up, [`SelectiveWake::woken_by_frame`]
+//! tells the source of the wake-up, and [`WakeFrame::check_wake`] whether the
+//! wake-up frame was received, if the peripheral came up in time to see it.
+//!
+//! ```no_run
+//! use mcan::bus::DynAux;
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::message::Dlc;
+//! use mcan::wake::{SelectiveWake, WakeFrame};
+//!
+//! fn sleep<T: SelectiveWake>(aux: &impl DynAux, transceiver: &mut T) -> Result<(), T::Error> {
+//!     let id = Id::Standard(StandardId::new(0x7f0).unwrap());
+//!     let wake = WakeFrame::new(id, 0, Dlc::new(1).unwrap())
+//!         .with_data_mask([0x01, 0, 0, 0, 0, 0, 0, 0]);
+//!     nb::block!(wake.enter_sleep(aux, transceiver))
+//! }
+//! ```
+
+use crate::bus::DynAux;
+use crate::message::{Dlc, Raw};
+use crate::rx_fifo::DynRxFifo;
+use embedded_can::Id;
+
+/// Wake-up frame pattern as defined for partial networking transceivers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WakeFrame {
+    /// Expected ID
+    pub id: Id,
+    /// Bits of the ID that are ignored
+    pub id_mask: u32,
+    /// Expected DLC
+    pub dlc: Dlc,
+    /// If set, at least one bit set in the mask has to be set in the data
+    /// field as well
+    pub data_mask: Option<[u8; 8]>,
+}
+
+impl WakeFrame {
+    /// Wakes on frames with `id`, ignoring the bits set in `id_mask`, and
+    /// data length code `dlc`, regardless of their data
+    pub const fn new(id: Id, id_mask: u32, dlc: Dlc) -> Self {
+        Self {
+            id,
+            id_mask,
+            dlc,
+            data_mask: None,
+        }
+    }
+
+    /// Only wakes if the data field has any of the bits in `mask` set
+    pub const fn with_data_mask(mut self, mask: [u8; 8]) -> Self {
+        self.data_mask = Some(mask);
+        self
+    }
+
+    /// Returns `true` if `message` is a wake-up frame
+    pub fn matches<M: Raw>(&self, message: &M) -> bool {
+        let id_matches = match (self.id, message.id()) {
+            (Id::Standard(expected), Id::Standard(id)) => {
+                (expected.as_raw() ^ id.as_raw()) as u32 & !self.id_mask == 0
+            }
+            (Id::Extended(expected), Id::Extended(id)) => {
+                (expected.as_raw() ^ id.as_raw()) & !self.id_mask == 0
+            }
+            _ => false,
+        };
+        // Classic data frames only
+        if !id_matches || message.fd_format() || message.is_remote_frame() {
+            return false;
+        }
+        if message.dlc() != self.dlc {
+            return false;
+        }
+        match self.data_mask {
+            Some(mask) => message
+                .data()
+                .iter()
+                .zip(mask.iter())
+                .any(|(data, mask)| data & mask != 0),
+            None => true,
+        }
+    }
+
+    /// Requests power down of the peripheral and arms the wake-up frame
+    /// detection of `transceiver` once the peripheral is ready to be switched
+    /// off. Fails with [`nb::Error::WouldBlock`] until then.
+    pub fn enter_sleep<T: SelectiveWake>(
+        &self,
+        aux: &impl DynAux,
+        transceiver: &mut T,
+    ) -> nb::Result<(), T::Error> {
+        if !aux.is_ready_for_power_off() {
+            aux.power_down_mode();
+            return Err(nb::Error::WouldBlock);
+        }
+        transceiver.sleep(self).map_err(nb::Error::Other)
+    }
+
+    /// Passes all messages in `fifo` to `f`. Returns `true` if one of them is
+    /// a wake-up frame.
+    pub fn check_wake<F: DynRxFifo>(&self, fifo: &mut F, mut f: impl FnMut(F::Message)) -> bool
+    where
+        F::Message: Raw,
+    {
+        let mut woken = false;
+        while let Ok(message) = fifo.receive() {
+            woken |= self.matches(&message);
+            f(message);
+        }
+        woken
+    }
+}
+
+/// Driver of a transceiver supporting selective wake-up
+pub trait SelectiveWake {
+    /// Error communicating with the transceiver
+    type Error;
+
+    /// Programs `frame` and enters sleep with selective wake-up enabled
+    fn sleep(&mut self, frame: &WakeFrame) -> Result<(), Self::Error>;
+
+    /// Returns `true` if the transceiver was woken by a wake-up frame, as
+    /// opposed to e.g. a local wake-up. Clears the wake-up source.
+    fn woken_by_frame(&mut self) -> Result<bool, Self::Error>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::tx::{AnyMessage, ClassicFrameType, FrameType, Message, MessageBuilder};
+    use embedded_can::StandardId;
+
+    fn frame(id: u16, data: &[u8]) -> Message<8> {
+        Message::new(MessageBuilder {
+            id: Id::Standard(StandardId::new(id).unwrap()),
+            frame_type: FrameType::Classic(ClassicFrameType::Data(data)),
+            store_tx_event: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn matches_id_dlc_and_data() {
+        let wake = WakeFrame::new(
+            Id::Standard(StandardId::new(0x700).unwrap()),
+            0x0f,
+            Dlc::new(2).unwrap(),
+        )
+        .with_data_mask([0x80, 0x01, 0, 0, 0, 0, 0, 0]);
+        assert!(wake.matches(&frame(0x70a, &[0x80, 0])));
+        assert!(wake.matches(&frame(0x700, &[0, 0x03])));
+        assert!(!wake.matches(&frame(0x710, &[0x80, 0])));
+        assert!(!wake.matches(&frame(0x700, &[0x7f, 0x02])));
+        assert!(!wake.matches(&frame(0x700, &[0x80])));
+        let wake = WakeFrame {
+            data_mask: None,
+            ..wake
+        };
+        assert!(wake.matches(&frame(0x700, &[0, 0])));
+    }
+}
@@ -0,0 +1,159 @@
+//! Waiting for a specific frame
+//!
+//! Simple request/response flows and tests often send a frame and wait for
+//! the answer with a given ID. [`Watch`] picks the first frame matching a
+//! predicate from an RX FIFO, giving up after a timeout. Frames received in
+//! the meantime that do not match are handed to a callback, so that they can
+//! be processed as usual instead of being lost.
+//!
+//! [`Watch::poll`] follows the conventions of [`nb`]: [`nb::block!`] waits
+//! for the frame, and an executor can poll it from a future woken by the RX
+//! FIFO interrupts.
+//!
+//! ```no_run
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::message::rx;
+//! use mcan::rx_fifo::DynRxFifo;
+//! use mcan::rx_timestamped::MonotonicClock;
+//! use mcan::timestamp::TimestampSource;
+//! use mcan::watch::{id_is, Watch};
+//! use fugit::ExtU32;
+//!
+//! fn wait_for_response(
+//!     fifo: &mut impl DynRxFifo<Message = rx::Message<8>>,
+//!     clock: &mut MonotonicClock<impl TimestampSource>,
+//! ) -> Option<rx::Message<8>> {
+//!     let response = Id::Standard(StandardId::new(0x7e8).unwrap());
+//!     let mut watch = Watch::new(id_is(response), 50.millis(), clock);
+//!     nb::block!(watch.poll(fifo, clock, |_other| {})).ok()
+//! }
+//! ```
+
+use crate::message::Raw;
+use crate::rx_fifo::DynRxFifo;
+use crate::rx_timestamped::MonotonicClock;
+use crate::timestamp::TimestampSource;
+use embedded_can::Id;
+use fugit::MicrosDurationU32;
+
+/// No matching frame was received within the timeout of a [`Watch`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timeout;
+
+/// Returns a predicate matching frames with `id`, for [`Watch::new`]
+pub fn id_is<M: Raw>(id: Id) -> impl FnMut(&M) -> bool {
+    move |message| message.id() == id
+}
+
+/// Waits for the first frame matching a predicate
+pub struct Watch<P> {
+    predicate: P,
+    /// Time the watch expires, in nanoseconds of the clock
+    deadline: u64,
+}
+
+impl<P> Watch<P> {
+    /// Starts watching for frames matching `predicate` for `timeout` from now
+    pub fn new<S: TimestampSource>(
+        predicate: P,
+        timeout: MicrosDurationU32,
+        clock: &mut MonotonicClock<S>,
+    ) -> Self {
+        let now = clock.now();
+        let deadline = clock
+            .to_nanos(now)
+            .saturating_add(u64::from(timeout.ticks()).saturating_mul(1000));
+        Self {
+            predicate,
+            deadline,
+        }
+    }
+
+    /// Receives frames from `fifo` until one matches. Frames that do not
+    /// match are passed to `others`.
+    ///
+    /// Returns [`nb::Error::WouldBlock`] while waiting and [`Timeout`] once
+    /// the watch has expired. Frames already in the FIFO when it expires are
+    /// still considered.
+    pub fn poll<R, S>(
+        &mut self,
+        fifo: &mut R,
+        clock: &mut MonotonicClock<S>,
+        others: impl FnMut(R::Message),
+    ) -> nb::Result<R::Message, Timeout>
+    where
+        R: DynRxFifo,
+        S: TimestampSource,
+        P: FnMut(&R::Message) -> bool,
+    {
+        let now = clock.now();
+        let now = clock.to_nanos(now);
+        self.poll_at(now, || fifo.receive().ok(), others)
+    }
+
+    fn poll_at<M>(
+        &mut self,
+        now: u64,
+        mut receive: impl FnMut() -> Option<M>,
+        mut others: impl FnMut(M),
+    ) -> nb::Result<M, Timeout>
+    where
+        P: FnMut(&M) -> bool,
+    {
+        while let Some(message) = receive() {
+            if (self.predicate)(&message) {
+                return Ok(message);
+            }
+            others(message);
+        }
+        if now >= self.deadline {
+            Err(nb::Error::Other(Timeout))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn matching_frame_is_picked_and_others_passed_on() {
+        let mut watch = Watch {
+            predicate: |&n: &u32| n % 10 == 0,
+            deadline: 1000,
+        };
+        let mut received = [1, 2, 20, 3].into_iter();
+        let mut others = Vec::new();
+        let result = watch.poll_at(0, || received.next(), |n| others.push(n));
+        assert_eq!(result, Ok(20));
+        assert_eq!(others, [1, 2]);
+        // The frame following the match is left in the FIFO
+        assert_eq!(received.next(), Some(3));
+    }
+
+    #[test]
+    fn watch_expires_after_timeout() {
+        let mut watch = Watch {
+            predicate: |&n: &u32| n == 0,
+            deadline: 1000,
+        };
+        let mut others = Vec::new();
+        let mut receive = [1].into_iter();
+        assert_eq!(
+            watch.poll_at(999, || receive.next(), |n| others.push(n)),
+            Err(nb::Error::WouldBlock)
+        );
+        assert_eq!(
+            watch.poll_at(1000, || None, |n| others.push(n)),
+            Err(nb::Error::Other(Timeout))
+        );
+        // A frame received just before the check still counts
+        assert_eq!(watch.poll_at(2000, || Some(0), |n| others.push(n)), Ok(0));
+        assert_eq!(others, [1]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Feeding a system watchdog on CAN liveness
+//!
+//! [`LivenessWatchdog`] feeds a [`Watchdog`] only while the CAN subsystem is
+//! alive: frames were received or transmitted since the last check and the
+//! peripheral is not bus off. A stuck bus or a stalled receive path then
+//! leads to a watchdog reset, like a stalled task would.
+//!
+//! The watchdog has to be checked at least once per watchdog period, and
+//! frames must flow at least that often. With a window watchdog, it must also
+//! not be checked too early, as every check with activity feeds it.
+//!
+//! ```no_run
+//! use mcan::monitor::DynMonitor;
+//! use mcan::watchdog::LivenessWatchdog;
+//!
+//! # fn kick_hardware_watchdog() {}
+//! fn periodic_task(liveness: &mut LivenessWatchdog<impl FnMut()>, monitor: &impl DynMonitor) {
+//!     liveness.check_monitor(monitor);
+//! }
+//!
+//! let mut liveness = LivenessWatchdog::new(kick_hardware_watchdog);
+//! // On every received or transmitted frame
+//! liveness.on_frame();
+//! ```
+
+use crate::monitor::DynMonitor;
+use crate::power::Transceiver;
+
+/// External watchdog that can be fed
+///
+/// Implemented for closures, so that a function kicking the hardware
+/// watchdog can be used directly.
+pub trait Watchdog {
+    /// Feeds the watchdog, postponing its reset
+    fn feed(&mut self);
+}
+
+impl<F: FnMut()> Watchdog for F {
+    fn feed(&mut self) {
+        self()
+    }
+}
+
+/// Feeds a [`Watchdog`] while frames are flowing and the peripheral is not
+/// bus off
+#[derive(Debug)]
+pub struct LivenessWatchdog<W> {
+    watchdog: W,
+    activity: bool,
+}
+
+impl<W: Watchdog> LivenessWatchdog<W> {
+    /// Wraps `watchdog`. It is fed on the first check after a frame.
+    pub fn new(watchdog: W) -> Self {
+        Self {
+            watchdog,
+            activity: false,
+        }
+    }
+
+    /// Records a received or transmitted frame
+    pub fn on_frame(&mut self) {
+        self.activity = true;
+    }
+
+    /// Feeds the watchdog if frames were recorded since the last check and
+    /// the peripheral is not `bus_off`. Returns `true` if it was fed.
+    pub fn check(&mut self, bus_off: bool) -> bool {
+        let alive = core::mem::take(&mut self.activity) && !bus_off;
+        if alive {
+            self.watchdog.feed();
+        }
+        alive
+    }
+
+    /// [`Self::check`] with the bus off state read from `monitor`
+    ///
+    /// Reading the protocol status clears some of its fields, see
+    /// [`DynMonitor::protocol_status`].
+    pub fn check_monitor(&mut self, monitor: &impl DynMonitor) -> bool {
+        self.check(monitor.protocol_status().bo().bit())
+    }
+
+    /// [`Self::check_monitor`] that also withholds feeding while
+    /// `transceiver` reports a fault, see [`Transceiver::fault`]
+    pub fn check_with_transceiver<T: Transceiver>(
+        &mut self,
+        monitor: &impl DynMonitor,
+        transceiver: &mut T,
+    ) -> Result<bool, T::Error> {
+        if transceiver.fault()? {
+            self.activity = false;
+            return Ok(false);
+        }
+        Ok(self.check_monitor(monitor))
+    }
+
+    /// Returns the watchdog
+    pub fn release(self) -> W {
+        self.watchdog
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn feeds_only_on_activity_without_bus_off() {
+        let mut fed = 0;
+        let mut liveness = LivenessWatchdog::new(|| fed += 1);
+        assert!(!liveness.check(false));
+        liveness.on_frame();
+        liveness.on_frame();
+        assert!(liveness.check(false));
+        assert!(!liveness.check(false));
+        liveness.on_frame();
+        assert!(!liveness.check(true));
+        assert!(!liveness.check(false));
+        assert_eq!(fed, 1);
+    }
+}
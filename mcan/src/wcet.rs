@@ -0,0 +1,164 @@
+//! Worst-case work of interrupt paths
+//!
+//! For worst-case execution time analysis, the work of the operations
+//! typically performed in interrupt handlers is given here as [`Cost`]s:
+//! the number of register reads and writes and of message RAM words
+//! accessed. Loops over queues are only bounded with the `_bounded`
+//! variants, e.g. [`DynRxFifo::receive_bounded`], whose costs are derived
+//! from the bound.
+//!
+//! The counts are upper bounds for the implementation of this version of the
+//! crate. Combined with the access times of the target, they bound the time
+//! spent in the peripheral accesses of a handler at compile time.
+//!
+//! ```
+//! use mcan::message::rx;
+//! use mcan::wcet::{self, Cost};
+//!
+//! // Interrupt handler draining at most 4 messages from an RX FIFO
+//! const HANDLER: Cost = wcet::INTERRUPT_FLAGS
+//!     .plus(wcet::CLEAR_INTERRUPTS)
+//!     .plus(wcet::rx_fifo_receive_bounded::<rx::Message<8>>(4));
+//! assert_eq!(HANDLER.register_reads, 7);
+//! ```
+//!
+//! [`DynRxFifo::receive_bounded`]: crate::rx_fifo::DynRxFifo::receive_bounded
+
+/// Peripheral accesses of an operation
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cost {
+    /// Number of register reads
+    pub register_reads: u32,
+    /// Number of register writes
+    pub register_writes: u32,
+    /// Number of message RAM words read or written
+    pub message_ram_words: u32,
+}
+
+impl Cost {
+    /// Cost of both operations
+    pub const fn plus(self, other: Cost) -> Self {
+        Self {
+            register_reads: self.register_reads.saturating_add(other.register_reads),
+            register_writes: self.register_writes.saturating_add(other.register_writes),
+            message_ram_words: self
+                .message_ram_words
+                .saturating_add(other.message_ram_words),
+        }
+    }
+
+    /// Cost of `n` repetitions of the operation
+    pub const fn times(self, n: u32) -> Self {
+        Self {
+            register_reads: self.register_reads.saturating_mul(n),
+            register_writes: self.register_writes.saturating_mul(n),
+            message_ram_words: self.message_ram_words.saturating_mul(n),
+        }
+    }
+}
+
+/// Number of message RAM words of `M`
+const fn words<M>() -> u32 {
+    (::core::mem::size_of::<M>() / 4) as u32
+}
+
+/// [`OwnedInterruptSet::interrupt_flags`](crate::interrupt::OwnedInterruptSet::interrupt_flags)
+pub const INTERRUPT_FLAGS: Cost = Cost {
+    register_reads: 1,
+    register_writes: 0,
+    message_ram_words: 0,
+};
+
+/// [`OwnedInterruptSet::clear_interrupts`](crate::interrupt::OwnedInterruptSet::clear_interrupts)
+pub const CLEAR_INTERRUPTS: Cost = Cost {
+    register_reads: 0,
+    register_writes: 1,
+    message_ram_words: 0,
+};
+
+/// [`DynRxFifo::receive`](crate::rx_fifo::DynRxFifo::receive) of a message
+/// `M`, including the skipped element of a message delivered ahead by
+/// [`DynRxFifo::receive_high_priority`](crate::rx_fifo::DynRxFifo::receive_high_priority)
+pub const fn rx_fifo_receive<M>() -> Cost {
+    Cost {
+        register_reads: 1,
+        register_writes: 1,
+        message_ram_words: words::<M>(),
+    }
+    .times(2)
+    .plus(Cost {
+        register_reads: 1,
+        register_writes: 0,
+        message_ram_words: 0,
+    })
+}
+
+/// [`DynRxFifo::receive_bounded`](crate::rx_fifo::DynRxFifo::receive_bounded)
+/// of at most `max` messages `M`
+pub const fn rx_fifo_receive_bounded<M>(max: u32) -> Cost {
+    Cost {
+        register_reads: 1,
+        register_writes: 1,
+        message_ram_words: words::<M>(),
+    }
+    .times(max.saturating_add(1))
+    .plus(Cost {
+        register_reads: 1,
+        register_writes: 0,
+        message_ram_words: 0,
+    })
+}
+
+/// [`DynTxEventFifo::pop`](crate::tx_event_fifo::DynTxEventFifo::pop)
+pub const TX_EVENT_FIFO_POP: Cost = Cost {
+    register_reads: 1,
+    register_writes: 1,
+    message_ram_words: 2,
+};
+
+/// [`DynTxEventFifo::pop_bounded`](crate::tx_event_fifo::DynTxEventFifo::pop_bounded)
+/// of at most `max` events
+pub const fn tx_event_fifo_pop_bounded(max: u32) -> Cost {
+    TX_EVENT_FIFO_POP.times(max).plus(Cost {
+        register_reads: 1,
+        register_writes: 0,
+        message_ram_words: 0,
+    })
+}
+
+/// [`DynTx::transmit_queued`](crate::tx_buffers::DynTx::transmit_queued) of
+/// a message `M`
+pub const fn tx_transmit_queued<M>() -> Cost {
+    Cost {
+        register_reads: 4,
+        register_writes: 1,
+        message_ram_words: words::<M>(),
+    }
+}
+
+/// [`DynTx::transmit_dedicated`](crate::tx_buffers::DynTx::transmit_dedicated)
+/// of a message `M`
+pub const fn tx_transmit_dedicated<M>() -> Cost {
+    Cost {
+        register_reads: 2,
+        register_writes: 1,
+        message_ram_words: words::<M>(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bounded_costs_scale_with_the_bound() {
+        let one = tx_event_fifo_pop_bounded(1);
+        let four = tx_event_fifo_pop_bounded(4);
+        assert_eq!(four, one.plus(TX_EVENT_FIFO_POP.times(3)));
+        assert_eq!(tx_event_fifo_pop_bounded(0).register_writes, 0);
+        assert_eq!(
+            TX_EVENT_FIFO_POP.times(u32::MAX).times(2).register_reads,
+            u32::MAX
+        );
+    }
+}
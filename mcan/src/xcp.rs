@@ -0,0 +1,190 @@
+//! Transport layer for XCP on CAN
+//!
+//! XCP is the calibration protocol used by measurement and calibration tools
+//! to access memory of running firmware. On CAN, XCP packets are carried in the
+//! data field of frames with two identifiers: the master sends command
+//! transfer objects (CTOs) with the command ID, while the slave answers with
+//! responses, events and data transfer objects (DTOs) on the response ID.
+//!
+//! [`XcpOnCan`] only handles the framing. The protocol layer, i.e. command
+//! processing and the contents of DAQ lists, is left to an XCP implementation.
+//! DAQ packets are transmitted with [`XcpOnCan::send_daq`], typically from the
+//! timer task sampling the DAQ list.
+//!
+//! ```no_run
+//! use mcan::embedded_can::{Id, StandardId};
+//! use mcan::message::{rx, tx};
+//! use mcan::tx_buffers::DynTx;
+//! use mcan::xcp::XcpOnCan;
+//!
+//! # fn process(command: &[u8], response: &mut [u8; 8]) -> usize { 0 }
+//! let xcp = XcpOnCan::new(
+//!     Id::Standard(StandardId::new(0x7f0).unwrap()),
+//!     Id::Standard(StandardId::new(0x7f1).unwrap()),
+//! )
+//! .with_padding(0x55);
+//!
+//! fn on_message(
+//!     xcp: &XcpOnCan,
+//!     message: &rx::Message<8>,
+//!     tx: &mut impl DynTx<Message = tx::Message<8>>,
+//! ) {
+//!     if let Some(command) = xcp.command(message) {
+//!         let mut response = [0; 8];
+//!         let len = process(command, &mut response);
+//!         let _ = xcp.send(tx, &response[..len]);
+//!     }
+//! }
+//! ```
+
+use crate::message::{rx, tx};
+use crate::tx_buffers::{DynTx, Error};
+use embedded_can::Id;
+
+/// Errors of sending XCP packets
+#[derive(Debug)]
+pub enum XcpError {
+    /// The packet does not fit into a frame
+    PacketTooLong,
+    /// The frame cannot be transmitted
+    Tx(Error),
+}
+
+impl From<Error> for XcpError {
+    fn from(value: Error) -> Self {
+        Self::Tx(value)
+    }
+}
+
+/// XCP on CAN transport of a slave, using Classic CAN frames
+#[derive(Copy, Clone, Debug)]
+pub struct XcpOnCan {
+    command_id: Id,
+    response_id: Id,
+    padding: Option<u8>,
+}
+
+impl XcpOnCan {
+    /// Maximum length of a command or data transfer object
+    pub const MAX_PACKET_LEN: usize = 8;
+
+    /// Creates a transport receiving commands on `command_id` and sending
+    /// responses, events and DAQ packets on `response_id`
+    pub const fn new(command_id: Id, response_id: Id) -> Self {
+        Self {
+            command_id,
+            response_id,
+            padding: None,
+        }
+    }
+
+    /// Pads sent frames to 8 bytes with `fill`, for masters that require a
+    /// constant data length (`MAX_DLC_REQUIRED`)
+    pub const fn with_padding(mut self, fill: u8) -> Self {
+        self.padding = Some(fill);
+        self
+    }
+
+    /// ID commands are received on
+    pub fn command_id(&self) -> Id {
+        self.command_id
+    }
+
+    /// ID responses and DAQ packets are sent on
+    pub fn response_id(&self) -> Id {
+        self.response_id
+    }
+
+    /// Returns the command packet carried by `message`, or `None` if the
+    /// message is not addressed to this slave
+    pub fn command<'m, M: rx::AnyMessage>(&self, message: &'m M) -> Option<&'m [u8]> {
+        (message.id() == self.command_id && !message.is_remote_frame() && !message.fd_format())
+            .then(|| message.data())
+    }
+
+    /// Builds the frame carrying `packet`, which is a response, an event, a
+    /// service request or a DAQ packet including its identification field
+    pub fn frame<M: tx::AnyMessage>(&self, packet: &[u8]) -> Result<M, XcpError> {
+        if packet.len() > Self::MAX_PACKET_LEN {
+            return Err(XcpError::PacketTooLong);
+        }
+        let mut data = [self.padding.unwrap_or(0); Self::MAX_PACKET_LEN];
+        for (to, from) in data.iter_mut().zip(packet) {
+            *to = *from;
+        }
+        let len = match self.padding {
+            Some(_) => Self::MAX_PACKET_LEN,
+            None => packet.len(),
+        };
+        let data = data.get(..len).ok_or(XcpError::PacketTooLong)?;
+        M::new(tx::MessageBuilder {
+            id: self.response_id,
+            frame_type: tx::FrameType::Classic(tx::ClassicFrameType::Data(data)),
+            store_tx_event: None,
+        })
+        .map_err(|_| XcpError::PacketTooLong)
+    }
+
+    /// Sends `packet` through the transmit queue
+    pub fn send<T>(&self, tx: &mut T, packet: &[u8]) -> nb::Result<(), XcpError>
+    where
+        T: DynTx,
+        T::Message: tx::AnyMessage,
+    {
+        let message = self.frame(packet)?;
+        tx.transmit_queued(message)
+            .map_err(|e| e.map(XcpError::from))
+    }
+
+    /// Sends a DAQ packet with the identification field `pid` and the sampled
+    /// `data` through the transmit queue
+    pub fn send_daq<T>(&self, tx: &mut T, pid: u8, data: &[u8]) -> nb::Result<(), XcpError>
+    where
+        T: DynTx,
+        T::Message: tx::AnyMessage,
+    {
+        let mut packet = [0; Self::MAX_PACKET_LEN];
+        let packet = packet
+            .get_mut(..data.len() + 1)
+            .ok_or(XcpError::PacketTooLong)?;
+        if let Some((first, rest)) = packet.split_first_mut() {
+            *first = pid;
+            for (to, from) in rest.iter_mut().zip(data) {
+                *to = *from;
+            }
+        }
+        self.send(tx, packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Raw;
+    use embedded_can::StandardId;
+
+    fn transport() -> XcpOnCan {
+        XcpOnCan::new(
+            Id::Standard(StandardId::new(0x100).unwrap()),
+            Id::Standard(StandardId::new(0x101).unwrap()),
+        )
+    }
+
+    #[test]
+    fn frames_packets() {
+        let frame: tx::Message<8> = transport().frame(&[0xff, 0x00]).unwrap();
+        assert_eq!(frame.id(), transport().response_id());
+        assert_eq!(frame.data(), [0xff, 0x00]);
+
+        let frame: tx::Message<8> = transport().with_padding(0xaa).frame(&[0xfe]).unwrap();
+        assert_eq!(
+            frame.data(),
+            [0xfe, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa]
+        );
+
+        assert!(matches!(
+            transport().frame::<tx::Message<8>>(&[0; 9]),
+            Err(XcpError::PacketTooLong)
+        ));
+    }
+}